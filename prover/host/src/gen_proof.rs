@@ -1,74 +1,223 @@
 use anyhow::anyhow;
-use share::zkvm::ZkVMInput;
-use share::{state::State, transaction::Block};
+use share::zkvm::{execute_batch, InvalidBatch, PublicValues, ZkVMInput};
+use share::{state::State, transaction::Block, utils::read_env_var};
 use sp1_sdk::{HashableKey, ProverClient, SP1Stdin};
 use std::time::Instant;
 
+use crate::proving::ProvingJob;
+
 /// The ELF (executable and linkable format) file for the Succinct RISC-V zkVM.
 pub const BATCH_VERIFIER_ELF: &[u8] =
     include_bytes!("../../program/elf/riscv32im-succinct-zkvm-elf");
 const MAX_PROVE_BLOCKS: usize = 4096;
 
-pub fn prove(state: State, blocks: Vec<Block>) -> Result<Option<Vec<u8>>, anyhow::Error> {
+/// SP1 prover selected by the `SP1_PROVER` env var, `cpu` when unset.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProverMode {
+    /// Executes only and returns an empty proof, accepted on chain by a program built
+    /// with the `mock-verifier` feature. For local development.
+    Mock,
+    Cpu,
+    Cuda,
+    /// Succinct prover network, reads `NETWORK_PRIVATE_KEY` and `NETWORK_RPC_URL`.
+    Network,
+}
+
+impl ProverMode {
+    pub fn from_env() -> Result<Self, anyhow::Error> {
+        match read_env_var("SP1_PROVER", "cpu".to_owned()).as_str() {
+            "mock" => Ok(Self::Mock),
+            "cpu" => Ok(Self::Cpu),
+            "cuda" => Ok(Self::Cuda),
+            "network" => Ok(Self::Network),
+            mode => Err(anyhow!(format!(
+                "invalid SP1_PROVER = {:?}, expected mock, cpu, cuda or network",
+                mode
+            ))),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Mock => "mock",
+            Self::Cpu => "cpu",
+            Self::Cuda => "cuda",
+            Self::Network => "network",
+        }
+    }
+}
+
+/// Outcome of `prove`, only a prover error is worth retrying as is.
+#[derive(Debug)]
+pub enum ProveResult {
+    /// The groth16 proof of the batch.
+    Proof(Vec<u8>),
+    /// The batch has no blocks, there is nothing to prove.
+    SkippedEmpty,
+    /// The blocks don't re-execute to the roots they claim, natively or in the zkVM.
+    InputMismatch(String),
+    /// The prover failed or the job was cancelled.
+    ProverError(String),
+}
+
+impl ProveResult {
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Self::ProverError(_))
+    }
+}
+
+impl std::fmt::Display for ProveResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Proof(proof) => write!(f, "proof of {} bytes", proof.len()),
+            Self::SkippedEmpty => write!(f, "no blocks to prove"),
+            Self::InputMismatch(reason) => write!(f, "input mismatch, {}", reason),
+            Self::ProverError(reason) => write!(f, "prover error, {}", reason),
+        }
+    }
+}
+
+/// Prove `blocks` on top of `state`, reporting each stage to `job` and stopping at the
+/// next one once it's cancelled. `forced_txns` are the bytes of the forced transactions
+/// the batch executes, see `L2StateClient::get_batch_forced_transactions`.
+/// `public_inputs` are the values the l2-state program verifies the proof against, see
+/// `L2StateClient::committed_public_inputs`.
+pub fn prove(
+    mode: ProverMode,
+    state: State,
+    blocks: Vec<Block>,
+    forced_txns: Vec<Vec<u8>>,
+    public_inputs: Vec<u8>,
+    job: &ProvingJob,
+) -> ProveResult {
+    if blocks.is_empty() {
+        return ProveResult::SkippedEmpty;
+    }
+    match prove_blocks(mode, state, blocks, forced_txns, public_inputs, job) {
+        Ok(proof) => ProveResult::Proof(proof),
+        Err(result) => result,
+    }
+}
+
+/// State after executing `blocks` on top of `state`, as the sequencer did, the pre-state
+/// of the batch following them. Its root is checked against the batch that follows.
+pub fn replay_blocks(state: &State, blocks: &[Block]) -> State {
+    let mut state = state.clone();
+    for block in blocks {
+        state.apply_txns(&block.txns, block.block_num);
+    }
+    state
+}
+
+fn prover_error(e: impl std::fmt::Display) -> ProveResult {
+    ProveResult::ProverError(e.to_string())
+}
+
+fn prove_blocks(
+    mode: ProverMode,
+    state: State,
+    blocks: Vec<Block>,
+    forced_txns: Vec<Vec<u8>>,
+    public_inputs: Vec<u8>,
+    job: &ProvingJob,
+) -> Result<Vec<u8>, ProveResult> {
     if blocks.len() > MAX_PROVE_BLOCKS {
-        return Err(anyhow!(format!(
+        return Err(prover_error(format!(
             "check block_tracs, blocks len = {:?} exceeds MAX_PROVE_BLOCKS = {:?}",
             blocks.len(),
             MAX_PROVE_BLOCKS
         )));
     }
 
-    let input = ZkVMInput { blocks, state };
+    // Send only the accounts the batch touches, with their multiproof
+    let input = ZkVMInput {
+        forced_txns,
+        ..ZkVMInput::with_partial_state(blocks, &state)
+    };
+    let input_bytes = input.to_bytes();
+    log::info!(
+        "zkVM input size: {} bytes, {} accounts of {} in full",
+        input_bytes.len(),
+        input.state.accounts.len(),
+        state.accounts.len()
+    );
+
+    // Re-execute natively first, so an invalid batch is reported before touching the zkVM
+    job.enter("executing").map_err(prover_error)?;
+    let expected_public_values = execute_batch(input.clone())
+        .map_err(|e| ProveResult::InputMismatch(format!("invalid batch: {}", e)))?;
+    // The program would reject the proof, e.g. of another withdrawal root
+    if expected_public_values.to_bytes() != public_inputs {
+        return Err(ProveResult::InputMismatch(
+            "public values don't match the committed batch".to_string(),
+        ));
+    }
 
     // Execute the program in sp1-vm
     let mut stdin = SP1Stdin::new();
-    stdin.write(&serde_json::to_string(&input).unwrap());
+    stdin.write_vec(input_bytes);
+    // The env prover reads the same SP1_PROVER, `mode` was validated from it
     let client = ProverClient::from_env();
 
-    let (mut _public_values, execution_report) = client
+    let (mut public_values, execution_report) = client
         .execute(BATCH_VERIFIER_ELF, &stdin.clone())
         .run()
-        .map_err(|e| anyhow!(format!("sp1-vm execution err: {:?}", e)))?;
+        .map_err(|e| prover_error(format!("sp1-vm execution err: {:?}", e)))?;
+
+    // The program commits an InvalidBatch instead of the public values when validation fails
+    if public_values.as_slice() != expected_public_values.to_bytes() {
+        let invalid = public_values.read::<InvalidBatch>();
+        return Err(ProveResult::InputMismatch(format!(
+            "sp1-vm rejected batch: {}",
+            invalid
+        )));
+    }
 
     log::info!(
         "Program executed successfully, Number of cycles: {:?}",
         execution_report.total_instruction_count()
     );
 
+    job.enter("setup").map_err(prover_error)?;
     let (pk, vk) = client.setup(BATCH_VERIFIER_ELF);
     log::info!("Batch ELF Verification Key: {:?}", vk.vk.bytes32());
 
     // Generate the proof
+    job.enter("proving").map_err(prover_error)?;
     let start = Instant::now();
     let mut proof = client
         .prove(&pk, &stdin)
         .groth16()
         .run()
-        .map_err(|e| anyhow!(format!("proving failed: {:?}", e)))?;
+        .map_err(|e| prover_error(format!("proving failed: {:?}", e)))?;
 
     let duration_mins = start.elapsed().as_secs() / 60;
     log::info!(
-        "Successfully generated proof!, time use: {:?} minutes",
+        "Successfully generated {} proof!, time use: {:?} minutes",
+        mode.as_str(),
         duration_mins
     );
 
     // Verify the proof.
+    job.enter("verifying").map_err(prover_error)?;
     client
         .verify(&proof, &vk)
-        .map_err(|e| anyhow!(format!("failed to verify proof: {:?}", e)))?;
+        .map_err(|e| prover_error(format!("failed to verify proof: {:?}", e)))?;
     log::info!("Successfully verified proof!");
 
     // Deserialize the public values.
-    let pi_bytes = proof.public_values.read::<[u8; 32]>();
-    log::info!("pi_hash generated with sp1-vm prove: {}", pi_bytes.len());
-
-    Ok(Some(
-        proof
-            .proof
-            .try_as_groth_16()
-            .unwrap_or_default()
-            .encoded_proof
-            .as_bytes()
-            .to_vec(),
-    ))
+    let proven = proof.public_values.read::<PublicValues>();
+    log::info!(
+        "pi_hash generated with sp1-vm prove: {:?}, da_hash: {:?}",
+        proven.pi_hash,
+        proven.da_hash
+    );
+
+    Ok(proof
+        .proof
+        .try_as_groth_16()
+        .unwrap_or_default()
+        .encoded_proof
+        .as_bytes()
+        .to_vec())
 }
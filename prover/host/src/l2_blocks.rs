@@ -0,0 +1,79 @@
+use anyhow::{anyhow, Result};
+use l2_state_client::state_call::{hash_nested_vector, L2StateClient};
+use serde_json::json;
+use share::transaction::Block;
+use solana_client::{rpc_client::RpcClient, rpc_request::RpcRequest};
+
+/// Number of blocks in the committed range [start_block_num, end_block_num], `None`
+/// for an inverted range (e.g. an `end_block_num` left unset by the batcher).
+pub fn block_count(start_block_num: u64, end_block_num: u64) -> Option<u64> {
+    end_block_num
+        .checked_sub(start_block_num)
+        .map(|count| count + 1)
+}
+
+/// Fetch blocks in range [start_block_num, end_block_num] from the sequencer rpc,
+/// the block db is only ever opened by the sequencer process.
+pub fn fetch_batch_blocks(
+    l2_rpc: &str,
+    start_block_num: u64,
+    end_block_num: u64,
+) -> Result<Vec<Block>> {
+    let client = RpcClient::new(l2_rpc.to_owned());
+    let blocks = client.send::<Vec<Block>>(
+        RpcRequest::Custom {
+            method: "getBatchBlocks",
+        },
+        json!([start_block_num, end_block_num]),
+    )?;
+    Ok(blocks)
+}
+
+/// Rebuild the blocks of a batch from its commit calldata on L1 (Solana as DA),
+/// so any prover can regenerate the proof input without the sequencer. The blocks
+/// of a hash-only batch come from its `reveal_batch` calldata.
+pub fn fetch_batch_blocks_from_l1(
+    l2_state_client: &L2StateClient,
+    batch_index: u64,
+) -> Result<Vec<Block>> {
+    let blocks = match l2_state_client.get_batch_calldata(batch_index)? {
+        Some(batch_info) => batch_info.blocks,
+        None => l2_state_client
+            .get_revealed_blocks(batch_index)?
+            .ok_or_else(|| {
+                anyhow!(
+                    "No commit calldata nor revealed blocks found for batch {}",
+                    batch_index
+                )
+            })?,
+    };
+
+    blocks
+        .iter()
+        .map(|data| {
+            Block::from_canonical_bytes(data)
+                .map_err(|e| anyhow!("Invalid block in batch {} calldata: {}", batch_index, e))
+        })
+        .collect()
+}
+
+/// Batch hash of `blocks` as committed through Solana calldata, the program stores
+/// the hash of the canonically encoded blocks.
+pub fn calculate_batch_hash(blocks: &[Block]) -> Result<[u8; 32]> {
+    let blocks = blocks.iter().map(Block::canonical_bytes).collect();
+    Ok(hash_nested_vector(&blocks))
+}
+
+#[cfg(test)]
+mod test {
+    use super::block_count;
+
+    #[test]
+    fn test_block_count() {
+        assert_eq!(block_count(1, 1), Some(1));
+        assert_eq!(block_count(11, 25), Some(15));
+        // Inverted range, e.g. the batcher's `end_block_num: 0` placeholder
+        assert_eq!(block_count(5, 0), None);
+        assert_eq!(block_count(u64::MAX, 0), None);
+    }
+}
@@ -1,45 +1,473 @@
-use std::time::Duration;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
+use axum::{routing::get, Router};
+use l2_state_client::event_listen;
 use l2_state_client::state_call::{BatchProof, L2StateClient};
-use share::state::StateDB;
-use share::transaction::load_blocks;
+use share::backoff::{Backoff, MAX_L1_BACKOFF};
+use share::liveness::unix_now;
+use share::state::{State, StateDB};
+use share::transaction::{
+    self, set_l1_oracle, set_withdrawal_addresses, withdrawal_addresses_hash,
+};
+use share::utils::{read_env_var, state_db_path};
+use share::{DEFAULT_L1_RPC, DEFAULT_L1_WS, DEFAULT_L2_RPC, WITHDRAWAL_ADDRESS};
+use solana_sdk::commitment_config::CommitmentConfig;
+use tokio::sync::mpsc;
 use tokio::time::sleep;
 
 mod gen_proof;
+mod l2_blocks;
+mod proving;
+mod trigger;
+
+use gen_proof::ProveResult;
+use proving::{ProofQueue, ProverStatus, ProvingJob};
+use trigger::{wait_for_batch, Wake};
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let mut state_db = StateDB::new("state_db");
+    // Must match the sequencer's `withdrawal_addresses`, comma separated
+    let withdrawal_addresses = read_env_var("WITHDRAWAL_ADDRESSES", WITHDRAWAL_ADDRESS.to_owned());
+    set_withdrawal_addresses(
+        withdrawal_addresses
+            .split(',')
+            .map(str::trim)
+            .filter(|address| !address.is_empty())
+            .map(String::from)
+            .collect(),
+    )?;
+    let mut state_db = StateDB::new(&state_db_path());
     state_db.load();
-    let state = state_db.state;
-    let l2_state_client = L2StateClient::new_local()?;
+    // Pre-state of the batch after the last finalized one
+    let mut startup_state = Some(state_db.state);
+    // Only act on finalized batch commits
+    let l2_state_client = L2StateClient::new_local_with_commitment(CommitmentConfig::finalized())?;
+    let program_config = l2_state_client.get_config()?;
+    // The L1 oracle of the program config, only its deposits are credited and the
+    // proofs must commit it
+    set_l1_oracle(program_config.l1_oracle);
+    // Proofs withdrawing on other addresses than the config's don't verify
+    if withdrawal_addresses_hash(&transaction::withdrawal_addresses())
+        != program_config.withdrawal_addresses_hash
+    {
+        log::error!(
+            "WITHDRAWAL_ADDRESSES {} don't match the program config, its authority must \
+             set_withdrawal_addresses_hash for the proofs to verify",
+            withdrawal_addresses
+        );
+    }
+    let l2_rpc = read_env_var("L2_RPC", DEFAULT_L2_RPC.to_owned());
+    // "l1": rebuild blocks from the commit calldata, "sequencer": fetch via getBatchBlocks
+    let block_source = read_env_var("PROVER_BLOCK_SOURCE", "l1".to_owned());
+    // mock, cpu, cuda or network
+    let prover_mode = gen_proof::ProverMode::from_env()?;
+    log::info!("Using SP1 {} prover", prover_mode.as_str());
+    if prover_mode == gen_proof::ProverMode::Mock {
+        log::warn!("Mock proofs are only accepted by a program built with `mock-verifier`");
+    }
 
-    loop {
-        sleep(Duration::from_secs(300)).await;
-        let last_finalized_index = l2_state_client.get_last_finalized_batch_index()?;
-        let next_batch_index = last_finalized_index + 1;
+    // Alert threshold of a prover that keeps failing, the sequencer's getHealth
+    // reports it as well from the finalized batch on L1
+    let stall_threshold = Duration::from_secs(read_env_var("PROVER_STALL_SECS", 3600));
+    // A proof taking longer is cancelled and the batch retried on a later round
+    let prove_timeout = Duration::from_secs(read_env_var("PROVE_TIMEOUT_SECS", 3600));
+    let status = Arc::new(ProverStatus::new());
+    let metrics_addr = read_env_var("PROVER_METRICS_ADDR", "0.0.0.0:9101".to_owned());
+    tokio::spawn(serve_metrics(metrics_addr, status.clone(), stall_threshold));
+    let liveness = &status.liveness;
 
-        let Some(batch) = l2_state_client.get_committed_batch(next_batch_index)? else {
-            continue;
-        };
+    // Prove as soon as a batch commit is finalized on L1, sweeping periodically for
+    // the events missed while the listener was down
+    let sweep_interval = Duration::from_secs(read_env_var("PROVER_SWEEP_SECS", 60));
+    // Minimum time between two rounds, so a burst of commits doesn't thrash the loop
+    let min_interval = Duration::from_secs(read_env_var("PROVER_MIN_INTERVAL_SECS", 5));
+    let (tx, mut committed) = mpsc::unbounded_channel();
+    let l1_rpc = read_env_var("L1_RPC", DEFAULT_L1_RPC.to_owned());
+    let l1_ws = read_env_var("L1_WS", DEFAULT_L1_WS.to_owned());
+    tokio::spawn(async move {
+        // Reconnected after an L1 outage, the sweep covers the commits missed meanwhile
+        let mut backoff = Backoff::new(Duration::from_secs(1), MAX_L1_BACKOFF);
+        loop {
+            let started = Instant::now();
+            let result = event_listen::create_batch_committed_listener(
+                l1_rpc.clone(),
+                l1_ws.clone(),
+                CommitmentConfig::finalized(),
+                tx.clone(),
+            )
+            .await;
+            if started.elapsed() > MAX_L1_BACKOFF {
+                backoff.success();
+            }
+            let retry_in = backoff.failure();
+            log::warn!(
+                "Batch commit listener stopped, reconnecting in {:?}: {:?}",
+                retry_in,
+                result
+            );
+            sleep(retry_in).await;
+        }
+    });
 
-        let block_count = batch.end_block_num - batch.start_block_num + 1;
-        let blocks = load_blocks(batch.start_block_num, block_count).unwrap_or_default();
-        if blocks.is_empty() {
-            continue;
+    // Batches proven at once, their proofs are still submitted in batch index order
+    let parallel_batches = read_env_var("PROVER_PARALLEL_BATCHES", 1usize).max(1);
+    let (proven_tx, mut proven) = mpsc::unbounded_channel::<Proven>();
+    // Batch hash of each batch being proven
+    let mut in_flight: HashMap<u64, [u8; 32]> = HashMap::new();
+    let mut proofs = ProofQueue::default();
+
+    // Check right away on startup, then after each proven batch for a backlog
+    let mut backlog = true;
+    let mut last_round: Option<Instant> = None;
+    // Batches that can't be proven as committed, e.g. their blocks don't re-execute
+    let mut unprovable: HashMap<u64, [u8; 32]> = HashMap::new();
+    // L1 reads retried through an outage, from a second up
+    let mut l1_backoff = Backoff::new(Duration::from_secs(1), MAX_L1_BACKOFF);
+    // State each batch is proven on top of, with its root: the loaded state for the
+    // batch after the last finalized one, the next ones replayed from the blocks of the
+    // batches before them. A batch is only proven once its pre-state is known
+    let mut pre_states: BTreeMap<u64, ([u8; 32], State)> = BTreeMap::new();
+    loop {
+        let mut finished = Vec::new();
+        if !std::mem::take(&mut backlog) {
+            tokio::select! {
+                wake = wait_for_batch(&mut committed, sweep_interval) => match wake {
+                    Wake::Committed(batch_index) => log::info!("Batch {} committed", batch_index),
+                    Wake::Sweep => log::debug!("Sweeping for unproven batches"),
+                },
+                Some(done) = proven.recv() => finished.push(done),
+            }
+        }
+        while let Ok(done) = proven.try_recv() {
+            finished.push(done);
+        }
+        for Proven {
+            batch_index,
+            batch_hash,
+            result,
+        } in finished
+        {
+            in_flight.remove(&batch_index);
+            match result {
+                ProveResult::Proof(proof) => proofs.insert(batch_index, proof),
+                result if result.is_retryable() => log::error!(
+                    "Failed to generate the proof of batch {}, retrying later: {}",
+                    batch_index,
+                    result
+                ),
+                result => {
+                    log::error!(
+                        "Not proving batch {} until it is committed anew: {}",
+                        batch_index,
+                        result
+                    );
+                    unprovable.insert(batch_index, batch_hash);
+                }
+            }
         }
-        let proof = gen_proof::prove(state.clone(), blocks)?;
-        let Some(proof) = proof else {
+        if let Some(last_round) = last_round {
+            sleep(min_interval.saturating_sub(last_round.elapsed())).await;
+        }
+        last_round = Some(Instant::now());
+        if liveness.is_stalled(unix_now(), stall_threshold) {
+            log::error!(
+                "Prover stalled, last success at {:?}",
+                liveness.last_success()
+            );
+        }
+        let unproven =
+            l2_state_client
+                .get_last_finalized_batch_index()
+                .and_then(|last_finalized_index| {
+                    let unproven_batches = l2_state_client.get_unproven_batches()?;
+                    Ok((last_finalized_index, unproven_batches))
+                });
+        let (mut last_finalized_index, unproven_batches) = match unproven {
+            Ok(unproven) => {
+                let failures = l1_backoff.success();
+                if failures > 0 {
+                    log::info!("L1 reachable again after {} failed reads", failures);
+                }
+                unproven
+            }
+            Err(e) => {
+                let retry_in = l1_backoff.failure();
+                log::warn!(
+                    "Failed to read batches from L1, retrying in {:?}: {:?}",
+                    retry_in,
+                    e
+                );
+                sleep(retry_in).await;
+                backlog = true;
+                continue;
+            }
+        };
+        let Some(&next_batch_index) = unproven_batches.first() else {
+            // Nothing to prove
+            liveness.record_success();
             continue;
         };
+        // Proving past a missing batch would finalize over it
+        if next_batch_index != last_finalized_index + 1 {
+            log::error!(
+                "Gap in committed batches, batch {} missing before {:?}",
+                last_finalized_index + 1,
+                unproven_batches
+            );
+            continue;
+        }
 
-        let batch_proof = BatchProof {
-            batch_index: next_batch_index,
-            proof,
-        };
+        if let Some(state) = startup_state.take() {
+            let root = state.calculate_state_root().unwrap_or_default();
+            pre_states.insert(last_finalized_index + 1, (root, state));
+        }
+
+        // Submit the proofs done so far, in order: a later one waits for the earlier
+        while let Some((batch_index, proof)) = proofs.pop_next(last_finalized_index) {
+            let batch_proof = BatchProof { batch_index, proof };
+            match l2_state_client.prove_batch(batch_proof) {
+                Ok(()) => {
+                    liveness.record_success();
+                    last_finalized_index = batch_index;
+                    backlog = true;
+                }
+                Err(e) => {
+                    log::error!("Failed to prove batch {}: {:?}", batch_index, e);
+                    break;
+                }
+            }
+        }
+        // Only the states of unfinalized batches are needed
+        pre_states.retain(|batch_index, _| *batch_index > last_finalized_index);
+
+        // Start proving the next batches up to `parallel_batches` at once, only those
+        // following the last finalized one without a gap
+        let lookahead: Vec<u64> = unproven_batches
+            .iter()
+            .copied()
+            .filter(|batch_index| *batch_index > last_finalized_index)
+            .zip(last_finalized_index + 1..)
+            .take_while(|(batch_index, expected)| batch_index == expected)
+            .map(|(batch_index, _)| batch_index)
+            .take(parallel_batches)
+            .collect();
+        log::info!(
+            "{} unproven batches, {} being proven, next to prove {:?}",
+            unproven_batches.len(),
+            in_flight.len(),
+            lookahead
+        );
+        for next_batch_index in lookahead {
+            if in_flight.len() >= parallel_batches {
+                break;
+            }
+            // Its pre-state was replayed when it started
+            if in_flight.contains_key(&next_batch_index) || proofs.contains(next_batch_index) {
+                continue;
+            }
+            let Some(prev_state_root) = pre_states.get(&next_batch_index).map(|(root, _)| *root)
+            else {
+                log::debug!("Pre-state of batch {} not known yet", next_batch_index);
+                break;
+            };
+            let batch = match l2_state_client.get_committed_batch(next_batch_index) {
+                Ok(Some(batch)) => batch,
+                Ok(None) => {
+                    log::error!("Unproven batch {} not found", next_batch_index);
+                    break;
+                }
+                Err(e) => {
+                    let retry_in = l1_backoff.failure();
+                    log::warn!(
+                        "Failed to read batch {} from L1, retrying in {:?}: {:?}",
+                        next_batch_index,
+                        retry_in,
+                        e
+                    );
+                    sleep(retry_in).await;
+                    backlog = true;
+                    break;
+                }
+            };
+            // Proving it again gives the same result until it is committed anew
+            if unprovable.get(&next_batch_index) == Some(&batch.batch_hash) {
+                continue;
+            }
+            if prev_state_root != batch.prev_state_root {
+                log::error!(
+                    "Batch {} starts from state root {:?}, the prover's is {:?}, skip proving",
+                    next_batch_index,
+                    batch.prev_state_root,
+                    prev_state_root
+                );
+                break;
+            }
+            let Some(block_count) =
+                l2_blocks::block_count(batch.start_block_num, batch.end_block_num)
+            else {
+                log::warn!(
+                    "Batch {} has an invalid block range [{}, {}], skip proving",
+                    next_batch_index,
+                    batch.start_block_num,
+                    batch.end_block_num
+                );
+                continue;
+            };
+            log::info!(
+                "Proving batch {} with {} blocks",
+                next_batch_index,
+                block_count
+            );
 
-        // Prove batch onchain
-        let _ = l2_state_client.prove_batch(batch_proof);
+            let blocks = match block_source.as_str() {
+                // Read blocks through the sequencer, which owns the block db
+                "sequencer" => l2_blocks::fetch_batch_blocks(
+                    &l2_rpc,
+                    batch.start_block_num,
+                    batch.end_block_num,
+                ),
+                _ => l2_blocks::fetch_batch_blocks_from_l1(&l2_state_client, next_batch_index),
+            };
+            let blocks = match blocks {
+                Ok(blocks) => blocks,
+                Err(e) => {
+                    log::warn!(
+                        "Failed to fetch blocks of batch {}: {:?}",
+                        next_batch_index,
+                        e
+                    );
+                    continue;
+                }
+            };
+            if blocks.is_empty() {
+                log::warn!(
+                    "No blocks fetched for batch {}, retrying later",
+                    next_batch_index
+                );
+                continue;
+            }
+            // Only prove exactly what was committed
+            match l2_blocks::calculate_batch_hash(&blocks) {
+                Ok(batch_hash) if batch_hash == batch.batch_hash => {}
+                Ok(batch_hash) => {
+                    log::error!(
+                        "Blocks of batch {} hash to {:?}, committed batch_hash is {:?}, skip proving",
+                        next_batch_index,
+                        batch_hash,
+                        batch.batch_hash
+                    );
+                    continue;
+                }
+                Err(e) => {
+                    log::error!(
+                        "Failed to hash blocks of batch {}: {:?}",
+                        next_batch_index,
+                        e
+                    );
+                    continue;
+                }
+            }
+            let public_inputs = match l2_state_client.committed_public_inputs(&batch) {
+                Ok(public_inputs) => public_inputs,
+                Err(e) => {
+                    log::warn!(
+                        "Failed to read the public inputs of batch {}: {:?}",
+                        next_batch_index,
+                        e
+                    );
+                    continue;
+                }
+            };
+            let forced_txns = match l2_state_client.get_batch_forced_transactions(&batch) {
+                Ok(forced_txns) => forced_txns,
+                Err(e) => {
+                    log::warn!(
+                        "Failed to fetch the forced transactions of batch {}: {:?}",
+                        next_batch_index,
+                        e
+                    );
+                    continue;
+                }
+            };
+            // The next batch is proven on top of this one
+            let state = pre_states[&next_batch_index].1.clone();
+            let post_state = gen_proof::replay_blocks(&state, &blocks);
+            let post_state_root = post_state.calculate_state_root().unwrap_or_default();
+            if post_state_root == batch.post_state_root {
+                pre_states.insert(next_batch_index + 1, (post_state_root, post_state));
+            } else {
+                log::error!(
+                    "Blocks of batch {} replay to state root {:?}, committed post_state_root is {:?}",
+                    next_batch_index,
+                    post_state_root,
+                    batch.post_state_root
+                );
+            }
+            // Prove on a blocking task, given up on after `prove_timeout`. The outcome
+            // wakes the loop up through `proven`
+            let job = Arc::new(ProvingJob::new(next_batch_index));
+            status.start(job.clone());
+            in_flight.insert(next_batch_index, batch.batch_hash);
+            let mut task = tokio::task::spawn_blocking({
+                let job = job.clone();
+                move || {
+                    gen_proof::prove(prover_mode, state, blocks, forced_txns, public_inputs, &job)
+                }
+            });
+            let (status, proven_tx) = (status.clone(), proven_tx.clone());
+            tokio::spawn(async move {
+                let result = match tokio::time::timeout(prove_timeout, &mut task).await {
+                    Ok(Ok(result)) => result,
+                    Ok(Err(e)) => ProveResult::ProverError(format!("proving task failed: {:?}", e)),
+                    Err(_) => {
+                        job.cancel();
+                        let stage = job.stage();
+                        // Still in flight until the blocking task returns at its next stage,
+                        // so the batch isn't proven twice at once
+                        let _ = task.await;
+                        ProveResult::ProverError(format!(
+                            "exceeded {:?} in stage {}, cancelled",
+                            prove_timeout, stage
+                        ))
+                    }
+                };
+                status.finish(next_batch_index);
+                let _ = proven_tx.send(Proven {
+                    batch_index: next_batch_index,
+                    batch_hash: batch.batch_hash,
+                    result,
+                });
+            });
+        }
+    }
+}
+
+// Outcome of proving a batch, sent back to the loop by its task
+struct Proven {
+    batch_index: u64,
+    batch_hash: [u8; 32],
+    result: ProveResult,
+}
+
+// Serve the prover's progress on `/metrics`
+async fn serve_metrics(addr: String, status: Arc<ProverStatus>, stall_threshold: Duration) {
+    let app = Router::new().route(
+        "/metrics",
+        get(move || {
+            let status = status.clone();
+            async move { status.render(stall_threshold) }
+        }),
+    );
+    match tokio::net::TcpListener::bind(&addr).await {
+        Ok(listener) => {
+            log::info!("Serving prover metrics on {}", addr);
+            if let Err(e) = axum::serve(listener, app).await {
+                log::error!("Prover metrics server failed: {:?}", e);
+            }
+        }
+        Err(e) => log::error!("Failed to bind prover metrics on {}: {:?}", addr, e),
     }
 }
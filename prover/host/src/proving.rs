@@ -0,0 +1,219 @@
+use anyhow::anyhow;
+use share::liveness::{unix_now, Liveness};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A batch being proven on a blocking task. The loop waits for it up to the proving
+/// timeout, then cancels it. A stage already running isn't interrupted, the task only
+/// stops when it enters the next one, and the batch stays in flight until then so no
+/// other proof of it starts while it runs.
+pub struct ProvingJob {
+    batch_index: u64,
+    started_at: Instant,
+    stage: Mutex<&'static str>,
+    cancelled: AtomicBool,
+}
+
+impl ProvingJob {
+    pub fn new(batch_index: u64) -> Self {
+        Self {
+            batch_index,
+            started_at: Instant::now(),
+            stage: Mutex::new("queued"),
+            cancelled: AtomicBool::new(false),
+        }
+    }
+
+    pub fn batch_index(&self) -> u64 {
+        self.batch_index
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    pub fn stage(&self) -> &'static str {
+        *self.stage.lock().unwrap()
+    }
+
+    /// Enter `stage`, failing if the job was cancelled in the meantime.
+    pub fn enter(&self, stage: &'static str) -> Result<(), anyhow::Error> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            return Err(anyhow!(
+                "proving batch {} cancelled before {}",
+                self.batch_index,
+                stage
+            ));
+        }
+        log::info!(
+            "Proving batch {}: {} after {:?}",
+            self.batch_index,
+            stage,
+            self.elapsed()
+        );
+        *self.stage.lock().unwrap() = stage;
+        Ok(())
+    }
+
+    /// Stop the job at its next `enter`, the current stage runs to its end.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Proofs of the batches proven in parallel, which finish in any order. The program
+/// finalizes batches one after the other, so they are submitted in batch index order.
+#[derive(Default)]
+pub struct ProofQueue {
+    proofs: BTreeMap<u64, Vec<u8>>,
+}
+
+impl ProofQueue {
+    pub fn insert(&mut self, batch_index: u64, proof: Vec<u8>) {
+        self.proofs.insert(batch_index, proof);
+    }
+
+    pub fn contains(&self, batch_index: u64) -> bool {
+        self.proofs.contains_key(&batch_index)
+    }
+
+    /// The proof to submit after `last_finalized`, `None` until it's done. Proofs of
+    /// batches finalized in the meantime are dropped.
+    pub fn pop_next(&mut self, last_finalized: u64) -> Option<(u64, Vec<u8>)> {
+        self.proofs = self.proofs.split_off(&(last_finalized + 1));
+        self.proofs.remove_entry(&(last_finalized + 1))
+    }
+}
+
+/// The prover's progress, served on `/metrics`.
+pub struct ProverStatus {
+    pub liveness: Liveness,
+    current: Mutex<BTreeMap<u64, Arc<ProvingJob>>>,
+}
+
+impl ProverStatus {
+    pub fn new() -> Self {
+        Self {
+            liveness: Liveness::new("prover"),
+            current: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn start(&self, job: Arc<ProvingJob>) {
+        self.current.lock().unwrap().insert(job.batch_index(), job);
+    }
+
+    pub fn finish(&self, batch_index: u64) {
+        self.current.lock().unwrap().remove(&batch_index);
+    }
+
+    /// Prometheus text format, the proving batch and its elapsed time are 0 when idle.
+    /// With batches proven in parallel they are the ones of the lowest, which the
+    /// others wait on to be submitted.
+    pub fn render(&self, stall_threshold: Duration) -> String {
+        let current = self.current.lock().unwrap();
+        let (batch_index, elapsed, stage) = match current.values().next() {
+            Some(job) => (job.batch_index(), job.elapsed().as_secs(), job.stage()),
+            None => (0, 0, "idle"),
+        };
+        let jobs = current.len();
+        drop(current);
+        format!(
+            "# HELP prover_proving_batch_index Batch being proven, 0 when idle\n\
+             # TYPE prover_proving_batch_index gauge\n\
+             prover_proving_batch_index {}\n\
+             # HELP prover_proving_elapsed_seconds Time spent on the batch being proven\n\
+             # TYPE prover_proving_elapsed_seconds gauge\n\
+             prover_proving_elapsed_seconds {}\n\
+             # HELP prover_proving_stage Stage of the batch being proven\n\
+             # TYPE prover_proving_stage gauge\n\
+             prover_proving_stage{{stage=\"{}\"}} 1\n\
+             # HELP prover_proving_jobs Batches being proven in parallel\n\
+             # TYPE prover_proving_jobs gauge\n\
+             prover_proving_jobs {}\n\
+             # HELP prover_last_success_timestamp_seconds Last success of the prover loop\n\
+             # TYPE prover_last_success_timestamp_seconds gauge\n\
+             prover_last_success_timestamp_seconds {}\n\
+             # HELP prover_stalled Whether the prover loop has no success within its threshold\n\
+             # TYPE prover_stalled gauge\n\
+             prover_stalled {}\n",
+            batch_index,
+            elapsed,
+            stage,
+            jobs,
+            self.liveness.last_success().unwrap_or_default(),
+            self.liveness.is_stalled(unix_now(), stall_threshold) as u8
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ProofQueue, ProverStatus, ProvingJob};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_proving_job() {
+        let status = ProverStatus::new();
+        assert!(status
+            .render(Duration::from_secs(3600))
+            .contains("prover_proving_stage{stage=\"idle\"} 1\n"));
+
+        let job = Arc::new(ProvingJob::new(7));
+        status.start(job.clone());
+        job.enter("executing").unwrap();
+        let metrics = status.render(Duration::from_secs(3600));
+        assert!(metrics.contains("prover_proving_batch_index 7\n"));
+        assert!(metrics.contains("prover_proving_stage{stage=\"executing\"} 1\n"));
+
+        // Timed out, the task stops at its next stage
+        job.cancel();
+        assert!(job.enter("proving").is_err());
+        assert_eq!(job.stage(), "executing");
+
+        status.finish(7);
+        assert!(status
+            .render(Duration::from_secs(3600))
+            .contains("prover_proving_batch_index 0\n"));
+    }
+
+    #[test]
+    fn test_parallel_proving() {
+        let status = ProverStatus::new();
+        for batch_index in [5, 4, 6] {
+            status.start(Arc::new(ProvingJob::new(batch_index)));
+        }
+        let metrics = status.render(Duration::from_secs(3600));
+        assert!(metrics.contains("prover_proving_batch_index 4\n"));
+        assert!(metrics.contains("prover_proving_jobs 3\n"));
+
+        // Batches 4 to 6 finish in reverse order, submitted from 4 on
+        let mut proofs = ProofQueue::default();
+        let mut submitted = Vec::new();
+        let mut last_finalized = 3;
+        for batch_index in [6, 5, 4] {
+            status.finish(batch_index);
+            proofs.insert(batch_index, vec![batch_index as u8]);
+            while let Some((batch_index, proof)) = proofs.pop_next(last_finalized) {
+                assert_eq!(proof, vec![batch_index as u8]);
+                submitted.push(batch_index);
+                last_finalized = batch_index;
+            }
+        }
+        assert_eq!(submitted, vec![4, 5, 6]);
+        assert!(status
+            .render(Duration::from_secs(3600))
+            .contains("prover_proving_jobs 0\n"));
+
+        // A proof of a batch finalized meanwhile, e.g. by another prover, is dropped
+        proofs.insert(7, vec![7]);
+        proofs.insert(9, vec![9]);
+        assert_eq!(proofs.pop_next(7), None);
+        assert!(!proofs.contains(7));
+        assert!(proofs.contains(9));
+        assert_eq!(proofs.pop_next(8), Some((9, vec![9])));
+    }
+}
@@ -0,0 +1,72 @@
+use l2_state_client::event_listen::BatchCommittedData;
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::time::sleep;
+
+/// What woke the prover loop up.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Wake {
+    /// A batch was committed on L1, the newest of the events received meanwhile.
+    Committed(u64),
+    /// No event within the sweep interval, or the listener is gone.
+    Sweep,
+}
+
+/// Wait for a batch commit event or the periodic sweep, which catches the events
+/// missed while the listener was down. A burst of events wakes the loop once.
+pub async fn wait_for_batch(
+    events: &mut UnboundedReceiver<BatchCommittedData>,
+    sweep_interval: Duration,
+) -> Wake {
+    let mut wake = tokio::select! {
+        Some(committed) = events.recv() => Wake::Committed(committed.event.batch_index),
+        _ = sleep(sweep_interval) => Wake::Sweep,
+    };
+    while let Ok(committed) = events.try_recv() {
+        wake = Wake::Committed(committed.event.batch_index);
+    }
+    wake
+}
+
+#[cfg(test)]
+mod test {
+    use super::{wait_for_batch, Wake};
+    use l2_state_client::event_listen::{BatchCommittedData, BatchCommittedEvent};
+    use solana_sdk::signature::Signature;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+
+    fn committed(batch_index: u64) -> BatchCommittedData {
+        BatchCommittedData {
+            event: BatchCommittedEvent {
+                batch_index,
+                start_block_num: 1,
+                end_block_num: 10,
+                batch_hash: [0; 32],
+                timestamp: 0,
+            },
+            slot: 0,
+            signature: Signature::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_batch() {
+        let sweep_interval = Duration::from_millis(10);
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        // A burst wakes the loop once
+        for batch_index in 1..=3 {
+            tx.send(committed(batch_index)).unwrap();
+        }
+        assert_eq!(
+            wait_for_batch(&mut rx, Duration::from_secs(3600)).await,
+            Wake::Committed(3)
+        );
+
+        // Swept without events, and once the listener is gone
+        assert_eq!(wait_for_batch(&mut rx, sweep_interval).await, Wake::Sweep);
+        drop(tx);
+        assert_eq!(wait_for_batch(&mut rx, sweep_interval).await, Wake::Sweep);
+    }
+}
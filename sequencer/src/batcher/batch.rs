@@ -0,0 +1,149 @@
+use anyhow::{anyhow, Result};
+use l2_state_client::state_call::BatchInfo;
+use share::state::count_withdrawals;
+use share::transaction::Block;
+use share::zkvm::executed_forced_count;
+
+/// Build `commit_batch` args from the blocks they cover, so every batch is assembled
+/// the same way.
+pub trait BatchInfoExt: Sized {
+    /// Batch `batch_index` of `blocks` on top of `prev_state_root`,
+    /// `prev_withdrawal_count` and `prev_next_forced_index`, the post state, withdrawal
+    /// queue length and next forced transaction of the previous batch (or the genesis
+    /// state root and 0s). `forced_txns` are the bytes of the forced transactions queued
+    /// on L1 from `prev_next_forced_index` on.
+    ///
+    /// The blocks must be contiguous and chain their state roots from
+    /// `prev_state_root`, the range, roots, withdrawal count and the forced transactions
+    /// executed are derived from them and `blocks` carries their canonical encoding.
+    fn from_blocks(
+        batch_index: u64,
+        blocks: &[Block],
+        prev_state_root: [u8; 32],
+        prev_withdrawal_count: u64,
+        prev_next_forced_index: u64,
+        forced_txns: &[Vec<u8>],
+    ) -> Result<Self>;
+}
+
+impl BatchInfoExt for BatchInfo {
+    fn from_blocks(
+        batch_index: u64,
+        blocks: &[Block],
+        prev_state_root: [u8; 32],
+        prev_withdrawal_count: u64,
+        prev_next_forced_index: u64,
+        forced_txns: &[Vec<u8>],
+    ) -> Result<Self> {
+        let first_block = blocks.first().ok_or_else(|| anyhow!("Empty batch"))?;
+        let start_block_num = first_block.block_num;
+
+        let mut state_root = prev_state_root;
+        for (block_num, block) in (start_block_num..).zip(blocks) {
+            if block.block_num != block_num {
+                return Err(anyhow!(
+                    "Batch {} has a gap, expected block #{} but got #{}",
+                    batch_index,
+                    block_num,
+                    block.block_num
+                ));
+            }
+            if block.prev_state_root != Some(state_root) {
+                return Err(anyhow!(
+                    "Block #{} doesn't chain from the previous state root",
+                    block_num
+                ));
+            }
+            state_root = block
+                .post_state_root
+                .ok_or_else(|| anyhow!("Block #{} has no post state root", block_num))?;
+        }
+
+        let last_block = &blocks[blocks.len() - 1];
+        let withdrawal_root = last_block
+            .withdrawal_root
+            .ok_or_else(|| anyhow!("Block #{} has no withdrawal root", last_block.block_num))?;
+        Ok(BatchInfo {
+            batch_index,
+            blocks: blocks.iter().map(Block::canonical_bytes).collect(),
+            start_block_num,
+            end_block_num: last_block.block_num,
+            prev_state_root,
+            post_state_root: state_root,
+            withdrawal_root,
+            withdrawal_count: prev_withdrawal_count
+                + blocks
+                    .iter()
+                    .map(|block| count_withdrawals(&block.txns))
+                    .sum::<u64>(),
+            next_forced_index: prev_next_forced_index
+                + executed_forced_count(forced_txns, blocks) as u64,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::BatchInfoExt;
+    use l2_state_client::state_call::BatchInfo;
+    use share::transaction::Block;
+
+    // Blocks `start..start + count` chaining roots `[n; 32]` from `[start - 1; 32]`
+    fn chained_blocks(start: u64, count: u64) -> Vec<Block> {
+        (start..start + count)
+            .map(|block_num| {
+                let mut block = Block::new(vec![]);
+                block.block_num = block_num;
+                block.prev_state_root = Some([block_num as u8 - 1; 32]);
+                block.post_state_root = Some([block_num as u8; 32]);
+                block.withdrawal_root = Some([100 + block_num as u8; 32]);
+                block
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_batch_info_from_blocks() {
+        let blocks = chained_blocks(11, 5);
+        let batch_info = BatchInfo::from_blocks(3, &blocks, [10; 32], 7, 2, &[]).unwrap();
+        assert_eq!(batch_info.batch_index, 3);
+        assert_eq!(batch_info.start_block_num, 11);
+        assert_eq!(batch_info.end_block_num, 15);
+        assert_eq!(batch_info.prev_state_root, [10; 32]);
+        assert_eq!(batch_info.post_state_root, [15; 32]);
+        assert_eq!(batch_info.withdrawal_root, [115; 32]);
+        // No withdrawals or forced transactions in the batch, the counts carry over
+        assert_eq!(batch_info.withdrawal_count, 7);
+        assert_eq!(batch_info.next_forced_index, 2);
+        // Bytes that aren't a signed transaction are done without being executed
+        let skipped = BatchInfo::from_blocks(3, &blocks, [10; 32], 7, 2, &[vec![1]]).unwrap();
+        assert_eq!(skipped.next_forced_index, 3);
+        assert_eq!(batch_info.blocks.len(), 5);
+        let block = Block::from_canonical_bytes(&batch_info.blocks[4]).unwrap();
+        assert_eq!(block.block_num, 15);
+
+        // A missing post state root is rejected, not committed as zeros
+        let mut blocks = chained_blocks(11, 5);
+        blocks[4].post_state_root = None;
+        assert!(BatchInfo::from_blocks(3, &blocks, [10; 32], 7, 2, &[]).is_err());
+
+        assert!(BatchInfo::from_blocks(3, &[], [10; 32], 7, 2, &[]).is_err());
+    }
+
+    #[test]
+    fn test_batch_info_gaps() {
+        // Block #13 is missing
+        let mut blocks = chained_blocks(11, 5);
+        blocks.remove(2);
+        assert!(BatchInfo::from_blocks(3, &blocks, [10; 32], 7, 2, &[]).is_err());
+
+        // Contiguous numbers but a root that doesn't chain
+        let mut blocks = chained_blocks(11, 5);
+        blocks[2].prev_state_root = Some([0; 32]);
+        assert!(BatchInfo::from_blocks(3, &blocks, [10; 32], 7, 2, &[]).is_err());
+
+        // The first block must start from the previous batch
+        let blocks = chained_blocks(11, 5);
+        assert!(BatchInfo::from_blocks(3, &blocks, [9; 32], 7, 2, &[]).is_err());
+    }
+}
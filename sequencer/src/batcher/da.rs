@@ -0,0 +1,119 @@
+use anyhow::{anyhow, Result};
+use sha2::{Digest, Sha256};
+use share::transaction::Block;
+use std::path::PathBuf;
+
+use crate::config::Config;
+
+/// Where the blocks of a batch were published.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DaPointer {
+    /// The serialized blocks, carried in the `commit_batch` calldata itself.
+    Calldata(Vec<Vec<u8>>),
+    /// A reference to blocks published elsewhere (e.g. a blob id or a tx hash on
+    /// another chain), only the reference goes into the calldata.
+    External(Vec<u8>),
+}
+
+impl DaPointer {
+    /// Payload of `BatchInfo.blocks`, the program stores its hash as the `batch_hash`.
+    pub fn into_calldata(self) -> Vec<Vec<u8>> {
+        match self {
+            DaPointer::Calldata(blocks) => blocks,
+            DaPointer::External(pointer) => vec![pointer],
+        }
+    }
+}
+
+/// Data availability layer the batcher publishes block data to before committing
+/// the batch on Solana.
+pub trait DaBackend: Send + Sync {
+    fn publish(&self, blocks: &[Block]) -> Result<DaPointer>;
+}
+
+/// Default backend, the blocks are posted as Solana calldata of `commit_batch`, in
+/// their canonical encoding the zkVM hashes as well.
+pub struct SolanaCalldataBackend;
+
+impl DaBackend for SolanaCalldataBackend {
+    fn publish(&self, blocks: &[Block]) -> Result<DaPointer> {
+        let blocks = blocks.iter().map(Block::canonical_bytes).collect();
+        Ok(DaPointer::Calldata(blocks))
+    }
+}
+
+/// Blob store on the local disk, for development. Each batch is written to
+/// `<dir>/<sha256 of the blocks>.json` and only the hash is committed on Solana.
+pub struct LocalBlobBackend {
+    pub dir: PathBuf,
+}
+
+impl DaBackend for LocalBlobBackend {
+    fn publish(&self, blocks: &[Block]) -> Result<DaPointer> {
+        let data = serde_json::to_vec(blocks)?;
+        let blob_hash: [u8; 32] = Sha256::digest(&data).into();
+        let file_name = blob_hash
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+        std::fs::create_dir_all(&self.dir)?;
+        std::fs::write(self.dir.join(format!("{}.json", file_name)), data)?;
+        Ok(DaPointer::External(blob_hash.to_vec()))
+    }
+}
+
+/// Backend selected by `da_backend`, `calldata` (default) or `local` storing blobs
+/// under `da_local_dir`.
+pub fn da_backend_from_config(config: &Config) -> Result<Box<dyn DaBackend>> {
+    match config.da_backend.as_str() {
+        "calldata" => Ok(Box::new(SolanaCalldataBackend)),
+        "local" => Ok(Box::new(LocalBlobBackend {
+            dir: PathBuf::from(&config.da_local_dir),
+        })),
+        backend => Err(anyhow!("Unknown DA_BACKEND: {}", backend)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DaBackend, DaPointer, LocalBlobBackend, SolanaCalldataBackend};
+    use share::transaction::Block;
+
+    #[test]
+    fn test_calldata_backend() {
+        let blocks: Vec<Block> = (1..=3)
+            .map(|block_num| {
+                let mut block = Block::new(vec![]);
+                block.block_num = block_num;
+                block
+            })
+            .collect();
+
+        // One calldata entry per block, decodable by the prover
+        let calldata = SolanaCalldataBackend
+            .publish(&blocks)
+            .unwrap()
+            .into_calldata();
+        assert_eq!(calldata.len(), 3);
+        let block = Block::from_canonical_bytes(&calldata[2]).unwrap();
+        assert_eq!(block.block_num, 3);
+
+        // An external pointer is committed alone
+        let pointer = DaPointer::External(b"blob:42".to_vec());
+        assert_eq!(pointer.into_calldata(), vec![b"blob:42".to_vec()]);
+    }
+
+    #[test]
+    fn test_local_blob_backend() {
+        let dir = std::env::temp_dir().join(format!("da_blobs_{}", std::process::id()));
+        let backend = LocalBlobBackend { dir: dir.clone() };
+        let blocks = vec![Block::new(vec![])];
+
+        let DaPointer::External(blob_hash) = backend.publish(&blocks).unwrap() else {
+            panic!("expected an external pointer");
+        };
+        assert_eq!(blob_hash.len(), 32);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
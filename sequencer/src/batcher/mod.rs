@@ -1 +1,3 @@
-pub mod tx_batcher;
\ No newline at end of file
+pub mod batch;
+pub mod da;
+pub mod tx_batcher;
@@ -1,59 +1,154 @@
 use std::vec;
 
+use crate::batcher::batch::BatchInfoExt;
+use crate::batcher::da::{da_backend_from_config, DaBackend};
+use crate::config::config;
+use crate::executor::genesis_state;
+use crate::metrics::BATCHER_LIVENESS;
 use crate::node::BLOCK_DB;
-use anyhow::Result;
-use l2_state_client::state_call::{BatchInfo, L2StateClient};
-use log::info;
-use share::transaction::{Block, BlockDB};
+use anyhow::{anyhow, Result};
+use l2_state_client::state_call::{BatchData, BatchInfo, L2StateClient};
+use serde::{Deserialize, Serialize};
+use share::backoff::Backoff;
+use share::kv::KvStore;
+use share::transaction::{decode_block, Block, BlockDB};
+use solana_sdk::packet::PACKET_DATA_SIZE;
+use std::time::Duration;
+use tokio::sync::{Mutex, RwLock};
+use tracing::{info, instrument, Span};
 
-static MAX_BLOCK_COUNT_IN_BATCH: u64 = 256;
+/// The calls the batcher makes to the l2-state program on L1.
+pub trait BatcherL1: Send + Sync {
+    fn get_committed_batch(&self, batch_index: u64) -> Result<Option<BatchData>>;
+    fn get_latest_batch(&self) -> Result<Option<BatchData>>;
+    fn commit_batch(&self, batch_info: BatchInfo) -> Result<()>;
+    fn commit_batch_transaction_size(&self, batch_info: &BatchInfo) -> Result<usize>;
+    /// Bytes of the forced transactions queued from index `start` on, in queue order.
+    fn get_forced_transactions(&self, start: u64) -> Result<Vec<Vec<u8>>>;
+}
+
+impl BatcherL1 for L2StateClient {
+    fn get_committed_batch(&self, batch_index: u64) -> Result<Option<BatchData>> {
+        L2StateClient::get_committed_batch(self, batch_index)
+    }
+
+    fn get_latest_batch(&self) -> Result<Option<BatchData>> {
+        L2StateClient::get_latest_batch(self)
+    }
+
+    fn commit_batch(&self, batch_info: BatchInfo) -> Result<()> {
+        L2StateClient::commit_batch(self, batch_info)
+    }
+
+    fn commit_batch_transaction_size(&self, batch_info: &BatchInfo) -> Result<usize> {
+        L2StateClient::commit_batch_transaction_size(self, batch_info)
+    }
+
+    fn get_forced_transactions(&self, start: u64) -> Result<Vec<Vec<u8>>> {
+        let end = self.get_forced_transaction_queue()?.next_index;
+        L2StateClient::get_forced_transactions(self, start, end)
+    }
+}
 
 pub struct TxBatcher {
-    l2_state_client: L2StateClient,
+    l2_state_client: Box<dyn BatcherL1>,
+    da_backend: Box<dyn DaBackend>,
+    // One submission at a time, the timer and `forceBatchSubmit` share the batcher
+    submitting: Mutex<()>,
 }
 
 impl TxBatcher {
     pub fn new() -> Result<Self> {
-        let l2_state_client = L2StateClient::new_local()?;
-        Ok(Self { l2_state_client })
+        let l2_state_client = config().l2_state_client()?;
+        Ok(Self::with_client(
+            Box::new(l2_state_client),
+            da_backend_from_config(config())?,
+        ))
     }
 
     pub fn _new_with_config(rpc_url: String, fee_payer_bytes: &[u8]) -> Result<Self> {
         let l2_state_client = L2StateClient::new(rpc_url, fee_payer_bytes)?;
-        Ok(Self { l2_state_client })
-    }
-
-    pub async fn smart_submit(&self) -> Result<()> {
-        let latest_batch = self.l2_state_client.get_latest_batch()?;
-        let mut next_batch = if let Some(batch) = latest_batch {
-            BatchInfo {
-                batch_index: batch.batch_index + 1,
-                blocks: vec![],
-                start_block_num: batch.end_block_num + 1,
-                end_block_num: 0,
-                prev_state_root: batch.post_state_root,
-                post_state_root: [0u8; 32],
-                withdrawal_root: [0u8; 32],
+        Ok(Self::with_client(
+            Box::new(l2_state_client),
+            da_backend_from_config(config())?,
+        ))
+    }
+
+    /// Batcher committing through `l2_state_client`, publishing the blocks to
+    /// `da_backend`.
+    pub fn with_client(
+        l2_state_client: Box<dyn BatcherL1>,
+        da_backend: Box<dyn DaBackend>,
+    ) -> Self {
+        Self {
+            l2_state_client,
+            da_backend,
+            submitting: Mutex::new(()),
+        }
+    }
+
+    /// One round of the batcher loop: commit the next batch of `block_db` and record
+    /// the outcome in `backoff`. Returns the wait before the next round, which grows
+    /// while L1 is unreachable. The block db is only locked for the submission, so
+    /// blocks keep being produced meanwhile and are committed once L1 is back.
+    pub async fn round(&self, block_db: &RwLock<BlockDB>, backoff: &mut Backoff) -> Duration {
+        match self.submit(&*block_db.read().await).await {
+            Ok(_) => {
+                let failures = backoff.success();
+                if failures > 0 {
+                    info!(failures, "Batcher recovered");
+                }
+                BATCHER_LIVENESS.record_success();
+                backoff.delay()
             }
-        } else {
-            BatchInfo {
-                batch_index: 1,
-                blocks: vec![],
-                start_block_num: 1,
-                end_block_num: 0,
-                prev_state_root: [0u8; 32],
-                post_state_root: [0u8; 32],
-                withdrawal_root: [0u8; 32],
+            Err(e) => {
+                let retry_in = backoff.failure();
+                tracing::error!(
+                    failures = backoff.failures(),
+                    "Batcher error, retrying in {:?}: {:?}",
+                    retry_in,
+                    e
+                );
+                retry_in
             }
-        };
+        }
+    }
+
+    /// Commit the next batch of blocks.
+    ///
+    /// A local commit marker is reconciled with L1 first, so a commit that landed but
+    /// timed out before confirming isn't submitted again with a different block range,
+    /// after a restart as well.
+    ///
+    /// Returns the index of the committed batch, `None` with nothing to submit.
+    pub async fn smart_submit(&self) -> Result<Option<u64>> {
+        self.submit(&*BLOCK_DB.read().await).await
+    }
+
+    // `smart_submit` of the blocks of `block_db`
+    #[instrument(skip_all, fields(batch_index = tracing::field::Empty))]
+    async fn submit(&self, block_db: &BlockDB) -> Result<Option<u64>> {
+        let _submitting = self.submitting.lock().await;
 
-        let block_db = BLOCK_DB.read().await;
+        // Reconcile a pending commit with L1
+        let marker = CommitMarker::load(block_db.db.as_ref())?;
+        let marker_landed = match &marker {
+            Some(marker) if !marker.confirmed => self
+                .l2_state_client
+                .get_committed_batch(marker.batch_index)?
+                .is_some_and(|batch| batch.end_block_num == marker.end_block_num),
+            _ => false,
+        };
+        let latest_on_chain = self
+            .l2_state_client
+            .get_latest_batch()?
+            .map(|batch| (batch.batch_index, batch.end_block_num));
 
         // Get latest block number from database
         let latest_block_num = match block_db.db.get("latest_block_num")? {
             Some(bytes) => {
                 let num_bytes: [u8; 8] = bytes
-                    .as_ref()
+                    .as_slice()
                     .try_into()
                     .map_err(|_| anyhow::anyhow!("Invalid block number format"))?;
                 u64::from_be_bytes(num_bytes)
@@ -61,64 +156,154 @@ impl TxBatcher {
             None => 0,
         };
 
-        if latest_block_num == 0 {
-            info!("No blocks to submit");
-            return Ok(());
-        }
+        let (batch_index, start_block_num, end_block_num) =
+            match plan_next_commit(marker.as_ref(), marker_landed, latest_on_chain) {
+                NextCommit::Resubmit(marker) => {
+                    info!(
+                        "Batch {} not found on L1, resubmitting the same blocks",
+                        marker.batch_index
+                    );
+                    (
+                        marker.batch_index,
+                        marker.start_block_num,
+                        marker.end_block_num,
+                    )
+                }
+                NextCommit::New {
+                    batch_index,
+                    start_block_num,
+                } => {
+                    if let Some(marker) = marker.filter(|marker| !marker.confirmed) {
+                        // Landed, the confirmation was lost
+                        CommitMarker {
+                            confirmed: true,
+                            ..marker
+                        }
+                        .save(block_db.db.as_ref())?;
+                    }
+                    let Some(end_block_num) = batch_end_block_num(
+                        start_block_num,
+                        latest_block_num,
+                        config().max_batch_blocks,
+                    ) else {
+                        info!("No new blocks to submit");
+                        return Ok(None);
+                    };
+                    (batch_index, start_block_num, end_block_num)
+                }
+            };
+
+        Span::current().record("batch_index", batch_index);
 
         // Determine which blocks to include in the next batch
         let blocks_to_submit = self
-            .collect_blocks_for_batch(&block_db, next_batch.start_block_num, latest_block_num)
+            .collect_blocks_for_batch(block_db, start_block_num, end_block_num)
             .await?;
 
         if blocks_to_submit.is_empty() {
             info!("No new blocks to submit");
-            return Ok(());
+            return Ok(None);
         }
+        let prev_state_root = prev_state_root(block_db, start_block_num)?;
+        let (prev_withdrawal_count, prev_next_forced_index) =
+            self.prev_batch_counts(batch_index)?;
+        // The forced transactions no batch executed yet, the batch claims the ones its
+        // blocks execute
+        let forced_txns = self
+            .l2_state_client
+            .get_forced_transactions(prev_next_forced_index)?;
+        let prev = PrevBatch {
+            state_root: prev_state_root,
+            withdrawal_count: prev_withdrawal_count,
+            next_forced_index: prev_next_forced_index,
+            forced_txns: &forced_txns,
+        };
 
-        next_batch.post_state_root = blocks_to_submit
-            .last()
-            .unwrap()
-            .post_state_root
-            .unwrap_or_default();
-        next_batch.withdrawal_root = blocks_to_submit
-            .last()
-            .unwrap()
-            .withdrawal_root
-            .unwrap_or_default();
+        // Drop trailing blocks until the commit transaction fits in a packet
+        let blocks_count = fit_blocks_in_packet(blocks_to_submit.len(), |count| {
+            let batch_info =
+                self.build_batch_info(batch_index, &prev, &blocks_to_submit[..count])?;
+            self.l2_state_client
+                .commit_batch_transaction_size(&batch_info)
+        })?;
+        if blocks_count < blocks_to_submit.len() {
+            info!(
+                start_block_num,
+                blocks = blocks_count,
+                dropped = blocks_to_submit.len() - blocks_count,
+                "Batch {} exceeds the packet size, splitting",
+                batch_index
+            );
+        }
+        let blocks_to_submit = &blocks_to_submit[..blocks_count];
+        let end_block_num = start_block_num + blocks_count as u64 - 1;
+        let next_batch = self.build_batch_info(batch_index, &prev, blocks_to_submit)?;
 
-        // Serialize block
-        let block_data = blocks_to_submit
-            .iter()
-            .map(|block| serde_json::to_vec(block).unwrap_or_default())
-            .collect();
+        // Submit the batch, marked pending until confirmed
+        let mut marker = CommitMarker {
+            batch_index,
+            start_block_num,
+            end_block_num,
+            confirmed: false,
+        };
+        marker.save(block_db.db.as_ref())?;
+        info!(
+            start_block_num,
+            blocks = blocks_to_submit.len(),
+            "Committing batch {} to Solana",
+            batch_index
+        );
+        self.l2_state_client.commit_batch(next_batch)?;
+        marker.confirmed = true;
+        marker.save(block_db.db.as_ref())?;
 
-        next_batch.blocks = block_data;
+        Ok(Some(batch_index))
+    }
 
-        // Submit the batch
-        info!("Committing batch {} to Solana", next_batch.batch_index);
-        self.l2_state_client.commit_batch(next_batch)?;
+    // Publish `blocks` to the DA layer and build the `commit_batch` args on top of the
+    // previous batch
+    fn build_batch_info(
+        &self,
+        batch_index: u64,
+        prev: &PrevBatch,
+        blocks: &[Block],
+    ) -> Result<BatchInfo> {
+        let mut batch_info = BatchInfo::from_blocks(
+            batch_index,
+            blocks,
+            prev.state_root,
+            prev.withdrawal_count,
+            prev.next_forced_index,
+            prev.forced_txns,
+        )?;
+        // Publish the block data, the calldata carries the blocks or a pointer to them
+        batch_info.blocks = self.da_backend.publish(blocks)?.into_calldata();
+        Ok(batch_info)
+    }
 
-        Ok(())
+    // Withdrawal queue length and next forced transaction at the end of the previous
+    // batch, read from L1
+    fn prev_batch_counts(&self, batch_index: u64) -> Result<(u64, u64)> {
+        if batch_index <= 1 {
+            return Ok((0, 0));
+        }
+        self.l2_state_client
+            .get_committed_batch(batch_index - 1)?
+            .map(|batch| (batch.withdrawal_count, batch.next_forced_index))
+            .ok_or_else(|| anyhow!("Batch {} not found on L1 yet", batch_index - 1))
     }
 
-    /// Collect blocks from the database for batching
+    /// Collect blocks `start_block_num..=end_block_num` from the database for batching
     async fn collect_blocks_for_batch(
         &self,
         block_db: &BlockDB,
         start_block_num: u64,
-        latest_block_num_local: u64,
+        end_block_num: u64,
     ) -> Result<Vec<Block>> {
-        let blocks_count = if latest_block_num_local - start_block_num > MAX_BLOCK_COUNT_IN_BATCH {
-            MAX_BLOCK_COUNT_IN_BATCH
-        } else {
-            latest_block_num_local - start_block_num
-        };
-
         let mut blocks = vec![];
-        for i in start_block_num..start_block_num + blocks_count {
-            if let Ok(Some(data)) = block_db.db.get(format!("block_{}", i)) {
-                if let Ok(block) = serde_json::from_slice::<Block>(&data) {
+        for i in start_block_num..=end_block_num {
+            if let Ok(Some(data)) = block_db.db.get(&format!("block_{}", i)) {
+                if let Some(block) = decode_block(&data) {
                     blocks.push(block);
                 }
             } else {
@@ -129,3 +314,325 @@ impl TxBatcher {
         Ok(blocks)
     }
 }
+
+// What a batch builds on from the previous one
+struct PrevBatch<'a> {
+    state_root: [u8; 32],
+    withdrawal_count: u64,
+    next_forced_index: u64,
+    // Bytes of the forced transactions queued from `next_forced_index` on
+    forced_txns: &'a [Vec<u8>],
+}
+
+// Post state root of the block before `start_block_num`, the genesis state root for
+// the first batch
+fn prev_state_root(block_db: &BlockDB, start_block_num: u64) -> Result<[u8; 32]> {
+    if start_block_num <= 1 {
+        return Ok(genesis_state().calculate_state_root().unwrap_or_default());
+    }
+    let prev_block_num = start_block_num - 1;
+    block_db
+        .db
+        .get(&format!("block_{}", prev_block_num))?
+        .and_then(|data| decode_block(&data))
+        .and_then(|block| block.post_state_root)
+        .ok_or_else(|| anyhow!("Missing state root of block #{}", prev_block_num))
+}
+
+/// Last batch the batcher committed, persisted in the block db.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CommitMarker {
+    pub batch_index: u64,
+    pub start_block_num: u64,
+    pub end_block_num: u64,
+    // Whether the commit transaction was confirmed
+    pub confirmed: bool,
+}
+
+impl CommitMarker {
+    const KEY: &'static str = "commit_marker";
+
+    pub fn load(db: &dyn KvStore) -> Result<Option<Self>> {
+        match db.get(Self::KEY)? {
+            Some(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn save(&self, db: &dyn KvStore) -> Result<()> {
+        db.insert(Self::KEY, serde_json::to_vec(self)?)?;
+        db.flush()?;
+        Ok(())
+    }
+}
+
+// Last block of a batch from `start_block_num`, at most `max_blocks` long. The latest
+// block is left for the next batch, `None` when that leaves nothing. The batch is cut
+// down further when its commit transaction doesn't fit, see `fit_blocks_in_packet`.
+fn batch_end_block_num(
+    start_block_num: u64,
+    latest_block_num: u64,
+    max_blocks: u64,
+) -> Option<u64> {
+    if latest_block_num <= start_block_num {
+        return None;
+    }
+    let blocks_count = (latest_block_num - start_block_num).min(max_blocks.max(1));
+    Some(start_block_num + blocks_count - 1)
+}
+
+// Largest number of leading blocks, out of `blocks_count`, whose commit transaction
+// fits in `PACKET_DATA_SIZE`, `tx_size` gives the transaction size of a prefix.
+fn fit_blocks_in_packet(
+    blocks_count: usize,
+    tx_size: impl Fn(usize) -> Result<usize>,
+) -> Result<usize> {
+    if tx_size(blocks_count)? <= PACKET_DATA_SIZE {
+        return Ok(blocks_count);
+    }
+    // Binary search, the first block alone must fit
+    let (mut fits, mut too_large) = (0, blocks_count);
+    while too_large - fits > 1 {
+        let count = (fits + too_large) / 2;
+        if tx_size(count)? <= PACKET_DATA_SIZE {
+            fits = count;
+        } else {
+            too_large = count;
+        }
+    }
+    if fits == 0 {
+        return Err(anyhow!(
+            "A single block exceeds the transaction size limit of {} bytes",
+            PACKET_DATA_SIZE
+        ));
+    }
+    Ok(fits)
+}
+
+#[derive(Debug, PartialEq)]
+enum NextCommit {
+    Resubmit(CommitMarker),
+    New {
+        batch_index: u64,
+        start_block_num: u64,
+    },
+}
+
+// Decide what to commit from the local marker, whether its pending commit landed,
+// and the latest (batch_index, end_block_num) on L1, which may lag behind the marker.
+fn plan_next_commit(
+    marker: Option<&CommitMarker>,
+    marker_landed: bool,
+    latest_on_chain: Option<(u64, u64)>,
+) -> NextCommit {
+    if let Some(marker) = marker {
+        if !marker.confirmed && !marker_landed {
+            return NextCommit::Resubmit(marker.clone());
+        }
+    }
+
+    let latest_local = marker.map(|marker| (marker.batch_index, marker.end_block_num));
+    match latest_local.max(latest_on_chain) {
+        Some((batch_index, end_block_num)) => NextCommit::New {
+            batch_index: batch_index + 1,
+            start_block_num: end_block_num + 1,
+        },
+        None => NextCommit::New {
+            batch_index: 1,
+            start_block_num: 1,
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        batch_end_block_num, fit_blocks_in_packet, plan_next_commit, BatcherL1, CommitMarker,
+        NextCommit, TxBatcher,
+    };
+    use crate::batcher::da::SolanaCalldataBackend;
+    use crate::executor::genesis_state;
+    use anyhow::{anyhow, Result};
+    use l2_state_client::state_call::{BatchData, BatchInfo};
+    use share::backoff::Backoff;
+    use share::kv::MemoryStore;
+    use share::transaction::{Block, BlockDB};
+    use solana_sdk::packet::PACKET_DATA_SIZE;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tokio::sync::RwLock;
+
+    // L1 that can go down, keeping the batches committed to it
+    #[derive(Default)]
+    struct FakeL1 {
+        down: bool,
+        batches: Vec<BatchData>,
+    }
+
+    impl BatcherL1 for Arc<Mutex<FakeL1>> {
+        fn get_committed_batch(&self, batch_index: u64) -> Result<Option<BatchData>> {
+            let l1 = self.lock().unwrap();
+            if l1.down {
+                return Err(anyhow!("L1 unreachable"));
+            }
+            Ok(l1
+                .batches
+                .iter()
+                .find(|batch| batch.batch_index == batch_index)
+                .cloned())
+        }
+
+        fn get_latest_batch(&self) -> Result<Option<BatchData>> {
+            let l1 = self.lock().unwrap();
+            if l1.down {
+                return Err(anyhow!("L1 unreachable"));
+            }
+            Ok(l1.batches.last().cloned())
+        }
+
+        fn commit_batch(&self, batch_info: BatchInfo) -> Result<()> {
+            let mut l1 = self.lock().unwrap();
+            if l1.down {
+                return Err(anyhow!("L1 unreachable"));
+            }
+            l1.batches.push(BatchData {
+                batch_index: batch_info.batch_index,
+                start_block_num: batch_info.start_block_num,
+                end_block_num: batch_info.end_block_num,
+                batch_hash: [0; 32],
+                prev_state_root: batch_info.prev_state_root,
+                post_state_root: batch_info.post_state_root,
+                withdrawal_root: batch_info.withdrawal_root,
+                withdrawal_count: batch_info.withdrawal_count,
+                next_forced_index: batch_info.next_forced_index,
+                forced_transactions_hash: [0; 32],
+            });
+            Ok(())
+        }
+
+        fn commit_batch_transaction_size(&self, _batch_info: &BatchInfo) -> Result<usize> {
+            Ok(0)
+        }
+
+        fn get_forced_transactions(&self, _start: u64) -> Result<Vec<Vec<u8>>> {
+            Ok(vec![])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_l1_outage_rounds() {
+        let l1 = Arc::new(Mutex::new(FakeL1::default()));
+        let batcher = TxBatcher::with_client(Box::new(l1.clone()), Box::new(SolanaCalldataBackend));
+        let block_db = RwLock::new(BlockDB::in_memory());
+        let state_root = Some(genesis_state().calculate_state_root().unwrap_or_default());
+        let mut backoff = Backoff::new(Duration::from_secs(10), Duration::from_secs(60));
+
+        // A block per round, L1 down for rounds 2 to 4
+        let mut delays = vec![];
+        for round in 0..7u64 {
+            let mut block = Block::new(vec![]);
+            block.block_num = round + 1;
+            block.prev_state_root = state_root;
+            block.post_state_root = state_root;
+            block.withdrawal_root = Some([0; 32]);
+            block_db.write().await.save_block(&block).unwrap();
+
+            l1.lock().unwrap().down = (2..=4).contains(&round);
+            delays.push(batcher.round(&block_db, &mut backoff).await.as_secs());
+        }
+
+        // Blocks were produced through the outage
+        assert_eq!(block_db.read().await.latest_block_num(), Some(7));
+        assert_eq!(delays, vec![10, 10, 20, 40, 60, 10, 10]);
+        // Batch 2 picks up the blocks of the outage, the latest waits for the next one
+        let ranges: Vec<_> = l1
+            .lock()
+            .unwrap()
+            .batches
+            .iter()
+            .map(|batch| {
+                (
+                    batch.batch_index,
+                    batch.start_block_num,
+                    batch.end_block_num,
+                )
+            })
+            .collect();
+        assert_eq!(ranges, vec![(1, 1, 1), (2, 2, 5), (3, 6, 6)]);
+    }
+
+    #[test]
+    fn test_commit_confirmation_timeout() {
+        let db = MemoryStore::new();
+
+        // Batch 3 was sent but the confirmation timed out
+        let marker = CommitMarker {
+            batch_index: 3,
+            start_block_num: 21,
+            end_block_num: 30,
+            confirmed: false,
+        };
+        marker.save(&db).unwrap();
+
+        // Restart, the marker survives
+        let marker = CommitMarker::load(&db).unwrap();
+        assert_eq!(marker.as_ref().map(|m| m.batch_index), Some(3));
+
+        // It landed but L1 still shows batch 2 as the latest
+        assert_eq!(
+            plan_next_commit(marker.as_ref(), true, Some((2, 20))),
+            NextCommit::New {
+                batch_index: 4,
+                start_block_num: 31
+            }
+        );
+
+        // It didn't land, the same blocks are resubmitted
+        assert_eq!(
+            plan_next_commit(marker.as_ref(), false, Some((2, 20))),
+            NextCommit::Resubmit(marker.clone().unwrap())
+        );
+
+        // No marker, follow L1
+        assert_eq!(
+            plan_next_commit(None, false, Some((2, 20))),
+            NextCommit::New {
+                batch_index: 3,
+                start_block_num: 21
+            }
+        );
+        assert_eq!(
+            plan_next_commit(None, false, None),
+            NextCommit::New {
+                batch_index: 1,
+                start_block_num: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_max_batch_blocks() {
+        // Blocks 21 to 99 are ready, the latest one waits for the next batch
+        assert_eq!(batch_end_block_num(21, 100, 256), Some(99));
+        assert_eq!(batch_end_block_num(21, 100, 4), Some(24));
+        assert_eq!(batch_end_block_num(21, 100, 1), Some(21));
+        assert_eq!(batch_end_block_num(100, 100, 4), None);
+
+        // The packet size binds first when the blocks are large
+        let tx_size = |count: usize| Ok(500 + 300 * count);
+        let blocks_count = (21..=batch_end_block_num(21, 100, 4).unwrap()).count();
+        assert_eq!(fit_blocks_in_packet(blocks_count, tx_size).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_fit_blocks_in_packet() {
+        // 500 bytes of overhead and 100 bytes per block, 7 blocks fit
+        let tx_size = |count: usize| Ok(500 + 100 * count);
+        assert_eq!(fit_blocks_in_packet(5, tx_size).unwrap(), 5);
+        assert_eq!(fit_blocks_in_packet(256, tx_size).unwrap(), 7);
+
+        // A single block too large can't be split
+        let tx_size = |count: usize| Ok(PACKET_DATA_SIZE * count + 1);
+        assert!(fit_blocks_in_packet(3, tx_size).is_err());
+    }
+}
@@ -0,0 +1,56 @@
+//! Replay every block of the block db on the genesis state and check the roots each
+//! block stores, without running a proof. Exits non-zero on the first mismatch.
+//!
+//! cargo run --bin verify -- --config config.example.toml
+use share::state::{verify_blocks, State};
+use share::transaction::{set_l1_oracle, set_withdrawal_addresses, BlockDB};
+use share::utils::read_env_var;
+use share::UNSAFE_PRIVATE_KEY;
+use solana_sdk::signature::{Keypair, Signer};
+use tracing::{error, info};
+use tracing_subscriber::EnvFilter;
+
+// The sequencer's config, the block db path, genesis and withdrawal addresses
+#[allow(dead_code)]
+#[path = "../config.rs"]
+mod config;
+
+fn main() {
+    dotenv::dotenv().ok();
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        )
+        .init();
+
+    let args: Vec<String> = std::env::args().collect();
+    let config_path = args
+        .iter()
+        .position(|arg| arg == "--config")
+        .and_then(|index| args.get(index + 1));
+    let config = match config::Config::load(config_path.map(String::as_str)).and_then(|config| {
+        set_withdrawal_addresses(config.withdrawal_addresses.clone()).map(|()| config)
+    }) {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Invalid sequencer config: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // The sequencer's oracle, see `oracle_keypair`, only its deposits were credited
+    let oracle = Keypair::from_base58_string(&read_env_var(
+        "L1_ORACLE_PRIVATE_KEY",
+        UNSAFE_PRIVATE_KEY.to_owned(),
+    ));
+    set_l1_oracle(oracle.pubkey());
+
+    let block_db = BlockDB::new(&config.block_db_path);
+    match verify_blocks(&block_db, State::genesis(config.dev_account.clone())) {
+        Ok(verified) => info!("Verified {} blocks of {}", verified, config.block_db_path),
+        Err(e) => {
+            error!("Verification failed: {:#}", e);
+            std::process::exit(1);
+        }
+    }
+}
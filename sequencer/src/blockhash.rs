@@ -0,0 +1,114 @@
+use share::transaction::BlockDB;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Blocks a blockhash stays valid for, like Solana's 150.
+pub const MAX_RECENT_BLOCKHASHES: u64 = 150;
+
+/// Blockhashes of the latest blocks, their post state roots. `getLatestBlockhash`
+/// returns the newest one and a recent blockhash is valid while it is in the window.
+pub struct RecentBlockhashes {
+    // (block_num, blockhash), oldest first
+    hashes: VecDeque<(u64, [u8; 32])>,
+}
+
+impl RecentBlockhashes {
+    pub fn new() -> Self {
+        Self {
+            hashes: VecDeque::new(),
+        }
+    }
+
+    /// The window of the blocks up to `latest_block_num` in `block_db`, block 0 being
+    /// the genesis state root.
+    pub fn load(block_db: &BlockDB, latest_block_num: u64, genesis_state_root: [u8; 32]) -> Self {
+        let mut recent = Self::new();
+        for block_num in latest_block_num.saturating_sub(MAX_RECENT_BLOCKHASHES)..=latest_block_num
+        {
+            if block_num == 0 {
+                recent.push(0, genesis_state_root);
+            } else if let Some(block) = block_db.get_block(block_num) {
+                recent.push(block_num, block.post_state_root.unwrap_or_default());
+            }
+        }
+        recent
+    }
+
+    /// Record the blockhash of block `block_num`, dropping the ones it expires.
+    pub fn push(&mut self, block_num: u64, blockhash: [u8; 32]) {
+        self.hashes.push_back((block_num, blockhash));
+        while self
+            .hashes
+            .front()
+            .is_some_and(|(oldest, _)| oldest + MAX_RECENT_BLOCKHASHES < block_num)
+        {
+            self.hashes.pop_front();
+        }
+    }
+
+    /// The newest block and its blockhash.
+    pub fn latest(&self) -> Option<(u64, [u8; 32])> {
+        self.hashes.back().copied()
+    }
+
+    /// Last block a transaction using the latest blockhash can land in.
+    pub fn last_valid_block_height(&self) -> u64 {
+        self.latest()
+            .map(|(block_num, _)| block_num)
+            .unwrap_or_default()
+            + MAX_RECENT_BLOCKHASHES
+    }
+
+    pub fn is_valid(&self, blockhash: &[u8; 32]) -> bool {
+        self.hashes.iter().any(|(_, hash)| hash == blockhash)
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref RECENT_BLOCKHASHES: Arc<RwLock<RecentBlockhashes>> = Arc::new(RwLock::new(RecentBlockhashes::new()));
+}
+
+#[cfg(test)]
+mod test {
+    use super::{RecentBlockhashes, MAX_RECENT_BLOCKHASHES};
+    use share::transaction::{Block, BlockDB};
+
+    #[test]
+    fn test_recent_blockhashes() {
+        let mut recent = RecentBlockhashes::new();
+        assert_eq!(recent.latest(), None);
+        for block_num in 1..=200u64 {
+            recent.push(block_num, [block_num as u8; 32]);
+        }
+        assert_eq!(recent.latest(), Some((200, [200; 32])));
+        assert_eq!(
+            recent.last_valid_block_height(),
+            200 + MAX_RECENT_BLOCKHASHES
+        );
+
+        // Blocks 50..=200 are in the window
+        assert!(recent.is_valid(&[50; 32]));
+        assert!(!recent.is_valid(&[49; 32]));
+    }
+
+    #[test]
+    fn test_load_recent_blockhashes() {
+        let mut block_db = BlockDB::in_memory();
+        for block_num in 1..=3 {
+            let mut block = Block::new(vec![]);
+            block.block_num = block_num;
+            block.post_state_root = Some([block_num as u8; 32]);
+            block_db.save_block(&block).unwrap();
+        }
+
+        let recent = RecentBlockhashes::load(&block_db, 3, [9; 32]);
+        assert_eq!(recent.latest(), Some((3, [3; 32])));
+        // The genesis root is still valid
+        assert!(recent.is_valid(&[9; 32]));
+
+        // A fresh chain starts from the genesis root
+        let recent = RecentBlockhashes::load(&BlockDB::in_memory(), 0, [9; 32]);
+        assert_eq!(recent.latest(), Some((0, [9; 32])));
+    }
+}
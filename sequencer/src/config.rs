@@ -0,0 +1,683 @@
+use anyhow::{anyhow, Context, Result};
+use axum::http::{HeaderName, HeaderValue, Method};
+use l2_state_client::state_call::L2StateClient;
+use serde::{Deserialize, Serialize};
+use share::{
+    DEFAULT_BLOCK_CACHE_SIZE, DEFAULT_BLOCK_DB_PATH, DEFAULT_L1_RPC, DEFAULT_L1_WS, DEFAULT_L2_RPC,
+    DEFAULT_STATE_DB_PATH, WITHDRAWAL_ADDRESS,
+};
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{read_keypair_file, Keypair},
+};
+use std::{net::SocketAddr, str::FromStr, sync::OnceLock, time::Duration};
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// All tunables of the sequencer.
+///
+/// Loaded from the file given by `--config` (TOML, or JSON for a `.json` path), then
+/// each field can be overridden by its env var. Missing fields keep their default.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Bind address of the rpc server, `SEQUENCER_ADDR`
+    pub rpc_addr: String,
+    /// Websocket address advertised by `getClusterNodes`, `SEQUENCER_WS_ADDR`
+    pub ws_addr: Option<String>,
+    /// `STATE_DB_PATH`
+    pub state_db_path: String,
+    /// `BLOCK_DB_PATH`
+    pub block_db_path: String,
+    /// Latest blocks kept in memory for the rpc reads, `BLOCK_CACHE_SIZE`
+    pub block_cache_size: usize,
+    /// Wait before a block with a nearly full mempool, `BLOCK_INTERVAL_MS`
+    pub block_interval_ms: u64,
+    /// Wait before a block with an almost empty mempool, an empty block is built
+    /// after it, `MAX_BLOCK_INTERVAL_MS`
+    pub max_block_interval_ms: u64,
+    /// A block is built right away once this many transactions are pending,
+    /// `TARGET_BLOCK_SIZE`
+    pub target_block_size: usize,
+    /// Time between two batch submissions, `BATCH_INTERVAL_SECS`
+    pub batch_interval_secs: u64,
+    /// Most blocks committed in one batch, fewer when they don't fit in a commit
+    /// transaction, `MAX_BATCH_BLOCKS`
+    pub max_batch_blocks: u64,
+    /// `MAX_MEMPOOL_SIZE`
+    pub max_mempool_size: usize,
+    /// Transfers of fewer lamports are rejected, 0 accepts any, `MIN_TRANSFER_LAMPORTS`
+    pub min_transfer_lamports: u64,
+    /// Transactions costing more compute units are rejected, `MAX_TXN_COMPUTE_UNITS`
+    pub max_txn_compute_units: u64,
+    /// A block takes transactions up to this many compute units, the rest wait in the
+    /// mempool, `MAX_BLOCK_COMPUTE_UNITS`
+    pub max_block_compute_units: u64,
+    /// Fee of a transaction per required signature, `LAMPORTS_PER_SIGNATURE`
+    pub lamports_per_signature: u64,
+    /// `getHealth` reports the batcher stalled after this long without a successful
+    /// submission round, `BATCHER_STALL_SECS`
+    pub batcher_stall_secs: u64,
+    /// `getHealth` reports the prover stalled when committed batches wait this long
+    /// without the finalized batch advancing on L1, `PROVER_STALL_SECS`
+    pub prover_stall_secs: u64,
+    /// Rpc calls taking longer are logged as a warning, `SLOW_RPC_MS`
+    pub slow_rpc_ms: u64,
+    /// Endpoints the deposit and forced transaction events are read from, `L1_RPC` and `L1_WS`
+    pub l1_rpc: String,
+    pub l1_ws: String,
+    /// Endpoint deposits are relayed to, `L2_RPC`
+    pub l2_rpc: String,
+    /// Solana cluster of the l2-state program the batcher commits to, `SOLANA_RPC`
+    pub solana_rpc: String,
+    /// Keypair file of the sequencer committing batches, `AUTHORITY_KEYPAIR_PATH`.
+    /// The local development key when unset.
+    pub authority_keypair_path: Option<String>,
+    /// Account funded in the genesis state, `DEV_ACCOUNT`
+    pub dev_account: Option<String>,
+    /// Addresses a transfer to which withdraws to L1, the prover and the program config
+    /// (`withdrawal_addresses_hash`) must use the same ones. `WITHDRAWAL_ADDRESSES`, comma
+    /// separated
+    pub withdrawal_addresses: Vec<String>,
+    /// `calldata` or `local`, `DA_BACKEND`. `local` requires `dev_mode`, its batches
+    /// can't be proven
+    pub da_backend: String,
+    /// Blob dir of the `local` DA backend, `DA_LOCAL_DIR`
+    pub da_local_dir: String,
+    /// Serve the dev-only `getStateSnapshot`, `ENABLE_DEBUG_RPC`
+    pub enable_debug_rpc: bool,
+    /// Token of the admin rpc methods (`forceBatchSubmit`), at least 16 characters.
+    /// They're disabled when unset, `ADMIN_TOKEN`
+    pub admin_token: Option<String>,
+    /// Allow cross-origin rpc calls from anywhere, for local development only,
+    /// `DEV_MODE`. The `cors_*` lists apply otherwise.
+    pub dev_mode: bool,
+    /// Browser origins allowed to call the rpc, none by default.
+    /// `CORS_ALLOWED_ORIGINS`, comma separated
+    pub cors_allowed_origins: Vec<String>,
+    /// `CORS_ALLOWED_METHODS`, comma separated
+    pub cors_allowed_methods: Vec<String>,
+    /// `CORS_ALLOWED_HEADERS`, comma separated
+    pub cors_allowed_headers: Vec<String>,
+    /// Keypair file of the funded L2 account paying `requestAirdrop`, the faucet is
+    /// disabled when unset, `FAUCET_KEYPAIR_PATH`
+    pub faucet_keypair_path: Option<String>,
+    /// Airdrops handed out by the faucet, per pubkey and in total, `FAUCET_DB_PATH`
+    pub faucet_db_path: String,
+    /// Largest airdrop of a request, `AIRDROP_MAX_LAMPORTS`
+    pub airdrop_max_lamports: u64,
+    /// Airdropped to a pubkey per UTC day, `AIRDROP_PUBKEY_DAILY_LAMPORTS`
+    pub airdrop_pubkey_daily_lamports: u64,
+    /// Airdropped by the faucet per UTC day, `AIRDROP_DAILY_LAMPORTS`
+    pub airdrop_daily_lamports: u64,
+    /// Wait between two airdrops to a pubkey, `AIRDROP_COOLDOWN_SECS`
+    pub airdrop_cooldown_secs: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            rpc_addr: "0.0.0.0:8898".to_string(),
+            ws_addr: None,
+            state_db_path: DEFAULT_STATE_DB_PATH.to_string(),
+            block_db_path: DEFAULT_BLOCK_DB_PATH.to_string(),
+            block_cache_size: DEFAULT_BLOCK_CACHE_SIZE,
+            block_interval_ms: 200,
+            max_block_interval_ms: 2000,
+            target_block_size: 256,
+            batch_interval_secs: 60,
+            max_batch_blocks: 256,
+            max_mempool_size: 1024,
+            min_transfer_lamports: 1,
+            max_txn_compute_units: 200_000,
+            max_block_compute_units: 1_500_000,
+            lamports_per_signature: 5000,
+            batcher_stall_secs: 600,
+            prover_stall_secs: 3600,
+            slow_rpc_ms: 1000,
+            l1_rpc: DEFAULT_L1_RPC.to_string(),
+            l1_ws: DEFAULT_L1_WS.to_string(),
+            l2_rpc: DEFAULT_L2_RPC.to_string(),
+            solana_rpc: "http://localhost:8899".to_string(),
+            authority_keypair_path: None,
+            dev_account: None,
+            withdrawal_addresses: vec![WITHDRAWAL_ADDRESS.to_string()],
+            da_backend: "calldata".to_string(),
+            da_local_dir: "da_blobs".to_string(),
+            enable_debug_rpc: false,
+            admin_token: None,
+            dev_mode: false,
+            cors_allowed_origins: vec![],
+            cors_allowed_methods: vec!["GET".to_string(), "POST".to_string()],
+            cors_allowed_headers: vec!["content-type".to_string()],
+            faucet_keypair_path: None,
+            faucet_db_path: "faucet_db".to_string(),
+            airdrop_max_lamports: 2_000_000_000,
+            airdrop_pubkey_daily_lamports: 10_000_000_000,
+            airdrop_daily_lamports: 1_000_000_000_000,
+            airdrop_cooldown_secs: 60,
+        }
+    }
+}
+
+impl Config {
+    /// Load the config file at `path` (the defaults without one), apply the env
+    /// overrides and validate the result.
+    pub fn load(path: Option<&str>) -> Result<Self> {
+        let mut config = match path {
+            Some(path) => Self::parse(
+                &std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read config file {}", path))?,
+                path.ends_with(".json"),
+            )
+            .with_context(|| format!("Failed to parse config file {}", path))?,
+            None => Self::default(),
+        };
+        config.apply_overrides(|var| std::env::var(var).ok())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn parse(content: &str, json: bool) -> Result<Self> {
+        if json {
+            Ok(serde_json::from_str(content)?)
+        } else {
+            Ok(toml::from_str(content)?)
+        }
+    }
+
+    // Override fields by the env vars `var` returns
+    fn apply_overrides(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<()> {
+        fn set<T: FromStr>(
+            field: &mut T,
+            name: &str,
+            var: &impl Fn(&str) -> Option<String>,
+        ) -> Result<()> {
+            if let Some(value) = var(name) {
+                *field = value
+                    .parse()
+                    .map_err(|_| anyhow!("Invalid value of {}: {:?}", name, value))?;
+            }
+            Ok(())
+        }
+        fn set_option(
+            field: &mut Option<String>,
+            name: &str,
+            var: &impl Fn(&str) -> Option<String>,
+        ) {
+            if let Some(value) = var(name) {
+                *field = Some(value).filter(|value| !value.is_empty());
+            }
+        }
+        fn set_list(field: &mut Vec<String>, name: &str, var: &impl Fn(&str) -> Option<String>) {
+            if let Some(value) = var(name) {
+                *field = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|value| !value.is_empty())
+                    .map(String::from)
+                    .collect();
+            }
+        }
+
+        set(&mut self.rpc_addr, "SEQUENCER_ADDR", &var)?;
+        set_option(&mut self.ws_addr, "SEQUENCER_WS_ADDR", &var);
+        set(&mut self.state_db_path, "STATE_DB_PATH", &var)?;
+        set(&mut self.block_db_path, "BLOCK_DB_PATH", &var)?;
+        set(&mut self.block_cache_size, "BLOCK_CACHE_SIZE", &var)?;
+        set(&mut self.block_interval_ms, "BLOCK_INTERVAL_MS", &var)?;
+        set(
+            &mut self.max_block_interval_ms,
+            "MAX_BLOCK_INTERVAL_MS",
+            &var,
+        )?;
+        set(&mut self.target_block_size, "TARGET_BLOCK_SIZE", &var)?;
+        set(&mut self.batch_interval_secs, "BATCH_INTERVAL_SECS", &var)?;
+        set(&mut self.max_batch_blocks, "MAX_BATCH_BLOCKS", &var)?;
+        set(&mut self.max_mempool_size, "MAX_MEMPOOL_SIZE", &var)?;
+        set(
+            &mut self.min_transfer_lamports,
+            "MIN_TRANSFER_LAMPORTS",
+            &var,
+        )?;
+        set(
+            &mut self.max_txn_compute_units,
+            "MAX_TXN_COMPUTE_UNITS",
+            &var,
+        )?;
+        set(
+            &mut self.max_block_compute_units,
+            "MAX_BLOCK_COMPUTE_UNITS",
+            &var,
+        )?;
+        set(
+            &mut self.lamports_per_signature,
+            "LAMPORTS_PER_SIGNATURE",
+            &var,
+        )?;
+        set(&mut self.batcher_stall_secs, "BATCHER_STALL_SECS", &var)?;
+        set(&mut self.prover_stall_secs, "PROVER_STALL_SECS", &var)?;
+        set(&mut self.slow_rpc_ms, "SLOW_RPC_MS", &var)?;
+        set(&mut self.l1_rpc, "L1_RPC", &var)?;
+        set(&mut self.l1_ws, "L1_WS", &var)?;
+        set(&mut self.l2_rpc, "L2_RPC", &var)?;
+        set(&mut self.solana_rpc, "SOLANA_RPC", &var)?;
+        set_option(
+            &mut self.authority_keypair_path,
+            "AUTHORITY_KEYPAIR_PATH",
+            &var,
+        );
+        set_option(&mut self.dev_account, "DEV_ACCOUNT", &var);
+        set_list(&mut self.withdrawal_addresses, "WITHDRAWAL_ADDRESSES", &var);
+        set(&mut self.da_backend, "DA_BACKEND", &var)?;
+        set(&mut self.da_local_dir, "DA_LOCAL_DIR", &var)?;
+        set(&mut self.enable_debug_rpc, "ENABLE_DEBUG_RPC", &var)?;
+        set_option(&mut self.admin_token, "ADMIN_TOKEN", &var);
+        set(&mut self.dev_mode, "DEV_MODE", &var)?;
+        set_list(&mut self.cors_allowed_origins, "CORS_ALLOWED_ORIGINS", &var);
+        set_list(&mut self.cors_allowed_methods, "CORS_ALLOWED_METHODS", &var);
+        set_list(&mut self.cors_allowed_headers, "CORS_ALLOWED_HEADERS", &var);
+        set_option(&mut self.faucet_keypair_path, "FAUCET_KEYPAIR_PATH", &var);
+        set(&mut self.faucet_db_path, "FAUCET_DB_PATH", &var)?;
+        set(&mut self.airdrop_max_lamports, "AIRDROP_MAX_LAMPORTS", &var)?;
+        set(
+            &mut self.airdrop_pubkey_daily_lamports,
+            "AIRDROP_PUBKEY_DAILY_LAMPORTS",
+            &var,
+        )?;
+        set(
+            &mut self.airdrop_daily_lamports,
+            "AIRDROP_DAILY_LAMPORTS",
+            &var,
+        )?;
+        set(
+            &mut self.airdrop_cooldown_secs,
+            "AIRDROP_COOLDOWN_SECS",
+            &var,
+        )?;
+        Ok(())
+    }
+
+    /// Check every field, the first invalid one is reported.
+    pub fn validate(&self) -> Result<()> {
+        SocketAddr::from_str(&self.rpc_addr)
+            .map_err(|_| anyhow!("rpc_addr is not a socket address: {}", self.rpc_addr))?;
+        if let Some(ws_addr) = &self.ws_addr {
+            SocketAddr::from_str(ws_addr)
+                .map_err(|_| anyhow!("ws_addr is not a socket address: {}", ws_addr))?;
+        }
+        if self.state_db_path.is_empty() || self.block_db_path.is_empty() {
+            return Err(anyhow!("state_db_path and block_db_path must be set"));
+        }
+        if self.state_db_path == self.block_db_path {
+            return Err(anyhow!("state_db_path and block_db_path must differ"));
+        }
+        if self.block_cache_size == 0 {
+            return Err(anyhow!("block_cache_size must be positive"));
+        }
+        if self.block_interval_ms == 0 {
+            return Err(anyhow!("block_interval_ms must be positive"));
+        }
+        if self.max_block_interval_ms < self.block_interval_ms {
+            return Err(anyhow!(
+                "max_block_interval_ms must be at least block_interval_ms"
+            ));
+        }
+        if self.target_block_size == 0 {
+            return Err(anyhow!("target_block_size must be positive"));
+        }
+        if self.batch_interval_secs == 0 {
+            return Err(anyhow!("batch_interval_secs must be positive"));
+        }
+        if self.max_batch_blocks == 0 {
+            return Err(anyhow!("max_batch_blocks must be positive"));
+        }
+        if self.max_mempool_size == 0 {
+            return Err(anyhow!("max_mempool_size must be positive"));
+        }
+        if self.max_txn_compute_units == 0 {
+            return Err(anyhow!("max_txn_compute_units must be positive"));
+        }
+        // A transaction within its limit always fits in a block
+        if self.max_block_compute_units < self.max_txn_compute_units {
+            return Err(anyhow!(
+                "max_block_compute_units must be at least max_txn_compute_units"
+            ));
+        }
+        if self.batcher_stall_secs < self.batch_interval_secs {
+            return Err(anyhow!(
+                "batcher_stall_secs must be at least batch_interval_secs"
+            ));
+        }
+        if self.prover_stall_secs == 0 {
+            return Err(anyhow!("prover_stall_secs must be positive"));
+        }
+        if self.slow_rpc_ms == 0 {
+            return Err(anyhow!("slow_rpc_ms must be positive"));
+        }
+        for (name, url) in [
+            ("l1_rpc", &self.l1_rpc),
+            ("l2_rpc", &self.l2_rpc),
+            ("solana_rpc", &self.solana_rpc),
+        ] {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                return Err(anyhow!("{} is not an http url: {}", name, url));
+            }
+        }
+        if !self.l1_ws.starts_with("ws://") && !self.l1_ws.starts_with("wss://") {
+            return Err(anyhow!("l1_ws is not a websocket url: {}", self.l1_ws));
+        }
+        if let Some(path) = &self.authority_keypair_path {
+            read_keypair_file(path)
+                .map_err(|e| anyhow!("Failed to read authority keypair {}: {}", path, e))?;
+        }
+        if let Some(dev_account) = &self.dev_account {
+            Pubkey::from_str(dev_account)
+                .map_err(|_| anyhow!("dev_account is not a pubkey: {}", dev_account))?;
+        }
+        if self.withdrawal_addresses.is_empty() {
+            return Err(anyhow!("withdrawal_addresses must not be empty"));
+        }
+        for address in &self.withdrawal_addresses {
+            Pubkey::from_str(address)
+                .map_err(|_| anyhow!("withdrawal address is not a pubkey: {}", address))?;
+        }
+        if !["calldata", "local"].contains(&self.da_backend.as_str()) {
+            return Err(anyhow!("Unknown da_backend: {}", self.da_backend));
+        }
+        // The prover can't fetch blobs from the sequencer's disk, only the hash of the
+        // blocks is committed and their batches can't be proven
+        if self.da_backend == "local" && !self.dev_mode {
+            return Err(anyhow!("da_backend local is for development, set dev_mode"));
+        }
+        if self
+            .admin_token
+            .as_ref()
+            .is_some_and(|token| token.len() < 16)
+        {
+            return Err(anyhow!("admin_token must be at least 16 characters"));
+        }
+        self.cors_origins()?;
+        self.cors_methods()?;
+        self.cors_headers()?;
+        if let Some(path) = &self.faucet_keypair_path {
+            read_keypair_file(path)
+                .map_err(|e| anyhow!("Failed to read faucet keypair {}: {}", path, e))?;
+        }
+        if self.faucet_db_path.is_empty() {
+            return Err(anyhow!("faucet_db_path must be set"));
+        }
+        if [&self.state_db_path, &self.block_db_path].contains(&&self.faucet_db_path) {
+            return Err(anyhow!(
+                "faucet_db_path must differ from state_db_path and block_db_path"
+            ));
+        }
+        if self.airdrop_max_lamports == 0
+            || self.airdrop_pubkey_daily_lamports < self.airdrop_max_lamports
+            || self.airdrop_daily_lamports < self.airdrop_pubkey_daily_lamports
+        {
+            return Err(anyhow!(
+                "airdrop caps must be positive and airdrop_max_lamports <= \
+                 airdrop_pubkey_daily_lamports <= airdrop_daily_lamports"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Time to wait since the last block with `pending` transactions: the max interval
+    /// while idle, shrinking linearly to `block_interval_ms` as the mempool fills up
+    /// to `target_block_size`, then none. Bursts make full blocks right away and a
+    /// trickle is gathered into fewer, larger ones.
+    pub fn block_interval(&self, pending: usize) -> Duration {
+        if pending >= self.target_block_size {
+            return Duration::ZERO;
+        }
+        let spread = self.max_block_interval_ms - self.block_interval_ms;
+        let missing = (self.target_block_size - pending) as u64;
+        Duration::from_millis(
+            self.block_interval_ms + spread * missing / self.target_block_size as u64,
+        )
+    }
+
+    pub fn batch_interval(&self) -> Duration {
+        Duration::from_secs(self.batch_interval_secs)
+    }
+
+    pub fn batcher_stall(&self) -> Duration {
+        Duration::from_secs(self.batcher_stall_secs)
+    }
+
+    pub fn prover_stall(&self) -> Duration {
+        Duration::from_secs(self.prover_stall_secs)
+    }
+
+    pub fn slow_rpc(&self) -> Duration {
+        Duration::from_millis(self.slow_rpc_ms)
+    }
+
+    /// `cors_allowed_origins` as header values, each an http(s) origin.
+    pub fn cors_origins(&self) -> Result<Vec<HeaderValue>> {
+        self.cors_allowed_origins
+            .iter()
+            .map(|origin| {
+                if !origin.starts_with("http://") && !origin.starts_with("https://") {
+                    return Err(anyhow!("CORS origin is not an http origin: {}", origin));
+                }
+                HeaderValue::from_str(origin)
+                    .map_err(|_| anyhow!("Invalid CORS origin: {}", origin))
+            })
+            .collect()
+    }
+
+    pub fn cors_methods(&self) -> Result<Vec<Method>> {
+        self.cors_allowed_methods
+            .iter()
+            .map(|method| {
+                Method::from_str(method).map_err(|_| anyhow!("Invalid CORS method: {}", method))
+            })
+            .collect()
+    }
+
+    pub fn cors_headers(&self) -> Result<Vec<HeaderName>> {
+        self.cors_allowed_headers
+            .iter()
+            .map(|header| {
+                HeaderName::from_str(header).map_err(|_| anyhow!("Invalid CORS header: {}", header))
+            })
+            .collect()
+    }
+
+    /// Keypair of the faucet, `None` when it is disabled.
+    pub fn faucet_keypair(&self) -> Result<Option<Keypair>> {
+        self.faucet_keypair_path
+            .as_ref()
+            .map(|path| {
+                read_keypair_file(path)
+                    .map_err(|e| anyhow!("Failed to read faucet keypair {}: {}", path, e))
+            })
+            .transpose()
+    }
+
+    /// Client of the l2-state program, signing with the authority keypair.
+    pub fn l2_state_client(&self) -> Result<L2StateClient> {
+        match &self.authority_keypair_path {
+            Some(path) => {
+                let keypair: Keypair = read_keypair_file(path)
+                    .map_err(|e| anyhow!("Failed to read authority keypair {}: {}", path, e))?;
+                L2StateClient::new(self.solana_rpc.clone(), &keypair.to_bytes())
+            }
+            None => L2StateClient::new_local(),
+        }
+    }
+}
+
+/// Set the config of this process, before anything reads it.
+pub fn init(config: Config) -> Result<()> {
+    CONFIG
+        .set(config)
+        .map_err(|_| anyhow!("Config already initialized"))
+}
+
+/// The config of this process, from the env alone if `init` wasn't called.
+pub fn config() -> &'static Config {
+    CONFIG.get_or_init(|| Config::load(None).expect("Invalid sequencer config"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::Config;
+    use share::{DEFAULT_BLOCK_DB_PATH, WITHDRAWAL_ADDRESS};
+    use solana_sdk::pubkey::Pubkey;
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    #[test]
+    fn test_config_file_and_overrides() {
+        let mut config = Config::parse(
+            r#"
+            rpc_addr = "127.0.0.1:9000"
+            block_interval_ms = 500
+            da_backend = "local"
+            dev_mode = true
+            "#,
+            false,
+        )
+        .unwrap();
+        assert_eq!(config.rpc_addr, "127.0.0.1:9000");
+        assert_eq!(
+            config.batch_interval_secs,
+            Config::default().batch_interval_secs
+        );
+
+        // Env vars win over the file
+        let withdrawal_address = Pubkey::new_unique().to_string();
+        let withdrawal_addresses = format!("{}, {}", WITHDRAWAL_ADDRESS, withdrawal_address);
+        let env = HashMap::from([
+            ("BLOCK_INTERVAL_MS", "100"),
+            ("DEV_ACCOUNT", ""),
+            ("WITHDRAWAL_ADDRESSES", withdrawal_addresses.as_str()),
+        ]);
+        config
+            .apply_overrides(|var| env.get(var).map(|value| value.to_string()))
+            .unwrap();
+        assert_eq!(config.block_interval_ms, 100);
+        assert_eq!(config.dev_account, None);
+        assert_eq!(
+            config.withdrawal_addresses,
+            vec![WITHDRAWAL_ADDRESS.to_string(), withdrawal_address]
+        );
+        config.validate().unwrap();
+
+        // JSON works as well, unknown fields are rejected
+        let config = Config::parse(r#"{"max_mempool_size": 16}"#, true).unwrap();
+        assert_eq!(config.max_mempool_size, 16);
+        assert!(Config::parse("mempool_size = 16", false).is_err());
+
+        // The documented example stays loadable
+        let example = Config::parse(include_str!("../config.example.toml"), false).unwrap();
+        assert_eq!(example, Config::default());
+    }
+
+    #[test]
+    fn test_config_validation() {
+        let invalid = [
+            Config {
+                rpc_addr: "localhost".to_string(),
+                ..Config::default()
+            },
+            Config {
+                block_cache_size: 0,
+                ..Config::default()
+            },
+            Config {
+                block_interval_ms: 0,
+                ..Config::default()
+            },
+            Config {
+                max_block_interval_ms: 100,
+                ..Config::default()
+            },
+            Config {
+                max_block_compute_units: 1_000,
+                ..Config::default()
+            },
+            Config {
+                max_batch_blocks: 0,
+                ..Config::default()
+            },
+            Config {
+                slow_rpc_ms: 0,
+                ..Config::default()
+            },
+            Config {
+                admin_token: Some("admin".to_string()),
+                ..Config::default()
+            },
+            Config {
+                cors_allowed_origins: vec!["*".to_string()],
+                ..Config::default()
+            },
+            Config {
+                cors_allowed_headers: vec!["content type".to_string()],
+                ..Config::default()
+            },
+            Config {
+                faucet_db_path: DEFAULT_BLOCK_DB_PATH.to_string(),
+                ..Config::default()
+            },
+            Config {
+                airdrop_pubkey_daily_lamports: 1_000_000_000,
+                ..Config::default()
+            },
+            Config {
+                l1_ws: "http://127.0.0.1:8900".to_string(),
+                ..Config::default()
+            },
+            Config {
+                da_backend: "celestia".to_string(),
+                ..Config::default()
+            },
+            Config {
+                da_backend: "local".to_string(),
+                ..Config::default()
+            },
+            Config {
+                authority_keypair_path: Some("/nonexistent/id.json".to_string()),
+                ..Config::default()
+            },
+            Config {
+                withdrawal_addresses: vec![],
+                ..Config::default()
+            },
+            Config {
+                withdrawal_addresses: vec!["AF111111111111111111111111111111".to_string()],
+                ..Config::default()
+            },
+        ];
+        for config in invalid {
+            assert!(config.validate().is_err(), "{:?}", config);
+        }
+
+        let mut config = Config::default();
+        let error = config
+            .apply_overrides(|var| (var == "MAX_MEMPOOL_SIZE").then(|| "many".to_string()))
+            .unwrap_err();
+        assert!(error.to_string().contains("MAX_MEMPOOL_SIZE"));
+    }
+
+    #[test]
+    fn test_adaptive_block_interval() {
+        let config = Config {
+            block_interval_ms: 200,
+            max_block_interval_ms: 2000,
+            target_block_size: 100,
+            ..Config::default()
+        };
+        assert_eq!(config.block_interval(0), Duration::from_millis(2000));
+        assert_eq!(config.block_interval(50), Duration::from_millis(1100));
+        assert_eq!(config.block_interval(99), Duration::from_millis(218));
+        // Full mempool, build immediately
+        assert_eq!(config.block_interval(100), Duration::ZERO);
+        assert_eq!(config.block_interval(5000), Duration::ZERO);
+    }
+}
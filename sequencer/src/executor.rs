@@ -1,15 +1,22 @@
-use anyhow::{anyhow, Result};
-use log::info;
+use anyhow::Result;
 use share::{
-    state::{StateDB, Withdrawal},
-    transaction::{parsing_instruction, Block, TransferOp},
-    WITHDRAWAL_ADDRESS,
+    lamports::Lamports,
+    state::{AppliedTxns, State, StateDB},
+    transaction::{compute_units, pre_process, priority_order, Block, BlockDB, PendingBlock},
 };
 use solana_sdk::transaction::Transaction;
-use std::{collections::HashMap, sync::Arc};
-use tokio::sync::RwLock;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+use tokio::sync::{broadcast, RwLock};
+use tracing::{debug, error, info, info_span, instrument, warn};
 
-pub static MAX_MEMPOOL_SIZE: usize = 1024;
+use crate::config::config;
+use crate::node::BLOCK_DB;
+
+// Balance changes buffered per subscriber, a slower one lags and skips the oldest
+static BALANCE_CHANGES_CAPACITY: usize = 4096;
 
 pub struct Executor {}
 
@@ -19,97 +26,658 @@ impl Executor {
     }
 
     pub async fn pending_size(&self) -> usize {
-        MEMPOOL.read().await.len()
+        MEMPOOL.read().await.len() + FORCED_TXNS.read().await.len()
     }
 
-    pub async fn execute(&self) -> Result<Block> {
+    /// Execute the pending transactions as block `block_num`, then publish the balance
+    /// changes on `BALANCE_CHANGES`.
+    ///
+    /// The drained transactions are logged in the block db before the state changes, so
+    /// a crash before the block is saved can't lose them, see `recover_pending_block`.
+    #[instrument(skip(self))]
+    pub async fn execute(&self, block_num: u64) -> Result<Block> {
+        let mut forced_txns = FORCED_TXNS.write().await;
         let mut pending_txns = MEMPOOL.write().await;
-        let mut transfers = Vec::new();
-
         let mut state_db = STATE.write().await;
-        let mut start_index = state_db.state.withdrawal_queue.len() as u64;
-
-        let mut withdrawals = Vec::new();
-        for txn in pending_txns.iter() {
-            match pre_process(txn) {
-                Ok(Some(op)) => {
-                    transfers.push(op.clone());
-                    if op.to == WITHDRAWAL_ADDRESS {
-                        let withdrawal = Withdrawal {
-                            from: op.from.clone(),
-                            to: op.from, // withdrawal to sender
-                            amount: op.amount as u64,
-                            index: start_index,
-                        };
-                        withdrawals.push(withdrawal);
-                        start_index += 1;
-                    }
-                }
-                _ => {}
-            }
-        }
-        state_db
-            .state
-            .withdrawal_queue
-            .extend_from_slice(&withdrawals);
-
-        let balances = &mut state_db.state.balances;
-        if !transfers.is_empty() {
-            let _ = transfer(balances, transfers);
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        let queued_forced: Vec<ForcedTransaction> = forced_txns.iter().cloned().collect();
+        let txns = take_block_txns(
+            &mut forced_txns,
+            &mut pending_txns,
+            now,
+            config().max_block_compute_units,
+        );
+        let forced = queued_forced[..queued_forced.len() - forced_txns.len()].to_vec();
+        let pending = PendingBlock {
+            block_num,
+            txns,
+            forced: forced
+                .iter()
+                .map(|forced| (forced.index, forced.deadline))
+                .collect(),
         };
+        if let Err(e) = BLOCK_DB.read().await.save_pending_block(&pending) {
+            // Nothing was applied, the transactions wait for the next block
+            requeue_block_txns(&mut pending_txns, &mut forced_txns, pending.txns, forced);
+            return Err(e);
+        }
+        let txns = pending.txns;
+        for txn in txns.iter() {
+            let signature = txn.signatures.first().cloned().unwrap_or_default();
+            let _span = info_span!("txn", signature = %signature).entered();
+            info!("Transaction included in block");
+        }
+        let snapshot = state_db.state.block_snapshot(&txns);
+        let (balance_changes, applied) =
+            apply_txns_with_changes(&mut state_db.state, &txns, block_num);
+        // Refused like a block that failed to save: the state goes back to before the
+        // block and the transactions back into the mempool
+        if let Err(e) = check_supply(&balance_changes, applied.minted, block_num) {
+            error!("{}, refusing the block", e);
+            state_db.state.restore(snapshot);
+            requeue_block_txns(&mut pending_txns, &mut forced_txns, txns, forced);
+            return Err(e.into());
+        }
+        let txns = seal_applied_txns(
+            &mut pending_txns,
+            &mut forced_txns,
+            txns,
+            forced,
+            applied.count,
+        );
+
+        let mut block = Block::new(txns);
+        block.block_num = block_num;
+        info!(txns = block.txns.len(), "Executed pending transactions");
 
-        let block = Block::new(pending_txns.to_vec());
-        pending_txns.drain(..);
+        // Never blocks, sending fails only without subscribers
+        for change in balance_changes {
+            debug!(
+                address = %change.address,
+                old = %change.old,
+                new = %change.new,
+                "Balance changed"
+            );
+            let _ = BALANCE_CHANGES.send(change);
+        }
         Ok(block)
     }
 }
-pub fn pre_process(txn: &Transaction) -> Result<Option<TransferOp>> {
-    let signature = txn.signatures[0].to_string();
-    // Parsing each instruction in the transaction
-    for (_i, instruction) in txn.message.instructions.iter().enumerate() {
-        let op = parsing_instruction(instruction, &txn)?;
-        if op.is_some() {
-            return Ok(op);
+
+/// Balance of an account changed by block `block_num`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BalanceChange {
+    pub address: String,
+    pub old: Lamports,
+    pub new: Lamports,
+    pub block_num: u64,
+}
+
+/// A block whose balance changes don't add up to the lamports its deposits minted, the
+/// executor created or lost lamports.
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error(
+    "Block {block_num} changes the total balance by {delta} lamports, its deposits minted {minted}"
+)]
+pub struct SupplyMismatch {
+    pub block_num: u64,
+    pub delta: i128,
+    pub minted: u128,
+}
+
+// Check the balance changes of block `block_num` conserve lamports: transfers only move
+// them and deposits mint them. A withdrawal is a transfer, the withdrawal address keeps
+// the lamports until they're released on L1.
+fn check_supply(
+    changes: &[BalanceChange],
+    minted: u128,
+    block_num: u64,
+) -> Result<(), SupplyMismatch> {
+    let delta: i128 = changes
+        .iter()
+        .map(|change| i128::from(change.new.0) - i128::from(change.old.0))
+        .sum();
+    if delta != minted as i128 {
+        return Err(SupplyMismatch {
+            block_num,
+            delta,
+            minted,
+        });
+    }
+    Ok(())
+}
+
+// Apply `txns` to `state`, returning the balances they changed and what was applied.
+fn apply_txns_with_changes(
+    state: &mut State,
+    txns: &[Transaction],
+    block_num: u64,
+) -> (Vec<BalanceChange>, AppliedTxns) {
+    let mut addresses: Vec<String> = txns
+        .iter()
+        .filter_map(|txn| pre_process(txn).ok())
+        .flatten()
+        .flat_map(|op| op.accounts().into_iter().cloned().collect::<Vec<_>>())
+        .collect();
+    addresses.sort();
+    addresses.dedup();
+    let old_balances: Vec<Lamports> = addresses
+        .iter()
+        .map(|address| state.get_balance(address))
+        .collect();
+
+    let applied = state.apply_txns(txns, block_num);
+
+    let changes = addresses
+        .into_iter()
+        .zip(old_balances)
+        .filter_map(|(address, old)| {
+            let new = state.get_balance(&address);
+            (new != old).then_some(BalanceChange {
+                address,
+                old,
+                new,
+                block_num,
+            })
+        })
+        .collect();
+    (changes, applied)
+}
+
+// The transactions of the block, the `applied` leading ones of `txns`. The one that
+// failed is dropped and the ones after it, never executed, are queued again, so a
+// sealed block holds only transactions that apply and the prover replaying it never
+// meets a failure.
+//
+// `forced` are the forced transactions leading `txns`, the ones never executed go
+// back ahead of the forced queue. A forced one that failed stays in the block as its
+// last transaction instead: the zkVM program holds the batch to executing it, and
+// executes it failing and stopping the block like the sequencer did.
+fn seal_applied_txns(
+    pending_txns: &mut Vec<Transaction>,
+    forced_txns: &mut VecDeque<ForcedTransaction>,
+    mut txns: Vec<Transaction>,
+    forced: Vec<ForcedTransaction>,
+    applied: usize,
+) -> Vec<Transaction> {
+    let mut rest = txns.split_off(applied.min(txns.len()));
+    if rest.is_empty() {
+        return txns;
+    }
+    let failed = rest.remove(0);
+    if applied < forced.len() {
+        warn!(
+            signature = %failed.signatures.first().cloned().unwrap_or_default(),
+            "Forced transaction failed, it ends the block"
+        );
+        txns.push(failed);
+        requeue_block_txns(
+            pending_txns,
+            forced_txns,
+            rest,
+            forced[applied + 1..].to_vec(),
+        );
+    } else {
+        warn!(
+            signature = %failed.signatures.first().cloned().unwrap_or_default(),
+            "Transaction failed, dropped from the block"
+        );
+        requeue_txns(pending_txns, rest);
+    }
+    txns
+}
+
+/// A transaction forced through L1, it's included ahead of the mempool.
+#[derive(Clone, Debug)]
+pub struct ForcedTransaction {
+    // Index in the forced transaction queue of the l2-state program
+    pub index: u64,
+    pub transaction: Transaction,
+    // Unix timestamp the transaction must be included by
+    pub deadline: i64,
+}
+
+// Drain the transactions of the next block, the forced ones come first in their L1
+// order, then the mempool's by `priority_order`. The block stops before the
+// transaction that would take it over `max_units`, the rest stays queued for the next
+// one.
+fn take_block_txns(
+    forced_txns: &mut VecDeque<ForcedTransaction>,
+    pending_txns: &mut Vec<Transaction>,
+    now: i64,
+    max_units: u64,
+) -> Vec<Transaction> {
+    let mut txns = Vec::new();
+    let (mut units, mut taken) = (0u64, 0usize);
+    // A transaction above the cap on its own still gets a block
+    let mut fits = |txn: &Transaction| {
+        let after = units.saturating_add(compute_units(txn));
+        if taken > 0 && after > max_units {
+            return false;
+        }
+        units = after;
+        taken += 1;
+        true
+    };
+    while let Some(forced) = forced_txns.front() {
+        if !fits(&forced.transaction) {
+            break;
         }
+        let forced = forced_txns.pop_front().expect("front exists");
+        if forced.deadline < now {
+            warn!(
+                deadline = forced.deadline,
+                "Forced transaction included after its deadline"
+            );
+        }
+        txns.push(forced.transaction);
+    }
+    if forced_txns.is_empty() {
+        pending_txns.sort_by(priority_order);
+        let pending = pending_txns.iter().take_while(|txn| fits(txn)).count();
+        txns.extend(pending_txns.drain(..pending));
+    }
+    if !forced_txns.is_empty() || !pending_txns.is_empty() {
+        debug!(
+            units,
+            left = forced_txns.len() + pending_txns.len(),
+            "Block compute cap reached"
+        );
     }
+    txns
+}
 
-    info!("Transaction processing completed: {}", signature);
-    Ok(None)
+// Put `txns` back at the front of the mempool, ahead of the ones received since.
+fn requeue_txns(pending_txns: &mut Vec<Transaction>, txns: Vec<Transaction>) {
+    pending_txns.splice(0..0, txns);
 }
 
-fn transfer(balances: &mut HashMap<String, u128>, ops: Vec<TransferOp>) -> Result<()> {
-    for op in ops {
-        let from = op.from;
-        let to = op.to;
-        let amount = op.amount;
-        // fetch sender's amount
-        let from_balance = balances.get(&from).copied().unwrap_or(0);
-        if from_balance < amount {
-            return Err(anyhow!("Insufficient balance for transfer"));
+// Put the never executed `txns` of a block back, `forced` leading them ahead of the
+// forced queue and the rest at the front of the mempool.
+fn requeue_block_txns(
+    pending_txns: &mut Vec<Transaction>,
+    forced_txns: &mut VecDeque<ForcedTransaction>,
+    mut txns: Vec<Transaction>,
+    forced: Vec<ForcedTransaction>,
+) {
+    let rest = txns.split_off(forced.len().min(txns.len()));
+    for forced in forced.into_iter().rev() {
+        forced_txns.push_front(forced);
+    }
+    requeue_txns(pending_txns, rest);
+}
+
+/// Put a block that was drained but never saved back into the queues it came from, it's
+/// logged by `execute` and cleared once the block is saved. The forced transactions
+/// keep their place ahead of the mempool. Only a block after `latest_block_num` is
+/// pending, returns the number of transactions requeued.
+pub fn recover_pending_block(
+    block_db: &BlockDB,
+    latest_block_num: u64,
+    forced_txns: &mut VecDeque<ForcedTransaction>,
+    pending_txns: &mut Vec<Transaction>,
+) -> Result<usize> {
+    match block_db.pending_block()? {
+        Some(pending) if pending.block_num > latest_block_num => {
+            let count = pending.txns.len();
+            let mut txns = pending.txns.into_iter();
+            let forced: Vec<ForcedTransaction> = pending
+                .forced
+                .into_iter()
+                .zip(txns.by_ref())
+                .map(|((index, deadline), transaction)| ForcedTransaction {
+                    index,
+                    transaction,
+                    deadline,
+                })
+                .collect();
+            for forced in forced.into_iter().rev() {
+                forced_txns.push_front(forced);
+            }
+            requeue_txns(pending_txns, txns.collect());
+            Ok(count)
         }
-        // change the balance
-        balances.insert(from, from_balance - amount);
-        let to_balance = balances.get(&to).copied().unwrap_or(0);
-        balances.insert(to.to_string(), to_balance + amount);
+        _ => Ok(0),
     }
-    Ok(())
+}
+
+/// Genesis state of this sequencer, the dev account is initialized with 100 SOL.
+pub fn genesis_state() -> State {
+    State::genesis(config().dev_account.clone())
 }
 
 // Global State instance
 lazy_static::lazy_static! {
     pub static ref STATE: Arc<RwLock<StateDB>> = {
-        let mut state_db = StateDB::new("state_db");
-        if let Ok(account)= std::env::var("DEV_ACCOUNT"){
-            if state_db.state.get_balance(&account)==0{
-                // Initialize dev account with 100 SOL
-                let balance_in_lamports = 100_u128 * 1_000_000_000_u128; // 100 SOL in lamports
-                state_db.state.set_balance(account, balance_in_lamports);// 1000000000
-            }
-        }
+        // Tests run without disk
+        #[cfg(not(test))]
+        let mut state_db = StateDB::new(&config().state_db_path);
+        #[cfg(test)]
+        let mut state_db = StateDB::in_memory();
+        // Seed the genesis state
+        state_db.state = genesis_state();
         Arc::new(RwLock::new(state_db))
     };
 }
 
+lazy_static::lazy_static! {
+    /// Balance changes of every block, subscribed to by the websocket layer.
+    pub static ref BALANCE_CHANGES: broadcast::Sender<BalanceChange> = broadcast::channel(BALANCE_CHANGES_CAPACITY).0;
+}
+
+lazy_static::lazy_static! {
+    pub static ref FORCED_TXNS: Arc<RwLock<VecDeque<ForcedTransaction>>> = Arc::new(RwLock::new(VecDeque::new()));
+}
+
 lazy_static::lazy_static! {
     pub static ref MEMPOOL: Arc<RwLock<Vec<Transaction>>>= Arc::new(RwLock::new(Vec::with_capacity(256)));
 }
+
+#[cfg(test)]
+mod test {
+    use super::{
+        apply_txns_with_changes, check_supply, recover_pending_block, requeue_block_txns,
+        seal_applied_txns, take_block_txns, BalanceChange, ForcedTransaction, SupplyMismatch,
+    };
+    use share::lamports::Lamports;
+    use share::state::State;
+    use share::transaction::{calculate_txns_root, priority_order, Block, BlockDB, PendingBlock};
+    use share::zkvm::{execute_batch, ZkVMInput};
+    use solana_sdk::{
+        signature::Keypair, signer::Signer, system_instruction, transaction::Transaction,
+    };
+    use std::collections::VecDeque;
+
+    fn transfer(lamports: u64) -> Transaction {
+        transfer_from(&Keypair::new(), lamports)
+    }
+
+    fn transfer_from(from: &Keypair, lamports: u64) -> Transaction {
+        let instruction =
+            system_instruction::transfer(&from.pubkey(), &Keypair::new().pubkey(), lamports);
+        Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&from.pubkey()),
+            &[from],
+            Default::default(),
+        )
+    }
+
+    #[test]
+    fn test_forced_txns_first() {
+        let forced = transfer(1);
+        let mut forced_txns = VecDeque::from([ForcedTransaction {
+            index: 0,
+            transaction: forced.clone(),
+            deadline: 100,
+        }]);
+        let mut pending_txns = vec![transfer(2), transfer(3)];
+
+        // Forced ahead of the mempool, past the deadline as well
+        let txns = take_block_txns(&mut forced_txns, &mut pending_txns, 200, u64::MAX);
+        assert_eq!(txns.len(), 3);
+        assert_eq!(txns[0], forced);
+        assert!(forced_txns.is_empty() && pending_txns.is_empty());
+    }
+
+    #[test]
+    fn test_block_compute_cap() {
+        let mut pending: Vec<_> = (2..=4).map(transfer).collect();
+        pending.sort_by(priority_order);
+        let mut forced_txns = VecDeque::from([ForcedTransaction {
+            index: 0,
+            transaction: transfer(1),
+            deadline: 100,
+        }]);
+        let mut pending_txns = pending.clone();
+
+        // Two 150 unit transfers fit in 300, the rest waits in the mempool
+        let txns = take_block_txns(&mut forced_txns, &mut pending_txns, 0, 300);
+        assert_eq!(txns.len(), 2);
+        assert_eq!(txns[1], pending[0]);
+        assert!(forced_txns.is_empty());
+        assert_eq!(pending_txns, pending[1..]);
+
+        // A transaction above the cap still gets a block of its own
+        let txns = take_block_txns(&mut forced_txns, &mut pending_txns, 0, 100);
+        assert_eq!(txns, pending[1..2]);
+        assert_eq!(pending_txns, pending[2..]);
+    }
+
+    #[test]
+    fn test_balance_changes() {
+        let from = Keypair::new();
+        let mut state = State::new();
+        state.set_balance(from.pubkey().to_string(), Lamports(1_000));
+
+        let txn = transfer_from(&from, 300);
+        let to = txn.message.account_keys[1].to_string();
+        let (changes, applied) = apply_txns_with_changes(&mut state, &[txn], 7);
+        assert_eq!(applied.minted, 0);
+        assert_eq!(changes.len(), 2);
+        assert!(changes.contains(&BalanceChange {
+            address: from.pubkey().to_string(),
+            old: Lamports(1_000),
+            new: Lamports(700),
+            block_num: 7,
+        }));
+        assert!(changes.contains(&BalanceChange {
+            address: to,
+            old: Lamports(0),
+            new: Lamports(300),
+            block_num: 7,
+        }));
+
+        // A failed transfer changes nothing
+        let (changes, _) = apply_txns_with_changes(&mut state, &[transfer(1)], 8);
+        assert!(changes.is_empty());
+    }
+
+    #[test]
+    fn test_failed_txn_not_sealed() {
+        let from = Keypair::new();
+        let mut genesis = State::new();
+        genesis.set_balance(from.pubkey().to_string(), Lamports(1_000));
+        let txns = vec![
+            transfer_from(&from, 300),
+            transfer_from(&from, 5_000),
+            transfer_from(&from, 200),
+        ];
+        let block_of = |txns: Vec<Transaction>, post_state: &State| {
+            let mut block = Block::new(txns);
+            block.block_num = 1;
+            block.txns_root = Some(calculate_txns_root(&block.txns));
+            block.prev_state_root = genesis.calculate_state_root();
+            block.post_state_root = post_state.calculate_state_root();
+            block
+        };
+
+        // The second transfer fails and stops the block, the third waits for the next
+        let mut state = genesis.clone();
+        let (_, applied) = apply_txns_with_changes(&mut state, &txns, 1);
+        assert_eq!(applied.count, 1);
+        let mut pending_txns = vec![];
+        let sealed = seal_applied_txns(
+            &mut pending_txns,
+            &mut VecDeque::new(),
+            txns.clone(),
+            vec![],
+            applied.count,
+        );
+        assert_eq!(sealed, txns[..1]);
+        assert_eq!(pending_txns, txns[2..]);
+
+        // The prover reaches the sequencer's root on the sealed block
+        let block = block_of(sealed, &state);
+        let input = ZkVMInput::with_partial_state(vec![block], &genesis);
+        assert!(execute_batch(input).is_ok());
+
+        // and on a block still holding the failed transfer, it stops where the
+        // sequencer did
+        let block = block_of(txns, &state);
+        let input = ZkVMInput::with_partial_state(vec![block], &genesis);
+        assert!(execute_batch(input).is_ok());
+    }
+
+    #[test]
+    fn test_failed_forced_txn_sealed() {
+        let from = Keypair::new();
+        let mut genesis = State::new();
+        genesis.set_balance(from.pubkey().to_string(), Lamports(1_000));
+        let mut state = genesis.clone();
+        let forced: Vec<ForcedTransaction> = [300, 5_000, 200]
+            .into_iter()
+            .enumerate()
+            .map(|(index, lamports)| ForcedTransaction {
+                index: index as u64,
+                transaction: transfer_from(&from, lamports),
+                deadline: 100,
+            })
+            .collect();
+        let pending = transfer(1);
+        let mut txns: Vec<Transaction> = forced.iter().map(|f| f.transaction.clone()).collect();
+        txns.push(pending.clone());
+
+        // The second forced transfer fails and ends the block, the third stays forced
+        // and the mempool's goes back into the mempool
+        let (_, applied) = apply_txns_with_changes(&mut state, &txns, 1);
+        let (mut pending_txns, mut forced_txns) = (vec![], VecDeque::new());
+        let sealed = seal_applied_txns(
+            &mut pending_txns,
+            &mut forced_txns,
+            txns.clone(),
+            forced.clone(),
+            applied.count,
+        );
+        assert_eq!(sealed, txns[..2]);
+        assert_eq!(forced_txns.len(), 1);
+        assert_eq!(forced_txns[0].index, 2);
+        assert_eq!(pending_txns, vec![pending]);
+
+        // The batch of the block executes both forced transactions, not the third
+        let mut block = Block::new(sealed);
+        block.block_num = 1;
+        block.txns_root = Some(calculate_txns_root(&block.txns));
+        block.prev_state_root = genesis.calculate_state_root();
+        block.post_state_root = state.calculate_state_root();
+        let forced_bytes: Vec<Vec<u8>> = forced
+            .iter()
+            .map(|forced| bincode::serialize(&forced.transaction).unwrap())
+            .collect();
+        let input = |forced_txns: &[Vec<u8>]| ZkVMInput {
+            forced_txns: forced_txns.to_vec(),
+            ..ZkVMInput::with_partial_state(vec![block.clone()], &genesis)
+        };
+        assert!(execute_batch(input(&forced_bytes[..2])).is_ok());
+        assert!(execute_batch(input(&forced_bytes)).is_err());
+    }
+
+    #[test]
+    fn test_refused_block_requeued() {
+        let forced = ForcedTransaction {
+            index: 4,
+            transaction: transfer(1),
+            deadline: 100,
+        };
+        let pending = transfer(2);
+        let (mut pending_txns, mut forced_txns) = (vec![transfer(3)], VecDeque::new());
+
+        // The forced transaction keeps its place in the forced queue
+        requeue_block_txns(
+            &mut pending_txns,
+            &mut forced_txns,
+            vec![forced.transaction.clone(), pending.clone()],
+            vec![forced.clone()],
+        );
+        assert_eq!(forced_txns.len(), 1);
+        assert_eq!(forced_txns[0].index, 4);
+        assert_eq!(pending_txns[0], pending);
+        assert_eq!(pending_txns.len(), 2);
+    }
+
+    #[test]
+    fn test_supply_conserved() {
+        use share::transaction::{deposit_instruction_data, L2_SYS_PROGRAM_KEY};
+        use share::UNSAFE_PRIVATE_KEY;
+        use solana_sdk::{instruction::Instruction, signature::Signature};
+
+        let from = Keypair::new();
+        let oracle = Keypair::from_base58_string(UNSAFE_PRIVATE_KEY);
+        let mut state = State::new();
+        state.set_balance(from.pubkey().to_string(), Lamports(1_000));
+        let deposit = Transaction::new_signed_with_payer(
+            &[Instruction {
+                program_id: *L2_SYS_PROGRAM_KEY,
+                accounts: vec![],
+                data: deposit_instruction_data(&from.pubkey(), 500, &Signature::from([7; 64])),
+            }],
+            Some(&oracle.pubkey()),
+            &[&oracle],
+            Default::default(),
+        );
+
+        // A transfer moves lamports, the deposit mints 500
+        let txns = [transfer_from(&from, 300), deposit];
+        let (mut changes, applied) = apply_txns_with_changes(&mut state, &txns, 9);
+        assert_eq!(applied.minted, 500);
+        check_supply(&changes, applied.minted, 9).unwrap();
+
+        // The recipient credited one lamport more than the sender was debited
+        let credited = changes
+            .iter_mut()
+            .find(|change| change.old == Lamports(0))
+            .unwrap();
+        credited.new = Lamports(credited.new.0 + 1);
+        let error = check_supply(&changes, applied.minted, 9).unwrap_err();
+        assert_eq!(
+            error,
+            SupplyMismatch {
+                block_num: 9,
+                delta: 501,
+                minted: 500
+            }
+        );
+    }
+
+    #[test]
+    fn test_recover_pending_block() {
+        let mut block_db = BlockDB::in_memory();
+        let drained = vec![transfer(1), transfer(2)];
+        let received = transfer(3);
+
+        // Crashed after draining block #1 led by a forced transaction, before saving it
+        block_db
+            .save_pending_block(&PendingBlock {
+                block_num: 1,
+                txns: drained.clone(),
+                forced: vec![(5, 100)],
+            })
+            .unwrap();
+        let mut forced_txns = VecDeque::from([ForcedTransaction {
+            index: 6,
+            transaction: transfer(4),
+            deadline: 200,
+        }]);
+        let mut pending_txns = vec![received.clone()];
+        assert_eq!(
+            recover_pending_block(&block_db, 0, &mut forced_txns, &mut pending_txns).unwrap(),
+            2
+        );
+        // The forced one goes back ahead of the forced queue, not into the mempool
+        assert_eq!(forced_txns.len(), 2);
+        assert_eq!((forced_txns[0].index, forced_txns[0].deadline), (5, 100));
+        assert_eq!(forced_txns[0].transaction, drained[0]);
+        assert_eq!(pending_txns, vec![drained[1].clone(), received]);
+
+        // Once the block is saved there's nothing to recover
+        let mut block = Block::new(drained);
+        block.block_num = 1;
+        block_db.save_block(&block).unwrap();
+        let mut pending_txns = vec![];
+        assert_eq!(
+            recover_pending_block(&block_db, 1, &mut VecDeque::new(), &mut pending_txns).unwrap(),
+            0
+        );
+        assert!(pending_txns.is_empty());
+    }
+}
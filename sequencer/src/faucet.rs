@@ -0,0 +1,239 @@
+use serde::{Deserialize, Serialize};
+use share::kv::{KvBatch, KvStore};
+use share::lamports::Lamports;
+use solana_sdk::pubkey::Pubkey;
+use std::sync::{Arc, Mutex, OnceLock};
+use thiserror::Error;
+
+use crate::config::Config;
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Caps of the `requestAirdrop` faucet, see the `airdrop_*` config.
+#[derive(Clone, Debug)]
+pub struct AirdropLimits {
+    pub max_per_request: Lamports,
+    pub pubkey_daily: Lamports,
+    pub daily: Lamports,
+    pub cooldown_secs: u64,
+}
+
+impl AirdropLimits {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            max_per_request: Lamports(config.airdrop_max_lamports),
+            pubkey_daily: Lamports(config.airdrop_pubkey_daily_lamports),
+            daily: Lamports(config.airdrop_daily_lamports),
+            cooldown_secs: config.airdrop_cooldown_secs,
+        }
+    }
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum AirdropError {
+    #[error("Airdrop of {requested} lamports is above the {max} lamports cap")]
+    AboveRequestCap { requested: Lamports, max: Lamports },
+    #[error("Airdrop cooldown, retry in {retry_after_secs}s")]
+    Cooldown { retry_after_secs: u64 },
+    #[error("Daily airdrop cap of this pubkey reached, {remaining} lamports left today")]
+    PubkeyDailyCap { remaining: Lamports },
+    #[error("Daily airdrop cap of the faucet reached, {remaining} lamports left today")]
+    DailyCap { remaining: Lamports },
+    #[error("Faucet storage error: {0}")]
+    Storage(String),
+}
+
+// Airdropped on `day`, days since the unix epoch, and the time of the last airdrop
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+struct Usage {
+    day: u64,
+    total: Lamports,
+    last_at: u64,
+}
+
+impl Usage {
+    // Airdropped on the day of `now`, a previous day's total is spent
+    fn today(&self, now: u64) -> Lamports {
+        if self.day == now / SECS_PER_DAY {
+            self.total
+        } else {
+            Lamports::ZERO
+        }
+    }
+}
+
+/// Airdrops handed out by the faucet, persisted so the caps hold across restarts.
+pub struct AirdropLedger {
+    db: Arc<dyn KvStore>,
+    limits: AirdropLimits,
+    // Serializes the check and the record of concurrent requests
+    lock: Mutex<()>,
+}
+
+impl AirdropLedger {
+    pub fn new(db_path: &str, limits: AirdropLimits) -> Self {
+        Self::with_store(Arc::new(sled::open(db_path).unwrap()), limits)
+    }
+
+    pub fn with_store(db: Arc<dyn KvStore>, limits: AirdropLimits) -> Self {
+        Self {
+            db,
+            limits,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Check an airdrop of `amount` to `to` at `now` (unix seconds) against the caps,
+    /// and record it when they allow it.
+    pub fn record(&self, to: &Pubkey, amount: Lamports, now: u64) -> Result<(), AirdropError> {
+        let limits = &self.limits;
+        if amount > limits.max_per_request {
+            return Err(AirdropError::AboveRequestCap {
+                requested: amount,
+                max: limits.max_per_request,
+            });
+        }
+
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let pubkey_key = format!("pubkey/{}", to);
+        let pubkey_usage = self.usage(&pubkey_key)?;
+        let total_usage = self.usage("total")?;
+        let next_at = pubkey_usage.last_at.saturating_add(limits.cooldown_secs);
+        if pubkey_usage.last_at > 0 && now < next_at {
+            return Err(AirdropError::Cooldown {
+                retry_after_secs: next_at - now,
+            });
+        }
+        let within = |usage: &Usage, cap: Lamports| {
+            let today = usage.today(now);
+            match today.checked_add(amount) {
+                Ok(after) if after <= cap => Ok(after),
+                _ => Err(cap.checked_sub(today).unwrap_or_default()),
+            }
+        };
+        let pubkey_total = within(&pubkey_usage, limits.pubkey_daily)
+            .map_err(|remaining| AirdropError::PubkeyDailyCap { remaining })?;
+        let total = within(&total_usage, limits.daily)
+            .map_err(|remaining| AirdropError::DailyCap { remaining })?;
+
+        let mut batch = KvBatch::default();
+        for (key, total) in [(pubkey_key.as_str(), pubkey_total), ("total", total)] {
+            let usage = Usage {
+                day: now / SECS_PER_DAY,
+                total,
+                last_at: now,
+            };
+            batch.insert(key, serde_json::to_vec(&usage).expect("usage serializes"));
+        }
+        self.db
+            .apply_batch(batch)
+            .map_err(|e| AirdropError::Storage(e.to_string()))
+    }
+
+    fn usage(&self, key: &str) -> Result<Usage, AirdropError> {
+        match self.db.get(key) {
+            Ok(Some(data)) => {
+                serde_json::from_slice(&data).map_err(|e| AirdropError::Storage(e.to_string()))
+            }
+            Ok(None) => Ok(Usage::default()),
+            Err(e) => Err(AirdropError::Storage(e.to_string())),
+        }
+    }
+}
+
+/// Ledger of the faucet, opened by the first `requestAirdrop`.
+pub static AIRDROP_LEDGER: OnceLock<AirdropLedger> = OnceLock::new();
+
+#[cfg(test)]
+mod test {
+    use super::{AirdropError, AirdropLedger, AirdropLimits, SECS_PER_DAY};
+    use share::kv::{KvStore, MemoryStore};
+    use share::lamports::Lamports;
+    use solana_sdk::pubkey::Pubkey;
+    use std::sync::Arc;
+
+    fn limits() -> AirdropLimits {
+        AirdropLimits {
+            max_per_request: Lamports(2),
+            pubkey_daily: Lamports(5),
+            daily: Lamports(8),
+            cooldown_secs: 60,
+        }
+    }
+
+    #[test]
+    fn test_airdrop_caps() {
+        let ledger = AirdropLedger::with_store(Arc::new(MemoryStore::new()), limits());
+        let (alice, bob, carol) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let day = 100 * SECS_PER_DAY;
+
+        assert_eq!(
+            ledger.record(&alice, Lamports(3), day),
+            Err(AirdropError::AboveRequestCap {
+                requested: Lamports(3),
+                max: Lamports(2)
+            })
+        );
+
+        // 2 + 2 + 1 fills alice's daily cap
+        for (offset, amount) in [(0, 2), (60, 2), (120, 1)] {
+            ledger
+                .record(&alice, Lamports(amount), day + offset)
+                .unwrap();
+        }
+        let error = ledger.record(&alice, Lamports(1), day + 180).unwrap_err();
+        assert_eq!(
+            error,
+            AirdropError::PubkeyDailyCap {
+                remaining: Lamports(0)
+            }
+        );
+
+        // 5 + 2 of the faucet's 8, then only 1 is left for carol
+        ledger.record(&bob, Lamports(2), day).unwrap();
+        assert_eq!(
+            ledger.record(&carol, Lamports(2), day),
+            Err(AirdropError::DailyCap {
+                remaining: Lamports(1)
+            })
+        );
+        ledger.record(&carol, Lamports(1), day).unwrap();
+
+        // The caps reset the next day
+        ledger
+            .record(&alice, Lamports(2), day + SECS_PER_DAY)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_airdrop_cooldown() {
+        let store: Arc<dyn KvStore> = Arc::new(MemoryStore::new());
+        let ledger = AirdropLedger::with_store(store.clone(), limits());
+        let alice = Pubkey::new_unique();
+        let now = 1_000_000;
+
+        ledger.record(&alice, Lamports(1), now).unwrap();
+        assert_eq!(
+            ledger.record(&alice, Lamports(1), now + 20),
+            Err(AirdropError::Cooldown {
+                retry_after_secs: 40
+            })
+        );
+        // A rejected request isn't recorded, the cooldown still runs from the first
+        ledger.record(&alice, Lamports(1), now + 60).unwrap();
+
+        // Kept across restarts
+        let ledger = AirdropLedger::with_store(store, limits());
+        assert!(matches!(
+            ledger.record(&alice, Lamports(1), now + 90),
+            Err(AirdropError::Cooldown { .. })
+        ));
+        ledger
+            .record(&Pubkey::new_unique(), Lamports(1), now + 90)
+            .unwrap();
+    }
+}
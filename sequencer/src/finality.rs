@@ -0,0 +1,333 @@
+use anyhow::{anyhow, Result};
+use share::liveness::Liveness;
+use share::state::State;
+use share::transaction::Block;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tokio::time::sleep;
+
+use crate::config::config;
+use crate::executor::genesis_state;
+use crate::metrics::PROVER_LIVENESS;
+use crate::node::BLOCK_DB;
+
+// How long an L1 read of the finalized batch is served from the cache
+const FINALIZED_BLOCK_TTL: Duration = Duration::from_secs(5);
+
+/// The last L2 block of the last batch proven on L1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FinalizedBlock {
+    pub batch_index: u64,
+    pub block_num: u64,
+    pub state_root: [u8; 32],
+    // Withdrawals of the finalized batches, the range of withdrawal proofs
+    pub withdrawal_count: u64,
+}
+
+/// The finalized block read from L1, reused for `ttl` so `getFinalizedBlock` doesn't
+/// query L1 on every call.
+pub struct FinalizedBlockCache {
+    cached: Option<(Instant, FinalizedBlock)>,
+    ttl: Duration,
+}
+
+impl FinalizedBlockCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self { cached: None, ttl }
+    }
+
+    pub fn get(&self, now: Instant) -> Option<FinalizedBlock> {
+        self.cached
+            .as_ref()
+            .filter(|(read_at, _)| now.duration_since(*read_at) < self.ttl)
+            .map(|(_, block)| block.clone())
+    }
+
+    pub fn set(&mut self, now: Instant, block: FinalizedBlock) {
+        self.cached = Some((now, block));
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref FINALIZED_BLOCK_CACHE: Arc<Mutex<FinalizedBlockCache>> = Arc::new(Mutex::new(FinalizedBlockCache::new(FINALIZED_BLOCK_TTL)));
+}
+
+/// The finalized block, from the cache or else from the l2-state program.
+pub async fn finalized_block() -> Result<FinalizedBlock> {
+    let mut cache = FINALIZED_BLOCK_CACHE.lock().await;
+    if let Some(block) = cache.get(Instant::now()) {
+        return Ok(block);
+    }
+    let block = tokio::task::spawn_blocking(read_finalized_block).await??;
+    cache.set(Instant::now(), block.clone());
+    Ok(block)
+}
+
+// Join the last finalized batch index with its batch, both read from L1
+fn read_finalized_block() -> Result<FinalizedBlock> {
+    let client = config().l2_state_client()?;
+    let batch_index = client.get_last_finalized_batch_index()?;
+    match client.get_committed_batch(batch_index)? {
+        Some(batch) => Ok(FinalizedBlock {
+            batch_index,
+            block_num: batch.end_block_num,
+            state_root: batch.post_state_root,
+            withdrawal_count: batch.withdrawal_count,
+        }),
+        // Nothing proven yet, the genesis state is final
+        None if batch_index == 0 => Ok(FinalizedBlock {
+            batch_index,
+            block_num: 0,
+            state_root: genesis_state().calculate_state_root().unwrap_or_default(),
+            withdrawal_count: 0,
+        }),
+        None => Err(anyhow!("Finalized batch {} not found on L1", batch_index)),
+    }
+}
+
+/// The state as of the finalized block, for reads with the `finalized` commitment.
+/// Advanced by replaying the blocks finalized since, from the genesis state again when
+/// finalization was rolled back.
+pub struct FinalizedState {
+    genesis: State,
+    block_num: u64,
+    // Root proven on L1 the state was checked against, `None` before any
+    state_root: Option<[u8; 32]>,
+    state: State,
+}
+
+impl FinalizedState {
+    pub fn new(genesis: State) -> Self {
+        Self {
+            state: genesis.clone(),
+            genesis,
+            block_num: 0,
+            state_root: None,
+        }
+    }
+
+    /// The state after block `block_num`, which must have the `state_root` proven on
+    /// L1. The blocks finalized since are read with `get_block`, a block already
+    /// reached is served as is.
+    pub fn advance(
+        &mut self,
+        get_block: impl Fn(u64) -> Option<Block>,
+        block_num: u64,
+        state_root: [u8; 32],
+    ) -> Result<&State> {
+        if block_num == self.block_num && self.state_root == Some(state_root) {
+            return Ok(&self.state);
+        }
+        if block_num < self.block_num {
+            *self = Self::new(self.genesis.clone());
+        }
+        // Replayed on a copy, a root that doesn't match keeps the state checked last
+        let mut state = self.state.clone();
+        for block_num in self.block_num + 1..=block_num {
+            let block = get_block(block_num)
+                .ok_or_else(|| anyhow!("Block {} missing from block db", block_num))?;
+            state.apply_txns(&block.txns, block_num);
+        }
+        if state.incremental_root() != Some(state_root) {
+            return Err(anyhow!(
+                "Replayed state of block {} doesn't match the root proven on L1",
+                block_num
+            ));
+        }
+        self.block_num = block_num;
+        self.state_root = Some(state_root);
+        self.state = state;
+        Ok(&self.state)
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref FINALIZED_STATE: Arc<Mutex<FinalizedState>> = Arc::new(Mutex::new(FinalizedState::new(genesis_state())));
+}
+
+/// Read the state as of the finalized block with `f`, along with the block number.
+///
+/// The blocks finalized since the last read are replayed off the async runtime, taking
+/// the block db lock one block at a time so block production goes on meanwhile.
+pub async fn read_finalized_state<T>(f: impl FnOnce(&State) -> T) -> Result<(u64, T)> {
+    let finalized = finalized_block().await?;
+    tokio::task::spawn_blocking(move || {
+        FINALIZED_STATE
+            .blocking_lock()
+            .advance(
+                |block_num| BLOCK_DB.blocking_read().get_block(block_num),
+                finalized.block_num,
+                finalized.state_root,
+            )
+            .map(|_| ())
+    })
+    .await??;
+    // Another read may have advanced it further since, the block number says where
+    let finalized_state = FINALIZED_STATE.lock().await;
+    Ok((finalized_state.block_num, f(&finalized_state.state)))
+}
+
+/// Record the prover alive when the finalized batch advanced since the `last_seen`
+/// index, or when no committed batch waits for a proof.
+pub fn observe_prover(
+    liveness: &Liveness,
+    last_seen: &AtomicU64,
+    finalized_index: u64,
+    latest_index: u64,
+) {
+    let previous = last_seen.swap(finalized_index, Ordering::Relaxed);
+    if finalized_index > previous || finalized_index >= latest_index {
+        liveness.record_success();
+    }
+}
+
+/// Read the finalized and latest committed batch from L1 every `interval`, the prover
+/// runs in its own process and L1 is where its progress shows.
+pub async fn watch_prover(interval: Duration) {
+    let last_seen = AtomicU64::new(0);
+    loop {
+        let indices = tokio::task::spawn_blocking(|| -> Result<(u64, u64)> {
+            let client = config().l2_state_client()?;
+            let finalized_index = client.get_last_finalized_batch_index()?;
+            let latest_index = client
+                .get_latest_batch()?
+                .map_or(0, |batch| batch.batch_index);
+            Ok((finalized_index, latest_index))
+        })
+        .await;
+        match indices {
+            Ok(Ok((finalized_index, latest_index))) => {
+                observe_prover(&PROVER_LIVENESS, &last_seen, finalized_index, latest_index)
+            }
+            Ok(Err(e)) => tracing::warn!("Failed to read the prover progress: {:?}", e),
+            Err(e) => tracing::warn!("Failed to read the prover progress: {:?}", e),
+        }
+        sleep(interval).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{observe_prover, FinalizedBlock, FinalizedBlockCache, FinalizedState};
+    use share::lamports::Lamports;
+    use share::liveness::Liveness;
+    use share::state::State;
+    use share::transaction::{Block, BlockDB};
+    use share::WITHDRAWAL_ADDRESS;
+    use solana_sdk::{
+        hash::Hash, pubkey::Pubkey, signature::Keypair, signer::Signer, system_transaction,
+    };
+    use std::str::FromStr;
+    use std::sync::atomic::AtomicU64;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_finalized_block_cache() {
+        let mut cache = FinalizedBlockCache::new(Duration::from_secs(5));
+        let now = Instant::now();
+        assert_eq!(cache.get(now), None);
+
+        let block = FinalizedBlock {
+            batch_index: 3,
+            block_num: 120,
+            state_root: [7u8; 32],
+            withdrawal_count: 4,
+        };
+        cache.set(now, block.clone());
+        assert_eq!(cache.get(now + Duration::from_secs(4)), Some(block));
+        // Stale, read L1 again
+        assert_eq!(cache.get(now + Duration::from_secs(5)), None);
+    }
+
+    #[test]
+    fn test_observe_prover() {
+        let liveness = Liveness::new("prover");
+        let last_seen = AtomicU64::new(0);
+
+        // Batches 1..=3 committed, none proven
+        observe_prover(&liveness, &last_seen, 0, 3);
+        assert_eq!(liveness.last_success(), None);
+
+        // The prover finalized batch 1, then is stuck on batch 2
+        observe_prover(&liveness, &last_seen, 1, 3);
+        let proven_at = liveness.last_success().unwrap();
+        observe_prover(&liveness, &last_seen, 1, 3);
+        assert_eq!(liveness.last_success(), Some(proven_at));
+
+        // Nothing to prove is healthy
+        let idle = Liveness::new("prover");
+        observe_prover(&idle, &AtomicU64::new(3), 3, 3);
+        assert!(idle.last_success().is_some());
+    }
+
+    #[test]
+    fn test_finalized_state() {
+        let user = Keypair::new();
+        let mut genesis = State::new();
+        genesis.set_balance(user.pubkey().to_string(), Lamports(1_000_000));
+
+        // Block 1 pays someone, block 2 withdraws, only block 1 is finalized
+        let withdrawal_address = Pubkey::from_str(WITHDRAWAL_ADDRESS).unwrap();
+        let mut block_db = BlockDB::in_memory();
+        let mut state = genesis.clone();
+        let mut roots = vec![];
+        for (block_num, to, lamports) in [
+            (1, Pubkey::new_unique(), 100_000),
+            (2, withdrawal_address, 200_000),
+        ] {
+            let txn = system_transaction::transfer(&user, &to, lamports, Hash::default());
+            let mut block = Block::new(vec![txn]);
+            block.block_num = block_num;
+            state.apply_txns(&block.txns, block_num);
+            roots.push(state.calculate_state_root().unwrap());
+            block.post_state_root = roots.last().copied();
+            block_db.save_block(&block).unwrap();
+        }
+
+        let get_block = |block_num| block_db.get_block(block_num);
+        let mut finalized = FinalizedState::new(genesis.clone());
+        let finalized_state = finalized.advance(get_block, 1, roots[0]).unwrap();
+        assert_eq!(
+            finalized_state.get_balance(&user.pubkey().to_string()),
+            Lamports(900_000)
+        );
+        assert!(finalized_state.withdrawal_queue.is_empty());
+        // The latest state has the withdrawal
+        assert_eq!(
+            state.get_balance(&user.pubkey().to_string()),
+            Lamports(700_000)
+        );
+
+        let finalized_state = finalized.advance(get_block, 2, roots[1]).unwrap();
+        assert_eq!(
+            finalized_state.get_balance(&user.pubkey().to_string()),
+            Lamports(700_000)
+        );
+
+        // Rolled back to block 1, replayed from genesis
+        let finalized_state = finalized.advance(get_block, 1, roots[0]).unwrap();
+        assert_eq!(
+            finalized_state.get_balance(&user.pubkey().to_string()),
+            Lamports(900_000)
+        );
+
+        // Already at block 1, served without reading any block
+        let finalized_state = finalized.advance(|_| None, 1, roots[0]).unwrap();
+        assert_eq!(
+            finalized_state.get_balance(&user.pubkey().to_string()),
+            Lamports(900_000)
+        );
+
+        // A root that doesn't match isn't served
+        assert!(finalized.advance(get_block, 2, [0; 32]).is_err());
+        assert_eq!(
+            finalized
+                .advance(get_block, 1, roots[0])
+                .unwrap()
+                .get_balance(&user.pubkey().to_string()),
+            Lamports(900_000)
+        );
+    }
+}
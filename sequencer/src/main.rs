@@ -1,20 +1,56 @@
+use crate::config::Config;
 use crate::node::Node;
-use log::info;
+use crate::oracle::l1_msg_oracle::oracle_keypair;
+use share::transaction::{set_l1_oracle, set_withdrawal_addresses};
+use solana_sdk::signer::Signer;
+use tracing::{error, info};
+use tracing_subscriber::EnvFilter;
 
 mod batcher;
+mod blockhash;
+mod config;
 mod executor;
+mod faucet;
+mod finality;
+mod metrics;
 mod node;
 mod oracle;
+mod prioritization_fee;
 mod rpc;
 mod validator;
+mod withdrawal;
 
 #[tokio::main]
 async fn main() {
     // Step1. init log sys
     dotenv::dotenv().ok();
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    // Log level is controlled by RUST_LOG, e.g. RUST_LOG=sequencer=debug
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        )
+        .init();
     info!("Starting solana layer2 sequencer...");
 
+    // Load the config, `--config <path>`, before anything reads it
+    let args: Vec<String> = std::env::args().collect();
+    let config_path = args
+        .iter()
+        .position(|arg| arg == "--config")
+        .and_then(|index| args.get(index + 1));
+    let loaded = Config::load(config_path.map(String::as_str))
+        .and_then(config::init)
+        .and_then(|()| set_withdrawal_addresses(config::config().withdrawal_addresses.clone()));
+    // Only the deposits of our oracle are credited
+    set_l1_oracle(oracle_keypair().pubkey());
+    match loaded {
+        Ok(()) => info!("Sequencer config: {:?}", config::config()),
+        Err(e) => {
+            error!("Invalid sequencer config: {:#}", e);
+            std::process::exit(1);
+        }
+    }
+
     // Step2. Start sequencer node
     tokio::spawn(async {
         let mut sequencer_node = Node::new().await.expect("Init sequencer node failed");
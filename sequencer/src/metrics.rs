@@ -0,0 +1,239 @@
+use share::liveness::{unix_now, Liveness};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::config::config;
+
+/// A monotonic counter exposed on `/metrics`.
+pub struct Counter {
+    name: &'static str,
+    help: &'static str,
+    value: AtomicU64,
+}
+
+impl Counter {
+    pub const fn new(name: &'static str, help: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            value: AtomicU64::new(0),
+        }
+    }
+
+    pub fn inc(&self) {
+        self.value.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+/// A value that goes up and down exposed on `/metrics`.
+pub struct Gauge {
+    name: &'static str,
+    help: &'static str,
+    value: AtomicU64,
+}
+
+impl Gauge {
+    pub const fn new(name: &'static str, help: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            value: AtomicU64::new(0),
+        }
+    }
+
+    pub fn set(&self, value: u64) {
+        self.value.store(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+// Upper bounds of the latency buckets, in seconds
+const LATENCY_BUCKETS: [f64; 10] = [0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0];
+
+/// Durations by label exposed on `/metrics` as a histogram.
+pub struct LatencyHistogram {
+    name: &'static str,
+    help: &'static str,
+    label: &'static str,
+    // Per label value: observations per bucket (the last one above all bounds), their
+    // total in seconds
+    series: Mutex<BTreeMap<String, ([u64; LATENCY_BUCKETS.len() + 1], f64)>>,
+}
+
+impl LatencyHistogram {
+    pub const fn new(name: &'static str, help: &'static str, label: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            label,
+            series: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn observe(&self, value: &str, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        let mut series = self.series.lock().unwrap();
+        let (counts, sum) = series.entry(value.to_string()).or_default();
+        counts[bucket] += 1;
+        *sum += seconds;
+    }
+
+    /// The Prometheus text format, buckets are cumulative.
+    fn render(&self) -> String {
+        let mut out = format!(
+            "# HELP {} {}\n# TYPE {} histogram\n",
+            self.name, self.help, self.name
+        );
+        for (value, (counts, sum)) in self.series.lock().unwrap().iter() {
+            let mut cumulative = 0;
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(counts) {
+                cumulative += count;
+                out.push_str(&format!(
+                    "{}_bucket{{{}=\"{}\",le=\"{}\"}} {}\n",
+                    self.name, self.label, value, bound, cumulative
+                ));
+            }
+            let total: u64 = counts.iter().sum();
+            out.push_str(&format!(
+                "{}_bucket{{{}=\"{}\",le=\"+Inf\"}} {}\n{}_sum{{{}=\"{}\"}} {}\n{}_count{{{}=\"{}\"}} {}\n",
+                self.name,
+                self.label,
+                value,
+                total,
+                self.name,
+                self.label,
+                value,
+                sum,
+                self.name,
+                self.label,
+                value,
+                total
+            ));
+        }
+        out
+    }
+}
+
+pub static WITHDRAWAL_PROOF_CACHE_HITS: Counter = Counter::new(
+    "withdrawal_proof_cache_hits_total",
+    "Withdrawal proofs served from a cached tree",
+);
+pub static WITHDRAWAL_PROOF_CACHE_MISSES: Counter = Counter::new(
+    "withdrawal_proof_cache_misses_total",
+    "Withdrawal proofs that had to build their tree",
+);
+
+pub static BLOCK_INTERVAL_MS: Gauge = Gauge::new(
+    "block_interval_ms",
+    "Current wait before the next block, adapted to the mempool size",
+);
+
+pub static RPC_LATENCY: LatencyHistogram = LatencyHistogram::new(
+    "rpc_request_duration_seconds",
+    "Time to serve an rpc call by method",
+    "method",
+);
+
+lazy_static::lazy_static! {
+    /// Rounds of the batcher loop that completed, with or without a batch to submit.
+    pub static ref BATCHER_LIVENESS: Liveness = Liveness::new("batcher");
+    /// Progress of the prover observed on L1, see `finality::observe_prover`.
+    pub static ref PROVER_LIVENESS: Liveness = Liveness::new("prover");
+}
+
+/// The watched loops and their stall thresholds.
+pub fn liveness() -> [(&'static Liveness, Duration); 2] {
+    [
+        (&BATCHER_LIVENESS, config().batcher_stall()),
+        (&PROVER_LIVENESS, config().prover_stall()),
+    ]
+}
+
+static COUNTERS: &[&Counter] = &[&WITHDRAWAL_PROOF_CACHE_HITS, &WITHDRAWAL_PROOF_CACHE_MISSES];
+static GAUGES: &[&Gauge] = &[&BLOCK_INTERVAL_MS];
+
+/// All metrics in the Prometheus text format.
+pub fn render() -> String {
+    let mut out = String::new();
+    for counter in COUNTERS {
+        out.push_str(&format!(
+            "# HELP {} {}\n# TYPE {} counter\n{} {}\n",
+            counter.name,
+            counter.help,
+            counter.name,
+            counter.name,
+            counter.get()
+        ));
+    }
+    for gauge in GAUGES {
+        out.push_str(&format!(
+            "# HELP {} {}\n# TYPE {} gauge\n{} {}\n",
+            gauge.name,
+            gauge.help,
+            gauge.name,
+            gauge.name,
+            gauge.get()
+        ));
+    }
+    out.push_str(&RPC_LATENCY.render());
+    let now = unix_now();
+    for (liveness, threshold) in liveness() {
+        let name = liveness.name();
+        out.push_str(&format!(
+            "# HELP {}_last_success_timestamp_seconds Last success of the {} loop\n# TYPE {}_last_success_timestamp_seconds gauge\n{}_last_success_timestamp_seconds {}\n",
+            name,
+            name,
+            name,
+            name,
+            liveness.last_success().unwrap_or_default()
+        ));
+        out.push_str(&format!(
+            "# HELP {}_stalled Whether the {} loop has no success within its threshold\n# TYPE {}_stalled gauge\n{}_stalled {}\n",
+            name,
+            name,
+            name,
+            name,
+            liveness.is_stalled(now, threshold) as u8
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::LatencyHistogram;
+    use std::time::Duration;
+
+    #[test]
+    fn test_latency_histogram() {
+        let histogram = LatencyHistogram::new("rpc_seconds", "Rpc latency", "method");
+        histogram.observe("getBalance", Duration::from_millis(3));
+        histogram.observe("getBalance", Duration::from_millis(300));
+        histogram.observe("getBalance", Duration::from_secs(10));
+        histogram.observe("getHealth", Duration::from_micros(500));
+
+        let out = histogram.render();
+        assert!(out.contains("# TYPE rpc_seconds histogram\n"));
+        // Buckets are cumulative
+        assert!(out.contains("rpc_seconds_bucket{method=\"getBalance\",le=\"0.001\"} 0\n"));
+        assert!(out.contains("rpc_seconds_bucket{method=\"getBalance\",le=\"0.005\"} 1\n"));
+        assert!(out.contains("rpc_seconds_bucket{method=\"getBalance\",le=\"0.5\"} 2\n"));
+        assert!(out.contains("rpc_seconds_bucket{method=\"getBalance\",le=\"5\"} 2\n"));
+        assert!(out.contains("rpc_seconds_bucket{method=\"getBalance\",le=\"+Inf\"} 3\n"));
+        assert!(out.contains("rpc_seconds_count{method=\"getBalance\"} 3\n"));
+        assert!(out.contains("rpc_seconds_count{method=\"getHealth\"} 1\n"));
+    }
+}
@@ -1,17 +1,25 @@
 use anyhow::Result;
-use share::transaction::{calculate_txns_root, Block, BlockDB};
-use share::utils::read_env_var;
-use share::{DEFAULT_L2_RPC, UNSAFE_PRIVATE_KEY};
-use std::sync::Arc;
+use share::backoff::{Backoff, MAX_L1_BACKOFF};
+use share::transaction::{Block, BlockDB};
+use std::future::Future;
+use std::sync::{Arc, OnceLock};
 use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 use tokio::time::sleep;
+use tracing::instrument;
 
 use crate::batcher::tx_batcher::TxBatcher;
-use crate::executor::{Executor, STATE};
-use crate::oracle::l1_msg_oracle::L1MsgOracle;
-
-static BLOCK_TIME_INTERVAL: Duration = Duration::from_millis(200);
+use crate::blockhash::{RecentBlockhashes, RECENT_BLOCKHASHES};
+use crate::config::config;
+use crate::executor::{
+    genesis_state, recover_pending_block, Executor, FORCED_TXNS, MEMPOOL, STATE,
+};
+use crate::finality;
+use crate::metrics::BLOCK_INTERVAL_MS;
+use crate::oracle::l1_msg_oracle::{oracle_keypair, L1MsgOracle};
+use crate::prioritization_fee::{
+    block_prioritization_fee, RecentPrioritizationFees, RECENT_PRIORITIZATION_FEES,
+};
 
 // For generate block and execute txn.
 pub struct Node {
@@ -27,17 +35,15 @@ impl Node {
     pub async fn new() -> Result<Self> {
         let block_db = BLOCK_DB.read().await;
         let executor = Executor::new();
-        let batcher = TxBatcher::new()?;
-        let l1_msg_oracle = L1MsgOracle::new(
-            read_env_var("L2_RPC", DEFAULT_L2_RPC.to_owned()),
-            &read_env_var("L1_ORACLE_PRIVATE_KEY", UNSAFE_PRIVATE_KEY.to_owned()),
-        )?;
+        let batcher = Arc::new(TxBatcher::new()?);
+        let _ = BATCHER.set(batcher.clone());
+        let l1_msg_oracle = L1MsgOracle::new(config().l2_rpc.clone(), oracle_keypair())?;
 
         // Initialize block number from database or start from 0
         let latest_block_num = match block_db.db.get("latest_block_num")? {
             Some(bytes) => {
                 let num_bytes: [u8; 8] = bytes
-                    .as_ref()
+                    .as_slice()
                     .try_into()
                     .map_err(|_| anyhow::anyhow!("Invalid block number format"))?;
                 u64::from_be_bytes(num_bytes)
@@ -48,17 +54,79 @@ impl Node {
         let latest_state_root = match block_db.db.get("latest_state_root")? {
             Some(bytes) => {
                 let num_bytes: [u8; 32] = bytes
-                    .as_ref()
+                    .as_slice()
                     .try_into()
                     .map_err(|_| anyhow::anyhow!("Invalid latest state root format"))?;
                 num_bytes
             }
-            None => Default::default(),
+            // Start from the genesis state root
+            None => STATE
+                .read()
+                .await
+                .state
+                .calculate_state_root()
+                .unwrap_or_default(),
         };
 
+        // Upgrade blocks stored by an older version
+        let migrated = block_db.migrate()?;
+        if migrated > 0 {
+            tracing::info!(
+                "Migrated {} blocks to the current storage version",
+                migrated
+            );
+        }
+
+        // Integrity check, blocks must be contiguous up to the latest one
+        let missing_blocks = block_db.missing_blocks(latest_block_num);
+        if !missing_blocks.is_empty() {
+            tracing::error!(
+                latest_block_num,
+                "Block db has gaps, missing blocks: {:?}",
+                missing_blocks
+            );
+        }
+
+        // Recover the state by replaying the blocks when the state db is behind the chain
+        if latest_block_num > 0 {
+            let mut state_db = STATE.write().await;
+            state_db.load();
+            if state_db.state.calculate_state_root() != Some(latest_state_root) {
+                tracing::warn!(
+                    "State db doesn't match block #{}, rebuilding it",
+                    latest_block_num
+                );
+                let state_root = state_db.rebuild_from_blocks(&block_db, genesis_state())?;
+                tracing::info!("Rebuilt state from blocks, state root: {:?}", state_root);
+            }
+        }
+
+        // Transactions of a block drained but not saved before the last shutdown
+        let recovered = recover_pending_block(
+            &block_db,
+            latest_block_num,
+            &mut *FORCED_TXNS.write().await,
+            &mut *MEMPOOL.write().await,
+        )?;
+        if recovered > 0 {
+            tracing::warn!(
+                "Requeued {} transactions of unsaved block #{}",
+                recovered,
+                latest_block_num + 1
+            );
+        }
+
+        *RECENT_BLOCKHASHES.write().await = RecentBlockhashes::load(
+            &block_db,
+            latest_block_num,
+            genesis_state().calculate_state_root().unwrap_or_default(),
+        );
+        *RECENT_PRIORITIZATION_FEES.write().await =
+            RecentPrioritizationFees::load(&block_db, latest_block_num);
+
         Ok(Self {
             executor,
-            batcher: Arc::new(batcher),
+            batcher,
             l1_msg_oracle: Arc::new(l1_msg_oracle),
             latest_block_num,
             latest_state_root,
@@ -67,43 +135,63 @@ impl Node {
     }
 
     pub async fn start(&mut self) -> Result<()> {
-        // Step1. Start batcher
+        // Step1. Start batcher, blocks keep being produced while L1 is unreachable and
+        // are committed once it's back
         let batcher = self.batcher.clone();
         tokio::spawn(async move {
+            let mut backoff = Backoff::new(config().batch_interval(), MAX_L1_BACKOFF);
+            let mut delay = backoff.delay();
             loop {
-                sleep(Duration::from_secs(60)).await;
-                if let Err(e) = batcher.smart_submit().await {
-                    log::info!("Batcher error: {:?}", e);
-                };
+                sleep(delay).await;
+                delay = batcher.round(&BLOCK_DB, &mut backoff).await;
             }
         });
 
+        tokio::spawn(finality::watch_prover(config().batch_interval()));
+
         let l1_msg_oracle = self.l1_msg_oracle.clone();
-        tokio::spawn(async move {
-            if let Err(e) = l1_msg_oracle.listen_deposite_event().await {
-                log::info!("l1_msg_oracle error: {:?}", e);
-            };
-        });
+        tokio::spawn(keep_listening("deposit", move || {
+            let l1_msg_oracle = l1_msg_oracle.clone();
+            async move { l1_msg_oracle.listen_deposite_event().await }
+        }));
+
+        let l1_msg_oracle = self.l1_msg_oracle.clone();
+        tokio::spawn(keep_listening("forced transaction", move || {
+            let l1_msg_oracle = l1_msg_oracle.clone();
+            async move { l1_msg_oracle.listen_forced_transaction_event().await }
+        }));
 
         // Step2. Start building block
         loop {
             let should_generate_block = {
                 let last_time = *self.last_block_time.read().await;
-                let time_elapsed = last_time.elapsed() >= BLOCK_TIME_INTERVAL;
-                (time_elapsed && self.executor.pending_size().await > 0)
-                    || last_time.elapsed() > 10 * BLOCK_TIME_INTERVAL
+                let block_interval = config().block_interval(self.executor.pending_size().await);
+                BLOCK_INTERVAL_MS.set(block_interval.as_millis() as u64);
+                last_time.elapsed() >= block_interval
             };
 
             if should_generate_block {
-                // Generate and save block
-                let mut block = self.create_block().await;
-                let _ = self.save_block(&mut block).await;
-
-                log::info!(
-                    "Generated block #{} with {} transactions",
-                    block.block_num,
-                    block.txns.len()
-                );
+                // Generate and save block, the chain only advances once it's persisted
+                match self.create_block().await {
+                    Ok(mut block) => match self.save_block(&mut block).await {
+                        Ok(()) => {
+                            self.latest_block_num = block.block_num;
+                            self.latest_state_root = block.post_state_root.unwrap_or_default();
+                            tracing::info!(
+                                "Generated block #{} with {} transactions",
+                                block.block_num,
+                                block.txns.len()
+                            );
+                        }
+                        Err(e) => {
+                            tracing::error!("Failed to save block #{}: {:?}", block.block_num, e);
+                            if let Err(e) = self.discard_block(block).await {
+                                tracing::error!("Failed to discard block: {:?}", e);
+                            }
+                        }
+                    },
+                    Err(e) => tracing::error!("Failed to create block: {:?}", e),
+                }
 
                 *self.last_block_time.write().await = Instant::now();
             }
@@ -113,55 +201,51 @@ impl Node {
         }
     }
 
-    pub async fn create_block(&mut self) -> Block {
-        let mut block = self.executor.execute().await.unwrap();
+    #[instrument(skip(self), fields(block_num = self.latest_block_num + 1))]
+    pub async fn create_block(&self) -> Result<Block> {
+        let mut block = self.executor.execute(self.latest_block_num + 1).await?;
         block.prev_state_root = Some(self.latest_state_root);
-        self.latest_block_num += 1;
-        block.block_num = self.latest_block_num;
 
-        block
+        Ok(block)
+    }
+
+    /// Undo a block that executed but failed to save, the state goes back to the
+    /// latest saved block and its transactions back into the mempool.
+    #[instrument(skip_all, fields(block_num = block.block_num))]
+    async fn discard_block(&self, block: Block) -> Result<()> {
+        {
+            let mut state_db = STATE.write().await;
+            let block_db = BLOCK_DB.read().await;
+            state_db.rebuild_from_blocks(&block_db, genesis_state())?;
+        }
+        MEMPOOL.write().await.splice(0..0, block.txns);
+        Ok(())
     }
 
     /// Save block to local storage
+    #[instrument(skip_all, fields(block_num = block.block_num))]
     async fn save_block(&self, block: &mut Block) -> Result<()> {
-        let state_db = STATE.read().await;
-        let state = &state_db.state;
-        let state_root = state.calculate_state_root().unwrap_or_default();
-        block.post_state_root = Some(state_root);
-        let withdrawal_root = state.calculate_withdrawal_root().unwrap_or_default();
-        block.withdrawal_root = Some(withdrawal_root);
-
-        let txns_root = calculate_txns_root(&block.txns);
-        block.txns_root = Some(txns_root);
-
-        // Serialize block
-        let block_data = serde_json::to_vec(block)
-            .map_err(|e| anyhow::anyhow!("Failed to serialize block: {}", e))?;
+        let mut state_db = STATE.write().await;
+        // Only the paths of the accounts the block changed are rehashed
+        state_db.state.seal_block(block);
+        let state_root = block.post_state_root.unwrap_or_default();
 
+        // Save block with latest_block_num & latest_state_root
         let mut block_db = BLOCK_DB.write().await;
-
-        // Save cache
-        if block_db.cache.len() == 128 {
-            block_db.cache.pop_front();
-        }
-        block_db.cache.push_back(block.clone());
-
-        // Save block
-        let block_key = format!("block_{}", block.block_num);
-        block_db.db.insert(block_key.as_bytes(), block_data)?;
-
-        // Update latest_block_num & latest_state_root
-        let block_num_bytes = block.block_num.to_be_bytes();
-        block_db
-            .db
-            .insert("latest_block_num", &block_num_bytes[..])?;
-        block_db
-            .db
-            .insert("latest_state_root", &block.post_state_root.unwrap())?;
+        block_db.save_block(block)?;
 
         // Save balance state
         state_db.save();
 
+        RECENT_BLOCKHASHES
+            .write()
+            .await
+            .push(block.block_num, state_root);
+        RECENT_PRIORITIZATION_FEES
+            .write()
+            .await
+            .push(block.block_num, block_prioritization_fee(block));
+
         // Flush to ensure data is persisted
         block_db.db.flush()?;
 
@@ -169,7 +253,46 @@ impl Node {
     }
 }
 
+// Run an L1 event listener, reconnecting with a backoff whenever it stops, e.g. when
+// L1 is unreachable. A listener that stayed up longer than the longest wait resets it.
+async fn keep_listening<F, Fut>(name: &'static str, listen: F)
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    let mut backoff = Backoff::new(Duration::from_secs(1), MAX_L1_BACKOFF);
+    loop {
+        let started = Instant::now();
+        let result = listen().await;
+        if started.elapsed() > MAX_L1_BACKOFF {
+            backoff.success();
+        }
+        let retry_in = backoff.failure();
+        tracing::warn!(
+            "L1 {} listener stopped, reconnecting in {:?}: {:?}",
+            name,
+            retry_in,
+            result
+        );
+        sleep(retry_in).await;
+    }
+}
+
+/// The batcher of the node, for `forceBatchSubmit`.
+pub static BATCHER: OnceLock<Arc<TxBatcher>> = OnceLock::new();
+
 // Global block db instance
+// Tests run without disk
+#[cfg(not(test))]
+fn block_db() -> BlockDB {
+    BlockDB::new(&config().block_db_path).with_cache_size(config().block_cache_size)
+}
+
+#[cfg(test)]
+fn block_db() -> BlockDB {
+    BlockDB::in_memory().with_cache_size(config().block_cache_size)
+}
+
 lazy_static::lazy_static! {
-    pub static ref BLOCK_DB: Arc<RwLock<BlockDB>> = Arc::new(RwLock::new(BlockDB::new("block_db")));
+    pub static ref BLOCK_DB: Arc<RwLock<BlockDB>> = Arc::new(RwLock::new(block_db()));
 }
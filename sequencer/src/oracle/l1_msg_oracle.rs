@@ -1,6 +1,10 @@
 use anyhow::Result;
-use l2_state_client::event_listen::{self, EventData};
-use share::{utils::read_env_var, DEFAULT_L1_RPC, DEFAULT_L1_WS, L2_SYS_PROGRAM_ID};
+use l2_state_client::event_listen::{self, EventData, ForcedTransactionData};
+use share::{
+    transaction::{deposit_instruction_data, forced_transaction},
+    utils::read_env_var,
+    L2_SYS_PROGRAM_ID, UNSAFE_PRIVATE_KEY,
+};
 use solana_client::rpc_client::RpcClient;
 use solana_sdk::{
     commitment_config::CommitmentConfig, instruction::Instruction, pubkey::Pubkey,
@@ -8,6 +12,17 @@ use solana_sdk::{
 };
 use tokio::sync::mpsc;
 
+use crate::config::config;
+use crate::executor::{ForcedTransaction, FORCED_TXNS};
+
+/// Key the oracle signs the messages relayed to L2 with, `L1_ORACLE_PRIVATE_KEY`.
+pub fn oracle_keypair() -> Keypair {
+    Keypair::from_base58_string(&read_env_var(
+        "L1_ORACLE_PRIVATE_KEY",
+        UNSAFE_PRIVATE_KEY.to_owned(),
+    ))
+}
+
 pub struct L1MsgOracle {
     client: RpcClient,
     signer: Keypair,
@@ -15,9 +30,8 @@ pub struct L1MsgOracle {
 }
 
 impl L1MsgOracle {
-    pub fn new(rpc_url: String, signer_key: &str) -> Result<Self> {
+    pub fn new(rpc_url: String, signer: Keypair) -> Result<Self> {
         let client = RpcClient::new_with_commitment(rpc_url, CommitmentConfig::confirmed());
-        let signer = Keypair::from_base58_string(signer_key);
         let program_id = Pubkey::from_str_const(L2_SYS_PROGRAM_ID);
 
         Ok(Self {
@@ -31,20 +45,24 @@ impl L1MsgOracle {
         let (tx, mut rx) = mpsc::unbounded_channel::<EventData>();
         let listener_handle = tokio::spawn(async {
             let _ = event_listen::create_listener(
-                read_env_var("L1_RPC", DEFAULT_L1_RPC.to_owned()),
-                read_env_var("L1_WS", DEFAULT_L1_WS.to_owned()),
+                config().l1_rpc.clone(),
+                config().l1_ws.clone(),
+                CommitmentConfig::processed(),
                 tx,
             )
             .await;
         });
         while let Some(event_data) = rx.recv().await {
-            log::info!(
+            tracing::info!(
                 "Received event: {} lamports from {}",
                 event_data.event.amount,
                 event_data.event.sender
             );
-            let mut param: Vec<u8> = event_data.event.sender.to_bytes().to_vec();
-            param.extend_from_slice(&event_data.event.amount.to_be_bytes());
+            let param = deposit_instruction_data(
+                &event_data.event.sender,
+                event_data.event.amount,
+                &event_data.signature,
+            );
             // Send deposite msg from L1 to L2;
             let _ = self.send_to_layer2(param);
         }
@@ -52,6 +70,42 @@ impl L1MsgOracle {
         Ok(())
     }
 
+    /// Queue the transactions forced through L1, the executor includes them ahead of
+    /// the mempool.
+    pub async fn listen_forced_transaction_event(&self) -> Result<()> {
+        let (tx, mut rx) = mpsc::unbounded_channel::<ForcedTransactionData>();
+        let listener_handle = tokio::spawn(async {
+            let _ = event_listen::create_forced_transaction_listener(
+                config().l1_rpc.clone(),
+                config().l1_ws.clone(),
+                CommitmentConfig::processed(),
+                tx,
+            )
+            .await;
+        });
+        while let Some(event_data) = rx.recv().await {
+            let event = event_data.event;
+            tracing::info!(
+                "Received forced transaction from {}, deadline {}",
+                event.sender,
+                event.deadline
+            );
+            // The zkVM program skips the same bytes, a batch is done with them without
+            // executing them
+            let Some(transaction) = forced_transaction(&event.transaction) else {
+                tracing::warn!("Skipped invalid forced transaction {}", event.index);
+                continue;
+            };
+            FORCED_TXNS.write().await.push_back(ForcedTransaction {
+                index: event.index,
+                transaction,
+                deadline: event.deadline,
+            });
+        }
+        listener_handle.await?;
+        Ok(())
+    }
+
     fn send_to_layer2(&self, param: Vec<u8>) -> Result<()> {
         // create sys ins.
         let instruction = Instruction {
@@ -73,7 +127,7 @@ impl L1MsgOracle {
 
         // send txn.
         let signature = self.client.send_and_confirm_transaction(&transaction)?;
-        log::info!("Initialize transaction signature: {}", signature);
+        tracing::info!("Initialize transaction signature: {}", signature);
         Ok(())
     }
 }
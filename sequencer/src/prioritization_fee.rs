@@ -0,0 +1,127 @@
+use share::transaction::{compute_unit_price, Block, BlockDB};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Blocks `getRecentPrioritizationFees` reports, like Solana's 150.
+pub const MAX_RECENT_PRIORITIZATION_FEES: u64 = 150;
+
+/// Prioritization fee of `block` in micro-lamports per compute unit: the lowest compute
+/// unit price of its transactions, what it took to land in it. 0 for an empty block or
+/// when one of them sets no price.
+pub fn block_prioritization_fee(block: &Block) -> u64 {
+    block.txns.iter().map(compute_unit_price).min().unwrap_or(0)
+}
+
+/// Prioritization fees of the latest blocks, for wallets estimating a priority fee.
+pub struct RecentPrioritizationFees {
+    // (block_num, prioritization fee), oldest first
+    fees: VecDeque<(u64, u64)>,
+}
+
+impl RecentPrioritizationFees {
+    pub fn new() -> Self {
+        Self {
+            fees: VecDeque::new(),
+        }
+    }
+
+    /// The window of the blocks up to `latest_block_num` in `block_db`.
+    pub fn load(block_db: &BlockDB, latest_block_num: u64) -> Self {
+        let mut recent = Self::new();
+        let start = latest_block_num.saturating_sub(MAX_RECENT_PRIORITIZATION_FEES - 1);
+        for block_num in start.max(1)..=latest_block_num {
+            if let Some(block) = block_db.get_block(block_num) {
+                recent.push(block_num, block_prioritization_fee(&block));
+            }
+        }
+        recent
+    }
+
+    /// Record the prioritization fee of block `block_num`, dropping the oldest beyond
+    /// the window.
+    pub fn push(&mut self, block_num: u64, fee: u64) {
+        self.fees.push_back((block_num, fee));
+        while self.fees.len() as u64 > MAX_RECENT_PRIORITIZATION_FEES {
+            self.fees.pop_front();
+        }
+    }
+
+    /// `(block_num, prioritization fee)` of the blocks in the window, oldest first.
+    pub fn fees(&self) -> Vec<(u64, u64)> {
+        self.fees.iter().copied().collect()
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref RECENT_PRIORITIZATION_FEES: Arc<RwLock<RecentPrioritizationFees>> = Arc::new(RwLock::new(RecentPrioritizationFees::new()));
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        block_prioritization_fee, RecentPrioritizationFees, MAX_RECENT_PRIORITIZATION_FEES,
+    };
+    use share::transaction::{Block, BlockDB};
+    use solana_sdk::{
+        compute_budget::ComputeBudgetInstruction, hash::Hash, pubkey::Pubkey, signature::Keypair,
+        signer::Signer, system_instruction, transaction::Transaction,
+    };
+
+    fn transfer_with_price(payer: &Keypair, price: Option<u64>) -> Transaction {
+        let mut instructions: Vec<_> = price
+            .map(ComputeBudgetInstruction::set_compute_unit_price)
+            .into_iter()
+            .collect();
+        instructions.push(system_instruction::transfer(
+            &payer.pubkey(),
+            &Pubkey::new_unique(),
+            100,
+        ));
+        Transaction::new_signed_with_payer(
+            &instructions,
+            Some(&payer.pubkey()),
+            &[payer],
+            Hash::default(),
+        )
+    }
+
+    #[test]
+    fn test_block_prioritization_fee() {
+        let payer = Keypair::new();
+        let block = Block::new(vec![
+            transfer_with_price(&payer, Some(3_000)),
+            transfer_with_price(&payer, Some(1_000)),
+        ]);
+        assert_eq!(block_prioritization_fee(&block), 1_000);
+
+        // A transaction without a price lands for free
+        let block = Block::new(vec![
+            transfer_with_price(&payer, Some(3_000)),
+            transfer_with_price(&payer, None),
+        ]);
+        assert_eq!(block_prioritization_fee(&block), 0);
+        assert_eq!(block_prioritization_fee(&Block::new(vec![])), 0);
+    }
+
+    #[test]
+    fn test_recent_prioritization_fees() {
+        let payer = Keypair::new();
+        let mut block_db = BlockDB::in_memory();
+        for block_num in 1..=3 {
+            let mut block = Block::new(vec![transfer_with_price(&payer, Some(block_num * 10))]);
+            block.block_num = block_num;
+            block_db.save_block(&block).unwrap();
+        }
+        let recent = RecentPrioritizationFees::load(&block_db, 3);
+        assert_eq!(recent.fees(), vec![(1, 10), (2, 20), (3, 30)]);
+
+        let mut recent = RecentPrioritizationFees::new();
+        for block_num in 1..=200 {
+            recent.push(block_num, 0);
+        }
+        let fees = recent.fees();
+        assert_eq!(fees.len() as u64, MAX_RECENT_PRIORITIZATION_FEES);
+        assert_eq!(fees[0].0, 51);
+    }
+}
@@ -1,19 +1,70 @@
-use axum::{http::StatusCode, response::Json, routing::post, Router};
+use axum::{
+    extract::Request,
+    http::StatusCode,
+    middleware::{self, Next},
+    response::{Json, Response},
+    routing::{get, post},
+    Extension, Router,
+};
 use base64::{self, engine::general_purpose, Engine};
-use log::{error, info, warn};
+use bincode::Options;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use share::utils::read_env_var;
-use solana_sdk::{bs58, transaction::Transaction};
-use solana_transaction_status::{Encodable, UiTransactionEncoding};
+use share::{
+    lamports::Lamports,
+    liveness::unix_now,
+    state::{AccountData, State, StateDB},
+    transaction::{compute_units, signature_fee, transaction_memo, Block, BlockDB},
+};
+use solana_sdk::{
+    bs58, commitment_config::CommitmentLevel, hash::Hash, message::VersionedMessage,
+    packet::PACKET_DATA_SIZE, pubkey::Pubkey, system_transaction, transaction::Transaction,
+};
+use solana_transaction_status::{Encodable, TransactionDetails, UiTransactionEncoding};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 use tower_http::cors::CorsLayer;
+use tracing::{error, info, instrument, warn, Span};
 
 use crate::{
-    executor::{MAX_MEMPOOL_SIZE, MEMPOOL, STATE},
-    node::BLOCK_DB,
-    validator::TransactionValidator,
+    batcher::tx_batcher::CommitMarker,
+    blockhash::RECENT_BLOCKHASHES,
+    config::{config, Config},
+    executor::{genesis_state, MEMPOOL, STATE},
+    faucet::{AirdropError, AirdropLedger, AirdropLimits, AIRDROP_LEDGER},
+    finality, metrics,
+    node::{BATCHER, BLOCK_DB},
+    prioritization_fee::RECENT_PRIORITIZATION_FEES,
+    validator::{TransactionValidator, ValidationError},
+    withdrawal::WITHDRAWAL_PROOF_CACHE,
 };
 
+// Max number of blocks returned by a single getBatchBlocks call
+static MAX_BATCH_BLOCKS_PER_REQUEST: u64 = 4096;
+
+// Max number of blocks and JSON bytes returned by a single getBlockRange call, the
+// rest of the range is left to the next page
+static MAX_BLOCK_RANGE_PER_REQUEST: u64 = 500;
+static MAX_BLOCK_RANGE_RESPONSE_BYTES: usize = 8 * 1024 * 1024;
+
+// Max number of accounts requested by a single getMultipleAccounts call, as Solana
+static MAX_MULTIPLE_ACCOUNTS_PER_REQUEST: usize = 100;
+
+// Max number of accounts and withdrawals returned by a single getStateSnapshot call
+static MAX_SNAPSHOT_ENTRIES_PER_REQUEST: usize = 1000;
+
+// Version reported to Solana tooling
+static SOLANA_CORE_VERSION: &str = "1.18.0";
+static FEATURE_SET: u32 = 2891131721;
+
+lazy_static::lazy_static! {
+    // The key the batcher commits batches with
+    static ref SEQUENCER_IDENTITY: Pubkey = config()
+        .l2_state_client()
+        .map(|client| client.payer())
+        .unwrap_or_default();
+}
+
 // JSON-RPC request structure
 #[derive(Debug, Deserialize)]
 pub struct JsonRpcRequest {
@@ -37,14 +88,33 @@ pub struct JsonRpcResponse {
 pub struct JsonRpcError {
     pub code: i32,
     pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
 }
 
+// Error codes, the same the Solana rpc returns
+pub const METHOD_NOT_FOUND: i32 = -32601;
+pub const INVALID_PARAMS: i32 = -32602;
+pub const INTERNAL_ERROR: i32 = -32603;
+pub const SEND_TRANSACTION_PREFLIGHT_FAILURE: i32 = -32002;
+pub const TRANSACTION_SIGNATURE_VERIFICATION_FAILURE: i32 = -32003;
+pub const BLOCK_NOT_AVAILABLE: i32 = -32004;
+pub const NODE_UNHEALTHY: i32 = -32005;
+// Not a Solana error code, a wrong or missing admin token
+pub const UNAUTHORIZED: i32 = -32090;
+// Not a Solana error code, an airdrop above the faucet's caps or within its cooldown
+pub const AIRDROP_LIMIT_EXCEEDED: i32 = -32091;
+
 pub async fn start() {
     // Step1. create router
     let app = Router::new()
-        .route("/", post(handle_rpc_request))
-        .layer(CorsLayer::permissive());
-    let addr = read_env_var("SEQUENCER_ADDR", "0.0.0.0:8898".to_owned());
+        .route(
+            "/",
+            post(handle_rpc_request).layer(middleware::from_fn(log_rpc_call)),
+        )
+        .route("/metrics", get(get_metrics))
+        .layer(cors_layer(config()).unwrap());
+    let addr = config().rpc_addr.clone();
     info!("Starting node rpc server on {:?}", addr);
 
     // Step2. start server
@@ -52,18 +122,81 @@ pub async fn start() {
     axum::serve(listener, app).await.unwrap();
 }
 
+// Any origin in dev mode, else only the configured origins, methods and headers
+fn cors_layer(config: &Config) -> anyhow::Result<CorsLayer> {
+    if config.dev_mode {
+        return Ok(CorsLayer::permissive());
+    }
+    Ok(CorsLayer::new()
+        .allow_origin(config.cors_origins()?)
+        .allow_methods(config.cors_methods()?)
+        .allow_headers(config.cors_headers()?))
+}
+
+// Prometheus metrics endpoint
+async fn get_metrics() -> String {
+    metrics::render()
+}
+
+/// Method and outcome of an rpc call, attached to its response for `log_rpc_call`.
+#[derive(Clone, Debug)]
+struct RpcCall {
+    // `unknown` for a method that isn't served, keeping the metric labels bounded
+    method: String,
+    error: Option<i32>,
+}
+
+// Log the method, duration and outcome of every rpc call and record its latency
+async fn log_rpc_call(request: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let response = next.run(request).await;
+    let elapsed = start.elapsed();
+    // A body that isn't a JSON-RPC request never reaches the handler
+    if let Some(call) = response.extensions().get::<RpcCall>() {
+        metrics::RPC_LATENCY.observe(&call.method, elapsed);
+        log_rpc_outcome(call, elapsed, config().slow_rpc());
+    }
+    response
+}
+
+fn log_rpc_outcome(call: &RpcCall, elapsed: Duration, slow_rpc: Duration) {
+    let duration_ms = elapsed.as_millis() as u64;
+    if elapsed > slow_rpc {
+        warn!(
+            method = %call.method,
+            duration_ms,
+            error = ?call.error,
+            "Slow rpc request"
+        );
+    } else if let Some(code) = call.error {
+        info!(method = %call.method, duration_ms, error = code, "Rpc request failed");
+    } else {
+        info!(method = %call.method, duration_ms, "Rpc request served");
+    }
+}
+
 // Main RPC handler
+#[instrument(
+    name = "rpc",
+    skip_all,
+    fields(method = %request.method, id = %request.id, signature = tracing::field::Empty)
+)]
 async fn handle_rpc_request(
     Json(request): Json<JsonRpcRequest>,
-) -> Result<Json<JsonRpcResponse>, StatusCode> {
+) -> Result<(Extension<RpcCall>, Json<JsonRpcResponse>), StatusCode> {
     info!("Received rpc request of method: {:?}", request.method);
+    let method = request.method.clone();
     let response = match request.method.as_str() {
         "getHealth" => get_health(request.id).await,
         "getVersion" => get_version(request.id).await,
+        "getIdentity" => get_identity(request.id).await,
+        "getClusterNodes" => get_cluster_nodes(request.id).await,
         "getAccountInfo" => get_account_info(request.id, request.params).await,
         "getBalance" => get_balance(request.id, request.params).await,
         "getLatestBlockhash" => get_latest_blockhash(request.id).await,
+        "isBlockhashValid" => is_blockhash_valid(request.id, request.params).await,
         "getFeeForMessage" => get_fee_for_message(request.id, request.params).await,
+        "getRecentPrioritizationFees" => get_recent_prioritization_fees(request.id).await,
         "sendTransaction" => send_transaction(request.id, request.params).await,
         "simulateTransaction" => simulate_transaction(request.id, request.params).await,
         "getTransaction" => get_transaction(request.id, request.params).await,
@@ -71,26 +204,90 @@ async fn handle_rpc_request(
         "confirmTransaction" => confirm_transaction(request.id, request.params).await,
         "getTokenAccountsByOwner" => get_token_accounts_by_owner(request.id, request.params).await,
         "getMultipleAccounts" => get_multiple_accounts(request.id, request.params).await,
+        "getBatchBlocks" => get_batch_blocks(request.id, request.params).await,
+        "getPendingBlocks" => get_pending_blocks(request.id).await,
+        "forceBatchSubmit" if config().admin_token.is_some() => {
+            force_batch_submit(request.id, request.params).await
+        }
+        "exportStateSnapshot" if config().admin_token.is_some() => {
+            export_state_snapshot(request.id, request.params).await
+        }
+        "importStateSnapshot" if config().admin_token.is_some() => {
+            import_state_snapshot(request.id, request.params).await
+        }
+        "requestAirdrop" if config().faucet_keypair_path.is_some() => {
+            request_airdrop(request.id, request.params).await
+        }
+        "getBlockRange" => get_block_range(request.id, request.params).await,
+        "getWithdrawalProof" => get_withdrawal_proof(request.id, request.params).await,
+        "getDepositStatus" => get_deposit_status(request.id, request.params).await,
+        "getFinalizedBlock" => get_finalized_block(request.id).await,
+        "getStateSnapshot" if config().enable_debug_rpc => {
+            get_state_snapshot(request.id, request.params).await
+        }
         _ => JsonRpcResponse {
             jsonrpc: request.jsonrpc,
             id: request.id,
             result: None,
             error: Some(JsonRpcError {
-                code: -32601,
+                code: METHOD_NOT_FOUND,
                 message: "Method not found".to_string(),
+                data: None,
             }),
         },
     };
-    Ok(Json(response))
+    let error = response.error.as_ref().map(|error| error.code);
+    let call = RpcCall {
+        method: if error == Some(METHOD_NOT_FOUND) {
+            "unknown".to_string()
+        } else {
+            method
+        },
+        error,
+    };
+    Ok((Extension(call), Json(response)))
 }
 
 // Health check endpoint
 async fn get_health(id: Value) -> JsonRpcResponse {
+    let now = unix_now();
+    let stalled: Vec<_> = metrics::liveness()
+        .into_iter()
+        .filter(|(liveness, threshold)| liveness.is_stalled(now, *threshold))
+        .map(|(liveness, _)| liveness)
+        .collect();
+    if stalled.is_empty() {
+        return JsonRpcResponse {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result: Some(serde_json::json!("ok")),
+            error: None,
+        };
+    }
+
+    let names: Vec<_> = stalled.iter().map(|liveness| liveness.name()).collect();
+    let last_success: serde_json::Map<String, Value> = stalled
+        .iter()
+        .map(|liveness| {
+            (
+                liveness.name().to_string(),
+                serde_json::json!(liveness.last_success()),
+            )
+        })
+        .collect();
+    warn!("Node is unhealthy, stalled: {:?}", names);
     JsonRpcResponse {
         jsonrpc: "2.0".to_string(),
         id,
-        result: Some(serde_json::json!("ok")),
-        error: None,
+        result: None,
+        error: Some(JsonRpcError {
+            code: NODE_UNHEALTHY,
+            message: format!("Node is unhealthy: {} stalled", names.join(", ")),
+            data: Some(serde_json::json!({
+                "stalled": names,
+                "lastSuccess": last_success
+            })),
+        }),
     }
 }
 
@@ -100,83 +297,257 @@ async fn get_version(id: Value) -> JsonRpcResponse {
         jsonrpc: "2.0".to_string(),
         id,
         result: Some(serde_json::json!({
-            "solana-core": "1.18.0",
-            "feature-set": 2891131721u32
+            "solana-core": SOLANA_CORE_VERSION,
+            "feature-set": FEATURE_SET
+        })),
+        error: None,
+    }
+}
+
+// Identity of the sequencer
+async fn get_identity(id: Value) -> JsonRpcResponse {
+    JsonRpcResponse {
+        jsonrpc: "2.0".to_string(),
+        id,
+        result: Some(serde_json::json!({
+            "identity": SEQUENCER_IDENTITY.to_string()
         })),
         error: None,
     }
 }
 
+// The cluster is this sequencer alone, it has no gossip nor tpu
+async fn get_cluster_nodes(id: Value) -> JsonRpcResponse {
+    JsonRpcResponse {
+        jsonrpc: "2.0".to_string(),
+        id,
+        result: Some(serde_json::json!([{
+            "pubkey": SEQUENCER_IDENTITY.to_string(),
+            "gossip": null,
+            "tpu": null,
+            "rpc": config().rpc_addr,
+            "pubsub": config().ws_addr,
+            "version": SOLANA_CORE_VERSION,
+            "featureSet": FEATURE_SET,
+            "shredVersion": 0
+        }])),
+        error: None,
+    }
+}
+
 // Get account information
 async fn get_account_info(id: Value, params: Option<Value>) -> JsonRpcResponse {
-    let pubkey = match params
-        .as_ref()
-        .and_then(|p| p.as_array())
-        .and_then(|arr| arr.get(0))
-        .and_then(|v| v.as_str())
-    {
-        Some(key) => key.to_string(),
-        None => {
-            return JsonRpcResponse {
-                jsonrpc: "2.0".to_string(),
-                id,
-                result: None,
-                error: Some(JsonRpcError {
-                    code: -32602,
-                    message: "Invalid params".to_string(),
-                }),
-            };
-        }
+    let pubkey = match param_str(&params, 0) {
+        Ok(pubkey) => pubkey.to_string(),
+        Err(error) => return error_response(id, error),
     };
 
-    // Get balance from state
-    let state_db = STATE.read().await;
-    let balance = state_db.state.get_balance(&pubkey);
+    let commitment = match commitment_param(&params, 1) {
+        Ok(commitment) => commitment,
+        Err(message) => return error_response(id, invalid_params(message)),
+    };
+
+    // Get account from state
+    let encoding = encoding_param(&params, 1);
+    let read_account = |state: &State| account_value(state, &pubkey, encoding);
+    let (slot, account) = match read_with_commitment(commitment, read_account).await {
+        Ok(read) => read,
+        Err(e) => return finalized_state_error(id, e),
+    };
 
     JsonRpcResponse {
         jsonrpc: "2.0".to_string(),
         id,
         result: Some(serde_json::json!({
             "context": {
-                "slot": 23816
+                "slot": slot
             },
-            "value": {
-                "data": ["", "base64"],
-                "executable": false,
-                "lamports": balance,
-                "owner": "11111111111111111111111111111111",
-                "rentEpoch": 361
-            }
+            "value": account
         })),
         error: None,
     }
 }
 
-// Get balance
-async fn get_balance(id: Value, params: Option<Value>) -> JsonRpcResponse {
-    let pubkey = match params
+// Response of a failed request
+fn error_response(id: Value, error: JsonRpcError) -> JsonRpcResponse {
+    JsonRpcResponse {
+        jsonrpc: "2.0".to_string(),
+        id,
+        result: None,
+        error: Some(error),
+    }
+}
+
+fn invalid_params(message: impl Into<String>) -> JsonRpcError {
+    JsonRpcError {
+        code: INVALID_PARAMS,
+        message: message.into(),
+        data: None,
+    }
+}
+
+// Param `index` of the positional `params`, `None` when it's missing or null
+fn param(params: &Option<Value>, index: usize) -> Option<&Value> {
+    params
         .as_ref()
         .and_then(|p| p.as_array())
-        .and_then(|arr| arr.get(0))
+        .and_then(|arr| arr.get(index))
+        .filter(|v| !v.is_null())
+}
+
+// The error of a param missing or of another type than `expected`
+fn expected_param(index: usize, expected: &str) -> JsonRpcError {
+    invalid_params(format!(
+        "Invalid params: expected {} at index {}",
+        expected, index
+    ))
+}
+
+fn param_str(params: &Option<Value>, index: usize) -> Result<&str, JsonRpcError> {
+    param(params, index)
         .and_then(|v| v.as_str())
-    {
-        Some(key) => key.to_string(),
-        None => {
-            return JsonRpcResponse {
-                jsonrpc: "2.0".to_string(),
-                id,
-                result: None,
-                error: Some(JsonRpcError {
-                    code: -32602,
-                    message: "Invalid params".to_string(),
-                }),
-            };
+        .ok_or_else(|| expected_param(index, "a string"))
+}
+
+fn param_u64(params: &Option<Value>, index: usize) -> Result<u64, JsonRpcError> {
+    param(params, index)
+        .and_then(|v| v.as_u64())
+        .ok_or_else(|| expected_param(index, "an unsigned integer"))
+}
+
+// An optional u64 param, `None` when it's missing or null
+fn param_opt_u64(params: &Option<Value>, index: usize) -> Result<Option<u64>, JsonRpcError> {
+    param(params, index)
+        .map(|v| {
+            v.as_u64()
+                .ok_or_else(|| expected_param(index, "an unsigned integer"))
+        })
+        .transpose()
+}
+
+fn param_array(params: &Option<Value>, index: usize) -> Result<&Vec<Value>, JsonRpcError> {
+    param(params, index)
+        .and_then(|v| v.as_array())
+        .ok_or_else(|| expected_param(index, "an array"))
+}
+
+// Commitment of the config object at `params[index]`. Processed and confirmed read the
+// latest state, as does a request without one, finalized the state proven on L1.
+fn commitment_param(params: &Option<Value>, index: usize) -> Result<CommitmentLevel, String> {
+    match param(params, index).and_then(|config| config.get("commitment")) {
+        None => Ok(CommitmentLevel::Confirmed),
+        Some(commitment) => commitment
+            .as_str()
+            .and_then(|commitment| CommitmentLevel::from_str(commitment).ok())
+            .ok_or_else(|| format!("Invalid commitment: {}", commitment)),
+    }
+}
+
+// Read the state at `commitment` with `f`, along with the slot it's at
+async fn read_with_commitment<T>(
+    commitment: CommitmentLevel,
+    f: impl FnOnce(&State) -> T,
+) -> anyhow::Result<(u64, T)> {
+    match commitment {
+        CommitmentLevel::Finalized => finality::read_finalized_state(f).await,
+        _ => {
+            let state_db = STATE.read().await;
+            let slot = RECENT_BLOCKHASHES
+                .read()
+                .await
+                .latest()
+                .map(|(block_num, _)| block_num)
+                .unwrap_or_default();
+            Ok((slot, f(&state_db.state)))
+        }
+    }
+}
+
+fn finalized_state_error(id: Value, e: anyhow::Error) -> JsonRpcResponse {
+    error!("Failed to read the finalized state: {:?}", e);
+    JsonRpcResponse {
+        jsonrpc: "2.0".to_string(),
+        id,
+        result: None,
+        error: Some(JsonRpcError {
+            code: INTERNAL_ERROR,
+            message: format!("Failed to read the finalized state: {}", e),
+            data: None,
+        }),
+    }
+}
+
+// Encoding of the account data, as requested by the config object's `encoding`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum AccountEncoding {
+    Base64,
+    Base64Zstd,
+}
+
+impl AccountEncoding {
+    // Encoded data and the name advertised next to it
+    fn encode(self, data: &[u8]) -> (String, &'static str) {
+        match self {
+            AccountEncoding::Base64 => (general_purpose::STANDARD.encode(data), "base64"),
+            AccountEncoding::Base64Zstd => match zstd::encode_all(data, 0) {
+                Ok(compressed) => (general_purpose::STANDARD.encode(compressed), "base64+zstd"),
+                Err(_) => (general_purpose::STANDARD.encode(data), "base64"),
+            },
         }
+    }
+}
+
+// Encoding of the config object at `params[index]`, base64 unless the client asks
+// for zstd, the other encodings (base58, jsonParsed) aren't supported
+fn encoding_param(params: &Option<Value>, index: usize) -> AccountEncoding {
+    match param(params, index)
+        .and_then(|config| config.get("encoding"))
+        .and_then(|encoding| encoding.as_str())
+    {
+        Some("base64+zstd") => AccountEncoding::Base64Zstd,
+        _ => AccountEncoding::Base64,
+    }
+}
+
+// Account info of `pubkey`, `null` like Solana when it doesn't exist: it was never
+// credited, or was swept once left with neither lamports nor data
+fn account_value(state: &State, pubkey: &str, encoding: AccountEncoding) -> Value {
+    state
+        .get_account(pubkey)
+        .map_or(Value::Null, |account| account_info_json(account, encoding))
+}
+
+// Account info in the solana rpc layout, data encoded with `encoding`
+fn account_info_json(account: &AccountData, encoding: AccountEncoding) -> Value {
+    let (data, encoding) = encoding.encode(&account.data);
+    serde_json::json!({
+        "data": [data, encoding],
+        "executable": account.executable,
+        "lamports": account.lamports,
+        "owner": account.owner,
+        "rentEpoch": 361,
+        "space": account.data.len()
+    })
+}
+
+// Get balance
+async fn get_balance(id: Value, params: Option<Value>) -> JsonRpcResponse {
+    let pubkey = match param_str(&params, 0) {
+        Ok(pubkey) => pubkey.to_string(),
+        Err(error) => return error_response(id, error),
+    };
+
+    let commitment = match commitment_param(&params, 1) {
+        Ok(commitment) => commitment,
+        Err(message) => return error_response(id, invalid_params(message)),
     };
 
     // Get balance from state
-    let state_db = STATE.read().await;
-    let balance = state_db.state.get_balance(&pubkey);
+    let (slot, balance) =
+        match read_with_commitment(commitment, |state| state.get_balance(&pubkey)).await {
+            Ok(read) => read,
+            Err(e) => return finalized_state_error(id, e),
+        };
 
     JsonRpcResponse {
         jsonrpc: "2.0".to_string(),
@@ -184,7 +555,7 @@ async fn get_balance(id: Value, params: Option<Value>) -> JsonRpcResponse {
         result: Some(serde_json::json!({
             "context": {
                 "apiVersion": "2.2.21",
-                "slot": 23816
+                "slot": slot
             },
             "value": balance
         })),
@@ -194,13 +565,9 @@ async fn get_balance(id: Value, params: Option<Value>) -> JsonRpcResponse {
 
 // Get latest blockhash
 async fn get_latest_blockhash(id: Value) -> JsonRpcResponse {
-    let block_db = BLOCK_DB.read().await;
-    // Use post_state_root
-    let post_state_root = match block_db.cache.back() {
-        Some(block) => block.post_state_root.unwrap_or_default(),
-        None => [0u8; 32],
-    };
-    let simple_blockhash = bs58::encode(post_state_root).into_string();
+    // The latest post_state_root, valid for MAX_RECENT_BLOCKHASHES blocks
+    let recent_blockhashes = RECENT_BLOCKHASHES.read().await;
+    let (block_num, blockhash) = recent_blockhashes.latest().unwrap_or_default();
 
     JsonRpcResponse {
         jsonrpc: "2.0".to_string(),
@@ -208,38 +575,75 @@ async fn get_latest_blockhash(id: Value) -> JsonRpcResponse {
         result: Some(serde_json::json!({
             "context": {
                 "apiVersion": "2.2.21",
-                "slot": 32001
+                "slot": block_num
             },
             "value": {
-                "blockhash": simple_blockhash,
-                "lastValidBlockHeight": 33001
+                "blockhash": bs58::encode(blockhash).into_string(),
+                "lastValidBlockHeight": recent_blockhashes.last_valid_block_height()
             }
         })),
         error: None,
     }
 }
 
+// Whether a recent blockhash is still in the window of `getLatestBlockhash`
+async fn is_blockhash_valid(id: Value, params: Option<Value>) -> JsonRpcResponse {
+    let blockhash = match param_str(&params, 0) {
+        Ok(blockhash) => blockhash,
+        Err(error) => return error_response(id, error),
+    };
+    let blockhash = bs58::decode(blockhash)
+        .into_vec()
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok());
+    let Some(blockhash) = blockhash else {
+        return error_response(id, invalid_params("Invalid params: not a base58 blockhash"));
+    };
+
+    let recent_blockhashes = RECENT_BLOCKHASHES.read().await;
+    let (block_num, _) = recent_blockhashes.latest().unwrap_or_default();
+    JsonRpcResponse {
+        jsonrpc: "2.0".to_string(),
+        id,
+        result: Some(serde_json::json!({
+            "context": { "slot": block_num },
+            "value": recent_blockhashes.is_valid(&blockhash)
+        })),
+        error: None,
+    }
+}
+
+// Prioritization fees of the recent blocks, `[{slot, prioritizationFee}]` oldest first,
+// each the lowest compute unit price in the block (0 when a transaction set none). The
+// optional account filter of Solana isn't applied, the fees are over all transactions.
+async fn get_recent_prioritization_fees(id: Value) -> JsonRpcResponse {
+    let fees: Vec<Value> = RECENT_PRIORITIZATION_FEES
+        .read()
+        .await
+        .fees()
+        .into_iter()
+        .map(|(block_num, fee)| {
+            serde_json::json!({
+                "slot": block_num,
+                "prioritizationFee": fee
+            })
+        })
+        .collect();
+
+    JsonRpcResponse {
+        jsonrpc: "2.0".to_string(),
+        id,
+        result: Some(serde_json::json!(fees)),
+        error: None,
+    }
+}
+
 // Get Transaction
 async fn get_transaction(id: Value, params: Option<Value>) -> JsonRpcResponse {
     // Extract transaction signature from params
-    let signature: String = match params
-        .as_ref()
-        .and_then(|p| p.as_array())
-        .and_then(|arr| arr.get(0))
-        .and_then(|v| v.as_str())
-    {
-        Some(sig) => sig.to_string(),
-        None => {
-            return JsonRpcResponse {
-                jsonrpc: "2.0".to_string(),
-                id,
-                result: None,
-                error: Some(JsonRpcError {
-                    code: -32602,
-                    message: "Invalid params: transaction signature required".to_string(),
-                }),
-            };
-        }
+    let signature = match param_str(&params, 0) {
+        Ok(signature) => signature.to_string(),
+        Err(error) => return error_response(id, error),
     };
 
     let block_db = BLOCK_DB.read().await;
@@ -258,8 +662,12 @@ async fn get_transaction(id: Value, params: Option<Value>) -> JsonRpcResponse {
                 result: Some(serde_json::json!({
                   "blockTime": 1679123456,
                   "meta": {
+                    "computeUnitsConsumed": compute_units(&txn),
                     "err": null,
-                    "fee": 5000,
+                    "fee": signature_fee(
+                        txn.message.header.num_required_signatures,
+                        config().lamports_per_signature
+                    ),
                     "innerInstructions": [],
                     "logMessages": [
                       "Program 11111111111111111111111111111111 invoke [1]",
@@ -280,6 +688,7 @@ async fn get_transaction(id: Value, params: Option<Value>) -> JsonRpcResponse {
                       "Ok": null
                     }
                   },
+                  "memo": transaction_memo(&txn),
                   "slot": 123456789,
                   "transaction": formatted_transaction,
                   "version": "legacy"
@@ -296,34 +705,45 @@ async fn get_transaction(id: Value, params: Option<Value>) -> JsonRpcResponse {
     }
 }
 
+// Longest encodings of a `PACKET_DATA_SIZE` transaction, anything longer is rejected
+// before it's decoded
+const MAX_BASE58_TRANSACTION_LEN: usize = 1683;
+const MAX_BASE64_TRANSACTION_LEN: usize = 1644;
+
 // Parse and decode transaction from RPC parameters
 fn parse_and_decode_transaction(
     id: &Value,
     params: Option<Value>,
-) -> Result<Transaction, JsonRpcResponse> {
-    let (transaction_str, encoding) = match params.as_ref().and_then(|p| p.as_array()) {
-        Some(arr) => {
-            let tx_str = arr.get(0).and_then(|v| v.as_str()).unwrap_or("");
-            let encoding = arr
-                .get(1)
-                .and_then(|v| v.as_object())
-                .and_then(|obj| obj.get("encoding"))
-                .and_then(|v| v.as_str())
-                .unwrap_or("base64");
-            (tx_str.to_string(), encoding)
-        }
-        None => {
-            return Err(JsonRpcResponse {
-                jsonrpc: "2.0".to_string(),
-                id: id.clone(),
-                result: None,
-                error: Some(JsonRpcError {
-                    code: -32602,
-                    message: "Invalid params".to_string(),
-                }),
-            });
-        }
+) -> Result<Transaction, Box<JsonRpcResponse>> {
+    let transaction_str = param_str(&params, 0)
+        .map_err(|error| Box::new(error_response(id.clone(), error)))?
+        .to_string();
+    let encoding = param(&params, 1)
+        .and_then(|config| config.get("encoding"))
+        .and_then(|v| v.as_str())
+        .unwrap_or("base64");
+
+    let max_len = match encoding {
+        "base58" => MAX_BASE58_TRANSACTION_LEN,
+        _ => MAX_BASE64_TRANSACTION_LEN,
     };
+    if transaction_str.is_empty() || transaction_str.len() > max_len {
+        return Err(Box::new(JsonRpcResponse {
+            jsonrpc: "2.0".to_string(),
+            id: id.clone(),
+            result: None,
+            error: Some(JsonRpcError {
+                code: INVALID_PARAMS,
+                message: format!(
+                    "Invalid {} transaction: {} bytes encoded, expected 1 to {}",
+                    encoding,
+                    transaction_str.len(),
+                    max_len
+                ),
+                data: None,
+            }),
+        }));
+    }
 
     // Decode transaction
     let transaction_bytes = match encoding {
@@ -331,67 +751,117 @@ fn parse_and_decode_transaction(
             Ok(bytes) => bytes,
             Err(e) => {
                 error!("Failed to decode base64 transaction: {}", e);
-                return Err(JsonRpcResponse {
+                return Err(Box::new(JsonRpcResponse {
                     jsonrpc: "2.0".to_string(),
                     id: id.clone(),
                     result: None,
                     error: Some(JsonRpcError {
-                        code: -32602,
+                        code: INVALID_PARAMS,
                         message: format!("Invalid base64 transaction: {}", e),
+                        data: None,
                     }),
-                });
+                }));
             }
         },
         "base58" => match bs58::decode(&transaction_str).into_vec() {
             Ok(bytes) => bytes,
             Err(e) => {
                 error!("Failed to decode base58 transaction: {}", e);
-                return Err(JsonRpcResponse {
+                return Err(Box::new(JsonRpcResponse {
                     jsonrpc: "2.0".to_string(),
                     id: id.clone(),
                     result: None,
                     error: Some(JsonRpcError {
-                        code: -32602,
+                        code: INVALID_PARAMS,
                         message: format!("Invalid base58 transaction: {}", e),
+                        data: None,
                     }),
-                });
+                }));
             }
         },
         _ => {
             error!("Unsupported encoding: {}", encoding);
-            return Err(JsonRpcResponse {
+            return Err(Box::new(JsonRpcResponse {
                 jsonrpc: "2.0".to_string(),
                 id: id.clone(),
                 result: None,
                 error: Some(JsonRpcError {
-                    code: -32602,
+                    code: INVALID_PARAMS,
                     message: format!("Unsupported encoding: {}", encoding),
+                    data: None,
                 }),
-            });
+            }));
         }
     };
 
-    let transaction: Transaction = match bincode::deserialize(&transaction_bytes) {
+    if transaction_bytes.len() > PACKET_DATA_SIZE {
+        return Err(Box::new(JsonRpcResponse {
+            jsonrpc: "2.0".to_string(),
+            id: id.clone(),
+            result: None,
+            error: Some(JsonRpcError {
+                code: INVALID_PARAMS,
+                message: format!(
+                    "Transaction too large: {} bytes, the limit is {}",
+                    transaction_bytes.len(),
+                    PACKET_DATA_SIZE
+                ),
+                data: None,
+            }),
+        }));
+    }
+
+    // Bounded as well, a length prefix can't make it allocate past the packet size
+    let transaction: Transaction = match bincode::options()
+        .with_limit(PACKET_DATA_SIZE as u64)
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .deserialize(&transaction_bytes)
+    {
         Ok(tx) => tx,
         Err(e) => {
             error!("Failed to deserialize transaction: {}", e);
-            return Err(JsonRpcResponse {
+            return Err(Box::new(JsonRpcResponse {
                 jsonrpc: "2.0".to_string(),
                 id: id.clone(),
                 result: None,
                 error: Some(JsonRpcError {
-                    code: -32602,
+                    code: INVALID_PARAMS,
                     message: format!("Invalid transaction format: {}", e),
+                    data: None,
                 }),
-            });
+            }));
         }
     };
 
     Ok(transaction)
 }
 
+// Fee of a base64 encoded message, `lamports_per_signature` per required signature.
+// `None` for anything that doesn't decode to a legacy or v0 message.
+fn message_fee(encoded_message: &str, lamports_per_signature: u64) -> Option<u64> {
+    let bytes = general_purpose::STANDARD.decode(encoded_message).ok()?;
+    let message: VersionedMessage = bincode::options()
+        .with_limit(PACKET_DATA_SIZE as u64)
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .deserialize(&bytes)
+        .ok()?;
+    Some(signature_fee(
+        message.header().num_required_signatures,
+        lamports_per_signature,
+    ))
+}
+
 // Get fee for message
-async fn get_fee_for_message(id: Value, _params: Option<Value>) -> JsonRpcResponse {
+async fn get_fee_for_message(id: Value, params: Option<Value>) -> JsonRpcResponse {
+    let message = match param_str(&params, 0) {
+        Ok(message) => message,
+        Err(error) => return error_response(id, error),
+    };
+    let Some(fee) = message_fee(message, config().lamports_per_signature) else {
+        return error_response(id, invalid_params("Invalid params: not a base64 message"));
+    };
     JsonRpcResponse {
         jsonrpc: "2.0".to_string(),
         id,
@@ -400,7 +870,7 @@ async fn get_fee_for_message(id: Value, _params: Option<Value>) -> JsonRpcRespon
                 "apiVersion": "2.2.21",
                 "slot": 23816
             },
-            "value": 5000
+            "value": fee
         })),
         error: None,
     }
@@ -411,43 +881,41 @@ async fn send_transaction(id: Value, params: Option<Value>) -> JsonRpcResponse {
     // Parse and decode transaction using the common function
     let transaction = match parse_and_decode_transaction(&id, params) {
         Ok(tx) => tx,
-        Err(error_response) => return error_response,
+        Err(error_response) => return *error_response,
     };
+    if let Some(signature) = transaction.signatures.first() {
+        Span::current().record("signature", signature.to_string().as_str());
+    }
 
     // Validate transaction
-    match TransactionValidator::validate_transaction(true, &transaction).await {
-        Ok(_) => {
-            info!("Transaction validation passed");
-        }
-        Err(e) => {
-            warn!("Transaction validation failed: {}", e);
-            return JsonRpcResponse {
-                jsonrpc: "2.0".to_string(),
-                id,
-                result: None,
-                error: Some(JsonRpcError {
-                    code: -32003,
-                    message: format!("Transaction validation failed: {}", e),
-                }),
-            };
-        }
+    if let Err(e) = TransactionValidator::validate_transaction(true, &transaction).await {
+        warn!("Transaction validation failed: {}", e);
+        return JsonRpcResponse {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result: None,
+            error: Some(validation_error(&e)),
+        };
     }
+    info!("Transaction validation passed");
 
     let signature = transaction.signatures[0].to_string();
     // Add transaction to mempool
     let mut mempool = MEMPOOL.write().await;
-    if mempool.len() > MAX_MEMPOOL_SIZE {
+    if mempool.len() > config().max_mempool_size {
         return JsonRpcResponse {
             jsonrpc: "2.0".to_string(),
             id,
-            result: Some(serde_json::json!(signature)),
+            result: None,
             error: Some(JsonRpcError {
-                code: -32602,
+                code: INTERNAL_ERROR,
                 message: "Mempool is full".to_string(),
+                data: None,
             }),
         };
     }
     mempool.push(transaction);
+    info!("Transaction added to mempool");
     return JsonRpcResponse {
         jsonrpc: "2.0".to_string(),
         id,
@@ -456,14 +924,59 @@ async fn send_transaction(id: Value, params: Option<Value>) -> JsonRpcResponse {
     };
 }
 
+// Map a validation failure to the error Solana returns for it, a signature failure
+// or a failed preflight carrying the simulation result in `data`.
+fn validation_error(e: &ValidationError) -> JsonRpcError {
+    match e {
+        ValidationError::Signature(_) => JsonRpcError {
+            code: TRANSACTION_SIGNATURE_VERIFICATION_FAILURE,
+            message: "Transaction signature verification failure".to_string(),
+            data: None,
+        },
+        ValidationError::Format(_) => JsonRpcError {
+            code: SEND_TRANSACTION_PREFLIGHT_FAILURE,
+            message: format!("Transaction simulation failed: {}", e),
+            data: Some(serde_json::json!({
+                "err": e.transaction_error(),
+                "logs": [],
+                "accounts": null,
+                "unitsConsumed": 0,
+                "returnData": null
+            })),
+        },
+    }
+}
+
 // Simulate transaction
 async fn simulate_transaction(id: Value, params: Option<Value>) -> JsonRpcResponse {
     // Parse and decode transaction using the common function
-    let _transaction = match parse_and_decode_transaction(&id, params) {
+    let transaction = match parse_and_decode_transaction(&id, params) {
         Ok(tx) => tx,
-        Err(error_response) => return error_response,
+        Err(error_response) => return *error_response,
     };
 
+    // Like Solana, a failed simulation is a successful response with `err` set
+    if let Err(e) = TransactionValidator::validate_transaction(false, &transaction).await {
+        return JsonRpcResponse {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result: Some(serde_json::json!({
+                "context": {
+                    "apiVersion": "2.2.21",
+                    "slot": 23816
+                },
+                "value": {
+                    "err": e.transaction_error(),
+                    "accounts": null,
+                    "logs": [format!("Transaction validation failed: {}", e)],
+                    "returnData": null,
+                    "unitsConsumed": 0
+                }
+            })),
+            error: None,
+        };
+    }
+
     return JsonRpcResponse {
         jsonrpc: "2.0".to_string(),
         id,
@@ -480,7 +993,7 @@ async fn simulate_transaction(id: Value, params: Option<Value>) -> JsonRpcRespon
                     "Program 11111111111111111111111111111111 success"
                 ],
                 "returnData": null,
-                "unitsConsumed": 150,
+                "unitsConsumed": compute_units(&transaction),
                 "innerInstructions": [],
                 "preBalances": [],
                 "postBalances": [],
@@ -494,24 +1007,9 @@ async fn simulate_transaction(id: Value, params: Option<Value>) -> JsonRpcRespon
 
 // Get signature statuses
 async fn get_signature_statuses(id: Value, params: Option<Value>) -> JsonRpcResponse {
-    let signatures = match params
-        .as_ref()
-        .and_then(|p| p.as_array())
-        .and_then(|arr| arr.get(0))
-        .and_then(|v| v.as_array())
-    {
-        Some(sigs) => sigs.clone(),
-        None => {
-            return JsonRpcResponse {
-                jsonrpc: "2.0".to_string(),
-                id,
-                result: None,
-                error: Some(JsonRpcError {
-                    code: -32602,
-                    message: "Invalid params".to_string(),
-                }),
-            };
-        }
+    let signatures = match param_array(&params, 0) {
+        Ok(signatures) => signatures,
+        Err(error) => return error_response(id, error),
     };
 
     let statuses: Vec<Value> = signatures
@@ -545,25 +1043,9 @@ async fn get_signature_statuses(id: Value, params: Option<Value>) -> JsonRpcResp
 
 // Confirm transaction
 async fn confirm_transaction(id: Value, params: Option<Value>) -> JsonRpcResponse {
-    let _signature = match params
-        .as_ref()
-        .and_then(|p| p.as_array())
-        .and_then(|arr| arr.get(0))
-        .and_then(|v| v.as_str())
-    {
-        Some(sig) => sig.to_string(),
-        None => {
-            return JsonRpcResponse {
-                jsonrpc: "2.0".to_string(),
-                id,
-                result: None,
-                error: Some(JsonRpcError {
-                    code: -32602,
-                    message: "Invalid params".to_string(),
-                }),
-            };
-        }
-    };
+    if let Err(error) = param_str(&params, 0) {
+        return error_response(id, error);
+    }
 
     JsonRpcResponse {
         jsonrpc: "2.0".to_string(),
@@ -601,68 +1083,1273 @@ async fn get_token_accounts_by_owner(id: Value, _params: Option<Value>) -> JsonR
 
 // Get multiple accounts
 async fn get_multiple_accounts(id: Value, params: Option<Value>) -> JsonRpcResponse {
-    let pubkeys = match params
-        .as_ref()
-        .and_then(|p| p.as_array())
-        .and_then(|arr| arr.get(0))
-        .and_then(|v| v.as_array())
+    let pubkeys = match param_array(&params, 0) {
+        Ok(pubkeys) => pubkeys,
+        Err(error) => return error_response(id, error),
+    };
+
+    if pubkeys.len() > MAX_MULTIPLE_ACCOUNTS_PER_REQUEST {
+        return error_response(
+            id,
+            invalid_params(format!(
+                "Too many accounts provided; max {}",
+                MAX_MULTIPLE_ACCOUNTS_PER_REQUEST
+            )),
+        );
+    }
+
+    // One read lock, the accounts are a consistent snapshot
+    let state_db = STATE.read().await;
+    let account_values = multiple_accounts(&state_db.state, pubkeys, encoding_param(&params, 1));
+
+    JsonRpcResponse {
+        jsonrpc: "2.0".to_string(),
+        id,
+        result: Some(serde_json::json!({
+            "context": {
+                "apiVersion": "2.2.21",
+                "slot": 23816
+            },
+            "value": account_values
+        })),
+        error: None,
+    }
+}
+
+// Accounts of `pubkeys` in the solana rpc layout, null for unknown accounts and
+// malformed pubkeys
+fn multiple_accounts(state: &State, pubkeys: &[Value], encoding: AccountEncoding) -> Vec<Value> {
+    pubkeys
+        .iter()
+        .map(|pubkey| {
+            pubkey
+                .as_str()
+                .filter(|pubkey| pubkey.parse::<Pubkey>().is_ok())
+                .map_or(Value::Null, |pubkey| account_value(state, pubkey, encoding))
+        })
+        .collect()
+}
+
+// Get blocks in range [start_block_num, end_block_num], used by the prover
+// to read blocks without opening the sequencer's block db.
+async fn get_batch_blocks(id: Value, params: Option<Value>) -> JsonRpcResponse {
+    let range = param_u64(&params, 0).and_then(|start| Ok((start, param_u64(&params, 1)?)));
+    let (start_block_num, end_block_num) = match range {
+        Ok(range) => range,
+        Err(error) => return error_response(id, error),
+    };
+    if start_block_num > end_block_num
+        || end_block_num - start_block_num >= MAX_BATCH_BLOCKS_PER_REQUEST
     {
-        Some(keys) => keys,
-        None => {
+        return error_response(
+            id,
+            invalid_params(format!(
+                "Invalid params: expected [start_block_num, end_block_num] covering at most {} blocks",
+                MAX_BATCH_BLOCKS_PER_REQUEST
+            )),
+        );
+    }
+
+    let block_db = BLOCK_DB.read().await;
+    let mut blocks = Vec::new();
+    for block_num in start_block_num..=end_block_num {
+        match block_db.get_block(block_num) {
+            Some(block) => blocks.push(block),
+            None => {
+                return JsonRpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id,
+                    result: None,
+                    error: Some(JsonRpcError {
+                        code: BLOCK_NOT_AVAILABLE,
+                        message: format!("Block not available for block number {}", block_num),
+                        data: None,
+                    }),
+                };
+            }
+        }
+    }
+
+    JsonRpcResponse {
+        jsonrpc: "2.0".to_string(),
+        id,
+        result: Some(serde_json::json!(blocks)),
+        error: None,
+    }
+}
+
+// Admin method, params: [adminToken]. Commit the next batch now instead of on the
+// batcher's timer, returns its index, `null` with nothing to submit.
+async fn force_batch_submit(id: Value, params: Option<Value>) -> JsonRpcResponse {
+    let token = param_str(&params, 0).ok();
+    if !is_admin(config().admin_token.as_deref(), token) {
+        warn!("Unauthorized forceBatchSubmit");
+        return unauthorized(id);
+    }
+
+    let submitted = match BATCHER.get() {
+        Some(batcher) => batcher.smart_submit().await,
+        None => Err(anyhow::anyhow!("Batcher not started")),
+    };
+    match submitted {
+        Ok(batch_index) => {
+            info!(batch_index, "Forced batch submission");
+            JsonRpcResponse {
+                jsonrpc: "2.0".to_string(),
+                id,
+                result: Some(serde_json::json!({ "batchIndex": batch_index })),
+                error: None,
+            }
+        }
+        Err(e) => {
+            error!("Forced batch submission failed: {:?}", e);
+            JsonRpcResponse {
+                jsonrpc: "2.0".to_string(),
+                id,
+                result: None,
+                error: Some(JsonRpcError {
+                    code: INTERNAL_ERROR,
+                    message: format!("Batch submission failed: {}", e),
+                    data: None,
+                }),
+            }
+        }
+    }
+}
+
+// Whether `token` is the configured admin token, compared in constant time
+fn is_admin(admin_token: Option<&str>, token: Option<&str>) -> bool {
+    match (admin_token, token) {
+        (Some(admin_token), Some(token)) if admin_token.len() == token.len() => {
+            admin_token
+                .bytes()
+                .zip(token.bytes())
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0
+        }
+        _ => false,
+    }
+}
+
+fn unauthorized(id: Value) -> JsonRpcResponse {
+    JsonRpcResponse {
+        jsonrpc: "2.0".to_string(),
+        id,
+        result: None,
+        error: Some(JsonRpcError {
+            code: UNAUTHORIZED,
+            message: "Unauthorized".to_string(),
+            data: None,
+        }),
+    }
+}
+
+// Admin only, params: [token]. The whole state as a base64 `State::export_snapshot`,
+// for a replica to import instead of replaying every block. Not `getStateSnapshot`,
+// which already names the paginated debug dump of the balances.
+async fn export_state_snapshot(id: Value, params: Option<Value>) -> JsonRpcResponse {
+    let token = param_str(&params, 0).ok();
+    if !is_admin(config().admin_token.as_deref(), token) {
+        warn!("Unauthorized exportStateSnapshot");
+        return unauthorized(id);
+    }
+
+    let state_db = STATE.read().await;
+    let snapshot = state_db.state.export_snapshot();
+    JsonRpcResponse {
+        jsonrpc: "2.0".to_string(),
+        id,
+        result: Some(serde_json::json!({
+            "stateRoot": state_db.state.calculate_state_root().unwrap_or_default(),
+            "snapshot": general_purpose::STANDARD.encode(snapshot)
+        })),
+        error: None,
+    }
+}
+
+// Admin only, params: [token, snapshot]. Replace the state with a base64 snapshot of
+// `exportStateSnapshot`, it must reach the state and withdrawal roots of the latest
+// block.
+async fn import_state_snapshot(id: Value, params: Option<Value>) -> JsonRpcResponse {
+    let token = param_str(&params, 0).ok();
+    if !is_admin(config().admin_token.as_deref(), token) {
+        warn!("Unauthorized importStateSnapshot");
+        return unauthorized(id);
+    }
+    let snapshot = match param_str(&params, 1) {
+        Ok(snapshot) => general_purpose::STANDARD.decode(snapshot),
+        Err(error) => return error_response(id, error),
+    };
+    let Ok(snapshot) = snapshot else {
+        return error_response(id, invalid_params("Expected a base64 snapshot"));
+    };
+
+    // Locked in the executor's order, the state before the block db
+    let mut state_db = STATE.write().await;
+    let (latest_state_root, latest_withdrawal_root) = {
+        let block_db = BLOCK_DB.read().await;
+        let latest_state_root = block_db
+            .latest_state_root()
+            .unwrap_or_else(|| genesis_state().calculate_state_root().unwrap_or_default());
+        let latest_withdrawal_root = block_db
+            .latest_block_num()
+            .and_then(|block_num| block_db.get_block(block_num))
+            .and_then(|block| block.withdrawal_root)
+            .unwrap_or_default();
+        (latest_state_root, latest_withdrawal_root)
+    };
+    match import_state(
+        &mut state_db,
+        &snapshot,
+        latest_state_root,
+        latest_withdrawal_root,
+    ) {
+        Ok(()) => {
+            info!(
+                accounts = state_db.state.accounts.len(),
+                "Imported state snapshot"
+            );
+            JsonRpcResponse {
+                jsonrpc: "2.0".to_string(),
+                id,
+                result: Some(serde_json::json!({ "stateRoot": latest_state_root })),
+                error: None,
+            }
+        }
+        Err(e) => {
+            error!("State snapshot import failed: {:?}", e);
+            JsonRpcResponse {
+                jsonrpc: "2.0".to_string(),
+                id,
+                result: None,
+                error: Some(JsonRpcError {
+                    code: INVALID_PARAMS,
+                    message: format!("Invalid state snapshot: {}", e),
+                    data: None,
+                }),
+            }
+        }
+    }
+}
+
+// Replace the state of `state_db` with `snapshot` and save it. A snapshot of another
+// root than `latest_state_root` would be rebuilt from the blocks on restart anyway,
+// one of another withdrawal queue than the latest block's would queue withdrawals the
+// prover can't reproduce.
+fn import_state(
+    state_db: &mut StateDB,
+    snapshot: &[u8],
+    latest_state_root: [u8; 32],
+    latest_withdrawal_root: [u8; 32],
+) -> anyhow::Result<()> {
+    let state = State::import_snapshot(snapshot)?;
+    let state_root = state.calculate_state_root().unwrap_or_default();
+    if state_root != latest_state_root {
+        return Err(anyhow::anyhow!(
+            "snapshot state root {:?} isn't the latest block's {:?}",
+            state_root,
+            latest_state_root
+        ));
+    }
+    let withdrawal_root = state.calculate_withdrawal_root().unwrap_or_default();
+    if withdrawal_root != latest_withdrawal_root {
+        return Err(anyhow::anyhow!(
+            "snapshot withdrawal root {:?} isn't the latest block's {:?}",
+            withdrawal_root,
+            latest_withdrawal_root
+        ));
+    }
+    state_db.state = state;
+    state_db.cache.clear();
+    state_db.save();
+    Ok(())
+}
+
+// Faucet, params: [pubkey, lamports]. Transfers from the faucet account within the
+// `airdrop_*` caps and returns the transaction signature, like Solana's faucet.
+async fn request_airdrop(id: Value, params: Option<Value>) -> JsonRpcResponse {
+    let error = |id, code, message: String| JsonRpcResponse {
+        jsonrpc: "2.0".to_string(),
+        id,
+        result: None,
+        error: Some(JsonRpcError {
+            code,
+            message,
+            data: None,
+        }),
+    };
+    let args = param_str(&params, 0)
+        .and_then(|to| Pubkey::from_str(to).map_err(|_| expected_param(0, "a pubkey")))
+        .and_then(|to| Ok((to, param_u64(&params, 1)?)));
+    let (to, lamports) = match args {
+        Ok(args) => args,
+        Err(e) => return error_response(id, e),
+    };
+    let faucet = match config().faucet_keypair() {
+        Ok(Some(faucet)) => faucet,
+        Ok(None) => return error(id, METHOD_NOT_FOUND, "Method not found".to_string()),
+        Err(e) => return error(id, INTERNAL_ERROR, format!("Faucet unavailable: {}", e)),
+    };
+
+    let (_, blockhash) = RECENT_BLOCKHASHES.read().await.latest().unwrap_or_default();
+    let transaction =
+        system_transaction::transfer(&faucet, &to, lamports, Hash::new_from_array(blockhash));
+    if let Err(e) = TransactionValidator::validate_transaction(true, &transaction).await {
+        warn!("Airdrop validation failed: {}", e);
+        return error(id, INTERNAL_ERROR, format!("Airdrop request failed: {}", e));
+    }
+
+    let ledger = AIRDROP_LEDGER.get_or_init(|| {
+        AirdropLedger::new(
+            &config().faucet_db_path,
+            AirdropLimits::from_config(config()),
+        )
+    });
+    let mut mempool = MEMPOOL.write().await;
+    if mempool.len() > config().max_mempool_size {
+        return error(id, INTERNAL_ERROR, "Mempool is full".to_string());
+    }
+    // Recorded last, a request failing before doesn't count against the caps
+    match ledger.record(&to, Lamports(lamports), unix_now()) {
+        Ok(()) => {}
+        Err(e @ AirdropError::Storage(_)) => {
+            error!("Failed to record airdrop: {}", e);
+            return error(id, INTERNAL_ERROR, e.to_string());
+        }
+        Err(e) => return error(id, AIRDROP_LIMIT_EXCEEDED, e.to_string()),
+    }
+    let signature = transaction.signatures[0].to_string();
+    mempool.push(transaction);
+    info!(%to, lamports, "Airdrop added to mempool");
+    JsonRpcResponse {
+        jsonrpc: "2.0".to_string(),
+        id,
+        result: Some(serde_json::json!(signature)),
+        error: None,
+    }
+}
+
+// Blocks produced after the last batch the batcher committed, the batching lag
+async fn get_pending_blocks(id: Value) -> JsonRpcResponse {
+    let block_db = BLOCK_DB.read().await;
+    let marker = match CommitMarker::load(block_db.db.as_ref()) {
+        Ok(marker) => marker,
+        Err(e) => {
             return JsonRpcResponse {
                 jsonrpc: "2.0".to_string(),
                 id,
                 result: None,
                 error: Some(JsonRpcError {
-                    code: -32602,
-                    message: "Invalid params: expected array of public keys".to_string(),
+                    code: INTERNAL_ERROR,
+                    message: format!("Failed to read the commit marker: {}", e),
+                    data: None,
                 }),
             };
         }
     };
+    let latest_block_num = block_db.latest_block_num().unwrap_or_default();
 
-    // Convert pubkeys to strings
-    let mut account_values = Vec::new();
-    for pubkey_value in pubkeys {
-        let pubkey = match pubkey_value.as_str() {
-            Some(key) => key.to_string(),
-            None => {
+    JsonRpcResponse {
+        jsonrpc: "2.0".to_string(),
+        id,
+        result: Some(pending_blocks(marker.as_ref(), latest_block_num)),
+        error: None,
+    }
+}
+
+// `[startBlockNum, endBlockNum]` after the end of the last commit, both null when
+// everything is batched. An unconfirmed commit counts as batched, it's reconciled with
+// L1 before the next one.
+fn pending_blocks(marker: Option<&CommitMarker>, latest_block_num: u64) -> Value {
+    let start_block_num = marker.map_or(1, |marker| marker.end_block_num + 1);
+    let count = (latest_block_num + 1).saturating_sub(start_block_num);
+    serde_json::json!({
+        "startBlockNum": (count > 0).then_some(start_block_num),
+        "endBlockNum": (count > 0).then_some(latest_block_num),
+        "count": count,
+        "lastBatchIndex": marker.map(|marker| marker.batch_index),
+        "lastBatchConfirmed": marker.map(|marker| marker.confirmed),
+        "latestBlockNum": latest_block_num
+    })
+}
+
+// Get a page of blocks for indexers and archival nodes, params: [startSlot, endSlot?,
+// {encoding?, transactionDetails?}]. Returns the blocks from `startSlot` up to `endSlot`
+// (the latest block by default), at most MAX_BLOCK_RANGE_PER_REQUEST blocks and
+// MAX_BLOCK_RANGE_RESPONSE_BYTES bytes, and `nextCursor`, the slot to continue from,
+// `null` once the range is done. `transactionDetails` "signatures" or "none" return
+// lightweight headers.
+async fn get_block_range(id: Value, params: Option<Value>) -> JsonRpcResponse {
+    let range = param_u64(&params, 0).and_then(|start| Ok((start, param_opt_u64(&params, 1)?)));
+    let (start, end) = match range {
+        Ok(range) => range,
+        Err(error) => return error_response(id, error),
+    };
+    let options = param(&params, 2).cloned().unwrap_or_default();
+    let encoding = match options.get("encoding") {
+        None | Some(Value::Null) => Some(UiTransactionEncoding::Json),
+        Some(encoding) => serde_json::from_value(encoding.clone()).ok(),
+    };
+    let details = match options.get("transactionDetails") {
+        None | Some(Value::Null) => Some(TransactionDetails::Full),
+        Some(details) => serde_json::from_value(details.clone()).ok(),
+    };
+    let (encoding, details) = match (encoding, details) {
+        (Some(encoding), Some(details))
+            if details != TransactionDetails::Accounts && end.is_none_or(|end| start <= end) =>
+        {
+            (encoding, details)
+        }
+        _ => {
+            return error_response(
+                id,
+                invalid_params("Invalid params: expected [startSlot, endSlot?, {encoding?, transactionDetails?}], transactionDetails one of full, signatures, none"),
+            );
+        }
+    };
+
+    let block_db = BLOCK_DB.read().await;
+    let latest_block_num = block_db.latest_block_num().unwrap_or_default();
+    let end = end.unwrap_or(latest_block_num).min(latest_block_num);
+    JsonRpcResponse {
+        jsonrpc: "2.0".to_string(),
+        id,
+        result: Some(block_range(&block_db, start, end, encoding, details)),
+        error: None,
+    }
+}
+
+fn block_range(
+    block_db: &BlockDB,
+    start: u64,
+    end: u64,
+    encoding: UiTransactionEncoding,
+    details: TransactionDetails,
+) -> Value {
+    let mut blocks = Vec::new();
+    let mut size = 0;
+    let mut next_cursor = None;
+    for block_num in start.max(1)..=end {
+        if blocks.len() as u64 >= MAX_BLOCK_RANGE_PER_REQUEST
+            || size >= MAX_BLOCK_RANGE_RESPONSE_BYTES
+        {
+            next_cursor = Some(block_num);
+            break;
+        }
+        let Some(block) = block_db.get_block(block_num) else {
+            break;
+        };
+        let block = block_json(&block, encoding, details);
+        size += block.to_string().len();
+        blocks.push(block);
+    }
+
+    serde_json::json!({
+        "blocks": blocks,
+        "nextCursor": next_cursor
+    })
+}
+
+fn block_json(
+    block: &Block,
+    encoding: UiTransactionEncoding,
+    details: TransactionDetails,
+) -> Value {
+    let mut json = serde_json::json!({
+        "slot": block.block_num,
+        "blockhash": bs58::encode(block.post_state_root.unwrap_or_default()).into_string(),
+        "previousBlockhash": bs58::encode(block.prev_state_root.unwrap_or_default()).into_string(),
+        "txnsRoot": block.txns_root,
+        "withdrawalRoot": block.withdrawal_root,
+        "transactionCount": block.txns.len()
+    });
+    match details {
+        TransactionDetails::Full => {
+            json["transactions"] = block
+                .txns
+                .iter()
+                .map(|txn| {
+                    serde_json::json!({
+                        "transaction": serde_json::to_value(txn.encode(encoding)).unwrap_or_default(),
+                        "memo": transaction_memo(txn)
+                    })
+                })
+                .collect();
+        }
+        TransactionDetails::Signatures => {
+            json["signatures"] = block
+                .txns
+                .iter()
+                .filter_map(|txn| txn.signatures.first())
+                .map(|signature| Value::String(signature.to_string()))
+                .collect();
+        }
+        TransactionDetails::None | TransactionDetails::Accounts => {}
+    }
+    json
+}
+
+// Get the merkle proof of a withdrawal, params: [index, range?] where range is the
+// withdrawal count of the finalized batch to prove against, by default the one
+// recorded on L1 for the last finalized batch
+async fn get_withdrawal_proof(id: Value, params: Option<Value>) -> JsonRpcResponse {
+    let args = param_u64(&params, 0).and_then(|index| Ok((index, param_opt_u64(&params, 1)?)));
+    let (index, range) = match args {
+        Ok(args) => args,
+        Err(error) => return error_response(id, error),
+    };
+    let range = match range {
+        Some(range) => range,
+        None => match finality::finalized_block().await {
+            Ok(finalized) => finalized.withdrawal_count,
+            Err(e) => {
+                error!("Failed to read the finalized batch: {:?}", e);
                 return JsonRpcResponse {
                     jsonrpc: "2.0".to_string(),
                     id,
                     result: None,
                     error: Some(JsonRpcError {
-                        code: -32602,
-                        message: "Invalid params: all public keys must be strings".to_string(),
+                        code: INTERNAL_ERROR,
+                        message: format!("Failed to read the finalized batch: {}", e),
+                        data: None,
                     }),
                 };
             }
-        };
+        },
+    };
 
-        // Get balance from state
-        let state_db = STATE.read().await;
-        let balance = state_db.state.get_balance(&pubkey);
-        let account_info = serde_json::json!({
-            "data": ["", "base64"],
-            "executable": false,
-            "lamports": balance,
-            "owner": "11111111111111111111111111111111",
-            "rentEpoch": 361,
-            "space": 0
-        });
-        account_values.push(account_info);
+    let state_db = STATE.read().await;
+    let proof = WITHDRAWAL_PROOF_CACHE
+        .lock()
+        .await
+        .proof(&state_db.state, index, range);
+    // The leaf's fields, to claim the withdrawal on L1 with the proof
+    let withdrawal = state_db
+        .state
+        .withdrawal_queue
+        .iter()
+        .find(|withdrawal| withdrawal.index == index);
+    match proof.zip(withdrawal) {
+        Some(((leaf_hash, proof, index, root), withdrawal)) => JsonRpcResponse {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result: Some(serde_json::json!({
+                "leafHash": leaf_hash,
+                "proof": proof,
+                "index": index,
+                "root": root,
+                "from": withdrawal.from,
+                "to": withdrawal.to,
+                "amount": withdrawal.amount
+            })),
+            error: None,
+        },
+        None => JsonRpcResponse {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result: None,
+            error: Some(JsonRpcError {
+                code: INVALID_PARAMS,
+                message: format!("No withdrawal {} in the first {} withdrawals", index, range),
+                data: None,
+            }),
+        },
+    }
+}
+
+// Whether the L1 deposit `l1_signature` was credited on L2, null while it isn't
+async fn get_deposit_status(id: Value, params: Option<Value>) -> JsonRpcResponse {
+    let l1_signature = match param_str(&params, 0) {
+        Ok(l1_signature) => l1_signature,
+        Err(error) => return error_response(id, error),
+    };
+
+    let state_db = STATE.read().await;
+    let result = match state_db.state.deposits.get(l1_signature) {
+        Some(deposit) => serde_json::json!({
+            "l1Signature": l1_signature,
+            "recipient": deposit.to,
+            "amount": deposit.amount,
+            "blockNum": deposit.block_num
+        }),
+        None => Value::Null,
+    };
+    JsonRpcResponse {
+        jsonrpc: "2.0".to_string(),
+        id,
+        result: Some(result),
+        error: None,
+    }
+}
+
+// The last L2 block proven on L1 and its state root, the L1 read is cached briefly.
+// The local block must match the proven root.
+async fn get_finalized_block(id: Value) -> JsonRpcResponse {
+    let finalized = match finality::finalized_block().await {
+        Ok(finalized) => finalized,
+        Err(e) => {
+            error!("Failed to read the finalized batch: {:?}", e);
+            return JsonRpcResponse {
+                jsonrpc: "2.0".to_string(),
+                id,
+                result: None,
+                error: Some(JsonRpcError {
+                    code: INTERNAL_ERROR,
+                    message: format!("Failed to read the finalized batch: {}", e),
+                    data: None,
+                }),
+            };
+        }
+    };
+
+    if finalized.block_num > 0 {
+        let block = BLOCK_DB.read().await.get_block(finalized.block_num);
+        let error = match block {
+            None => Some(JsonRpcError {
+                code: BLOCK_NOT_AVAILABLE,
+                message: format!(
+                    "Block not available for block number {}",
+                    finalized.block_num
+                ),
+                data: None,
+            }),
+            Some(block) if block.post_state_root != Some(finalized.state_root) => {
+                error!(
+                    block_num = finalized.block_num,
+                    "Local block diverges from the state root proven on L1"
+                );
+                Some(JsonRpcError {
+                    code: INTERNAL_ERROR,
+                    message: format!(
+                        "Block {} diverges from the state root proven on L1",
+                        finalized.block_num
+                    ),
+                    data: None,
+                })
+            }
+            Some(_) => None,
+        };
+        if error.is_some() {
+            return JsonRpcResponse {
+                jsonrpc: "2.0".to_string(),
+                id,
+                result: None,
+                error,
+            };
+        }
     }
 
     JsonRpcResponse {
         jsonrpc: "2.0".to_string(),
         id,
         result: Some(serde_json::json!({
-            "context": {
-                "apiVersion": "2.2.21",
-                "slot": 23816
-            },
-            "value": account_values
+            "batchIndex": finalized.batch_index,
+            "blockNum": finalized.block_num,
+            "stateRoot": finalized.state_root,
+            "withdrawalCount": finalized.withdrawal_count
         })),
         error: None,
     }
 }
+
+// Dev only, the balances and withdrawals behind the state and withdrawal roots, to diff
+// against the prover's state when the roots don't match. params: [offset, limit], a
+// page of at most MAX_SNAPSHOT_ENTRIES_PER_REQUEST accounts sorted by address and the
+// withdrawals at the same positions of the queue sorted by index.
+async fn get_state_snapshot(id: Value, params: Option<Value>) -> JsonRpcResponse {
+    let offset = param_u64(&params, 0).unwrap_or(0) as usize;
+    let limit = param_u64(&params, 1)
+        .map_or(MAX_SNAPSHOT_ENTRIES_PER_REQUEST, |limit| limit as usize)
+        .min(MAX_SNAPSHOT_ENTRIES_PER_REQUEST);
+
+    let state_db = STATE.read().await;
+    JsonRpcResponse {
+        jsonrpc: "2.0".to_string(),
+        id,
+        result: Some(state_snapshot(&state_db.state, offset, limit)),
+        error: None,
+    }
+}
+
+fn state_snapshot(state: &State, offset: usize, limit: usize) -> Value {
+    let mut accounts: Vec<(&String, Lamports)> = state
+        .accounts
+        .iter()
+        .map(|(address, account)| (address, account.lamports))
+        .collect();
+    accounts.sort();
+    let accounts: Vec<Value> = accounts
+        .into_iter()
+        .skip(offset)
+        .take(limit)
+        .map(|(address, lamports)| serde_json::json!({ "address": address, "lamports": lamports }))
+        .collect();
+
+    let mut withdrawals: Vec<_> = state.withdrawal_queue.iter().collect();
+    withdrawals.sort_by_key(|withdrawal| withdrawal.index);
+    let withdrawals: Vec<_> = withdrawals.into_iter().skip(offset).take(limit).collect();
+
+    serde_json::json!({
+        "stateRoot": state.calculate_state_root(),
+        "withdrawalRoot": state.calculate_withdrawal_root(),
+        "totalAccounts": state.accounts.len(),
+        "totalWithdrawals": state.withdrawal_queue.len(),
+        "offset": offset,
+        "accounts": accounts,
+        "withdrawals": withdrawals
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        account_value, block_range, commitment_param, cors_layer, encoding_param,
+        get_cluster_nodes, get_identity, handle_rpc_request, import_state, is_admin, message_fee,
+        multiple_accounts, param_array, param_opt_u64, param_str, param_u64,
+        parse_and_decode_transaction, pending_blocks, state_snapshot, validation_error,
+        AccountEncoding, JsonRpcRequest, INVALID_PARAMS, MAX_BLOCK_RANGE_PER_REQUEST,
+        METHOD_NOT_FOUND, SEND_TRANSACTION_PREFLIGHT_FAILURE,
+        TRANSACTION_SIGNATURE_VERIFICATION_FAILURE,
+    };
+    use crate::batcher::tx_batcher::CommitMarker;
+    use crate::config::Config;
+    use crate::validator::ValidationError;
+    use axum::{body::Body, extract::Request, http::header, routing::get, Json, Router};
+    use base64::{engine::general_purpose, Engine};
+    use serde_json::Value;
+    use share::lamports::Lamports;
+    use share::state::{AccountData, State, StateDB, Withdrawal};
+    use share::transaction::{Block, BlockDB};
+    use solana_sdk::commitment_config::CommitmentLevel;
+    use solana_sdk::{
+        bs58, hash::Hash, packet::PACKET_DATA_SIZE, pubkey::Pubkey, signature::Keypair,
+        signer::Signer, system_transaction,
+    };
+    use solana_transaction_status::{TransactionDetails, UiTransactionEncoding};
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_fee_for_message() {
+        use solana_sdk::{message::Message, system_instruction};
+
+        let (payer, sender) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let transfer = system_instruction::transfer(&sender, &Pubkey::new_unique(), 1);
+        let encode = |message: &Message| general_purpose::STANDARD.encode(message.serialize());
+
+        // The sender pays its own fee
+        let one_signature = Message::new(std::slice::from_ref(&transfer), Some(&sender));
+        assert_eq!(message_fee(&encode(&one_signature), 5000), Some(5000));
+        // The fee payer and the sender sign, twice the base fee
+        let two_signatures = Message::new(&[transfer], Some(&payer));
+        assert_eq!(two_signatures.header.num_required_signatures, 2);
+        assert_eq!(message_fee(&encode(&two_signatures), 5000), Some(10_000));
+        assert_eq!(message_fee(&encode(&two_signatures), 7), Some(14));
+
+        assert_eq!(message_fee("not base64!", 5000), None);
+        assert_eq!(
+            message_fee(&general_purpose::STANDARD.encode([1, 2]), 5000),
+            None
+        );
+    }
+
+    #[test]
+    fn test_validation_error_json() {
+        let error = validation_error(&ValidationError::Signature("bad".to_string()));
+        let json = serde_json::to_value(&error).unwrap();
+        assert_eq!(json["code"], TRANSACTION_SIGNATURE_VERIFICATION_FAILURE);
+        assert!(json.get("data").is_none());
+
+        let error = validation_error(&ValidationError::Format(
+            "Transaction has no instructions".to_string(),
+        ));
+        let json = serde_json::to_value(&error).unwrap();
+        assert_eq!(json["code"], SEND_TRANSACTION_PREFLIGHT_FAILURE);
+        assert_eq!(json["data"]["err"], "SanitizeFailure");
+        assert_eq!(json["data"]["logs"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn test_oversized_transaction() {
+        let id = serde_json::json!(1);
+        let decode = |transaction: String, encoding: &str| {
+            let params = serde_json::json!([transaction, { "encoding": encoding }]);
+            parse_and_decode_transaction(&id, Some(params)).map_err(|response| {
+                let error = response.error.unwrap();
+                assert_eq!(error.code, INVALID_PARAMS);
+                error.message
+            })
+        };
+
+        let from = Keypair::new();
+        let transfer =
+            system_transaction::transfer(&from, &Pubkey::new_unique(), 1, Hash::default());
+        let bytes = bincode::serialize(&transfer).unwrap();
+        assert_eq!(
+            decode(general_purpose::STANDARD.encode(&bytes), "base64"),
+            Ok(transfer.clone())
+        );
+        assert_eq!(
+            decode(bs58::encode(&bytes).into_string(), "base58"),
+            Ok(transfer)
+        );
+
+        // Rejected before decoding
+        assert!(decode(String::new(), "base64").is_err());
+        let oversized = general_purpose::STANDARD.encode(vec![0u8; 1024 * 1024]);
+        assert!(decode(oversized, "base64")
+            .unwrap_err()
+            .contains("expected 1 to"));
+        let oversized = bs58::encode(vec![1u8; 4096]).into_string();
+        assert!(decode(oversized, "base58")
+            .unwrap_err()
+            .contains("expected 1 to"));
+
+        // Within the encoded limit, but a byte over the packet size once decoded
+        let oversized = general_purpose::STANDARD.encode(vec![0u8; PACKET_DATA_SIZE + 1]);
+        assert!(decode(oversized, "base64")
+            .unwrap_err()
+            .contains("too large"));
+    }
+
+    #[test]
+    fn test_import_state() {
+        let mut state = State::new();
+        state.set_balance("a".to_string(), Lamports(7));
+        let snapshot = state.export_snapshot();
+        let state_root = state.calculate_state_root().unwrap();
+
+        // Only a snapshot of the latest block's state is imported
+        let mut state_db = StateDB::in_memory();
+        let error = import_state(&mut state_db, &snapshot, [1; 32], [0; 32]).unwrap_err();
+        assert!(error.to_string().starts_with("snapshot state root"));
+        assert!(state_db.state.accounts.is_empty());
+        // with the latest block's withdrawals
+        let error = import_state(&mut state_db, &snapshot, state_root, [1; 32]).unwrap_err();
+        assert!(error.to_string().starts_with("snapshot withdrawal root"));
+        assert!(state_db.state.accounts.is_empty());
+
+        import_state(&mut state_db, &snapshot, state_root, [0; 32]).unwrap();
+        assert_eq!(state_db.state.calculate_state_root(), Some(state_root));
+        // Saved, a restart loads it
+        state_db.state = State::new();
+        state_db.load();
+        assert_eq!(state_db.state.get_balance("a"), Lamports(7));
+    }
+
+    #[tokio::test]
+    async fn test_identity_and_cluster_nodes() {
+        let identity = get_identity(serde_json::json!(1)).await.result.unwrap();
+        let nodes = get_cluster_nodes(serde_json::json!(2))
+            .await
+            .result
+            .unwrap();
+
+        // A single node, the sequencer itself
+        let nodes = nodes.as_array().unwrap();
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(nodes[0]["pubkey"], identity["identity"]);
+        assert_ne!(identity["identity"], Pubkey::default().to_string());
+        assert!(nodes[0]["rpc"].is_string());
+    }
+
+    #[tokio::test]
+    async fn test_rpc_call_outcome() {
+        let request = |method: &str| {
+            Json(JsonRpcRequest {
+                jsonrpc: "2.0".to_string(),
+                id: serde_json::json!(1),
+                method: method.to_string(),
+                params: None,
+            })
+        };
+
+        let (call, _) = handle_rpc_request(request("getIdentity")).await.unwrap();
+        assert_eq!(call.method, "getIdentity");
+        assert_eq!(call.error, None);
+
+        // Unserved methods share a label
+        let (call, Json(response)) = handle_rpc_request(request("getFoo")).await.unwrap();
+        assert_eq!(call.method, "unknown");
+        assert_eq!(call.error, Some(METHOD_NOT_FOUND));
+        assert_eq!(response.error.unwrap().code, METHOD_NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_cors_origins() {
+        async fn allowed_origin(config: &Config, origin: &str) -> Option<String> {
+            let app = Router::new()
+                .route("/metrics", get(|| async { "" }))
+                .layer(cors_layer(config).unwrap());
+            let request = Request::builder()
+                .uri("/metrics")
+                .header(header::ORIGIN, origin)
+                .body(Body::empty())
+                .unwrap();
+            let response = app.oneshot(request).await.unwrap();
+            response
+                .headers()
+                .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+                .map(|value| value.to_str().unwrap().to_string())
+        }
+
+        let config = Config {
+            cors_allowed_origins: vec!["https://app.example.com".to_string()],
+            ..Config::default()
+        };
+        assert_eq!(
+            allowed_origin(&config, "https://app.example.com").await,
+            Some("https://app.example.com".to_string())
+        );
+        // The browser gets no allow-origin header and blocks the response
+        assert_eq!(
+            allowed_origin(&config, "https://evil.example.com").await,
+            None
+        );
+        assert_eq!(
+            allowed_origin(&Config::default(), "https://app.example.com").await,
+            None
+        );
+
+        // Anything goes in dev mode
+        let config = Config {
+            dev_mode: true,
+            ..Config::default()
+        };
+        assert_eq!(
+            allowed_origin(&config, "https://evil.example.com").await,
+            Some("*".to_string())
+        );
+    }
+
+    #[test]
+    fn test_is_admin() {
+        let admin_token = Some("0123456789abcdef");
+        assert!(is_admin(admin_token, Some("0123456789abcdef")));
+        assert!(!is_admin(admin_token, Some("0123456789abcdeF")));
+        assert!(!is_admin(admin_token, Some("0123456789")));
+        assert!(!is_admin(admin_token, None));
+        // Disabled without a token
+        assert!(!is_admin(None, Some("")));
+    }
+
+    #[test]
+    fn test_pending_blocks() {
+        // Nothing committed yet
+        let pending = pending_blocks(None, 5);
+        assert_eq!(pending["startBlockNum"], 1);
+        assert_eq!(pending["endBlockNum"], 5);
+        assert_eq!(pending["count"], 5);
+        assert!(pending["lastBatchIndex"].is_null());
+
+        let marker = CommitMarker {
+            batch_index: 3,
+            start_block_num: 4,
+            end_block_num: 9,
+            confirmed: true,
+        };
+        let pending = pending_blocks(Some(&marker), 12);
+        assert_eq!(pending["startBlockNum"], 10);
+        assert_eq!(pending["endBlockNum"], 12);
+        assert_eq!(pending["count"], 3);
+        assert_eq!(pending["lastBatchIndex"], 3);
+
+        // Everything batched
+        let pending = pending_blocks(Some(&marker), 9);
+        assert_eq!(pending["count"], 0);
+        assert!(pending["startBlockNum"].is_null() && pending["endBlockNum"].is_null());
+        assert_eq!(pending_blocks(None, 0)["count"], 0);
+    }
+
+    #[test]
+    fn test_commitment_param() {
+        let params = |config: Value| {
+            Some(serde_json::json!([
+                "11111111111111111111111111111111",
+                config
+            ]))
+        };
+        assert_eq!(
+            commitment_param(&params(serde_json::json!({"commitment": "finalized"})), 1),
+            Ok(CommitmentLevel::Finalized)
+        );
+        assert_eq!(
+            commitment_param(&params(serde_json::json!({"commitment": "processed"})), 1),
+            Ok(CommitmentLevel::Processed)
+        );
+        // Without one the latest state is read
+        assert_eq!(
+            commitment_param(&params(serde_json::json!({})), 1),
+            Ok(CommitmentLevel::Confirmed)
+        );
+        assert_eq!(
+            commitment_param(
+                &Some(serde_json::json!(["11111111111111111111111111111111"])),
+                1
+            ),
+            Ok(CommitmentLevel::Confirmed)
+        );
+        assert!(commitment_param(&params(serde_json::json!({"commitment": "max"})), 1).is_err());
+    }
+
+    #[test]
+    fn test_param_extractors() {
+        let params = Some(serde_json::json!(["abc", 7, ["x"], null]));
+        assert_eq!(param_str(&params, 0).unwrap(), "abc");
+        assert_eq!(param_u64(&params, 1).unwrap(), 7);
+        assert_eq!(
+            param_array(&params, 2).unwrap(),
+            &vec![serde_json::json!("x")]
+        );
+        assert_eq!(param_opt_u64(&params, 1).unwrap(), Some(7));
+
+        // Missing, null and of another type alike, an INVALID_PARAMS error naming the param
+        for index in [1, 3, 4] {
+            let error = param_str(&params, index).unwrap_err();
+            assert_eq!(error.code, INVALID_PARAMS);
+            assert_eq!(
+                error.message,
+                format!("Invalid params: expected a string at index {}", index)
+            );
+        }
+        assert!(param_u64(&params, 0).is_err());
+        assert!(param_array(&None, 0).is_err());
+        assert!(param_str(&Some(serde_json::json!({"0": "abc"})), 0).is_err());
+
+        // An optional param may be missing or null, not of another type
+        assert_eq!(param_opt_u64(&params, 3).unwrap(), None);
+        assert_eq!(param_opt_u64(&params, 4).unwrap(), None);
+        assert!(param_opt_u64(&params, 0).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_missing_params() {
+        for method in [
+            "getAccountInfo",
+            "getBalance",
+            "isBlockhashValid",
+            "getFeeForMessage",
+            "sendTransaction",
+            "simulateTransaction",
+            "getTransaction",
+            "getSignatureStatuses",
+            "confirmTransaction",
+            "getMultipleAccounts",
+            "getBatchBlocks",
+            "getBlockRange",
+            "getWithdrawalProof",
+            "getDepositStatus",
+        ] {
+            let request = Json(JsonRpcRequest {
+                jsonrpc: "2.0".to_string(),
+                id: serde_json::json!(1),
+                method: method.to_string(),
+                params: None,
+            });
+            let (_, Json(response)) = handle_rpc_request(request).await.unwrap();
+            let error = response.error.unwrap();
+            assert_eq!(error.code, INVALID_PARAMS);
+            assert!(error.message.ends_with("at index 0"));
+        }
+    }
+
+    #[test]
+    fn test_state_snapshot() {
+        let mut state = State::new();
+        for (index, address) in ["c", "a", "b"].into_iter().enumerate() {
+            state.set_balance(address.to_string(), Lamports(100 + index as u64));
+            state.withdrawal_queue.push(Withdrawal {
+                from: Pubkey::new_unique().to_string(),
+                to: Pubkey::new_unique().to_string(),
+                amount: Lamports(1),
+                index: 2 - index as u64,
+            });
+        }
+
+        let snapshot = state_snapshot(&state, 0, 2);
+        assert_eq!(snapshot["totalAccounts"], 3);
+        assert_eq!(
+            snapshot["stateRoot"],
+            serde_json::json!(state.calculate_state_root())
+        );
+        assert_eq!(
+            snapshot["withdrawalRoot"],
+            serde_json::json!(state.calculate_withdrawal_root())
+        );
+        assert_eq!(snapshot["accounts"][0]["address"], "a");
+        assert_eq!(snapshot["accounts"][0]["lamports"], 101);
+        assert_eq!(snapshot["withdrawals"][0]["index"], 0);
+        assert_eq!(snapshot["accounts"].as_array().unwrap().len(), 2);
+
+        // The next page
+        let snapshot = state_snapshot(&state, 2, 2);
+        assert_eq!(snapshot["accounts"][0]["address"], "c");
+        assert_eq!(snapshot["withdrawals"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_multiple_accounts() {
+        let mut state = State::new();
+        let alice = Pubkey::new_unique().to_string();
+        let bob = Pubkey::new_unique().to_string();
+        state.set_balance(alice.clone(), Lamports(1_000));
+        state.set_balance(bob.clone(), Lamports(2_000));
+
+        let accounts = multiple_accounts(
+            &state,
+            &[
+                serde_json::json!(alice),
+                serde_json::json!(Pubkey::new_unique().to_string()),
+                serde_json::json!("not a pubkey"),
+                serde_json::json!(42),
+                serde_json::json!(bob),
+            ],
+            AccountEncoding::Base64,
+        );
+        assert_eq!(accounts.len(), 5);
+        assert_eq!(accounts[0]["lamports"], 1_000);
+        assert!(accounts[1].is_null());
+        assert!(accounts[2].is_null());
+        assert!(accounts[3].is_null());
+        assert_eq!(accounts[4]["lamports"], 2_000);
+    }
+
+    #[test]
+    fn test_account_value() {
+        let mut state = State::new();
+        let alice = Keypair::new();
+        state.set_balance(alice.pubkey().to_string(), Lamports(1_000));
+
+        // Never credited
+        assert!(account_value(
+            &state,
+            &Pubkey::new_unique().to_string(),
+            AccountEncoding::Base64
+        )
+        .is_null());
+
+        // Holding data without lamports, it exists
+        let data_account = Pubkey::new_unique().to_string();
+        state.set_account(
+            data_account.clone(),
+            AccountData {
+                data: vec![0; 8],
+                ..AccountData::new(Lamports::ZERO)
+            },
+        );
+        let account = account_value(&state, &data_account, AccountEncoding::Base64);
+        assert_eq!(account["lamports"], 0);
+        assert_eq!(account["space"], 8);
+
+        // Emptied by a transfer, swept like Solana's zero lamport accounts
+        let txn =
+            system_transaction::transfer(&alice, &Pubkey::new_unique(), 1_000, Hash::default());
+        state.apply_txns(&[txn], 1);
+        assert!(
+            account_value(&state, &alice.pubkey().to_string(), AccountEncoding::Base64).is_null()
+        );
+    }
+
+    #[test]
+    fn test_account_zstd_encoding() {
+        let mut state = State::new();
+        let pubkey = Pubkey::new_unique().to_string();
+        let data: Vec<u8> = (0..1_000u32).map(|i| (i % 7) as u8).collect();
+        state.set_account(
+            pubkey.clone(),
+            AccountData {
+                data: data.clone(),
+                ..AccountData::new(Lamports(1_000))
+            },
+        );
+
+        // Requested by the config object, base64 otherwise
+        let params = Some(serde_json::json!([pubkey, {"encoding": "base64+zstd"}]));
+        let encoding = encoding_param(&params, 1);
+        assert_eq!(encoding, AccountEncoding::Base64Zstd);
+        for params in [
+            None,
+            Some(serde_json::json!([pubkey])),
+            Some(serde_json::json!([pubkey, {"encoding": "base58"}])),
+        ] {
+            assert_eq!(encoding_param(&params, 1), AccountEncoding::Base64);
+        }
+
+        let account = account_value(&state, &pubkey, encoding);
+        assert_eq!(account["data"][1], "base64+zstd");
+        assert_eq!(account["space"], data.len());
+        let compressed = general_purpose::STANDARD
+            .decode(account["data"][0].as_str().unwrap())
+            .unwrap();
+        assert!(compressed.len() < data.len());
+        assert_eq!(zstd::decode_all(&compressed[..]).unwrap(), data);
+
+        // The same account through getMultipleAccounts
+        let accounts = multiple_accounts(&state, &[serde_json::json!(pubkey)], encoding);
+        assert_eq!(accounts[0], account);
+    }
+
+    #[test]
+    fn test_block_range() {
+        let mut block_db = BlockDB::in_memory();
+        let from = Keypair::new();
+        let total_blocks = MAX_BLOCK_RANGE_PER_REQUEST + 10;
+        for block_num in 1..=total_blocks {
+            let txn =
+                system_transaction::transfer(&from, &Pubkey::new_unique(), 1, Hash::default());
+            let mut block = Block::new(vec![txn]);
+            block.block_num = block_num;
+            block.post_state_root = Some([block_num as u8; 32]);
+            block_db.save_block(&block).unwrap();
+        }
+
+        // The first page stops at the cap and points to the rest
+        let page = block_range(
+            &block_db,
+            1,
+            total_blocks,
+            UiTransactionEncoding::Base64,
+            TransactionDetails::Full,
+        );
+        let blocks = page["blocks"].as_array().unwrap();
+        assert_eq!(blocks.len() as u64, MAX_BLOCK_RANGE_PER_REQUEST);
+        assert_eq!(blocks[0]["slot"], 1);
+        assert_eq!(blocks[0]["transactions"].as_array().unwrap().len(), 1);
+        assert_eq!(page["nextCursor"], MAX_BLOCK_RANGE_PER_REQUEST + 1);
+
+        let page = block_range(
+            &block_db,
+            MAX_BLOCK_RANGE_PER_REQUEST + 1,
+            total_blocks,
+            UiTransactionEncoding::Base64,
+            TransactionDetails::Full,
+        );
+        assert_eq!(page["blocks"].as_array().unwrap().len(), 10);
+        assert!(page["nextCursor"].is_null());
+
+        // Header-only pages leave the transactions out
+        let page = block_range(
+            &block_db,
+            1,
+            2,
+            UiTransactionEncoding::Json,
+            TransactionDetails::None,
+        );
+        let blocks = page["blocks"].as_array().unwrap();
+        assert_eq!(blocks.len(), 2);
+        assert!(blocks[1].get("transactions").is_none());
+        assert_eq!(blocks[1]["transactionCount"], 1);
+        assert_eq!(
+            blocks[1]["blockhash"],
+            solana_sdk::bs58::encode([2u8; 32]).into_string()
+        );
+
+        let page = block_range(
+            &block_db,
+            1,
+            1,
+            UiTransactionEncoding::Json,
+            TransactionDetails::Signatures,
+        );
+        assert_eq!(page["blocks"][0]["signatures"].as_array().unwrap().len(), 1);
+    }
+}
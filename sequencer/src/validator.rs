@@ -1,6 +1,35 @@
-use anyhow::{anyhow, Result};
-use log::info;
-use solana_sdk::{signature::Signature, transaction::Transaction};
+use share::lamports::Lamports;
+use share::transaction::{
+    compute_units, l1_oracle, parsing_instruction, signature_fee, signed_by, SysOp,
+};
+use solana_sdk::{
+    sanitize::Sanitize,
+    signature::Signature,
+    transaction::{Transaction, TransactionError},
+};
+use thiserror::Error;
+use tracing::info;
+
+use crate::config::config;
+/// Why a transaction was rejected, maps to the Solana `TransactionError` reported over rpc.
+#[derive(Debug, Error)]
+pub enum ValidationError {
+    #[error("{0}")]
+    Signature(String),
+    #[error("{0}")]
+    Format(String),
+}
+
+impl ValidationError {
+    pub fn transaction_error(&self) -> TransactionError {
+        match self {
+            ValidationError::Signature(_) => TransactionError::SignatureFailure,
+            ValidationError::Format(_) => TransactionError::SanitizeFailure,
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, ValidationError>;
 
 pub struct TransactionValidator {
     // state about verify
@@ -22,10 +51,16 @@ impl TransactionValidator {
         // 2. check format of txn
         Self::validate_transaction_format(transaction)?;
 
-        // 3. check user balance
+        // 3. check transfer amounts
+        Self::validate_transfer_amounts(transaction, config().min_transfer_lamports)?;
+
+        // 4. check compute units
+        Self::validate_compute_units(transaction, config().max_txn_compute_units)?;
+
+        // 5. check user balance
         Self::validate_account_balances(transaction).await?;
 
-        // 4. checkout txn fee
+        // 6. checkout txn fee
         Self::validate_fees(transaction)?;
 
         info!("Transaction validation completed successfully");
@@ -34,16 +69,23 @@ impl TransactionValidator {
 
     fn validate_signatures(transaction: &Transaction) -> Result<()> {
         if transaction.signatures.is_empty() {
-            return Err(anyhow!("Transaction has no signatures"));
+            return Err(ValidationError::Format(
+                "Transaction has no signatures".to_string(),
+            ));
         }
 
         // verify: sig should not empty
         for (i, signature) in transaction.signatures.iter().enumerate() {
             if signature == &Signature::default() {
-                return Err(anyhow!("Signature {} is default/empty", i));
+                return Err(ValidationError::Signature(format!(
+                    "Signature {} is default/empty",
+                    i
+                )));
             }
         }
-        transaction.verify()?;
+        transaction
+            .verify()
+            .map_err(|e| ValidationError::Signature(e.to_string()))?;
 
         info!("Signature validation passed");
         Ok(())
@@ -54,38 +96,305 @@ impl TransactionValidator {
 
         // check count of account
         if message.account_keys.is_empty() {
-            return Err(anyhow!("Transaction has no account keys"));
+            return Err(ValidationError::Format(
+                "Transaction has no account keys".to_string(),
+            ));
         }
 
         // check ins
         if message.instructions.is_empty() {
-            return Err(anyhow!("Transaction has no instructions"));
+            return Err(ValidationError::Format(
+                "Transaction has no instructions".to_string(),
+            ));
+        }
+
+        // check header and instruction indices, the same rules as solana message sanitization
+        transaction
+            .sanitize()
+            .map_err(|e| ValidationError::Format(format!("Malformed transaction: {}", e)))?;
+
+        // check every account key appears once, index lookups must be unambiguous
+        if message.has_duplicates() {
+            return Err(ValidationError::Format(
+                "Transaction has duplicate account keys".to_string(),
+            ));
+        }
+
+        // check the system instructions are supported by the executor
+        for instruction in message.instructions.iter() {
+            match parsing_instruction(instruction, transaction) {
+                Ok(Some(SysOp::Unsupported { kind })) => {
+                    return Err(ValidationError::Format(format!(
+                        "Unsupported system instruction: {}",
+                        kind
+                    )));
+                }
+                // Only the oracle relays L1 deposits, `apply_txns` checks it as well
+                Ok(Some(SysOp::Deposit { .. })) if !signed_by(transaction, &l1_oracle()) => {
+                    return Err(ValidationError::Signature(
+                        "Deposit not signed by the L1 oracle".to_string(),
+                    ));
+                }
+                // Without the L1 signature a deposit can't be told from its replay
+                Ok(Some(SysOp::Deposit {
+                    l1_signature: None, ..
+                })) => {
+                    return Err(ValidationError::Format(
+                        "Deposit without its L1 signature".to_string(),
+                    ));
+                }
+                Err(e) => return Err(ValidationError::Format(e.to_string())),
+                Ok(_) => {}
+            }
         }
 
         // check count of account adn sig.
         if transaction.signatures.len() != message.header.num_required_signatures as usize {
-            return Err(anyhow!(
+            return Err(ValidationError::Format(format!(
                 "Signature count mismatch: expected {}, got {}",
                 message.header.num_required_signatures,
                 transaction.signatures.len()
-            ));
+            )));
         }
 
         info!("Transaction format validation passed");
         Ok(())
     }
 
+    // Dust transfers bloat blocks without any economic effect
+    fn validate_transfer_amounts(transaction: &Transaction, min_lamports: u64) -> Result<()> {
+        for instruction in transaction.message.instructions.iter() {
+            if let Ok(Some(SysOp::Transfer(transfer))) =
+                parsing_instruction(instruction, transaction)
+            {
+                if transfer.amount < Lamports(min_lamports) {
+                    return Err(ValidationError::Format(format!(
+                        "Transfer of {} lamports is below the minimum of {}",
+                        transfer.amount, min_lamports
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_compute_units(transaction: &Transaction, max_units: u64) -> Result<()> {
+        let units = compute_units(transaction);
+        if units > max_units {
+            return Err(ValidationError::Format(format!(
+                "Transaction costs {} compute units, above the limit of {}",
+                units, max_units
+            )));
+        }
+        Ok(())
+    }
+
     async fn validate_account_balances(_transaction: &Transaction) -> Result<()> {
         // Do nothing
         info!("Account balance validation passed (simplified)");
         Ok(())
     }
 
-    fn validate_fees(_txn: &Transaction) -> Result<()> {
-        let _estimated_fee = 5000;
+    fn validate_fees(txn: &Transaction) -> Result<()> {
+        let _estimated_fee = signature_fee(
+            txn.message.header.num_required_signatures,
+            config().lamports_per_signature,
+        );
 
         // Do nothing
         info!("Fee validation passed");
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{TransactionValidator, ValidationError};
+    use share::transaction::pre_process;
+    use solana_sdk::{
+        instruction::{AccountMeta, Instruction},
+        message::Message,
+        signature::Keypair,
+        signer::Signer,
+        system_instruction::{self, SystemInstruction},
+        system_program,
+        transaction::Transaction,
+    };
+
+    fn transfer_transaction(from: &Keypair) -> Transaction {
+        transfer_transaction_of(from, 1)
+    }
+
+    fn transfer_transaction_of(from: &Keypair, lamports: u64) -> Transaction {
+        let instruction =
+            system_instruction::transfer(&from.pubkey(), &Keypair::new().pubkey(), lamports);
+        Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&from.pubkey()),
+            &[from],
+            Default::default(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_account_keys() {
+        let from = Keypair::new();
+        let transaction = transfer_transaction(&from);
+        assert!(
+            TransactionValidator::validate_transaction(true, &transaction)
+                .await
+                .is_ok()
+        );
+
+        // The recipient key replaced by the sender's
+        let mut transaction = transfer_transaction(&from);
+        transaction.message.account_keys[1] = from.pubkey();
+        let error = TransactionValidator::validate_transaction(false, &transaction)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ValidationError::Format(_)));
+        assert!(error.to_string().contains("duplicate"));
+    }
+
+    #[tokio::test]
+    async fn test_signer_mismatch() {
+        let from = Keypair::new();
+
+        // More required signers than account keys
+        let mut transaction = transfer_transaction(&from);
+        transaction.message.header.num_required_signatures = 4;
+        let error = TransactionValidator::validate_transaction(false, &transaction)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ValidationError::Format(_)));
+        assert!(error.to_string().starts_with("Malformed transaction"));
+
+        // A signature count not matching the header
+        let mut transaction = transfer_transaction(&from);
+        transaction.message.header.num_required_signatures = 2;
+        assert!(
+            TransactionValidator::validate_transaction(false, &transaction)
+                .await
+                .is_err()
+        );
+
+        // The signer isn't the first key
+        let mut transaction = transfer_transaction(&from);
+        transaction.message.account_keys.swap(0, 1);
+        let error = TransactionValidator::validate_transaction(true, &transaction)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ValidationError::Signature(_)));
+        assert_eq!(
+            error.to_string(),
+            "Transaction did not pass signature verification"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dust_transfer() {
+        let from = Keypair::new();
+
+        // A 0 lamport transfer is rejected by the default minimum of 1
+        let error =
+            TransactionValidator::validate_transaction(true, &transfer_transaction_of(&from, 0))
+                .await
+                .unwrap_err();
+        assert!(matches!(error, ValidationError::Format(_)));
+        assert_eq!(
+            error.to_string(),
+            "Transfer of 0 lamports is below the minimum of 1"
+        );
+
+        let transaction = transfer_transaction_of(&from, 999);
+        assert!(TransactionValidator::validate_transfer_amounts(&transaction, 1_000).is_err());
+        let transaction = transfer_transaction_of(&from, 1_000);
+        assert!(TransactionValidator::validate_transfer_amounts(&transaction, 1_000).is_ok());
+        // 0 accepts any amount
+        let transaction = transfer_transaction_of(&from, 0);
+        assert!(TransactionValidator::validate_transfer_amounts(&transaction, 0).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_empty_instructions() {
+        let from = Keypair::new();
+        let message = Message::new(&[], Some(&from.pubkey()));
+        let transaction = Transaction::new(&[&from], message, Default::default());
+        let error = TransactionValidator::validate_transaction(true, &transaction)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ValidationError::Format(_)));
+        assert_eq!(error.to_string(), "Transaction has no instructions");
+
+        // Nothing to execute, even without a signature
+        assert!(pre_process(&transaction).unwrap().is_empty());
+        let mut unsigned = transaction.clone();
+        unsigned.signatures.clear();
+        assert!(pre_process(&unsigned).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_multi_signer_transfer() {
+        // The fee payer and the sender sign
+        let (payer, from) = (Keypair::new(), Keypair::new());
+        let instruction = system_instruction::transfer(&from.pubkey(), &Keypair::new().pubkey(), 1);
+        let message = Message::new(&[instruction], Some(&payer.pubkey()));
+        let transaction = Transaction::new(&[&payer, &from], message, Default::default());
+        assert_eq!(transaction.signatures.len(), 2);
+        assert!(
+            TransactionValidator::validate_transaction(true, &transaction)
+                .await
+                .is_ok()
+        );
+
+        // The sender's signature missing
+        let mut missing = transaction.clone();
+        missing.signatures.pop();
+        let error = TransactionValidator::validate_transaction(true, &missing)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ValidationError::Format(_)));
+        assert_eq!(
+            error.to_string(),
+            "Malformed transaction: index out of bounds"
+        );
+
+        // The sender's signature made by another key
+        let mut forged = transaction.clone();
+        forged.signatures[1] = Keypair::new().sign_message(&forged.message_data());
+        let error = TransactionValidator::validate_transaction(true, &forged)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ValidationError::Signature(_)));
+        assert_eq!(
+            error.to_string(),
+            "Transaction did not pass signature verification"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_transfer_from_non_signer() {
+        // Only the fee payer signs, the victim is listed as a plain writable account
+        let (payer, victim) = (Keypair::new(), Keypair::new());
+        let instruction = Instruction::new_with_bincode(
+            system_program::id(),
+            &SystemInstruction::Transfer { lamports: 1 },
+            vec![
+                AccountMeta::new(victim.pubkey(), false),
+                AccountMeta::new(payer.pubkey(), false),
+            ],
+        );
+        let message = Message::new(&[instruction], Some(&payer.pubkey()));
+        let transaction = Transaction::new(&[&payer], message, Default::default());
+
+        let error = TransactionValidator::validate_transaction(true, &transaction)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ValidationError::Format(_)));
+        assert_eq!(
+            error.to_string(),
+            format!("System instruction account {} didn't sign", victim.pubkey())
+        );
+        assert!(pre_process(&transaction).is_err());
+    }
+}
@@ -0,0 +1,103 @@
+use share::state::{State, WithdrawalProof, WithdrawalTree};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::metrics::{WITHDRAWAL_PROOF_CACHE_HITS, WITHDRAWAL_PROOF_CACHE_MISSES};
+
+// Bound of the cached trees, one per finalized range users withdraw against
+const MAX_CACHED_TREES: usize = 64;
+
+/// Withdrawal trees built for `getWithdrawalProof`, keyed by range.
+///
+/// The withdrawal queue is append-only, so the tree over its first `range` withdrawals
+/// and its root never change, and every user withdrawing against the same finalized
+/// root shares one tree. The cache is dropped when the queue shrinks (state reload).
+pub struct WithdrawalProofCache {
+    trees: HashMap<u64, WithdrawalTree>,
+    queue_len: usize,
+}
+
+impl WithdrawalProofCache {
+    pub fn new() -> Self {
+        Self {
+            trees: HashMap::new(),
+            queue_len: 0,
+        }
+    }
+
+    /// Proof of withdrawal `index` against the root of the first `range` withdrawals.
+    pub fn proof(&mut self, state: &State, index: u64, range: u64) -> Option<WithdrawalProof> {
+        let queue_len = state.withdrawal_queue.len();
+        if queue_len < self.queue_len {
+            self.trees.clear();
+        }
+        self.queue_len = queue_len;
+
+        if let Some(tree) = self.trees.get(&range) {
+            WITHDRAWAL_PROOF_CACHE_HITS.inc();
+            return tree.proof(index);
+        }
+        WITHDRAWAL_PROOF_CACHE_MISSES.inc();
+        let tree = state.withdrawal_tree(range)?;
+        let proof = tree.proof(index);
+        if self.trees.len() >= MAX_CACHED_TREES {
+            self.trees.clear();
+        }
+        self.trees.insert(range, tree);
+        proof
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref WITHDRAWAL_PROOF_CACHE: Arc<Mutex<WithdrawalProofCache>> = Arc::new(Mutex::new(WithdrawalProofCache::new()));
+}
+
+#[cfg(test)]
+mod test {
+    use super::WithdrawalProofCache;
+    use crate::metrics::{WITHDRAWAL_PROOF_CACHE_HITS, WITHDRAWAL_PROOF_CACHE_MISSES};
+    use share::lamports::Lamports;
+    use share::state::{State, Withdrawal};
+    use solana_sdk::pubkey::Pubkey;
+
+    fn state_with_withdrawals(count: u64) -> State {
+        let mut state = State::new();
+        for index in 0..count {
+            state.withdrawal_queue.push(Withdrawal {
+                from: Pubkey::new_unique().to_string(),
+                to: Pubkey::new_unique().to_string(),
+                amount: Lamports(1_000 + index),
+                index,
+            });
+        }
+        state
+    }
+
+    #[test]
+    fn test_withdrawal_proof_cache() {
+        let mut state = state_with_withdrawals(100);
+        let mut cache = WithdrawalProofCache::new();
+        let misses = WITHDRAWAL_PROOF_CACHE_MISSES.get();
+        let hits = WITHDRAWAL_PROOF_CACHE_HITS.get();
+
+        // One tree per range, the same proofs as building it every time
+        for index in 0..80 {
+            assert_eq!(
+                cache.proof(&state, index, 80),
+                state.generate_withdrawal_merkle_proof(index, 80)
+            );
+        }
+        assert_eq!(WITHDRAWAL_PROOF_CACHE_MISSES.get() - misses, 1);
+        assert_eq!(WITHDRAWAL_PROOF_CACHE_HITS.get() - hits, 79);
+        assert_eq!(cache.proof(&state, 80, 80), None);
+
+        // Appending keeps the finalized range, a shrunk queue drops it
+        state.withdrawal_queue.truncate(50);
+        assert_eq!(cache.proof(&state, 0, 80), None);
+        assert_eq!(
+            cache.proof(&state, 0, 50),
+            state.generate_withdrawal_merkle_proof(0, 50)
+        );
+    }
+}
@@ -0,0 +1,86 @@
+//! Backoff of the loops talking to L1 (batcher, oracle, prover), which keep going
+//! through an L1 outage: each consecutive failure doubles the wait, the first success
+//! after it resets the wait.
+
+use std::time::Duration;
+
+/// Longest wait between two attempts to reach L1.
+pub const MAX_L1_BACKOFF: Duration = Duration::from_secs(300);
+
+#[derive(Clone, Debug)]
+pub struct Backoff {
+    interval: Duration,
+    max: Duration,
+    failures: u32,
+}
+
+impl Backoff {
+    /// Wait `interval` while healthy, doubled per consecutive failure up to `max`.
+    pub fn new(interval: Duration, max: Duration) -> Self {
+        Self {
+            interval,
+            max: max.max(interval),
+            failures: 0,
+        }
+    }
+
+    /// Wait before the next attempt.
+    pub fn delay(&self) -> Duration {
+        let factor = 1u32.checked_shl(self.failures).unwrap_or(u32::MAX);
+        self.interval.saturating_mul(factor).min(self.max)
+    }
+
+    /// Record a failed attempt, returns the wait before the next one.
+    pub fn failure(&mut self) -> Duration {
+        self.failures = self.failures.saturating_add(1);
+        self.delay()
+    }
+
+    /// Record a successful attempt, returns the failures it recovered from.
+    pub fn success(&mut self) -> u32 {
+        std::mem::take(&mut self.failures)
+    }
+
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Backoff;
+    use std::time::Duration;
+
+    #[test]
+    fn test_l1_outage_backoff() {
+        let mut backoff = Backoff::new(Duration::from_secs(10), Duration::from_secs(60));
+        // L1 down for rounds 2 to 6
+        let l1_up = |round: u32| !(2..=6).contains(&round);
+
+        let mut delays = Vec::new();
+        for round in 0..10 {
+            let delay = if l1_up(round) {
+                backoff.success();
+                backoff.delay()
+            } else {
+                backoff.failure()
+            };
+            delays.push(delay.as_secs());
+        }
+        // Doubled through the outage up to the cap, back to the interval once L1 returns
+        assert_eq!(delays, vec![10, 10, 20, 40, 60, 60, 60, 10, 10, 10]);
+
+        // Recovered from the 5 failures of the outage
+        for _ in 0..5 {
+            backoff.failure();
+        }
+        assert_eq!(backoff.success(), 5);
+        assert_eq!(backoff.failures(), 0);
+
+        // No overflow however long the outage
+        for _ in 0..100 {
+            backoff.failure();
+        }
+        assert_eq!(backoff.delay(), Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,54 @@
+use sha2::{Digest, Sha256};
+use sha3::Keccak256;
+
+/// Hash algorithm of the state and withdrawal merkle trees.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HashAlgo {
+    Sha256,
+    Keccak256,
+}
+
+/// Algorithm selected at build time, `Keccak256` with the `keccak` feature.
+#[cfg(not(feature = "keccak"))]
+pub const HASH_ALGO: HashAlgo = HashAlgo::Sha256;
+#[cfg(feature = "keccak")]
+pub const HASH_ALGO: HashAlgo = HashAlgo::Keccak256;
+
+impl HashAlgo {
+    /// Hash the concatenation of `vals`.
+    pub fn hashv(&self, vals: &[&[u8]]) -> [u8; 32] {
+        match self {
+            HashAlgo::Sha256 => {
+                let mut hasher = Sha256::new();
+                vals.iter().for_each(|val| hasher.update(val));
+                hasher.finalize().into()
+            }
+            HashAlgo::Keccak256 => {
+                let mut hasher = Keccak256::new();
+                vals.iter().for_each(|val| hasher.update(val));
+                hasher.finalize().into()
+            }
+        }
+    }
+}
+
+/// Verify a merkle inclusion proof, mirrors `verify_merkle_proof` of the l2-state program.
+pub fn verify_merkle_proof(
+    algo: HashAlgo,
+    leaf_hash: [u8; 32],
+    proof: &[[u8; 32]],
+    index: u64,
+    root: [u8; 32],
+) -> bool {
+    let mut node = leaf_hash;
+
+    for (height, sibling) in proof.iter().enumerate() {
+        if (index >> height) & 1 == 1 {
+            node = algo.hashv(&[sibling, &node]);
+        } else {
+            node = algo.hashv(&[&node, sibling]);
+        }
+    }
+
+    node == root
+}
@@ -0,0 +1,168 @@
+//! Key-value storage behind `StateDB` and `BlockDB`: sled on disk, or an in-memory
+//! map for tests, which then need no directory and don't contend on sled's lock.
+
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+/// Writes applied together by `KvStore::apply_batch`.
+#[derive(Default)]
+pub struct KvBatch {
+    // `None` removes the key
+    ops: Vec<(String, Option<Vec<u8>>)>,
+}
+
+impl KvBatch {
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<Vec<u8>>) {
+        self.ops.push((key.into(), Some(value.into())));
+    }
+
+    pub fn remove(&mut self, key: impl Into<String>) {
+        self.ops.push((key.into(), None));
+    }
+}
+
+pub trait KvStore: Send + Sync {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+    fn insert(&self, key: &str, value: Vec<u8>) -> Result<()>;
+    fn remove(&self, key: &str) -> Result<()>;
+    fn contains_key(&self, key: &str) -> Result<bool> {
+        Ok(self.get(key)?.is_some())
+    }
+    /// Apply all the writes of `batch` atomically.
+    fn apply_batch(&self, batch: KvBatch) -> Result<()>;
+    /// Entries whose key starts with `prefix`, in key order.
+    fn scan_prefix(&self, prefix: &str) -> Result<Vec<(String, Vec<u8>)>>;
+    fn flush(&self) -> Result<()>;
+}
+
+impl KvStore for sled::Db {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(sled::Tree::get(self, key)?.map(|value| value.to_vec()))
+    }
+
+    fn insert(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        sled::Tree::insert(self, key, value)?;
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<()> {
+        sled::Tree::remove(self, key)?;
+        Ok(())
+    }
+
+    fn contains_key(&self, key: &str) -> Result<bool> {
+        Ok(sled::Tree::contains_key(self, key)?)
+    }
+
+    fn apply_batch(&self, batch: KvBatch) -> Result<()> {
+        let mut sled_batch = sled::Batch::default();
+        for (key, value) in batch.ops {
+            match value {
+                Some(value) => sled_batch.insert(key.as_bytes(), value),
+                None => sled_batch.remove(key.as_bytes()),
+            }
+        }
+        sled::Tree::apply_batch(self, sled_batch)?;
+        Ok(())
+    }
+
+    fn scan_prefix(&self, prefix: &str) -> Result<Vec<(String, Vec<u8>)>> {
+        sled::Tree::scan_prefix(self, prefix)
+            .map(|entry| {
+                let (key, value) = entry?;
+                Ok((String::from_utf8_lossy(&key).into_owned(), value.to_vec()))
+            })
+            .collect()
+    }
+
+    fn flush(&self) -> Result<()> {
+        sled::Tree::flush(self)?;
+        Ok(())
+    }
+}
+
+/// In-memory store, ordered like sled so prefix scans match.
+#[derive(Default)]
+pub struct MemoryStore {
+    map: RwLock<BTreeMap<String, Vec<u8>>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl KvStore for MemoryStore {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.map.read().unwrap().get(key).cloned())
+    }
+
+    fn insert(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        self.map.write().unwrap().insert(key.to_string(), value);
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<()> {
+        self.map.write().unwrap().remove(key);
+        Ok(())
+    }
+
+    fn apply_batch(&self, batch: KvBatch) -> Result<()> {
+        let mut map = self.map.write().unwrap();
+        for (key, value) in batch.ops {
+            match value {
+                Some(value) => map.insert(key, value),
+                None => map.remove(&key),
+            };
+        }
+        Ok(())
+    }
+
+    fn scan_prefix(&self, prefix: &str) -> Result<Vec<(String, Vec<u8>)>> {
+        Ok(self
+            .map
+            .read()
+            .unwrap()
+            .range(prefix.to_string()..)
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect())
+    }
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{KvBatch, KvStore, MemoryStore};
+
+    #[test]
+    fn test_kv_stores() {
+        let sled_db = sled::Config::new().temporary(true).open().unwrap();
+        let stores: [&dyn KvStore; 2] = [&sled_db, &MemoryStore::new()];
+        for store in stores {
+            store.insert("block_2", vec![2]).unwrap();
+            store.insert("latest", vec![9]).unwrap();
+            let mut batch = KvBatch::default();
+            batch.insert("block_1", vec![1]);
+            batch.remove("latest");
+            store.apply_batch(batch).unwrap();
+
+            assert_eq!(store.get("block_1").unwrap(), Some(vec![1]));
+            assert!(!store.contains_key("latest").unwrap());
+            assert_eq!(
+                store.scan_prefix("block_").unwrap(),
+                vec![
+                    ("block_1".to_string(), vec![1]),
+                    ("block_2".to_string(), vec![2])
+                ]
+            );
+            store.remove("block_2").unwrap();
+            assert_eq!(store.get("block_2").unwrap(), None);
+        }
+    }
+}
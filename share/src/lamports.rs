@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// An amount of lamports, on L2 and on the L1 bridge alike. Wider amounts only enter
+/// through `TryFrom<u128>`, which fails instead of truncating.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct Lamports(pub u64);
+
+/// An amount that doesn't fit in `Lamports`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, thiserror::Error)]
+#[error("{0} lamports overflows u64")]
+pub struct LamportsOverflow(pub u128);
+
+impl Lamports {
+    pub const ZERO: Lamports = Lamports(0);
+
+    pub fn checked_add(self, other: Lamports) -> Result<Lamports, LamportsOverflow> {
+        self.0
+            .checked_add(other.0)
+            .map(Lamports)
+            .ok_or(LamportsOverflow(u128::from(self.0) + u128::from(other.0)))
+    }
+
+    /// `None` when `other` is more than `self`.
+    pub fn checked_sub(self, other: Lamports) -> Option<Lamports> {
+        self.0.checked_sub(other.0).map(Lamports)
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl From<u64> for Lamports {
+    fn from(lamports: u64) -> Self {
+        Lamports(lamports)
+    }
+}
+
+impl From<Lamports> for u64 {
+    fn from(lamports: Lamports) -> Self {
+        lamports.0
+    }
+}
+
+impl From<Lamports> for u128 {
+    fn from(lamports: Lamports) -> Self {
+        u128::from(lamports.0)
+    }
+}
+
+impl TryFrom<u128> for Lamports {
+    type Error = LamportsOverflow;
+
+    fn try_from(lamports: u128) -> Result<Self, Self::Error> {
+        u64::try_from(lamports)
+            .map(Lamports)
+            .map_err(|_| LamportsOverflow(lamports))
+    }
+}
+
+impl fmt::Display for Lamports {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Lamports, LamportsOverflow};
+
+    #[test]
+    fn test_lamports_conversion() {
+        assert_eq!(Lamports::try_from(1_000u128), Ok(Lamports(1_000)));
+        assert_eq!(Lamports::try_from(u64::MAX as u128), Ok(Lamports(u64::MAX)));
+        // Rejected rather than wrapped to 0
+        let above = u64::MAX as u128 + 1;
+        assert_eq!(Lamports::try_from(above), Err(LamportsOverflow(above)));
+
+        assert_eq!(
+            Lamports(u64::MAX).checked_add(Lamports(1)),
+            Err(LamportsOverflow(above))
+        );
+        assert_eq!(Lamports(1).checked_sub(Lamports(2)), None);
+        assert_eq!(Lamports(3).checked_sub(Lamports(2)), Some(Lamports(1)));
+
+        // Stored as a plain number, like the u128 balances before it
+        assert_eq!(serde_json::to_string(&Lamports(42)).unwrap(), "42");
+        assert_eq!(
+            serde_json::from_str::<Lamports>("42").unwrap(),
+            Lamports(42)
+        );
+    }
+}
@@ -1,14 +1,32 @@
+pub mod backoff;
+pub mod hash;
+pub mod kv;
+pub mod lamports;
+pub mod liveness;
 pub mod state;
+pub mod state_tree;
+pub mod storage;
 pub mod transaction;
 pub mod utils;
 pub mod zkvm;
 
-// L2 Widhdrawal address
-pub static WITHDRAWAL_ADDRESS: &str = "AF111111111111111111111111111111";
+// Default L2 withdrawal address, a transfer to it withdraws to L1. Overridden at
+// startup by `transaction::set_withdrawal_addresses`.
+pub static WITHDRAWAL_ADDRESS: &str = "Withdraw11111111111111111111111111111111111";
+// System program, the owner of plain lamport accounts.
+pub static SYSTEM_PROGRAM_ID: &str = "11111111111111111111111111111111";
 // L2 Sys Program ID.
 pub static L2_SYS_PROGRAM_ID: &str = "My11111111111111111111111111111111111111111";
+// SPL Memo program, v3 and the legacy v1.
+pub static MEMO_PROGRAM_IDS: [&str; 2] = [
+    "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr",
+    "Memo1UhkJRfHyvLMcVucJwxXeuD728EqVDDwQDxFMNo",
+];
 pub static DEFAULT_L1_RPC: &str = "http://localhost:8898";
 pub static DEFAULT_L1_WS: &str = "ws://127.0.0.1:8900";
 pub static DEFAULT_L2_RPC: &str = "http://localhost:8899";
+pub static DEFAULT_STATE_DB_PATH: &str = "state_db";
+pub static DEFAULT_BLOCK_DB_PATH: &str = "block_db";
+pub static DEFAULT_BLOCK_CACHE_SIZE: usize = 128;
 pub static UNSAFE_PRIVATE_KEY: &str =
     "2bCxRJ2GSYnbEHMPsxcf6dWzFtNzaQLFXbNDSAqr2aWaSBgFbhnFejoC4z9LHcLGzkjvY6ZtBFWDoEzcVqq82PSo";
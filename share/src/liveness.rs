@@ -0,0 +1,83 @@
+//! Liveness of the background loops (batcher, prover), which log their errors and keep
+//! looping: a loop that hasn't succeeded for a while is wedged, not alive.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Last success of a loop, in unix seconds.
+pub struct Liveness {
+    name: &'static str,
+    started: u64,
+    last_success: AtomicU64,
+}
+
+impl Liveness {
+    pub fn new(name: &'static str) -> Self {
+        Self::started_at(name, unix_now())
+    }
+
+    pub fn started_at(name: &'static str, started: u64) -> Self {
+        Self {
+            name,
+            started,
+            last_success: AtomicU64::new(0),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn record_success(&self) {
+        self.record_success_at(unix_now());
+    }
+
+    pub fn record_success_at(&self, now: u64) {
+        self.last_success.fetch_max(now, Ordering::Relaxed);
+    }
+
+    /// `None` until the first success.
+    pub fn last_success(&self) -> Option<u64> {
+        Some(self.last_success.load(Ordering::Relaxed)).filter(|last| *last > 0)
+    }
+
+    /// Whether the loop hasn't succeeded within `threshold`, counted from its start
+    /// until the first success.
+    pub fn is_stalled(&self, now: u64, threshold: Duration) -> bool {
+        let since = self.last_success().unwrap_or(self.started);
+        now.saturating_sub(since) > threshold.as_secs()
+    }
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::Liveness;
+    use std::time::Duration;
+
+    #[test]
+    fn test_liveness() {
+        let threshold = Duration::from_secs(300);
+        let liveness = Liveness::started_at("batcher", 1_000);
+        assert_eq!(liveness.last_success(), None);
+
+        // A fresh loop gets the threshold for its first success
+        assert!(!liveness.is_stalled(1_300, threshold));
+        assert!(liveness.is_stalled(1_301, threshold));
+
+        liveness.record_success_at(1_200);
+        assert_eq!(liveness.last_success(), Some(1_200));
+        assert!(!liveness.is_stalled(1_500, threshold));
+        assert!(liveness.is_stalled(1_501, threshold));
+
+        // Never goes backwards
+        liveness.record_success_at(1_100);
+        assert_eq!(liveness.last_success(), Some(1_200));
+    }
+}
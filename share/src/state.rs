@@ -1,63 +1,580 @@
+use anyhow::{anyhow, Result};
+use log::warn;
 use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
-use std::collections::HashMap;
+use solana_sdk::{pubkey::Pubkey, transaction::Transaction};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::kv::{KvStore, MemoryStore};
+use crate::{
+    hash::{HashAlgo, HASH_ALGO},
+    lamports::Lamports,
+    state_tree::{self, account_key, leaf_hash, IncrementalTree, TreeNode},
+    storage,
+    transaction::{
+        calculate_txns_root, is_withdrawal_address, l1_oracle, pre_process, signed_by, Block,
+        BlockDB, SysOp, TransferOp,
+    },
+    SYSTEM_PROGRAM_ID,
+};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct State {
-    pub balances: HashMap<String, u128>, // address -> balance
+    pub accounts: HashMap<String, AccountData>, // address -> account
     pub withdrawal_queue: Vec<Withdrawal>,
+    // Tree nodes standing in for the accounts left out of a partial state
+    #[serde(default)]
+    pub pruned: Vec<TreeNode>,
+    // Credited L1 deposits, L1 signature -> deposit, not part of the state root
+    #[serde(default)]
+    pub deposits: HashMap<String, DepositRecord>,
+    // Index of the next queued withdrawal, see `next_withdrawal_index`
+    #[serde(default)]
+    withdrawal_index: WithdrawalIndex,
+    // Tree of `accounts` behind `incremental_root`, built on its first call and kept
+    // up to date by the account setters
+    #[serde(skip)]
+    tree: Option<IncrementalTree>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AccountData {
+    pub lamports: Lamports,
+    pub owner: String,
+    pub data: Vec<u8>,
+    pub executable: bool,
+}
+
+impl AccountData {
+    /// Whether the account holds neither lamports nor data, it is swept from the state.
+    pub fn is_empty(&self) -> bool {
+        self.lamports.is_zero() && self.data.is_empty()
+    }
+
+    /// A plain lamport account owned by the system program.
+    pub fn new(lamports: Lamports) -> Self {
+        Self {
+            lamports,
+            owner: SYSTEM_PROGRAM_ID.to_string(),
+            data: Vec::new(),
+            executable: false,
+        }
+    }
+}
+
+/// An L1 deposit credited on L2.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DepositRecord {
+    pub to: String,
+    pub amount: Lamports,
+    // L2 block crediting the deposit
+    pub block_num: u64,
+}
+
+/// Outcome of `State::apply_txns` for a block's transactions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AppliedTxns {
+    /// Leading transactions gone through, the ones without system ops to apply
+    /// included. The transaction after them failed and neither it nor the rest were
+    /// applied.
+    pub count: usize,
+    /// Lamports minted by the credited deposits.
+    pub minted: u128,
+}
+
+/// What `State::apply_txns` can change for a block, taken by `State::block_snapshot`
+/// before applying it so `State::restore` can undo the block.
+#[derive(Clone, Debug)]
+pub struct BlockSnapshot {
+    // Accounts the block's ops touch, `None` for the ones that don't exist yet
+    accounts: Vec<(String, Option<AccountData>)>,
+    withdrawals: usize,
+    next_withdrawal_index: u64,
+    // L1 signatures of the block's deposits not credited yet
+    new_deposits: Vec<String>,
+}
+
+/// Why a system op failed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SysOpError {
+    InsufficientBalance,
+    // Crediting the account would take its balance past u64::MAX
+    BalanceOverflow,
+    AccountInUse(String),
+    Unsupported(String),
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Withdrawal {
     pub from: String,
     pub to: String,
-    pub amount: u64,
+    pub amount: Lamports,
     pub index: u64,
 }
 
+/// Allocator of withdrawal indices. Atomic so executors sharing the state hand out
+/// unique indices, a clone starts from the same next index.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+struct WithdrawalIndex(AtomicU64);
+
+impl Clone for WithdrawalIndex {
+    fn clone(&self) -> Self {
+        Self(AtomicU64::new(self.0.load(Ordering::SeqCst)))
+    }
+}
+
+/// Withdrawal merkle proof to claim on L1.
+pub type WithdrawalProof = (
+    [u8; 32],      // leaf_hash
+    Vec<[u8; 32]>, // proof
+    u64,           // index
+    [u8; 32],      // root
+);
+
+/// Version of the `State::export_snapshot` format.
+pub const SNAPSHOT_VERSION: u8 = 1;
+
+// A full state as exported for fast sync, maps are sorted so the encoding is
+// deterministic.
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    version: u8,
+    // Root the imported state must reach
+    state_root: [u8; 32],
+    accounts: BTreeMap<String, AccountData>,
+    withdrawal_queue: Vec<Withdrawal>,
+    pruned: Vec<TreeNode>,
+    deposits: BTreeMap<String, DepositRecord>,
+    next_withdrawal_index: u64,
+}
+
+// Genesis balance of the dev account, 100 SOL in lamports.
+pub static DEV_ACCOUNT_GENESIS_BALANCE: Lamports = Lamports(100 * 1_000_000_000);
+
 impl State {
     pub fn new() -> Self {
         Self {
-            balances: HashMap::new(),
+            accounts: HashMap::new(),
             withdrawal_queue: Vec::new(),
+            pruned: Vec::new(),
+            deposits: HashMap::new(),
+            withdrawal_index: WithdrawalIndex::default(),
+            tree: None,
+        }
+    }
+
+    /// Allocate the index of a new withdrawal. Indices are unique and contiguous from 0
+    /// whatever executes the transactions queueing them, which keeps the withdrawal
+    /// tree valid (see `sorted_withdrawals`).
+    pub fn next_withdrawal_index(&self) -> u64 {
+        self.withdrawal_index.0.fetch_add(1, Ordering::SeqCst)
+    }
+
+    /// Index the next withdrawal gets, without allocating it.
+    pub fn peek_withdrawal_index(&self) -> u64 {
+        self.withdrawal_index.0.load(Ordering::SeqCst)
+    }
+
+    /// Build the genesis state, seeding the dev account (if any) with 100 SOL.
+    pub fn genesis(dev_account: Option<String>) -> Self {
+        let mut state = Self::new();
+        if let Some(account) = dev_account {
+            state.set_balance(account, DEV_ACCOUNT_GENESIS_BALANCE);
+        }
+        state
+    }
+
+    /// Canonical genesis state root, `[0u8; 32]` for an empty genesis.
+    pub fn genesis_state_root(dev_account: Option<String>) -> [u8; 32] {
+        Self::genesis(dev_account)
+            .calculate_state_root()
+            .unwrap_or_default()
+    }
+
+    /// Encode the whole state with its root, for a replica to start from it with
+    /// `import_snapshot` instead of replaying every block. The same state always
+    /// encodes to the same bytes.
+    pub fn export_snapshot(&self) -> Vec<u8> {
+        let snapshot = Snapshot {
+            version: SNAPSHOT_VERSION,
+            state_root: self.calculate_state_root().unwrap_or_default(),
+            accounts: self.accounts.clone().into_iter().collect(),
+            withdrawal_queue: self.withdrawal_queue.clone(),
+            pruned: self.pruned.clone(),
+            deposits: self.deposits.clone().into_iter().collect(),
+            next_withdrawal_index: self.peek_withdrawal_index(),
+        };
+        bincode::serialize(&snapshot).expect("snapshot serializes")
+    }
+
+    /// Decode a state exported by `export_snapshot`, failing on another version, when
+    /// it doesn't reach the root it was exported with, or when its next withdrawal
+    /// index isn't the length of its withdrawal queue.
+    pub fn import_snapshot(bytes: &[u8]) -> Result<Self> {
+        let snapshot: Snapshot =
+            bincode::deserialize(bytes).map_err(|e| anyhow!("Invalid snapshot: {}", e))?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(anyhow!("Unsupported snapshot version {}", snapshot.version));
+        }
+        // Withdrawals are never removed from the queue, the indices are 0..len
+        if snapshot.next_withdrawal_index != snapshot.withdrawal_queue.len() as u64 {
+            return Err(anyhow!(
+                "Snapshot next withdrawal index {} doesn't follow its {} withdrawals",
+                snapshot.next_withdrawal_index,
+                snapshot.withdrawal_queue.len()
+            ));
+        }
+        if let Some(withdrawal) = snapshot
+            .withdrawal_queue
+            .iter()
+            .find(|withdrawal| !withdrawal_addresses_valid(withdrawal))
+        {
+            return Err(anyhow!(
+                "Snapshot withdrawal {} has an address that isn't a pubkey",
+                withdrawal.index
+            ));
+        }
+        let state = Self {
+            accounts: snapshot.accounts.into_iter().collect(),
+            withdrawal_queue: snapshot.withdrawal_queue,
+            pruned: snapshot.pruned,
+            deposits: snapshot.deposits.into_iter().collect(),
+            withdrawal_index: WithdrawalIndex(AtomicU64::new(snapshot.next_withdrawal_index)),
+            tree: None,
+        };
+        let state_root = state.calculate_state_root().unwrap_or_default();
+        if state_root != snapshot.state_root {
+            return Err(anyhow!(
+                "Snapshot state root mismatch, exported {:?}, imported {:?}",
+                snapshot.state_root,
+                state_root
+            ));
+        }
+        Ok(state)
+    }
+
+    /// Witness of `addresses`: a partial state keeping only those accounts in full,
+    /// plus the pruned tree nodes proving them against the state root, which is
+    /// unchanged. Accounts in `addresses` that don't exist yet can be created on it.
+    /// The withdrawal queue is kept whole, the zkVM program commits its root.
+    pub fn partial(&self, addresses: &HashSet<String>) -> Self {
+        let mut state = Self::new();
+        state.withdrawal_queue = self.withdrawal_queue.clone();
+        state.withdrawal_index = self.withdrawal_index.clone();
+        for address in addresses {
+            if let Some(account) = self.accounts.get(address) {
+                state.accounts.insert(address.clone(), account.clone());
+            }
         }
+        let keep = addresses
+            .iter()
+            .map(|address| account_key(HASH_ALGO, address))
+            .collect();
+        state.pruned = state_tree::prune(HASH_ALGO, self.tree_nodes(HASH_ALGO), keep);
+        state
     }
-    pub fn get_balance(&self, address: &str) -> u128 {
-        *self.balances.get(address).unwrap_or(&0)
+
+    /// Whether the account was left out of this partial state.
+    pub fn is_pruned(&self, address: &str) -> bool {
+        let key = account_key(HASH_ALGO, address);
+        self.pruned.iter().any(|node| node.covers(&key))
+    }
+
+    pub fn get_account(&self, address: &str) -> Option<&AccountData> {
+        self.accounts.get(address)
     }
 
-    pub fn set_balance(&mut self, address: String, balance: u128) {
-        self.balances.insert(address, balance);
+    pub fn set_account(&mut self, address: String, account: AccountData) {
+        self.accounts.insert(address.clone(), account);
+        self.update_leaf(&address);
     }
 
-    pub fn add_balance(&mut self, address: String, amount: u128) {
-        let current_balance = self.get_balance(&address);
-        let new_balance = current_balance.saturating_add(amount);
+    /// Balances view over `accounts`, address -> lamports.
+    pub fn balances(&self) -> HashMap<String, Lamports> {
+        self.accounts
+            .iter()
+            .map(|(address, account)| (address.clone(), account.lamports))
+            .collect()
+    }
+
+    pub fn get_balance(&self, address: &str) -> Lamports {
+        self.accounts
+            .get(address)
+            .map(|account| account.lamports)
+            .unwrap_or_default()
+    }
+
+    pub fn set_balance(&mut self, address: String, balance: Lamports) {
+        self.accounts
+            .entry(address.clone())
+            .or_insert_with(|| AccountData::new(Lamports::ZERO))
+            .lamports = balance;
+        self.update_leaf(&address);
+    }
+
+    /// Credit `address`, failing rather than wrapping past u64::MAX.
+    pub fn add_balance(&mut self, address: String, amount: Lamports) -> Result<(), SysOpError> {
+        let new_balance = self
+            .get_balance(&address)
+            .checked_add(amount)
+            .map_err(|_| SysOpError::BalanceOverflow)?;
         self.set_balance(address, new_balance);
+        Ok(())
     }
 
-    pub fn sub_balance(&mut self, address: String, amount: u128) -> bool {
-        let current_balance = self.get_balance(&address);
-        if current_balance >= amount {
-            let new_balance = current_balance - amount;
-            self.set_balance(address, new_balance);
-            true
-        } else {
-            false
+    /// Execute the system ops of block `block_num`'s `txns`, queueing the transfers to
+    /// the withdrawal address and recording the credited deposits. Shared by the
+    /// sequencer, the replay and the prover, they must stay in lockstep. A transaction
+    /// that fails stops the block, see `AppliedTxns`.
+    pub fn apply_txns(&mut self, txns: &[Transaction], block_num: u64) -> AppliedTxns {
+        let mut applied = AppliedTxns::default();
+        for txn in txns {
+            let Ok(ops) = pre_process(txn) else {
+                applied.count += 1;
+                continue;
+            };
+            // a failed transaction stops the block, as does a withdrawal whose leaf
+            // can't be built
+            if !self.deposits_creditable(txn, &ops)
+                || !withdrawals_queueable(&ops)
+                || self.apply_txn_ops(&ops).is_err()
+            {
+                break;
+            }
+            applied.count += 1;
+            // Only an applied transaction queues its withdrawals, a failed one would
+            // leave a gap in the indices
+            for transfer in ops.iter().filter_map(withdrawal_transfer) {
+                let withdrawal = self.withdrawal(transfer);
+                self.withdrawal_queue.push(withdrawal);
+            }
+            for op in ops {
+                if let SysOp::Deposit {
+                    to,
+                    amount,
+                    l1_signature,
+                } = op
+                {
+                    applied.minted += u128::from(amount);
+                    if let Some(l1_signature) = l1_signature {
+                        self.deposits.entry(l1_signature).or_insert(DepositRecord {
+                            to,
+                            amount,
+                            block_num,
+                        });
+                    }
+                }
+            }
+        }
+        applied
+    }
+
+    // Whether the deposits of `txn` can be credited: carrying a valid signature of the
+    // L1 oracle and each L1 deposit once. A deposit relayed without its L1 signature is
+    // only checked for the signer, the validator no longer accepts them.
+    fn deposits_creditable(&self, txn: &Transaction, ops: &[SysOp]) -> bool {
+        let mut l1_signatures = HashSet::new();
+        for op in ops {
+            if let SysOp::Deposit { l1_signature, .. } = op {
+                if !signed_by(txn, &l1_oracle()) {
+                    return false;
+                }
+                if let Some(l1_signature) = l1_signature {
+                    if self.deposits.contains_key(l1_signature)
+                        || !l1_signatures.insert(l1_signature)
+                    {
+                        return false;
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    // The withdrawal `transfer` queues, with a newly allocated index
+    fn withdrawal(&self, transfer: &TransferOp) -> Withdrawal {
+        Withdrawal {
+            from: transfer.from.clone(),
+            to: transfer.from.clone(), // withdrawal to sender
+            amount: transfer.amount,
+            index: self.next_withdrawal_index(),
+        }
+    }
+
+    /// Apply the ops of one transaction, all or nothing: when one fails the accounts
+    /// touched by the earlier ones are restored. The accounts it leaves empty are swept.
+    pub fn apply_txn_ops(&mut self, ops: &[SysOp]) -> Result<(), SysOpError> {
+        let snapshot: Vec<(String, Option<AccountData>)> = ops
+            .iter()
+            .flat_map(|op| op.accounts())
+            .map(|address| (address.clone(), self.accounts.get(address).cloned()))
+            .collect();
+        for op in ops {
+            if let Err(e) = self.apply_sys_op(op) {
+                for (address, account) in snapshot {
+                    match account {
+                        Some(account) => self.accounts.insert(address.clone(), account),
+                        None => self.accounts.remove(&address),
+                    };
+                    self.update_leaf(&address);
+                }
+                return Err(e);
+            }
+        }
+        self.sweep_empty_accounts(ops.iter().flat_map(|op| op.accounts()));
+        Ok(())
+    }
+
+    /// Snapshot what applying `txns` can change: the accounts their ops touch, the
+    /// withdrawal queue and the deposits they credit. Cheaper than a clone of the state,
+    /// it grows with the block only.
+    pub fn block_snapshot(&self, txns: &[Transaction]) -> BlockSnapshot {
+        let ops: Vec<SysOp> = txns
+            .iter()
+            .filter_map(|txn| pre_process(txn).ok())
+            .flatten()
+            .collect();
+        let mut addresses: Vec<&String> = ops.iter().flat_map(|op| op.accounts()).collect();
+        addresses.sort();
+        addresses.dedup();
+        BlockSnapshot {
+            accounts: addresses
+                .into_iter()
+                .map(|address| (address.clone(), self.accounts.get(address).cloned()))
+                .collect(),
+            withdrawals: self.withdrawal_queue.len(),
+            next_withdrawal_index: self.peek_withdrawal_index(),
+            new_deposits: ops
+                .iter()
+                .filter_map(|op| match op {
+                    SysOp::Deposit {
+                        l1_signature: Some(l1_signature),
+                        ..
+                    } if !self.deposits.contains_key(l1_signature) => Some(l1_signature.clone()),
+                    _ => None,
+                })
+                .collect(),
+        }
+    }
+
+    /// Undo the block `snapshot` was taken before, the state is back to where it was.
+    pub fn restore(&mut self, snapshot: BlockSnapshot) {
+        for (address, account) in snapshot.accounts {
+            match account {
+                Some(account) => self.accounts.insert(address.clone(), account),
+                None => self.accounts.remove(&address),
+            };
+            self.update_leaf(&address);
+        }
+        self.withdrawal_queue.truncate(snapshot.withdrawals);
+        self.withdrawal_index = WithdrawalIndex(AtomicU64::new(snapshot.next_withdrawal_index));
+        for l1_signature in snapshot.new_deposits {
+            self.deposits.remove(&l1_signature);
+        }
+    }
+
+    /// Remove the `addresses` left empty, so zero-balance accounts don't inflate the
+    /// state tree. Only the accounts touched by a transaction are swept, which a
+    /// partial state holds in full, so the prover sweeps the same ones.
+    fn sweep_empty_accounts<'a>(&mut self, addresses: impl Iterator<Item = &'a String>) {
+        for address in addresses {
+            if self
+                .accounts
+                .get(address)
+                .is_some_and(AccountData::is_empty)
+            {
+                self.accounts.remove(address);
+                self.update_leaf(address);
+            }
+        }
+    }
+
+    /// Apply a system op, the state is unchanged when it fails.
+    pub fn apply_sys_op(&mut self, op: &SysOp) -> Result<(), SysOpError> {
+        match op {
+            // Moves nothing, the sender only needs the amount, like Solana. Nothing is
+            // written either, so a missing sender isn't created and swept
+            SysOp::Transfer(transfer) if transfer.from == transfer.to => {
+                if self.get_balance(&transfer.from) < transfer.amount {
+                    return Err(SysOpError::InsufficientBalance);
+                }
+            }
+            SysOp::Transfer(transfer) => {
+                // check sender's amount and change the balance
+                // check the credit first, a failed op leaves the state unchanged
+                if self
+                    .get_balance(&transfer.to)
+                    .checked_add(transfer.amount)
+                    .is_err()
+                {
+                    return Err(SysOpError::BalanceOverflow);
+                }
+                if !self.sub_balance(transfer.from.clone(), transfer.amount) {
+                    return Err(SysOpError::InsufficientBalance);
+                }
+                self.add_balance(transfer.to.clone(), transfer.amount)?;
+            }
+            SysOp::CreateAccount {
+                from,
+                to,
+                lamports,
+                space,
+                owner,
+            } => {
+                // Like Solana, an account holding lamports or data is in use
+                if self
+                    .get_account(to)
+                    .is_some_and(|account| !account.is_empty())
+                {
+                    return Err(SysOpError::AccountInUse(to.clone()));
+                }
+                if !self.sub_balance(from.clone(), *lamports) {
+                    return Err(SysOpError::InsufficientBalance);
+                }
+                self.set_account(
+                    to.clone(),
+                    AccountData {
+                        lamports: *lamports,
+                        owner: owner.clone(),
+                        data: vec![0; *space as usize],
+                        executable: false,
+                    },
+                );
+            }
+            SysOp::Deposit { to, amount, .. } => self.add_balance(to.clone(), *amount)?,
+            SysOp::Memo { .. } | SysOp::ComputeBudget { .. } => {}
+            SysOp::Unsupported { kind } => return Err(SysOpError::Unsupported(kind.clone())),
+        }
+        Ok(())
+    }
+
+    pub fn sub_balance(&mut self, address: String, amount: Lamports) -> bool {
+        match self.get_balance(&address).checked_sub(amount) {
+            Some(new_balance) => {
+                self.set_balance(address, new_balance);
+                true
+            }
+            None => false,
         }
     }
 }
 
 pub struct StateDB {
-    pub db: sled::Db,
-    pub cache: HashMap<String, u128>,
+    pub db: Arc<dyn KvStore>,
+    pub cache: HashMap<String, Lamports>,
     pub state: State,
 }
 
 impl StateDB {
     pub fn new(db_path: &str) -> Self {
-        let db = sled::open(db_path).unwrap();
+        Self::with_store(Arc::new(sled::open(db_path).unwrap()))
+    }
+
+    /// A state db on `store`, e.g. a `MemoryStore` in tests.
+    pub fn with_store(db: Arc<dyn KvStore>) -> Self {
         StateDB {
             db,
             cache: HashMap::new(),
@@ -65,250 +582,1334 @@ impl StateDB {
         }
     }
 
+    pub fn in_memory() -> Self {
+        Self::with_store(Arc::new(MemoryStore::new()))
+    }
+
     pub fn save(&self) {
-        let balances = serde_json::to_vec(&self.state.balances).unwrap();
-        self.db.insert("balance_state", balances).unwrap();
+        let accounts = storage::encode(&self.state.accounts).unwrap();
+        self.db.insert("account_state", accounts).unwrap();
 
-        let withdrawal = bincode::serialize(&self.state.withdrawal_queue).unwrap();
+        let withdrawal = storage::encode(&self.state.withdrawal_queue).unwrap();
         self.db.insert("withdrawal_queue", withdrawal).unwrap();
+
+        let deposits = storage::encode(&self.state.deposits).unwrap();
+        self.db.insert("deposits", deposits).unwrap();
+
+        let withdrawal_index = storage::encode(&self.state.peek_withdrawal_index()).unwrap();
+        self.db
+            .insert("next_withdrawal_index", withdrawal_index)
+            .unwrap();
     }
 
+    /// Load the saved state, data stored in an older version is upgraded and saved back.
     pub fn load(&mut self) {
-        if let Ok(Some(data)) = self.db.get("balance_state") {
+        let mut upgraded = false;
+        if let Ok(Some(data)) = self.db.get("account_state") {
+            // v0 is plain JSON
+            if let Ok((accounts, version)) =
+                storage::decode(&data, |v0| serde_json::from_slice(v0).ok())
+            {
+                self.state.accounts = accounts;
+                self.state.tree = None;
+                upgraded |= version < storage::STORAGE_VERSION;
+            }
+        } else if let Ok(Some(data)) = self.db.get("balance_state") {
+            // Legacy db with balances only, load them as system accounts
             if let Ok(user_balances) = serde_json::from_slice::<HashMap<String, u128>>(&data) {
-                self.state.balances = user_balances;
+                for (address, balance) in user_balances {
+                    match Lamports::try_from(balance) {
+                        Ok(balance) => self.state.set_balance(address, balance),
+                        Err(e) => warn!("Skipping legacy balance of {}: {}", address, e),
+                    }
+                }
+                upgraded = true;
             }
         }
         if let Ok(Some(data)) = self.db.get("withdrawal_queue") {
-            if let Ok(withdrawal_queue) = bincode::deserialize(&data) {
+            // v0 is bincode
+            if let Ok((withdrawal_queue, version)) =
+                storage::decode(&data, |v0| bincode::deserialize(v0).ok())
+            {
                 self.state.withdrawal_queue = withdrawal_queue;
+                upgraded |= version < storage::STORAGE_VERSION;
             }
         }
+        if let Ok(Some(data)) = self.db.get("deposits") {
+            // Recorded since v1
+            if let Ok((deposits, _)) = storage::decode(&data, |_| None) {
+                self.state.deposits = deposits;
+            }
+        }
+        // Older dbs don't record it, their withdrawal indices are the queue positions
+        let withdrawal_index = match self.db.get("next_withdrawal_index") {
+            Ok(Some(data)) => storage::decode(&data, |_| None)
+                .ok()
+                .map(|(index, _)| index),
+            _ => None,
+        };
+        self.state.withdrawal_index = WithdrawalIndex(AtomicU64::new(
+            withdrawal_index.unwrap_or(self.state.withdrawal_queue.len() as u64),
+        ));
+        if upgraded {
+            self.save();
+        }
+    }
+
+    /// Rebuild the state by replaying every block of `block_db` on top of `genesis`,
+    /// for when the state db is lost or corrupted. Each block must reach its own
+    /// post_state_root and the last one `latest_state_root`, the rebuilt state is
+    /// then saved. Returns the rebuilt state root.
+    pub fn rebuild_from_blocks(&mut self, block_db: &BlockDB, genesis: State) -> Result<[u8; 32]> {
+        let latest_block_num = block_db.latest_block_num().unwrap_or(0);
+        let mut state = genesis;
+        let mut state_root = state.calculate_state_root().unwrap_or_default();
+        for block_num in 1..=latest_block_num {
+            let block = block_db
+                .get_block(block_num)
+                .ok_or_else(|| anyhow!("Block {} missing from block db", block_num))?;
+            if block.prev_state_root.unwrap_or_default() != state_root {
+                return Err(anyhow!("Block {} prev_state_root mismatch", block_num));
+            }
+            state.apply_txns(&block.txns, block_num);
+            state_root = state.calculate_state_root().unwrap_or_default();
+            if block.post_state_root.unwrap_or_default() != state_root {
+                return Err(anyhow!("Block {} post_state_root mismatch", block_num));
+            }
+        }
+        if let Some(latest_state_root) = block_db.latest_state_root() {
+            if latest_state_root != state_root {
+                return Err(anyhow!(
+                    "Rebuilt state root doesn't match latest_state_root"
+                ));
+            }
+        }
+
+        self.state = state;
+        self.save();
+        Ok(state_root)
     }
 }
 
-// Calculate hash for a account's state
-fn calculate_user_hash(address: &str, balance: &u128) -> [u8; 32] {
-    let mut hasher = Sha256::new();
+/// Replay blocks `1..=latest_block_num` of `block_db` on `genesis` and check the roots
+/// each one stores against the replay: txns root, prev and post state root, withdrawal
+/// root. Returns the number of blocks verified, the first mismatch is the error.
+pub fn verify_blocks(block_db: &BlockDB, genesis: State) -> Result<u64> {
+    let latest_block_num = block_db.latest_block_num().unwrap_or(0);
+    let mut state = genesis;
+    let mut state_root = state.incremental_root();
+    for block_num in 1..=latest_block_num {
+        let block = block_db
+            .get_block(block_num)
+            .ok_or_else(|| anyhow!("Block {} missing from block db", block_num))?;
+        // Blocks store the root of an empty state or queue as zeroes, see `seal_block`
+        let check = |field: &str, stored: Option<[u8; 32]>, replayed: Option<[u8; 32]>| {
+            let (stored, replayed) = (stored.unwrap_or_default(), replayed.unwrap_or_default());
+            if stored == replayed {
+                Ok(())
+            } else {
+                Err(anyhow!(
+                    "Block {} {} mismatch, stored {:?}, replayed {:?}",
+                    block_num,
+                    field,
+                    stored,
+                    replayed
+                ))
+            }
+        };
+        check(
+            "txns_root",
+            block.txns_root,
+            Some(calculate_txns_root(&block.txns)),
+        )?;
+        check("prev_state_root", block.prev_state_root, state_root)?;
 
-    // Hash user address
-    hasher.update(address.as_bytes());
-    // Hash user balance
-    hasher.update(&balance.to_be_bytes());
+        state.apply_txns(&block.txns, block_num);
+        state_root = state.incremental_root();
+        check("post_state_root", block.post_state_root, state_root)?;
+        check(
+            "withdrawal_root",
+            block.withdrawal_root,
+            state.calculate_withdrawal_root(),
+        )?;
+    }
+    Ok(latest_block_num)
+}
 
-    hasher.finalize().into()
+// Calculate hash for a account's state
+fn calculate_account_hash(algo: HashAlgo, address: &str, account: &AccountData) -> [u8; 32] {
+    // Hash account address, lamports, owner, executable flag and data
+    algo.hashv(&[
+        address.as_bytes(),
+        // 16 bytes, the width balances were hashed at when they were u128
+        &u128::from(account.lamports).to_be_bytes(),
+        account.owner.as_bytes(),
+        &[account.executable as u8],
+        &account.data,
+    ])
+}
+
+// Whether the withdrawals of `ops` have pubkey addresses, the leaves hash them as such
+fn withdrawals_queueable(ops: &[SysOp]) -> bool {
+    ops.iter()
+        .filter_map(withdrawal_transfer)
+        .all(|transfer| Pubkey::from_str(&transfer.from).is_ok())
 }
 
 // Calculate hash for a withdrawal
-fn calculate_withdrawal_hash(withdrawal: &Withdrawal) -> [u8; 32] {
-    let mut hasher = Sha256::new();
+// The transfer of `op` when it withdraws to L1
+fn withdrawal_transfer(op: &SysOp) -> Option<&TransferOp> {
+    match op {
+        SysOp::Transfer(transfer) if is_withdrawal_address(&transfer.to) => Some(transfer),
+        _ => None,
+    }
+}
+
+/// Number of withdrawals `txns` append to the withdrawal queue, as `apply_txns`
+/// queues them when they all apply, which holds for the blocks the sequencer seals.
+pub fn count_withdrawals(txns: &[Transaction]) -> u64 {
+    txns.iter()
+        .filter_map(|txn| pre_process(txn).ok())
+        .map(|ops| ops.iter().filter_map(withdrawal_transfer).count() as u64)
+        .sum()
+}
+
+/// Leaf of a withdrawal in the withdrawal tree, `from || to || amount || index` with
+/// the amount and index big endian. `bridge::withdrawal` of the l2-state program
+/// rebuilds the same bytes from the accounts it's given.
+pub fn withdrawal_leaf_bytes(from: &Pubkey, to: &Pubkey, amount: u64, index: u64) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(80);
+    bytes.extend_from_slice(&from.to_bytes());
+    bytes.extend_from_slice(&to.to_bytes());
+    bytes.extend_from_slice(&amount.to_be_bytes());
+    bytes.extend_from_slice(&index.to_be_bytes());
+    bytes
+}
 
-    // Hash withdrawal fields
-    hasher.update(withdrawal.from.as_bytes());
-    hasher.update(withdrawal.to.as_bytes());
-    hasher.update(&withdrawal.amount.to_be_bytes());
-    hasher.update(&withdrawal.index.to_be_bytes());
+/// Hash of `withdrawal_leaf_bytes`, the leaf the withdrawal proofs start from.
+pub fn withdrawal_leaf_hash(
+    algo: HashAlgo,
+    from: &Pubkey,
+    to: &Pubkey,
+    amount: u64,
+    index: u64,
+) -> [u8; 32] {
+    algo.hashv(&[&withdrawal_leaf_bytes(from, to, amount, index)])
+}
 
-    hasher.finalize().into()
+// The addresses of a queued withdrawal are pubkeys, `apply_txns` and `import_snapshot`
+// reject the ones that aren't
+fn withdrawal_addresses_valid(withdrawal: &Withdrawal) -> bool {
+    [&withdrawal.from, &withdrawal.to]
+        .iter()
+        .all(|address| Pubkey::from_str(address).is_ok())
+}
+
+fn calculate_withdrawal_hash(algo: HashAlgo, withdrawal: &Withdrawal) -> [u8; 32] {
+    let pubkey = |address: &str| {
+        Pubkey::from_str(address).expect("withdrawal addresses are checked when queued")
+    };
+    withdrawal_leaf_hash(
+        algo,
+        &pubkey(&withdrawal.from),
+        &pubkey(&withdrawal.to),
+        withdrawal.amount.into(),
+        withdrawal.index,
+    )
 }
 
 impl State {
-    //  State root of binary tree
+    //  State root of the sparse merkle tree, see `state_tree`
     pub fn calculate_state_root(&self) -> Option<[u8; 32]> {
-        if self.balances.is_empty() {
+        self.calculate_state_root_with(HASH_ALGO)
+    }
+
+    pub fn calculate_state_root_with(&self, algo: HashAlgo) -> Option<[u8; 32]> {
+        if self.accounts.is_empty() && self.pruned.is_empty() {
             return None;
         }
 
-        // Calculate hash for each user's state
-        let mut leaf_hashes: Vec<[u8; 32]> = self
-            .balances
-            .iter()
-            .map(|(user_id, value)| calculate_user_hash(user_id, &value))
-            .collect();
+        Some(state_tree::root(algo, self.tree_nodes(algo)))
+    }
 
-        // Sorting
-        if leaf_hashes.len() % 2 == 1 {
-            leaf_hashes.push(leaf_hashes[leaf_hashes.len() - 1]);
+    /// Same root as `calculate_state_root`, from a tree cached across calls that only
+    /// rehashes the paths of the accounts changed since, see `update_leaf`. Built on
+    /// the first call. Partial states are computed from scratch.
+    pub fn incremental_root(&mut self) -> Option<[u8; 32]> {
+        if !self.pruned.is_empty() {
+            return self.calculate_state_root();
         }
+        if self.accounts.is_empty() {
+            return None;
+        }
+        if self.tree.is_none() {
+            let leaves = self
+                .tree_nodes(HASH_ALGO)
+                .into_iter()
+                .filter_map(|node| match node {
+                    TreeNode::Leaf { key, hash } => Some((key, hash)),
+                    TreeNode::Subtree { .. } => None,
+                });
+            self.tree = Some(IncrementalTree::from_leaves(HASH_ALGO, leaves));
+        }
+        self.tree.as_ref().map(IncrementalTree::root)
+    }
 
-        // Build binary tree bottom-up
-        let mut nodes: Vec<MerkleNode> =
-            leaf_hashes.into_iter().map(MerkleNode::new_leaf).collect();
+    /// Set the roots of `block`, executed on this state, as the sequencer saves it. A
+    /// root of an empty state or withdrawal queue is stored as zeroes.
+    pub fn seal_block(&mut self, block: &mut Block) {
+        block.post_state_root = Some(self.incremental_root().unwrap_or_default());
+        block.withdrawal_root = Some(self.calculate_withdrawal_root().unwrap_or_default());
+        block.txns_root = Some(calculate_txns_root(&block.txns));
+    }
 
-        // Build tree level by level until we have one root node
-        while nodes.len() > 1 {
-            let mut next_level = Vec::new();
+    /// Rehash the leaf of `address` in the tree of `incremental_root`, removing it if
+    /// the account is gone. The account setters call it, code writing `accounts`
+    /// directly must too.
+    pub fn update_leaf(&mut self, address: &str) {
+        let Some(tree) = self.tree.as_mut() else {
+            return;
+        };
+        let key = account_key(HASH_ALGO, address);
+        match self.accounts.get(address) {
+            Some(account) => tree.insert(
+                key,
+                leaf_hash(
+                    HASH_ALGO,
+                    &calculate_account_hash(HASH_ALGO, address, account),
+                ),
+            ),
+            None => tree.remove(&key),
+        }
+    }
 
-            for i in (0..nodes.len()).step_by(2) {
-                let left = nodes[i].clone();
-                let right = if i + 1 < nodes.len() {
-                    nodes[i + 1].clone()
-                } else {
-                    left.clone()
-                };
+    // Leaves of the accounts held in full, plus the pruned nodes (hashed with HASH_ALGO)
+    fn tree_nodes(&self, algo: HashAlgo) -> Vec<TreeNode> {
+        self.accounts
+            .iter()
+            .map(|(address, account)| TreeNode::Leaf {
+                key: account_key(algo, address),
+                hash: leaf_hash(algo, &calculate_account_hash(algo, address, account)),
+            })
+            .chain(self.pruned.iter().cloned())
+            .collect()
+    }
 
-                next_level.push(MerkleNode::new_internal(left, right));
-            }
+    pub fn calculate_withdrawal_root(&self) -> Option<[u8; 32]> {
+        self.calculate_withdrawal_root_with(HASH_ALGO)
+    }
 
-            nodes = next_level;
+    /// Withdrawals ordered by `index`, the leaf order of the withdrawal tree.
+    ///
+    /// The indices must be exactly `0..n`, so the root doesn't depend on the order
+    /// withdrawals were queued in. `None` on a gap or a duplicated index.
+    pub fn sorted_withdrawals(&self) -> Option<Vec<&Withdrawal>> {
+        let mut withdrawals: Vec<&Withdrawal> = self.withdrawal_queue.iter().collect();
+        withdrawals.sort_by_key(|withdrawal| withdrawal.index);
+        let contiguous = withdrawals
+            .iter()
+            .enumerate()
+            .all(|(position, withdrawal)| withdrawal.index == position as u64);
+        if !contiguous {
+            warn!("Withdrawal queue indices aren't contiguous");
+            return None;
         }
+        Some(withdrawals)
+    }
 
-        if let Some(root) = nodes.into_iter().next() {
-            Some(root.hash)
-        } else {
-            None
-        }
+    pub fn calculate_withdrawal_root_with(&self, algo: HashAlgo) -> Option<[u8; 32]> {
+        let withdrawals = self.sorted_withdrawals()?;
+        WithdrawalTree::build(algo, &withdrawals).map(|tree| tree.root())
     }
 
-    pub fn calculate_withdrawal_root(&self) -> Option<[u8; 32]> {
-        if self.withdrawal_queue.is_empty() {
+    pub fn generate_withdrawal_merkle_proof(
+        &self,
+        index: u64,
+        range: u64, // Batch boundary
+    ) -> Option<WithdrawalProof> {
+        self.generate_withdrawal_merkle_proof_with(HASH_ALGO, index, range)
+    }
+
+    pub fn generate_withdrawal_merkle_proof_with(
+        &self,
+        algo: HashAlgo,
+        index: u64,
+        range: u64, // Batch boundary
+    ) -> Option<WithdrawalProof> {
+        self.withdrawal_tree_with(algo, range)?.proof(index)
+    }
+
+    /// Tree over the first `range` withdrawals, the ones of a finalized batch.
+    pub fn withdrawal_tree(&self, range: u64) -> Option<WithdrawalTree> {
+        self.withdrawal_tree_with(HASH_ALGO, range)
+    }
+
+    pub fn withdrawal_tree_with(&self, algo: HashAlgo, range: u64) -> Option<WithdrawalTree> {
+        let withdrawals = self.sorted_withdrawals()?;
+        WithdrawalTree::build(algo, withdrawals.get(..range as usize)?)
+    }
+}
+
+/// Merkle tree of withdrawal leaves, all levels are kept so a proof is O(log n).
+#[derive(Clone, Debug)]
+pub struct WithdrawalTree {
+    // levels[0] are the leaf hashes, the last level is the root
+    levels: Vec<Vec<[u8; 32]>>,
+    leaf_count: usize,
+}
+
+impl WithdrawalTree {
+    pub fn build(algo: HashAlgo, withdrawals: &[&Withdrawal]) -> Option<Self> {
+        if withdrawals.is_empty() {
             return None;
         }
 
         // Calculate hash for each withdrawal
-        let mut leaf_hashes: Vec<[u8; 32]> = self
-            .withdrawal_queue
+        let mut leaf_hashes: Vec<[u8; 32]> = withdrawals
             .iter()
-            .map(|withdrawal| calculate_withdrawal_hash(withdrawal))
+            .map(|withdrawal| calculate_withdrawal_hash(algo, withdrawal))
             .collect();
+        let leaf_count = leaf_hashes.len();
 
+        // If odd number of leaves, pad with an empty one
         if leaf_hashes.len() % 2 == 1 {
             leaf_hashes.push([0u8; 32]);
         }
 
-        // Build binary tree bottom-up
-        let mut nodes: Vec<MerkleNode> =
-            leaf_hashes.into_iter().map(MerkleNode::new_leaf).collect();
+        // Build tree level by level until we have one root node,
+        // a node without sibling is hashed with itself
+        let mut levels = vec![leaf_hashes];
+        while let Some(level) = levels.last().filter(|level| level.len() > 1) {
+            let next_level = level
+                .chunks(2)
+                .map(|pair| algo.hashv(&[&pair[0], pair.get(1).unwrap_or(&pair[0])]))
+                .collect();
+            levels.push(next_level);
+        }
 
-        // Build tree level by level until we have one root node
-        while nodes.len() > 1 {
-            let mut next_level = Vec::new();
+        Some(Self { levels, leaf_count })
+    }
 
-            for i in (0..nodes.len()).step_by(2) {
-                let left = nodes[i].clone();
-                let right = if i + 1 < nodes.len() {
-                    nodes[i + 1].clone()
-                } else {
-                    left.clone()
-                };
+    pub fn root(&self) -> [u8; 32] {
+        self.levels.last().map(|level| level[0]).unwrap_or_default()
+    }
+
+    pub fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+    /// Proof of the withdrawal at `index`, the sibling hashes from the leaf up.
+    pub fn proof(&self, index: u64) -> Option<WithdrawalProof> {
+        if index as usize >= self.leaf_count {
+            return None;
+        }
+        let leaf_hash = self.levels[0][index as usize];
+
+        let mut proof = Vec::new();
+        let mut current_index = index as usize;
+        for level in &self.levels[..self.levels.len() - 1] {
+            // If no sibling, use the same node (for odd number of nodes)
+            let sibling = level
+                .get(current_index ^ 1)
+                .unwrap_or(&level[current_index]);
+            proof.push(*sibling);
+            current_index /= 2;
+        }
 
-                next_level.push(MerkleNode::new_internal(left, right));
+        Some((leaf_hash, proof, index, self.root()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        count_withdrawals, verify_blocks, withdrawal_leaf_bytes, withdrawal_leaf_hash, AccountData,
+        AppliedTxns, DepositRecord, State, StateDB, SysOpError, Withdrawal, SNAPSHOT_VERSION,
+    };
+    use crate::{
+        hash::{verify_merkle_proof, HashAlgo},
+        lamports::Lamports,
+        storage,
+        transaction::{
+            deposit_instruction_data, set_withdrawal_addresses, Block, BlockDB, SysOp, TransferOp,
+            L2_SYS_PROGRAM_KEY,
+        },
+        UNSAFE_PRIVATE_KEY, WITHDRAWAL_ADDRESS,
+    };
+    use solana_sdk::{
+        hash::Hash,
+        instruction::{AccountMeta, Instruction},
+        pubkey::Pubkey,
+        signature::{Keypair, Signature, Signer},
+        system_instruction::{self, SystemInstruction},
+        system_transaction,
+        transaction::Transaction,
+    };
+    use std::collections::{HashMap, HashSet};
+    use std::str::FromStr;
+
+    fn state_with_withdrawals(count: u64) -> State {
+        let mut state = State::new();
+        for index in 0..count {
+            state.withdrawal_queue.push(Withdrawal {
+                from: Pubkey::new_from_array([index as u8; 32]).to_string(),
+                to: Pubkey::new_from_array([100 + index as u8; 32]).to_string(),
+                amount: Lamports(1_000 + index),
+                index,
+            });
+        }
+        state
+    }
+
+    #[test]
+    fn test_withdrawal_proof_round_trip() {
+        let state = state_with_withdrawals(5);
+        for algo in [HashAlgo::Sha256, HashAlgo::Keccak256] {
+            let root = state.calculate_withdrawal_root_with(algo).unwrap();
+            for index in 0..5 {
+                let (leaf_hash, proof, index, proof_root) = state
+                    .generate_withdrawal_merkle_proof_with(algo, index, 5)
+                    .unwrap();
+                assert_eq!(proof_root, root);
+                assert!(verify_merkle_proof(algo, leaf_hash, &proof, index, root));
             }
+        }
+    }
+
+    #[test]
+    fn test_withdrawal_leaf_hash() {
+        let from = Pubkey::new_from_array([1; 32]);
+        let to = Pubkey::new_from_array([2; 32]);
+        let bytes = withdrawal_leaf_bytes(&from, &to, 1_000, 7);
+        assert_eq!(bytes.len(), 80);
+        assert_eq!(&bytes[64..72], &1_000u64.to_be_bytes());
+        assert_eq!(&bytes[72..], &7u64.to_be_bytes());
+
+        // Pinned, the program hashes the same bytes
+        assert_eq!(
+            withdrawal_leaf_hash(HashAlgo::Sha256, &from, &to, 1_000, 7),
+            [
+                68, 27, 44, 124, 253, 221, 241, 191, 38, 178, 54, 204, 209, 177, 97, 50, 85, 66,
+                41, 41, 117, 90, 108, 94, 188, 182, 17, 195, 247, 242, 219, 77
+            ]
+        );
+
+        // The tree leaves are the same hashes
+        let state = state_with_withdrawals(2);
+        let (leaf_hash, ..) = state
+            .generate_withdrawal_merkle_proof_with(HashAlgo::Keccak256, 1, 2)
+            .unwrap();
+        assert_eq!(
+            leaf_hash,
+            withdrawal_leaf_hash(
+                HashAlgo::Keccak256,
+                &Pubkey::new_from_array([1; 32]),
+                &Pubkey::new_from_array([101; 32]),
+                1_001,
+                1
+            )
+        );
+    }
+
+    #[test]
+    fn test_withdrawal_root_order_independent() {
+        let state = state_with_withdrawals(5);
+        let mut reordered = state.clone();
+        reordered.withdrawal_queue.reverse();
+        reordered.withdrawal_queue.swap(1, 3);
+        assert_eq!(
+            reordered.calculate_withdrawal_root(),
+            state.calculate_withdrawal_root()
+        );
+        assert_eq!(
+            reordered.generate_withdrawal_merkle_proof(2, 5),
+            state.generate_withdrawal_merkle_proof(2, 5)
+        );
+
+        // A gap or a duplicated index has no root
+        let mut gap = state.clone();
+        gap.withdrawal_queue[4].index = 7;
+        assert_eq!(gap.calculate_withdrawal_root(), None);
+        let mut duplicated = state.clone();
+        duplicated.withdrawal_queue[4].index = 3;
+        assert_eq!(duplicated.calculate_withdrawal_root(), None);
+        assert_eq!(duplicated.generate_withdrawal_merkle_proof(0, 5), None);
+    }
+
+    #[test]
+    fn test_hash_algo_roots_differ() {
+        let mut state = state_with_withdrawals(3);
+        state.set_balance("alice".to_string(), Lamports(100));
+        state.set_balance("bob".to_string(), Lamports(200));
+
+        assert_ne!(
+            state.calculate_state_root_with(HashAlgo::Sha256),
+            state.calculate_state_root_with(HashAlgo::Keccak256)
+        );
+        assert_ne!(
+            state.calculate_withdrawal_root_with(HashAlgo::Sha256),
+            state.calculate_withdrawal_root_with(HashAlgo::Keccak256)
+        );
+
+        // A proof built with one algorithm doesn't verify with the other
+        let (leaf_hash, proof, index, root) = state
+            .generate_withdrawal_merkle_proof_with(HashAlgo::Keccak256, 1, 3)
+            .unwrap();
+        assert!(!verify_merkle_proof(
+            HashAlgo::Sha256,
+            leaf_hash,
+            &proof,
+            index,
+            root
+        ));
+    }
+
+    #[test]
+    fn test_account_data_in_state_root() {
+        let mut state = State::new();
+        state.set_balance("alice".to_string(), Lamports(100));
+        state.set_account(
+            "counter".to_string(),
+            AccountData {
+                lamports: Lamports(10),
+                owner: "program".to_string(),
+                data: vec![0u8; 8],
+                executable: false,
+            },
+        );
+        let root = state.calculate_state_root();
+
+        // balances is a view over accounts
+        assert_eq!(state.balances().get("alice"), Some(&Lamports(100)));
+        assert_eq!(state.get_balance("counter"), Lamports(10));
+
+        // Same balances but different account data changes the root
+        state.accounts.get_mut("counter").unwrap().data[0] = 1;
+        assert_ne!(state.calculate_state_root(), root);
+
+        // Updating the balance keeps the account data
+        state.set_balance("counter".to_string(), Lamports(20));
+        assert_eq!(state.get_account("counter").unwrap().data[0], 1);
+    }
 
-            nodes = next_level;
+    #[test]
+    fn test_witness_root_matches_full_state() {
+        let mut state = State::new();
+        for i in 0..200 {
+            state.set_balance(format!("account_{}", i), Lamports(1_000 + i as u64));
         }
 
-        if let Some(root) = nodes.into_iter().next() {
-            Some(root.hash)
-        } else {
-            None
+        for touched in [1, 2, 17, 200] {
+            // Existing accounts plus some that don't exist yet
+            let addresses: HashSet<String> = (0..touched)
+                .map(|i| format!("account_{}", i * 7 % 200))
+                .chain((0..3).map(|i| format!("new_{}", i)))
+                .collect();
+            let mut full = state.clone();
+            let mut witness = state.partial(&addresses);
+            assert_eq!(witness.calculate_state_root(), full.calculate_state_root());
+            assert!(witness.pruned.len() < 200);
+
+            for address in addresses.iter() {
+                assert!(!witness.is_pruned(address));
+                full.add_balance(address.clone(), Lamports(5)).unwrap();
+                witness.add_balance(address.clone(), Lamports(5)).unwrap();
+            }
+            assert_eq!(witness.calculate_state_root(), full.calculate_state_root());
         }
+
+        // Untouched accounts are covered by the witness
+        let witness = state.partial(&HashSet::from(["account_0".to_string()]));
+        assert!(witness.is_pruned("account_1"));
     }
 
-    pub fn generate_withdrawal_merkle_proof(
-        &self,
-        index: u64,
-        range: u64, // Batch boundary
-    ) -> Option<(
-        [u8; 32],      // leaf_hash
-        Vec<[u8; 32]>, // proof
-        u64,           // index
-        [u8; 32],      // root
-    )> {
-        if self.withdrawal_queue.is_empty() || index as usize >= self.withdrawal_queue.len() {
-            return None;
+    fn temporary_state_db() -> StateDB {
+        StateDB::in_memory()
+    }
+
+    #[test]
+    fn test_atomic_multi_transfer() {
+        let alice = Keypair::new();
+        let (bob, carol) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut state = State::new();
+        state.set_balance(alice.pubkey().to_string(), Lamports(1_000));
+
+        // The second leg overdraws, the first one is rolled back
+        let instructions = [
+            system_instruction::transfer(&alice.pubkey(), &bob, 600),
+            system_instruction::transfer(&alice.pubkey(), &carol, 600),
+        ];
+        let txn = Transaction::new_signed_with_payer(
+            &instructions,
+            Some(&alice.pubkey()),
+            &[&alice],
+            Hash::default(),
+        );
+        state.apply_txns(&[txn], 1);
+        assert_eq!(
+            state.get_balance(&alice.pubkey().to_string()),
+            Lamports(1_000)
+        );
+        assert!(state.get_account(&bob.to_string()).is_none());
+        assert!(state.get_account(&carol.to_string()).is_none());
+
+        // Both legs apply when they fit
+        let instructions = [
+            system_instruction::transfer(&alice.pubkey(), &bob, 600),
+            system_instruction::transfer(&alice.pubkey(), &carol, 400),
+        ];
+        let txn = Transaction::new_signed_with_payer(
+            &instructions,
+            Some(&alice.pubkey()),
+            &[&alice],
+            Hash::default(),
+        );
+        state.apply_txns(&[txn], 2);
+        assert_eq!(state.get_balance(&alice.pubkey().to_string()), Lamports(0));
+        assert_eq!(state.get_balance(&bob.to_string()), Lamports(600));
+        assert_eq!(state.get_balance(&carol.to_string()), Lamports(400));
+    }
+
+    #[test]
+    fn test_lamports_overflow() {
+        let alice = Keypair::new();
+        let bob = Pubkey::new_unique();
+        let mut state = State::new();
+        state.set_balance(alice.pubkey().to_string(), Lamports(u64::MAX));
+
+        // The largest withdrawal is queued as is, not truncated
+        let withdraw = TransferOp {
+            from: alice.pubkey().to_string(),
+            to: WITHDRAWAL_ADDRESS.to_string(),
+            amount: Lamports(u64::MAX),
+        };
+        assert_eq!(state.withdrawal(&withdraw).amount, Lamports(u64::MAX));
+
+        // An amount above u64::MAX never makes it into a transfer
+        assert!(Lamports::try_from(u64::MAX as u128 + 1).is_err());
+
+        // A credit past u64::MAX is rejected, the debit is left untouched
+        state.set_balance(bob.to_string(), Lamports(1));
+        let transfer = SysOp::Transfer(TransferOp {
+            from: alice.pubkey().to_string(),
+            to: bob.to_string(),
+            amount: Lamports(u64::MAX),
+        });
+        assert_eq!(
+            state.apply_txn_ops(&[transfer]),
+            Err(SysOpError::BalanceOverflow)
+        );
+        assert_eq!(
+            state.get_balance(&alice.pubkey().to_string()),
+            Lamports(u64::MAX)
+        );
+        assert_eq!(state.get_balance(&bob.to_string()), Lamports(1));
+        assert_eq!(
+            state.add_balance(bob.to_string(), Lamports(u64::MAX)),
+            Err(SysOpError::BalanceOverflow)
+        );
+    }
+
+    #[test]
+    fn test_self_transfer() {
+        let alice = Keypair::new();
+        let mut state = State::new();
+        state.set_balance(alice.pubkey().to_string(), Lamports(1_000));
+        let state_root = state.incremental_root();
+
+        let txn = system_transaction::transfer(&alice, &alice.pubkey(), 400, Hash::default());
+        state.apply_txns(std::slice::from_ref(&txn), 1);
+        assert_eq!(
+            state.get_balance(&alice.pubkey().to_string()),
+            Lamports(1_000)
+        );
+        assert_eq!(state.incremental_root(), state_root);
+        assert_eq!(state.calculate_state_root(), state_root);
+
+        // Above the balance it fails like any transfer
+        let self_transfer = |address: &str, amount: u64| {
+            SysOp::Transfer(TransferOp {
+                from: address.to_string(),
+                to: address.to_string(),
+                amount: Lamports(amount),
+            })
+        };
+        assert_eq!(
+            state.apply_sys_op(&self_transfer(&alice.pubkey().to_string(), 1_001)),
+            Err(SysOpError::InsufficientBalance)
+        );
+        // A missing account isn't created
+        state.apply_sys_op(&self_transfer("bob", 0)).unwrap();
+        assert!(state.get_account("bob").is_none());
+        assert_eq!(state.calculate_state_root(), state_root);
+    }
+
+    #[test]
+    fn test_sweep_empty_accounts() {
+        let alice = Keypair::new();
+        let bob = Pubkey::new_unique();
+        let mut state = State::new();
+        state.set_balance(alice.pubkey().to_string(), Lamports(1_000));
+
+        // Alice sends everything, her empty account leaves the state root
+        let txn = system_transaction::transfer(&alice, &bob, 1_000, Hash::default());
+        state.apply_txns(&[txn], 1);
+        assert!(state.get_account(&alice.pubkey().to_string()).is_none());
+        let mut expected = State::new();
+        expected.set_balance(bob.to_string(), Lamports(1_000));
+        assert_eq!(
+            state.calculate_state_root(),
+            expected.calculate_state_root()
+        );
+
+        // A 0 lamport transfer creates no account
+        let carol = Keypair::new();
+        let dave = Pubkey::new_unique();
+        state.set_balance(carol.pubkey().to_string(), Lamports(10));
+        let txn = system_transaction::transfer(&carol, &dave, 0, Hash::default());
+        state.apply_txns(&[txn], 2);
+        assert!(state.get_account(&dave.to_string()).is_none());
+
+        // An account holding data is kept without lamports
+        let data_account = Keypair::new();
+        let instruction = system_instruction::create_account(
+            &carol.pubkey(),
+            &data_account.pubkey(),
+            0,
+            8,
+            &Pubkey::new_unique(),
+        );
+        let txn = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&carol.pubkey()),
+            &[&carol, &data_account],
+            Hash::default(),
+        );
+        state.apply_txns(&[txn], 3);
+        assert!(state
+            .get_account(&data_account.pubkey().to_string())
+            .is_some());
+    }
+
+    #[test]
+    fn test_incremental_root() {
+        let users: Vec<Keypair> = (0..20).map(|_| Keypair::new()).collect();
+        let mut state = State::new();
+        assert_eq!(state.incremental_root(), None);
+        for user in &users {
+            state.set_balance(user.pubkey().to_string(), Lamports(1_000));
         }
-        let history_queue = &self.withdrawal_queue[0..range as usize];
+        assert_eq!(state.incremental_root(), state.calculate_state_root());
 
-        // Calculate hash for each withdrawal
-        let mut leaf_hashes: Vec<[u8; 32]> = history_queue
-            .iter()
-            .map(|withdrawal| calculate_withdrawal_hash(withdrawal))
-            .collect();
+        for round in 0..20u64 {
+            let from = &users[round as usize % users.len()];
+            let txns = vec![
+                // A new account, a transfer between users, and one emptying the sender
+                system_transaction::transfer(
+                    from,
+                    &Pubkey::new_unique(),
+                    10 + round,
+                    Hash::default(),
+                ),
+                system_transaction::transfer(
+                    from,
+                    &users[(round as usize + 7) % users.len()].pubkey(),
+                    5,
+                    Hash::default(),
+                ),
+                system_transaction::transfer(
+                    from,
+                    &Pubkey::new_unique(),
+                    state.get_balance(&from.pubkey().to_string()).0 - 15 - round,
+                    Hash::default(),
+                ),
+            ];
+            state.apply_txns(&txns, round + 1);
+            assert_eq!(state.incremental_root(), state.calculate_state_root());
+        }
 
-        let leaf_hash = leaf_hashes[index as usize];
+        // A rolled back transaction leaves the root unchanged
+        let root = state.incremental_root();
+        let broke = Keypair::new();
+        let txn = system_transaction::transfer(&broke, &Pubkey::new_unique(), 1, Hash::default());
+        state.apply_txns(&[txn], 21);
+        assert_eq!(state.incremental_root(), root);
+    }
 
-        // If odd number of leaves, duplicate the last one
-        if leaf_hashes.len() % 2 == 1 {
-            leaf_hashes.push([0u8; 32]);
+    // Block production at 100k accounts, rebuilding the root vs the incremental tree
+    #[test]
+    #[ignore]
+    fn bench_incremental_root() {
+        let mut state = State::new();
+        for index in 0..100_000 {
+            state.set_balance(format!("account_{}", index), Lamports(1_000));
         }
+        state.incremental_root();
+
+        let payers: Vec<Keypair> = (0..100).map(|_| Keypair::new()).collect();
+        for payer in &payers {
+            state.set_balance(payer.pubkey().to_string(), Lamports(1_000_000));
+        }
+        let txns: Vec<Transaction> = payers
+            .iter()
+            .map(|payer| {
+                system_transaction::transfer(payer, &Pubkey::new_unique(), 1, Hash::default())
+            })
+            .collect();
 
-        // Build binary tree bottom-up and collect proof
-        let mut nodes: Vec<MerkleNode> =
-            leaf_hashes.into_iter().map(MerkleNode::new_leaf).collect();
+        let mut full = state.clone();
+        let start = std::time::Instant::now();
+        full.apply_txns(&txns, 1);
+        let full_root = full.calculate_state_root();
+        let full_time = start.elapsed();
 
-        let mut proof = Vec::new();
-        let mut current_index = index;
+        let start = std::time::Instant::now();
+        state.apply_txns(&txns, 1);
+        let incremental_root = state.incremental_root();
+        let incremental_time = start.elapsed();
 
-        // Build tree level by level and collect sibling hashes for the proof
-        while nodes.len() > 1 {
-            let mut next_level = Vec::new();
+        assert_eq!(full_root, incremental_root);
+        assert!(
+            incremental_time < full_time,
+            "incremental {:?}, full rebuild {:?}",
+            incremental_time,
+            full_time
+        );
+    }
 
-            // Find sibling for current index
-            let sibling_index = if current_index % 2 == 0 {
-                current_index + 1
-            } else {
-                current_index - 1
+    #[test]
+    fn test_deposit_recorded() {
+        let oracle = Keypair::from_base58_string(UNSAFE_PRIVATE_KEY);
+        let depositor = Pubkey::new_unique();
+        let l1_signature = Signature::from([7u8; 64]);
+        let deposit = |signer: &Keypair, l1_signature: &Signature| {
+            let instruction = Instruction {
+                program_id: *L2_SYS_PROGRAM_KEY,
+                accounts: vec![],
+                data: deposit_instruction_data(&depositor, 500, l1_signature),
             };
+            Transaction::new_signed_with_payer(
+                &[instruction],
+                Some(&signer.pubkey()),
+                &[signer],
+                Hash::default(),
+            )
+        };
+        let txn = deposit(&oracle, &l1_signature);
 
-            // Add sibling hash to proof if it exists
-            if (sibling_index as usize) < nodes.len() {
-                proof.push(nodes[sibling_index as usize].hash);
-            } else {
-                // If no sibling, use the same node (for odd number of nodes)
-                proof.push(nodes[current_index as usize].hash);
-            }
+        let mut state = State::new();
+        let applied = state.apply_txns(std::slice::from_ref(&txn), 3);
+        assert_eq!(applied.minted, 500);
+        assert_eq!(state.get_balance(&depositor.to_string()), Lamports(500));
+        let record = DepositRecord {
+            to: depositor.to_string(),
+            amount: Lamports(500),
+            block_num: 3,
+        };
+        assert_eq!(state.deposits.get(&l1_signature.to_string()), Some(&record));
 
-            // Build next level
-            for i in (0..nodes.len()).step_by(2) {
-                let left = nodes[i].clone();
-                let right = if i + 1 < nodes.len() {
-                    nodes[i + 1].clone()
-                } else {
-                    left.clone()
-                };
+        // Relayed again, not by the oracle, or naming the oracle as its signer without
+        // its signature, nothing is credited
+        let forged = deposit(&Keypair::new(), &Signature::from([8u8; 64]));
+        let mut unsigned = deposit(&oracle, &Signature::from([9u8; 64]));
+        unsigned.signatures[0] = Signature::default();
+        for txn in [txn, forged, unsigned] {
+            let applied = state.apply_txns(&[txn], 4);
+            assert_eq!(applied, AppliedTxns::default());
+        }
+        assert_eq!(state.get_balance(&depositor.to_string()), Lamports(500));
+        assert_eq!(state.deposits.len(), 1);
 
-                next_level.push(MerkleNode::new_internal(left, right));
-            }
+        // Persisted with the state
+        let mut state_db = temporary_state_db();
+        state_db.state = state;
+        state_db.save();
+        state_db.state = State::new();
+        state_db.load();
+        assert_eq!(state_db.state.deposits.len(), 1);
+    }
 
-            nodes = next_level;
-            current_index = current_index / 2;
+    #[test]
+    fn test_state_snapshot_round_trip() {
+        let alice = Pubkey::new_unique().to_string();
+        let mut state = State::genesis(Some(alice.clone()));
+        state.set_balance("bob".to_string(), Lamports(42));
+        for amount in [10, 20] {
+            let index = state.next_withdrawal_index();
+            state.withdrawal_queue.push(Withdrawal {
+                from: alice.clone(),
+                to: Pubkey::new_unique().to_string(),
+                amount: Lamports(amount),
+                index,
+            });
         }
+        state.deposits.insert(
+            "l1-signature".to_string(),
+            DepositRecord {
+                to: "bob".to_string(),
+                amount: Lamports(42),
+                block_num: 1,
+            },
+        );
+
+        let snapshot = state.export_snapshot();
+        let imported = State::import_snapshot(&snapshot).unwrap();
+        assert_eq!(
+            imported.calculate_state_root(),
+            state.calculate_state_root()
+        );
+        assert_eq!(
+            imported.calculate_withdrawal_root(),
+            state.calculate_withdrawal_root()
+        );
+        assert_eq!(imported.peek_withdrawal_index(), 2);
+        assert_eq!(imported.deposits.len(), 1);
+        // Deterministic, whatever the order of the maps
+        assert_eq!(imported.export_snapshot(), snapshot);
 
-        if let Some(root) = nodes.into_iter().next() {
-            Some((leaf_hash, proof, index, root.hash))
-        } else {
-            None
+        // A snapshot not matching its root, or of another version, is rejected
+        let mut tampered = state.clone();
+        tampered.set_balance("bob".to_string(), Lamports(43));
+        let mut bytes = tampered.export_snapshot();
+        bytes[1..33].copy_from_slice(&state.calculate_state_root().unwrap());
+        let error = State::import_snapshot(&bytes).unwrap_err();
+        assert!(error.to_string().contains("mismatch"));
+        // As is one allocating an index no withdrawal was queued with
+        let tampered = state.clone();
+        tampered.next_withdrawal_index();
+        let error = State::import_snapshot(&tampered.export_snapshot()).unwrap_err();
+        assert!(error.to_string().contains("next withdrawal index 3"));
+        // As is one with a withdrawal address that isn't a pubkey
+        let mut tampered = state.clone();
+        tampered.withdrawal_queue[1].to = "bob".to_string();
+        let error = State::import_snapshot(&tampered.export_snapshot()).unwrap_err();
+        assert!(error.to_string().contains("withdrawal 1"));
+        let mut bytes = snapshot.clone();
+        bytes[0] = SNAPSHOT_VERSION + 1;
+        assert!(State::import_snapshot(&bytes).is_err());
+        assert!(State::import_snapshot(&snapshot[..10]).is_err());
+    }
+
+    #[test]
+    fn test_withdrawal_indices_contiguous() {
+        let alice = Pubkey::new_unique().to_string();
+        let withdraw = |amount: u64| TransferOp {
+            from: alice.clone(),
+            to: WITHDRAWAL_ADDRESS.to_string(),
+            amount: Lamports(amount),
+        };
+        let mut state_db = temporary_state_db();
+
+        // Two blocks with a restart in between, the allocator survives it
+        for transfer in [withdraw(1_000), withdraw(2_000)] {
+            let withdrawal = state_db.state.withdrawal(&transfer);
+            state_db.state.withdrawal_queue.push(withdrawal);
         }
+        state_db.save();
+        state_db.state = State::new();
+        state_db.load();
+        assert_eq!(state_db.state.peek_withdrawal_index(), 2);
+        let withdrawal = state_db.state.withdrawal(&withdraw(3_000));
+        state_db.state.withdrawal_queue.push(withdrawal);
+
+        let indices: Vec<u64> = state_db
+            .state
+            .withdrawal_queue
+            .iter()
+            .map(|withdrawal| withdrawal.index)
+            .collect();
+        assert_eq!(indices, vec![0, 1, 2]);
+        assert!(state_db.state.calculate_withdrawal_root().is_some());
+
+        // Concurrent allocations never hand out the same index
+        let state = &state_db.state;
+        let mut allocated: Vec<u64> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..4)
+                .map(|_| {
+                    scope.spawn(|| {
+                        (0..100)
+                            .map(|_| state.next_withdrawal_index())
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect()
+        });
+        allocated.sort();
+        assert_eq!(allocated, (3..403).collect::<Vec<_>>());
     }
-}
 
-#[derive(Clone, Debug)]
-pub struct MerkleNode {
-    pub hash: [u8; 32],
-    pub left: Option<Box<MerkleNode>>,
-    pub right: Option<Box<MerkleNode>>,
-}
+    #[test]
+    fn test_failed_withdrawal_not_queued() {
+        let alice = Keypair::new();
+        let withdrawal_address = Pubkey::from_str(WITHDRAWAL_ADDRESS).unwrap();
+        let mut state = State::genesis(Some(alice.pubkey().to_string()));
+        let withdraw = |lamports: u64| {
+            system_transaction::transfer(&alice, &withdrawal_address, lamports, Hash::default())
+        };
+
+        // More than alice holds, the transfer fails and allocates no index
+        state.apply_txns(&[withdraw(u64::MAX)], 1);
+        assert_eq!(state.peek_withdrawal_index(), 0);
+        assert!(state.withdrawal_queue.is_empty());
+
+        // The next withdrawal that applies gets index 0
+        state.apply_txns(&[withdraw(1_000)], 2);
+        assert_eq!(state.peek_withdrawal_index(), 1);
+        assert_eq!(state.withdrawal_queue.len(), 1);
+        assert_eq!(state.withdrawal_queue[0].index, 0);
+    }
+
+    #[test]
+    fn test_transfer_from_non_signer() {
+        let (payer, victim) = (Keypair::new(), Keypair::new());
+        let mut state = State::genesis(Some(victim.pubkey().to_string()));
+        let instruction = Instruction::new_with_bincode(
+            solana_sdk::system_program::id(),
+            &SystemInstruction::Transfer { lamports: 1_000 },
+            vec![
+                AccountMeta::new(victim.pubkey(), false),
+                AccountMeta::new(payer.pubkey(), false),
+            ],
+        );
+        let txn = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&payer.pubkey()),
+            &[&payer],
+            Hash::default(),
+        );
+
+        // Signed by the payer only, the victim's lamports don't move
+        let root = state.calculate_state_root();
+        state.apply_txns(&[txn], 1);
+        assert_eq!(state.calculate_state_root(), root);
+        assert_eq!(state.get_balance(&payer.pubkey().to_string()), Lamports(0));
+    }
+
+    #[test]
+    fn test_block_snapshot_restore() {
+        let alice = Keypair::new();
+        let oracle = Keypair::from_base58_string(UNSAFE_PRIVATE_KEY);
+        let withdrawal_address = Pubkey::from_str(WITHDRAWAL_ADDRESS).unwrap();
+        let l1_signature = Signature::from([7u8; 64]);
+        let mut state = State::genesis(Some(alice.pubkey().to_string()));
+        let root = state.incremental_root();
+        let deposit = Transaction::new_signed_with_payer(
+            &[Instruction {
+                program_id: *L2_SYS_PROGRAM_KEY,
+                accounts: vec![],
+                data: deposit_instruction_data(&alice.pubkey(), 500, &l1_signature),
+            }],
+            Some(&oracle.pubkey()),
+            &[&oracle],
+            Hash::default(),
+        );
+        let txns = [
+            system_transaction::transfer(&alice, &Pubkey::new_unique(), 1_000, Hash::default()),
+            system_transaction::transfer(&alice, &withdrawal_address, 2_000, Hash::default()),
+            deposit,
+        ];
+
+        let snapshot = state.block_snapshot(&txns);
+        assert_eq!(state.apply_txns(&txns, 1).count, 3);
+        assert_eq!(state.withdrawal_queue.len(), 1);
+
+        // Back to the state before the block, the same block applies again
+        state.restore(snapshot);
+        assert_eq!(state.incremental_root(), root);
+        assert_eq!(
+            state.calculate_state_root(),
+            State::genesis(Some(alice.pubkey().to_string())).calculate_state_root()
+        );
+        assert!(state.withdrawal_queue.is_empty());
+        assert_eq!(state.peek_withdrawal_index(), 0);
+        assert!(state.deposits.is_empty());
+        assert_eq!(state.apply_txns(&txns, 1).count, 3);
+    }
+
+    #[test]
+    fn test_withdrawal_addresses() {
+        let alice = Keypair::new();
+        let withdrawal_address = Pubkey::new_unique();
+        let txns = [system_transaction::transfer(
+            &alice,
+            &withdrawal_address,
+            1_000,
+            Hash::default(),
+        )];
+
+        // A plain transfer under the default address
+        assert_eq!(count_withdrawals(&txns), 0);
 
-impl MerkleNode {
-    fn new_leaf(hash: [u8; 32]) -> Self {
-        MerkleNode {
-            hash,
-            left: None,
-            right: None,
+        // The same transfer withdraws once its target is a withdrawal address, the
+        // default is kept for the tests running alongside
+        set_withdrawal_addresses(vec![
+            WITHDRAWAL_ADDRESS.to_string(),
+            withdrawal_address.to_string(),
+        ])
+        .unwrap();
+        let mut state = State::genesis(Some(alice.pubkey().to_string()));
+        state.apply_txns(&txns, 1);
+        assert_eq!(count_withdrawals(&txns), 1);
+        assert_eq!(state.withdrawal_queue.len(), 1);
+        assert_eq!(state.withdrawal_queue[0].to, alice.pubkey().to_string());
+        set_withdrawal_addresses(vec![WITHDRAWAL_ADDRESS.to_string()]).unwrap();
+        assert_eq!(count_withdrawals(&txns), 0);
+
+        // Only pubkeys are accepted
+        assert!(
+            set_withdrawal_addresses(vec!["AF111111111111111111111111111111".to_string()]).is_err()
+        );
+        assert!(set_withdrawal_addresses(vec![]).is_err());
+    }
+
+    #[test]
+    fn test_rebuild_from_blocks() {
+        let alice = Keypair::new();
+        let bob = Pubkey::new_unique();
+        let genesis = State::genesis(Some(alice.pubkey().to_string()));
+
+        // Sequence a few blocks of transfers
+        let mut state_db = temporary_state_db();
+        state_db.state = genesis.clone();
+        let mut block_db = BlockDB::in_memory();
+        let mut state_root = genesis.calculate_state_root();
+        for block_num in 1..=3 {
+            let txns = vec![
+                system_transaction::transfer(&alice, &bob, 1_000, Hash::default()),
+                system_transaction::transfer(&alice, &bob, 10, Hash::default()),
+            ];
+            state_db.state.apply_txns(&txns, block_num);
+            let mut block = Block::new(txns);
+            block.block_num = block_num;
+            block.prev_state_root = state_root;
+            state_root = state_db.state.calculate_state_root();
+            block.post_state_root = state_root;
+            block_db.save_block(&block).unwrap();
+            state_db.save();
         }
+
+        // Lose the state db and rebuild it
+        let mut rebuilt = temporary_state_db();
+        let rebuilt_root = rebuilt.rebuild_from_blocks(&block_db, genesis.clone());
+        assert_eq!(rebuilt_root.ok(), state_root);
+        assert_eq!(rebuilt.state.get_balance(&bob.to_string()), Lamports(3_030));
+        assert_eq!(
+            rebuilt.state.calculate_withdrawal_root(),
+            state_db.state.calculate_withdrawal_root()
+        );
+
+        // The saved state loads back to the same root
+        let mut loaded = StateDB {
+            db: rebuilt.db.clone(),
+            cache: HashMap::new(),
+            state: State::new(),
+        };
+        loaded.load();
+        assert_eq!(loaded.state.calculate_state_root(), state_root);
+
+        // Replaying from another genesis diverges at the first block
+        assert!(temporary_state_db()
+            .rebuild_from_blocks(&block_db, State::new())
+            .is_err());
     }
 
-    fn new_internal(left: MerkleNode, right: MerkleNode) -> Self {
-        let mut hasher = Sha256::new();
+    #[test]
+    fn test_verify_blocks() {
+        let alice = Keypair::new();
+        let withdrawal_address = Pubkey::from_str(WITHDRAWAL_ADDRESS).unwrap();
+        let genesis = State::genesis(Some(alice.pubkey().to_string()));
 
-        hasher.update(&left.hash);
-        hasher.update(&right.hash);
+        // Blocks built the way the sequencer saves them, the first one before any
+        // withdrawal is queued
+        let mut state = genesis.clone();
+        let mut block_db = BlockDB::in_memory();
+        let mut state_root = state.incremental_root().unwrap_or_default();
+        for block_num in 1..=3 {
+            let mut txns = vec![system_transaction::transfer(
+                &alice,
+                &Pubkey::new_unique(),
+                1_000,
+                Hash::default(),
+            )];
+            if block_num > 1 {
+                txns.push(system_transaction::transfer(
+                    &alice,
+                    &withdrawal_address,
+                    10,
+                    Hash::default(),
+                ));
+            }
+            let mut block = Block::new(txns);
+            block.block_num = block_num;
+            block.prev_state_root = Some(state_root);
+            state.apply_txns(&block.txns, block_num);
+            state.seal_block(&mut block);
+            state_root = block.post_state_root.unwrap();
+            block_db.save_block(&block).unwrap();
+        }
+        assert_eq!(
+            block_db.get_block(1).unwrap().withdrawal_root,
+            Some([0; 32])
+        );
+        assert_eq!(verify_blocks(&block_db, genesis.clone()).unwrap(), 3);
+
+        // A tampered withdrawal root is reported at its block
+        let mut block = block_db.get_block(2).unwrap();
+        block.withdrawal_root = Some([0; 32]);
+        block_db.save_block(&block).unwrap();
+        let error = verify_blocks(&block_db, genesis.clone()).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Block 2 withdrawal_root mismatch"));
+
+        // As is a transaction dropped from a block
+        let mut block = block_db.get_block(1).unwrap();
+        block.txns.pop();
+        block_db.save_block(&block).unwrap();
+        let error = verify_blocks(&block_db, genesis).unwrap_err();
+        assert!(error.to_string().starts_with("Block 1 txns_root mismatch"));
+    }
+
+    #[test]
+    fn test_load_v0_state() {
+        let mut state = state_with_withdrawals(3);
+        state.set_balance("alice".to_string(), Lamports(1_000));
+        let mut state_db = temporary_state_db();
 
-        MerkleNode {
-            hash: hasher.finalize().into(),
-            left: Some(Box::new(left)),
-            right: Some(Box::new(right)),
+        // Unversioned blobs, accounts as JSON and withdrawals as bincode
+        let accounts = serde_json::to_vec(&state.accounts).unwrap();
+        state_db.db.insert("account_state", accounts).unwrap();
+        let withdrawals = bincode::serialize(&state.withdrawal_queue).unwrap();
+        state_db.db.insert("withdrawal_queue", withdrawals).unwrap();
+
+        state_db.load();
+        assert_eq!(state_db.state.get_balance("alice"), Lamports(1_000));
+        // Without a recorded allocator the next index follows the queue
+        assert_eq!(state_db.state.peek_withdrawal_index(), 3);
+        assert_eq!(
+            state_db.state.calculate_withdrawal_root(),
+            state.calculate_withdrawal_root()
+        );
+
+        // Upgraded in place
+        for key in ["account_state", "withdrawal_queue"] {
+            let data = state_db.db.get(key).unwrap().unwrap();
+            let header: serde_json::Value = serde_json::from_slice(&data).unwrap();
+            assert_eq!(header["version"], storage::STORAGE_VERSION);
         }
+        let mut reloaded = StateDB {
+            db: state_db.db.clone(),
+            cache: HashMap::new(),
+            state: State::new(),
+        };
+        reloaded.load();
+        assert_eq!(
+            reloaded.state.calculate_state_root(),
+            state.calculate_state_root()
+        );
     }
 }
@@ -0,0 +1,268 @@
+//! Sparse merkle tree of the l2 accounts.
+//!
+//! An account sits at the path given by `account_key(address)`. A subtree holding a
+//! single leaf collapses to that leaf and an empty subtree hashes to `EMPTY_HASH`, so
+//! building the root costs O(n log n) hashes. A partial state keeps the touched
+//! accounts and replaces everything else by the few `TreeNode`s hanging off their
+//! paths, which is a multiproof of those accounts against the full root.
+
+use serde::{Deserialize, Serialize};
+
+use crate::hash::HashAlgo;
+
+pub const EMPTY_HASH: [u8; 32] = [0u8; 32];
+const KEY_BITS: usize = 256;
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum TreeNode {
+    /// A single account leaf.
+    Leaf { key: [u8; 32], hash: [u8; 32] },
+    /// Every leaf whose key starts with the first `depth` bits of `prefix`.
+    Subtree {
+        depth: u16,
+        prefix: [u8; 32],
+        hash: [u8; 32],
+    },
+}
+
+impl TreeNode {
+    fn key(&self) -> &[u8; 32] {
+        match self {
+            TreeNode::Leaf { key, .. } => key,
+            TreeNode::Subtree { prefix, .. } => prefix,
+        }
+    }
+
+    /// Whether the leaf at `key` is inside this node.
+    pub fn covers(&self, key: &[u8; 32]) -> bool {
+        match self {
+            TreeNode::Leaf { key: leaf_key, .. } => leaf_key == key,
+            TreeNode::Subtree { depth, prefix, .. } => prefix_of(key, *depth as usize) == *prefix,
+        }
+    }
+}
+
+/// Position of an account in the tree.
+pub fn account_key(algo: HashAlgo, address: &str) -> [u8; 32] {
+    algo.hashv(&[address.as_bytes()])
+}
+
+pub fn leaf_hash(algo: HashAlgo, account_hash: &[u8; 32]) -> [u8; 32] {
+    algo.hashv(&[&[0u8], account_hash])
+}
+
+fn branch_hash(algo: HashAlgo, left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    algo.hashv(&[&[1u8], left, right])
+}
+
+fn bit(key: &[u8; 32], depth: usize) -> bool {
+    (key[depth / 8] >> (7 - depth % 8)) & 1 == 1
+}
+
+// First `depth` bits of `key`, the rest zeroed
+fn prefix_of(key: &[u8; 32], depth: usize) -> [u8; 32] {
+    let mut prefix = [0u8; 32];
+    for i in 0..depth {
+        if bit(key, i) {
+            prefix[i / 8] |= 1 << (7 - i % 8);
+        }
+    }
+    prefix
+}
+
+/// Root of the tree made of `nodes`, a mix of leaves and pruned subtrees.
+pub fn root(algo: HashAlgo, mut nodes: Vec<TreeNode>) -> [u8; 32] {
+    nodes.sort_by(|a, b| a.key().cmp(b.key()));
+    root_of(algo, &nodes, 0)
+}
+
+// `nodes` are sorted and all under the same `depth` bits prefix
+fn root_of(algo: HashAlgo, nodes: &[TreeNode], depth: usize) -> [u8; 32] {
+    match nodes {
+        [] => EMPTY_HASH,
+        [TreeNode::Leaf { hash, .. }] => *hash,
+        [TreeNode::Subtree {
+            depth: node_depth,
+            hash,
+            ..
+        }] if *node_depth as usize == depth => *hash,
+        // Duplicated keys, no valid root
+        _ if depth == KEY_BITS => EMPTY_HASH,
+        _ => {
+            let split = nodes.partition_point(|node| !bit(node.key(), depth));
+            branch_hash(
+                algo,
+                &root_of(algo, &nodes[..split], depth + 1),
+                &root_of(algo, &nodes[split..], depth + 1),
+            )
+        }
+    }
+}
+
+/// Prune the tree made of `nodes` down to the paths of the `keep` keys.
+///
+/// Leaves at `keep` keys are left out of the result, the caller carries them in full.
+/// Everything else collapses into the largest subtrees that hold no `keep` key.
+pub fn prune(algo: HashAlgo, mut nodes: Vec<TreeNode>, mut keep: Vec<[u8; 32]>) -> Vec<TreeNode> {
+    nodes.sort_by(|a, b| a.key().cmp(b.key()));
+    keep.sort();
+    let mut pruned = Vec::new();
+    prune_into(algo, &nodes, &keep, 0, &mut pruned);
+    pruned
+}
+
+fn prune_into(
+    algo: HashAlgo,
+    nodes: &[TreeNode],
+    keep: &[[u8; 32]],
+    depth: usize,
+    pruned: &mut Vec<TreeNode>,
+) {
+    match nodes {
+        [] => {}
+        // Nothing to keep below, collapse it
+        [leaf @ TreeNode::Leaf { .. }] if keep.is_empty() => pruned.push(leaf.clone()),
+        _ if keep.is_empty() => pruned.push(TreeNode::Subtree {
+            depth: depth as u16,
+            prefix: prefix_of(nodes[0].key(), depth),
+            hash: root_of(algo, nodes, depth),
+        }),
+        // Already pruned, can't be opened
+        [subtree @ TreeNode::Subtree {
+            depth: node_depth, ..
+        }] if *node_depth as usize == depth => pruned.push(subtree.clone()),
+        // Only kept leaves left
+        _ if nodes.iter().all(
+            |node| matches!(node, TreeNode::Leaf { key, .. } if keep.binary_search(key).is_ok()),
+        ) => {}
+        _ if depth == KEY_BITS => {}
+        _ => {
+            let split = nodes.partition_point(|node| !bit(node.key(), depth));
+            let keep_split = keep.partition_point(|key| !bit(key, depth));
+            prune_into(
+                algo,
+                &nodes[..split],
+                &keep[..keep_split],
+                depth + 1,
+                pruned,
+            );
+            prune_into(
+                algo,
+                &nodes[split..],
+                &keep[keep_split..],
+                depth + 1,
+                pruned,
+            );
+        }
+    }
+}
+
+/// The tree of the accounts held in full, caching its inner nodes so updating a leaf
+/// rehashes only its path, O(log n) instead of rebuilding the root. Has the same root
+/// as `root` over the same leaves.
+#[derive(Clone, Debug)]
+pub struct IncrementalTree {
+    algo: HashAlgo,
+    root: Node,
+}
+
+#[derive(Clone, Debug, Default)]
+enum Node {
+    #[default]
+    Empty,
+    Leaf {
+        key: [u8; 32],
+        hash: [u8; 32],
+    },
+    // Two leaves or more below, split on the bit of its depth
+    Branch {
+        hash: [u8; 32],
+        children: Box<[Node; 2]>,
+    },
+}
+
+impl Node {
+    fn hash(&self) -> [u8; 32] {
+        match self {
+            Node::Empty => EMPTY_HASH,
+            Node::Leaf { hash, .. } | Node::Branch { hash, .. } => *hash,
+        }
+    }
+
+    fn branch(algo: HashAlgo, children: [Node; 2]) -> Node {
+        Node::Branch {
+            hash: branch_hash(algo, &children[0].hash(), &children[1].hash()),
+            children: Box::new(children),
+        }
+    }
+
+    fn insert(&mut self, algo: HashAlgo, key: [u8; 32], hash: [u8; 32], depth: usize) {
+        match std::mem::take(self) {
+            Node::Empty => *self = Node::Leaf { key, hash },
+            Node::Leaf { key: leaf_key, .. } if leaf_key == key => *self = Node::Leaf { key, hash },
+            leaf @ Node::Leaf { key: leaf_key, .. } => {
+                // Push the leaf one level down and insert next to it
+                let mut children = [Node::Empty, Node::Empty];
+                children[bit(&leaf_key, depth) as usize] = leaf;
+                children[bit(&key, depth) as usize].insert(algo, key, hash, depth + 1);
+                *self = Node::branch(algo, children);
+            }
+            Node::Branch { mut children, .. } => {
+                children[bit(&key, depth) as usize].insert(algo, key, hash, depth + 1);
+                *self = Node::branch(algo, *children);
+            }
+        }
+    }
+
+    fn remove(&mut self, algo: HashAlgo, key: &[u8; 32], depth: usize) {
+        match std::mem::take(self) {
+            Node::Leaf { key: leaf_key, .. } if leaf_key == *key => {}
+            Node::Branch { mut children, .. } => {
+                children[bit(key, depth) as usize].remove(algo, key, depth + 1);
+                *self = match *children {
+                    // A single leaf left below collapses into it
+                    [Node::Empty, Node::Empty] => Node::Empty,
+                    [leaf @ Node::Leaf { .. }, Node::Empty]
+                    | [Node::Empty, leaf @ Node::Leaf { .. }] => leaf,
+                    children => Node::branch(algo, children),
+                };
+            }
+            node => *self = node,
+        }
+    }
+}
+
+impl IncrementalTree {
+    pub fn new(algo: HashAlgo) -> Self {
+        Self {
+            algo,
+            root: Node::Empty,
+        }
+    }
+
+    /// Tree of `(key, leaf hash)` leaves.
+    pub fn from_leaves(
+        algo: HashAlgo,
+        leaves: impl IntoIterator<Item = ([u8; 32], [u8; 32])>,
+    ) -> Self {
+        let mut tree = Self::new(algo);
+        for (key, hash) in leaves {
+            tree.insert(key, hash);
+        }
+        tree
+    }
+
+    /// Set the leaf at `key`.
+    pub fn insert(&mut self, key: [u8; 32], hash: [u8; 32]) {
+        self.root.insert(self.algo, key, hash, 0);
+    }
+
+    /// Remove the leaf at `key`, if any.
+    pub fn remove(&mut self, key: &[u8; 32]) {
+        self.root.remove(self.algo, key, 0);
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.root.hash()
+    }
+}
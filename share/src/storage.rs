@@ -0,0 +1,53 @@
+//! Versioned encoding of the persisted `Block`s, accounts and withdrawal queue.
+//!
+//! Values are stored as a JSON envelope `{"version": n, "data": ..}`. Blobs written
+//! before versioning have no envelope, they are version 0 and are read by the
+//! caller's legacy decoder, then rewritten in the current version on load.
+
+use anyhow::{anyhow, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// Version written by this binary.
+pub const STORAGE_VERSION: u8 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Envelope<T> {
+    version: u8,
+    data: T,
+}
+
+#[derive(Deserialize)]
+struct Header {
+    version: u8,
+}
+
+/// Encode `data` in the current version.
+pub fn encode<T: Serialize>(data: &T) -> Result<Vec<u8>> {
+    let envelope = Envelope {
+        version: STORAGE_VERSION,
+        data,
+    };
+    serde_json::to_vec(&envelope).map_err(|e| anyhow!("Failed to encode: {}", e))
+}
+
+/// Decode a persisted value and the version it was stored in, `v0` decodes the
+/// blobs written before versioning.
+pub fn decode<T: DeserializeOwned>(
+    bytes: &[u8],
+    v0: impl FnOnce(&[u8]) -> Option<T>,
+) -> Result<(T, u8)> {
+    let Ok(header) = serde_json::from_slice::<Header>(bytes) else {
+        return v0(bytes)
+            .map(|data| (data, 0))
+            .ok_or_else(|| anyhow!("Failed to decode v0 data"));
+    };
+    // Upgrades from older versions go here, matching on `header.version`
+    match header.version {
+        STORAGE_VERSION => {
+            let envelope = serde_json::from_slice::<Envelope<T>>(bytes)
+                .map_err(|e| anyhow!("Failed to decode v{}: {}", STORAGE_VERSION, e))?;
+            Ok((envelope.data, STORAGE_VERSION))
+        }
+        version => Err(anyhow!("Unsupported storage version {}", version)),
+    }
+}
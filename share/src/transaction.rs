@@ -1,29 +1,218 @@
 use std::collections::VecDeque;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
 
 use anyhow::{anyhow, Result};
 use log::{info, warn};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use solana_sdk::{pubkey::Pubkey, system_instruction::SystemInstruction, transaction::Transaction};
+use solana_sdk::{
+    compute_budget,
+    pubkey::Pubkey,
+    sanitize::Sanitize,
+    signature::{Keypair, Signature, Signer},
+    system_instruction::SystemInstruction,
+    transaction::Transaction,
+};
 
-use crate::{L2_SYS_PROGRAM_ID, WITHDRAWAL_ADDRESS};
+use crate::kv::{KvBatch, KvStore, MemoryStore};
+use crate::lamports::Lamports;
+use crate::{
+    storage, DEFAULT_BLOCK_CACHE_SIZE, L2_SYS_PROGRAM_ID, MEMO_PROGRAM_IDS, UNSAFE_PRIVATE_KEY,
+    WITHDRAWAL_ADDRESS,
+};
 
 lazy_static::lazy_static! {
-    pub static ref WITHDRAWAL_ADDRESS_KEY: Pubkey = WITHDRAWAL_ADDRESS.parse::<Pubkey>().unwrap_or_default();
     pub static ref L2_SYS_PROGRAM_KEY: Pubkey  = L2_SYS_PROGRAM_ID.parse::<Pubkey>().unwrap_or_default();
+    pub static ref MEMO_PROGRAM_KEYS: Vec<Pubkey> = MEMO_PROGRAM_IDS.iter().map(|id| id.parse::<Pubkey>().unwrap_or_default()).collect();
+    // Addresses a transfer to which withdraws to L1, see `set_withdrawal_addresses`
+    static ref WITHDRAWAL_ADDRESSES: RwLock<Vec<String>> = RwLock::new(vec![WITHDRAWAL_ADDRESS.to_string()]);
+    // The only signer of deposits, see `set_l1_oracle`
+    static ref L1_ORACLE: RwLock<Pubkey> = RwLock::new(Keypair::from_base58_string(UNSAFE_PRIVATE_KEY).pubkey());
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Set the withdrawal addresses, `WITHDRAWAL_ADDRESS` by default, before executing any
+/// block. The sequencer and the prover must agree on them, the prover passes them to
+/// the zkVM with `ZkVMInput`. Each must be a pubkey.
+pub fn set_withdrawal_addresses(addresses: Vec<String>) -> Result<()> {
+    if addresses.is_empty() {
+        return Err(anyhow!("At least one withdrawal address is required"));
+    }
+    for address in &addresses {
+        Pubkey::from_str(address)
+            .map_err(|_| anyhow!("Withdrawal address is not a pubkey: {}", address))?;
+    }
+    *WITHDRAWAL_ADDRESSES
+        .write()
+        .unwrap_or_else(|e| e.into_inner()) = addresses;
+    Ok(())
+}
+
+pub fn withdrawal_addresses() -> Vec<String> {
+    WITHDRAWAL_ADDRESSES
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+}
+
+/// Hash of a set of withdrawal addresses, committed by the zkVM program: the sha256 of
+/// their pubkey bytes, sorted and deduplicated. The l2-state program checks proofs against
+/// the `withdrawal_addresses_hash` of its config.
+pub fn withdrawal_addresses_hash(addresses: &[String]) -> [u8; 32] {
+    let mut pubkeys: Vec<Pubkey> = addresses
+        .iter()
+        .filter_map(|address| Pubkey::from_str(address).ok())
+        .collect();
+    pubkeys.sort();
+    pubkeys.dedup();
+    let mut hasher = Sha256::new();
+    for pubkey in pubkeys {
+        hasher.update(pubkey.to_bytes());
+    }
+    hasher.finalize().into()
+}
+
+/// Whether a transfer to `address` withdraws to L1.
+pub fn is_withdrawal_address(address: &str) -> bool {
+    WITHDRAWAL_ADDRESSES
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .any(|withdrawal_address| withdrawal_address == address)
+}
+
+/// Set the L1 oracle, the pubkey of `UNSAFE_PRIVATE_KEY` by default, before executing
+/// any block. Only the deposits it signs are credited, the sequencer and the prover must
+/// agree on it, the prover passes it to the zkVM with `ZkVMInput`.
+pub fn set_l1_oracle(oracle: Pubkey) {
+    *L1_ORACLE.write().unwrap_or_else(|e| e.into_inner()) = oracle;
+}
+
+pub fn l1_oracle() -> Pubkey {
+    *L1_ORACLE.read().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Whether `pubkey` signs `txn`: it's one of the account keys its signatures cover and
+/// its signature verifies. The zkVM checks the deposits with it, where nothing else
+/// verifies signatures.
+pub fn signed_by(txn: &Transaction, pubkey: &Pubkey) -> bool {
+    let message = &txn.message;
+    message
+        .account_keys
+        .iter()
+        .take(message.header.num_required_signatures as usize)
+        .position(|key| key == pubkey)
+        .and_then(|position| txn.signatures.get(position))
+        .is_some_and(|signature| signature.verify(pubkey.as_ref(), &txn.message_data()))
+}
+
+/// The L2 transaction of the bytes forced through L1, `None` when the sequencer skips
+/// them: not the canonical bincode of a transaction carrying the valid signatures of
+/// all its signers. The zkVM only holds the sequencer to executing the `Some` ones, see
+/// `zkvm::forced_transaction_executed`.
+pub fn forced_transaction(bytes: &[u8]) -> Option<Transaction> {
+    let txn: Transaction = bincode::deserialize(bytes).ok()?;
+    if bincode::serialize(&txn).ok()? != bytes || txn.signatures.is_empty() {
+        return None;
+    }
+    // Nobody can force a transfer out of another account
+    txn.sanitize().ok()?;
+    txn.verify().ok()?;
+    Some(txn)
+}
+
+/// sha256 of the bincode bytes of `txn`, the hash the l2-state program queues for the
+/// forced transaction with these bytes.
+pub fn transaction_hash(txn: &Transaction) -> [u8; 32] {
+    let bytes = bincode::serialize(txn).expect("transaction is always serializable");
+    Sha256::digest(bytes).into()
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub struct TransferOp {
     pub from: String,
     pub to: String,
-    pub amount: u128,
+    pub amount: Lamports,
+}
+
+/// A system program instruction, as the executor sees it.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SysOp {
+    Transfer(TransferOp),
+    /// Fund the new account `to` from `from`, with `space` zeroed bytes owned by `owner`.
+    CreateAccount {
+        from: String,
+        to: String,
+        lamports: Lamports,
+        space: u64,
+        owner: String,
+    },
+    /// Credit `to` with an L1 deposit relayed by the oracle, `l1_signature` is the L1
+    /// deposit transaction (unknown for deposits relayed by older oracles).
+    Deposit {
+        to: String,
+        amount: Lamports,
+        l1_signature: Option<String>,
+    },
+    /// An SPL Memo, e.g. the reference tag of an exchange deposit. It is kept with the
+    /// transaction for indexers and changes no account.
+    Memo {
+        memo: String,
+    },
+    /// A ComputeBudget instruction, `compute_unit_price` in micro-lamports for
+    /// `SetComputeUnitPrice`. The L2 charges no priority fee, the price is only reported
+    /// for fee estimation, it changes no account.
+    ComputeBudget {
+        compute_unit_price: Option<u64>,
+    },
+    /// A system instruction the executor doesn't support, e.g. `Allocate` or a nonce
+    /// instruction, `kind` is its name.
+    Unsupported {
+        kind: String,
+    },
+}
+
+/// Compute units charged per instruction, a flat cost by kind. Like Solana's builtins,
+/// a system or compute budget instruction costs 150 units. They bound the work of a
+/// transaction and of a block, and so the cost of proving it.
+pub const SYSTEM_INSTRUCTION_COMPUTE_UNITS: u64 = 150;
+pub const DEPOSIT_COMPUTE_UNITS: u64 = 150;
+pub const MEMO_COMPUTE_UNITS: u64 = 500;
+pub const COMPUTE_BUDGET_COMPUTE_UNITS: u64 = 150;
+/// An instruction of another program isn't executed, it's charged for its bytes
+/// hashed into the block.
+pub const OTHER_INSTRUCTION_COMPUTE_UNITS: u64 = 150;
+
+impl SysOp {
+    pub fn compute_units(&self) -> u64 {
+        match self {
+            SysOp::Transfer(_) | SysOp::CreateAccount { .. } | SysOp::Unsupported { .. } => {
+                SYSTEM_INSTRUCTION_COMPUTE_UNITS
+            }
+            SysOp::Deposit { .. } => DEPOSIT_COMPUTE_UNITS,
+            SysOp::Memo { .. } => MEMO_COMPUTE_UNITS,
+            SysOp::ComputeBudget { .. } => COMPUTE_BUDGET_COMPUTE_UNITS,
+        }
+    }
+
+    /// Accounts the op reads or writes.
+    pub fn accounts(&self) -> Vec<&String> {
+        match self {
+            SysOp::Transfer(op) => vec![&op.from, &op.to],
+            SysOp::CreateAccount { from, to, .. } => vec![from, to],
+            SysOp::Deposit { to, .. } => vec![to],
+            SysOp::Memo { .. } | SysOp::ComputeBudget { .. } | SysOp::Unsupported { .. } => {
+                vec![]
+            }
+        }
+    }
 }
 
+/// The system op of `instruction`, `None` for the instructions of other programs.
 pub fn parsing_instruction(
     instruction: &solana_sdk::instruction::CompiledInstruction,
     txn: &Transaction,
-) -> Result<Option<TransferOp>> {
+) -> Result<Option<SysOp>> {
     let program_id_index = instruction.program_id_index as usize;
 
     if program_id_index >= txn.message.account_keys.len() {
@@ -33,12 +222,18 @@ pub fn parsing_instruction(
     let program_id = &txn.message.account_keys[program_id_index];
     // Only parsing system program instructions
     match program_id {
-        &solana_sdk::system_program::ID => return parsing_sys_instruction(instruction, txn),
-        val if val == &*WITHDRAWAL_ADDRESS_KEY => {
-            info!("Processing withdrawal instruction");
+        &solana_sdk::system_program::ID => {
+            return parsing_sys_instruction(instruction, txn).map(Some)
         }
         val if val == &*L2_SYS_PROGRAM_KEY => {
             info!("Processing l2 system instruction");
+            return parsing_deposit(&instruction.data).map(Some);
+        }
+        val if MEMO_PROGRAM_KEYS.contains(val) => {
+            return parsing_memo(&instruction.data).map(Some);
+        }
+        &compute_budget::ID => {
+            return parsing_compute_budget(&instruction.data).map(Some);
         }
         _ => info!(
             "Processing withdrawal instruction for program: {}",
@@ -49,45 +244,195 @@ pub fn parsing_instruction(
     Ok(None)
 }
 
+/// The ops of the supported system instructions of `txn`, executed all or nothing.
+pub fn pre_process(txn: &Transaction) -> Result<Vec<SysOp>> {
+    // Only for logging, the validator rejects unsigned transactions
+    let signature = txn
+        .signatures
+        .first()
+        .map(ToString::to_string)
+        .unwrap_or_default();
+    let mut ops = Vec::new();
+    // Parsing each instruction in the transaction
+    for instruction in txn.message.instructions.iter() {
+        match parsing_instruction(instruction, txn)? {
+            Some(SysOp::Unsupported { kind }) => {
+                warn!("Skipping unsupported system instruction: {}", kind)
+            }
+            Some(op) => ops.push(op),
+            None => {}
+        }
+    }
+
+    info!("Transaction processing completed: {}", signature);
+    Ok(ops)
+}
+
+/// The memos of `txn`, joined with "; " like the `memo` of Solana's
+/// `getSignaturesForAddress`, `None` without any.
+pub fn transaction_memo(txn: &Transaction) -> Option<String> {
+    let memos: Vec<String> = txn
+        .message
+        .instructions
+        .iter()
+        .filter_map(|instruction| match parsing_instruction(instruction, txn) {
+            Ok(Some(SysOp::Memo { memo })) => Some(memo),
+            _ => None,
+        })
+        .collect();
+    (!memos.is_empty()).then(|| memos.join("; "))
+}
+
+/// Compute unit price of `txn` in micro-lamports, set by its `SetComputeUnitPrice`
+/// instruction, 0 without one like on Solana.
+pub fn compute_unit_price(txn: &Transaction) -> u64 {
+    txn.message
+        .instructions
+        .iter()
+        .find_map(|instruction| match parsing_instruction(instruction, txn) {
+            Ok(Some(SysOp::ComputeBudget {
+                compute_unit_price: Some(price),
+            })) => Some(price),
+            _ => None,
+        })
+        .unwrap_or(0)
+}
+
+/// Order the sequencer includes mempool transactions in: priority (compute unit price)
+/// descending, then first signature ascending, so equal priorities don't depend on
+/// arrival order. The prover replays the block's order, the sequencer sets it.
+pub fn priority_order(a: &Transaction, b: &Transaction) -> std::cmp::Ordering {
+    compute_unit_price(b)
+        .cmp(&compute_unit_price(a))
+        .then_with(|| a.signatures.first().cmp(&b.signatures.first()))
+}
+
+/// Fee of a transaction with `num_required_signatures` signatures, charged per signature
+/// like on Solana. `getFeeForMessage` quotes it.
+pub fn signature_fee(num_required_signatures: u8, lamports_per_signature: u64) -> u64 {
+    u64::from(num_required_signatures).saturating_mul(lamports_per_signature)
+}
+
+/// Compute units of `txn`, the sum of its instructions' costs. An instruction that
+/// fails to parse is charged like one of another program.
+pub fn compute_units(txn: &Transaction) -> u64 {
+    txn.message
+        .instructions
+        .iter()
+        .map(|instruction| match parsing_instruction(instruction, txn) {
+            Ok(Some(op)) => op.compute_units(),
+            _ => OTHER_INSTRUCTION_COMPUTE_UNITS,
+        })
+        .sum()
+}
+
+/// Data of the L2 system instruction crediting a deposit: the depositor, the amount
+/// (big endian) and the L1 deposit transaction signature.
+pub fn deposit_instruction_data(sender: &Pubkey, amount: u64, l1_signature: &Signature) -> Vec<u8> {
+    let mut data = sender.to_bytes().to_vec();
+    data.extend_from_slice(&amount.to_be_bytes());
+    data.extend_from_slice(l1_signature.as_ref());
+    data
+}
+
+fn parsing_deposit(data: &[u8]) -> Result<SysOp> {
+    // Older oracles didn't relay the L1 signature
+    if data.len() != 40 && data.len() != 104 {
+        return Err(anyhow!("Invalid deposit instruction length {}", data.len()));
+    }
+    let to = Pubkey::try_from(&data[..32]).map_err(|e| anyhow!("Invalid depositor: {}", e))?;
+    let amount = u64::from_be_bytes(data[32..40].try_into()?);
+    let l1_signature = match data.get(40..) {
+        Some(signature) if !signature.is_empty() => Some(
+            Signature::try_from(signature)
+                .map_err(|e| anyhow!("Invalid deposit signature: {}", e))?
+                .to_string(),
+        ),
+        _ => None,
+    };
+    Ok(SysOp::Deposit {
+        to: to.to_string(),
+        amount: Lamports(amount),
+        l1_signature,
+    })
+}
+
+// The memo program fails on data that isn't UTF-8, so does parsing
+fn parsing_memo(data: &[u8]) -> Result<SysOp> {
+    let memo = std::str::from_utf8(data).map_err(|e| anyhow!("Invalid memo: {}", e))?;
+    Ok(SysOp::Memo {
+        memo: memo.to_string(),
+    })
+}
+
+// Borsh encoded `ComputeBudgetInstruction`: a one byte variant, then a u32 for the
+// limits or a u64 for `SetComputeUnitPrice`
+fn parsing_compute_budget(data: &[u8]) -> Result<SysOp> {
+    let compute_unit_price = match data {
+        [3, price @ ..] if price.len() == 8 => Some(u64::from_le_bytes(
+            price.try_into().expect("length checked"),
+        )),
+        [1 | 2 | 4, limit @ ..] if limit.len() == 4 => None,
+        _ => return Err(anyhow!("Invalid compute budget instruction")),
+    };
+    Ok(SysOp::ComputeBudget { compute_unit_price })
+}
+
 fn parsing_sys_instruction(
     instruction: &solana_sdk::instruction::CompiledInstruction,
     txn: &Transaction,
-) -> Result<Option<TransferOp>> {
-    if instruction.data.is_empty() {
-        return Ok(None);
-    }
+) -> Result<SysOp> {
+    let account = |position: usize| {
+        instruction
+            .accounts
+            .get(position)
+            .and_then(|index| txn.message.account_keys.get(*index as usize))
+            .map(|key| key.to_string())
+            .ok_or_else(|| anyhow!("Missing account {} of system instruction", position))
+    };
+    // The funding account signs, as the system program requires on Solana
+    let signer_account = |position: usize| {
+        let key = account(position)?;
+        let signer = instruction
+            .accounts
+            .get(position)
+            .is_some_and(|index| *index < txn.message.header.num_required_signatures);
+        if !signer {
+            return Err(anyhow!("System instruction account {} didn't sign", key));
+        }
+        Ok(key)
+    };
 
-    // transfer Instructions
     match bincode::deserialize::<SystemInstruction>(&instruction.data) {
-        Ok(SystemInstruction::Transfer { lamports }) => {
-            // transfer ins
-            if instruction.accounts.len() >= 2 {
-                let from_index = instruction.accounts[0] as usize;
-                let to_index = instruction.accounts[1] as usize;
-
-                if from_index < txn.message.account_keys.len()
-                    && to_index < txn.message.account_keys.len()
-                {
-                    let from_pubkey = txn.message.account_keys[from_index].to_string();
-                    let to_pubkey = txn.message.account_keys[to_index].to_string();
-
-                    return Ok(Some(TransferOp {
-                        from: from_pubkey,
-                        to: to_pubkey,
-                        amount: lamports as u128,
-                    }));
-                }
-            }
-        }
-        Ok(_) => {
-            info!("Non-transfer system instruction");
-        }
-        Err(e) => {
-            warn!("Failed to deserialize system instruction: {}", e);
+        Ok(SystemInstruction::Transfer { lamports }) => Ok(SysOp::Transfer(TransferOp {
+            from: signer_account(0)?,
+            to: account(1)?,
+            amount: Lamports(lamports),
+        })),
+        Ok(SystemInstruction::CreateAccount {
+            lamports,
+            space,
+            owner,
+        }) => Ok(SysOp::CreateAccount {
+            from: signer_account(0)?,
+            to: account(1)?,
+            lamports: Lamports(lamports),
+            space,
+            owner: owner.to_string(),
+        }),
+        Ok(other) => {
+            // The variant name, e.g. `Allocate` of `Allocate { space: 8 }`
+            let kind = format!("{:?}", other);
+            let kind = kind.split([' ', '(']).next().unwrap_or_default();
+            info!("Unsupported system instruction: {}", kind);
+            Ok(SysOp::Unsupported {
+                kind: kind.to_string(),
+            })
         }
+        Err(e) => Err(anyhow!("Failed to deserialize system instruction: {}", e)),
     }
-    Ok(None)
 }
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Block {
     pub block_num: u64,
@@ -109,15 +454,26 @@ impl Block {
             withdrawal_root: None,
         }
     }
+
+    /// Canonical encoding of the block, for hashing and DA. Bincode has a fixed field
+    /// order and no map ordering or number formatting to vary, unlike the JSON the
+    /// block db stores.
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("block is always serializable")
+    }
+
+    pub fn from_canonical_bytes(bytes: &[u8]) -> Result<Self> {
+        bincode::deserialize(bytes).map_err(|e| anyhow!("Invalid canonical block: {}", e))
+    }
 }
 
 /// Calculate txns root for the block
 pub fn calculate_txns_root(txns: &[Transaction]) -> [u8; 32] {
     let mut hasher = Sha256::new();
 
-    // Hash all transactions in the block
+    // Hash all transactions in the block, in their canonical (wire) encoding
     for txn in txns {
-        if let Ok(txn_data) = serde_json::to_vec(txn) {
+        if let Ok(txn_data) = bincode::serialize(txn) {
             hasher.update(&txn_data);
         }
     }
@@ -125,12 +481,16 @@ pub fn calculate_txns_root(txns: &[Transaction]) -> [u8; 32] {
     hasher.finalize().into()
 }
 
-pub fn load_blocks(start: u64, length: u64) -> Option<Vec<Block>> {
-    let db = sled::open("block_db").ok()?;
+/// Open the block db at `db_path` and load `length` blocks from `start`.
+///
+/// sled is single-process: this fails while a sequencer holds the same db open,
+/// processes running next to a live sequencer should use `getBatchBlocks` instead.
+pub fn load_blocks(db_path: &str, start: u64, length: u64) -> Option<Vec<Block>> {
+    let db = sled::open(db_path).ok()?;
     let mut blocks = vec![];
     for i in start..start + length {
-        if let Ok(Some(data)) = db.get(format!("block_{}", i)) {
-            if let Ok(block) = serde_json::from_slice::<Block>(&data) {
+        if let Ok(Some(data)) = db.get(&format!("block_{}", i)) {
+            if let Some(block) = decode_block(&data) {
                 blocks.push(block);
             }
         } else {
@@ -140,19 +500,141 @@ pub fn load_blocks(start: u64, length: u64) -> Option<Vec<Block>> {
     Some(blocks)
 }
 
+/// Decode a block stored by any version of `BlockDB`, v0 blocks are plain JSON.
+pub fn decode_block(bytes: &[u8]) -> Option<Block> {
+    storage::decode(bytes, |v0| serde_json::from_slice(v0).ok())
+        .ok()
+        .map(|(block, _)| block)
+}
+
+/// A block drained from the queues but never saved, logged by
+/// `BlockDB::save_pending_block`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct PendingBlock {
+    pub block_num: u64,
+    pub txns: Vec<Transaction>,
+    /// L1 queue index and deadline of the forced transactions leading `txns`, they go
+    /// back into the forced queue. Logs written before it was recorded have none.
+    #[serde(default)]
+    pub forced: Vec<(u64, i64)>,
+}
+
 pub struct BlockDB {
-    pub db: sled::Db,
+    pub db: Arc<dyn KvStore>,
+    /// The latest blocks, at most `cache_size` of them
     pub cache: VecDeque<Block>,
+    cache_size: usize,
 }
 
 impl BlockDB {
     pub fn new(db_path: &str) -> Self {
-        let block_db = sled::open(db_path).unwrap();
+        Self::with_store(Arc::new(sled::open(db_path).unwrap()))
+    }
+
+    /// A block db on `store`, e.g. a `MemoryStore` in tests.
+    pub fn with_store(db: Arc<dyn KvStore>) -> Self {
         Self {
-            db: block_db,
-            cache: VecDeque::with_capacity(128),
+            db,
+            cache: VecDeque::with_capacity(DEFAULT_BLOCK_CACHE_SIZE),
+            cache_size: DEFAULT_BLOCK_CACHE_SIZE,
         }
     }
+
+    pub fn in_memory() -> Self {
+        Self::with_store(Arc::new(MemoryStore::new()))
+    }
+
+    /// Keep the latest `cache_size` blocks in memory instead of the default 128.
+    pub fn with_cache_size(mut self, cache_size: usize) -> Self {
+        self.cache_size = cache_size.max(1);
+        self.cache.truncate(self.cache_size);
+        self
+    }
+
+    /// Persist `block` and move `latest_block_num`/`latest_state_root` to it in a single
+    /// atomic batch, so a crash can't leave a gap or a root without its block.
+    pub fn save_block(&mut self, block: &Block) -> Result<()> {
+        let block_data = storage::encode(block)?;
+
+        let mut batch = KvBatch::default();
+        batch.insert(format!("block_{}", block.block_num), block_data);
+        batch.insert("latest_block_num", &block.block_num.to_be_bytes()[..]);
+        batch.insert(
+            "latest_state_root",
+            &block.post_state_root.unwrap_or_default()[..],
+        );
+        // The block is durable, its drained transactions no longer need the log
+        batch.remove("pending_block");
+        self.db.apply_batch(batch)?;
+
+        // Save cache
+        while self.cache.len() >= self.cache_size {
+            self.cache.pop_front();
+        }
+        self.cache.push_back(block.clone());
+        Ok(())
+    }
+
+    /// Log the transactions drained from the mempool for block `block_num` before they
+    /// are executed, `save_block` clears it. After a crash in between they are found
+    /// with `pending_block` instead of being lost.
+    pub fn save_pending_block(&self, pending: &PendingBlock) -> Result<()> {
+        self.db.insert("pending_block", storage::encode(pending)?)?;
+        self.db.flush()
+    }
+
+    /// The block drained but never saved, if any.
+    pub fn pending_block(&self) -> Result<Option<PendingBlock>> {
+        match self.db.get("pending_block")? {
+            Some(data) => Ok(Some(storage::decode(&data, |_| None)?.0)),
+            None => Ok(None),
+        }
+    }
+
+    /// Block numbers missing from `block_1..=latest_block_num`.
+    pub fn missing_blocks(&self, latest_block_num: u64) -> Vec<u64> {
+        (1..=latest_block_num)
+            .filter(|block_num| {
+                !matches!(
+                    self.db.contains_key(&format!("block_{}", block_num)),
+                    Ok(true)
+                )
+            })
+            .collect()
+    }
+
+    pub fn latest_block_num(&self) -> Option<u64> {
+        let bytes = self.db.get("latest_block_num").ok()??;
+        Some(u64::from_be_bytes(bytes.as_slice().try_into().ok()?))
+    }
+
+    pub fn latest_state_root(&self) -> Option<[u8; 32]> {
+        let bytes = self.db.get("latest_state_root").ok()??;
+        bytes.as_slice().try_into().ok()
+    }
+
+    pub fn get_block(&self, block_num: u64) -> Option<Block> {
+        let data = self.db.get(&format!("block_{}", block_num)).ok()??;
+        decode_block(&data)
+    }
+
+    /// Rewrite the blocks stored in an older version in the current one,
+    /// returns how many were upgraded.
+    pub fn migrate(&self) -> Result<u64> {
+        let mut batch = KvBatch::default();
+        let mut migrated = 0;
+        for (key, data) in self.db.scan_prefix("block_")? {
+            let (block, version) =
+                storage::decode::<Block>(&data, |v0| serde_json::from_slice(v0).ok())?;
+            if version < storage::STORAGE_VERSION {
+                batch.insert(key, storage::encode(&block)?);
+                migrated += 1;
+            }
+        }
+        self.db.apply_batch(batch)?;
+        Ok(migrated)
+    }
+
     pub fn search_txn(&self, signature: &str) -> Option<Transaction> {
         for block in self.cache.clone() {
             for txn in block.txns {
@@ -167,3 +649,483 @@ impl BlockDB {
         None
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::withdrawal_addresses_hash;
+    use super::{
+        calculate_txns_root, compute_unit_price, compute_units, deposit_instruction_data,
+        forced_transaction, parsing_deposit, parsing_instruction, pre_process, priority_order,
+        transaction_memo, Block, BlockDB, PendingBlock, SysOp, COMPUTE_BUDGET_COMPUTE_UNITS,
+        L2_SYS_PROGRAM_KEY, MEMO_COMPUTE_UNITS, MEMO_PROGRAM_KEYS, OTHER_INSTRUCTION_COMPUTE_UNITS,
+        SYSTEM_INSTRUCTION_COMPUTE_UNITS,
+    };
+    use crate::{lamports::Lamports, state::State, storage, WITHDRAWAL_ADDRESS};
+    use solana_sdk::{
+        bs58,
+        compute_budget::ComputeBudgetInstruction,
+        hash::Hash,
+        instruction::Instruction,
+        pubkey::Pubkey,
+        signature::{Keypair, Signature, Signer},
+        system_instruction,
+        transaction::Transaction,
+    };
+
+    fn temporary_block_db() -> BlockDB {
+        BlockDB::in_memory()
+    }
+
+    #[test]
+    fn test_save_block_atomic() {
+        let mut block_db = temporary_block_db();
+        for block_num in 1..=3 {
+            let mut block = Block::new(vec![]);
+            block.block_num = block_num;
+            block.post_state_root = Some([block_num as u8; 32]);
+            block_db.save_block(&block).unwrap();
+        }
+
+        let latest = block_db.db.get("latest_block_num").unwrap().unwrap();
+        assert_eq!(latest, 3u64.to_be_bytes());
+        let root = block_db.db.get("latest_state_root").unwrap().unwrap();
+        assert_eq!(root, [3u8; 32]);
+        assert!(block_db.missing_blocks(3).is_empty());
+
+        // A hole is reported
+        block_db.db.remove("block_2").unwrap();
+        assert_eq!(block_db.missing_blocks(3), vec![2]);
+    }
+
+    #[test]
+    fn test_pending_block() {
+        let block_db = temporary_block_db();
+        assert!(block_db.pending_block().unwrap().is_none());
+
+        let payer = Keypair::new();
+        let txns = vec![Transaction::new_signed_with_payer(
+            &[system_instruction::transfer(
+                &payer.pubkey(),
+                &Pubkey::new_unique(),
+                100,
+            )],
+            Some(&payer.pubkey()),
+            &[&payer],
+            Hash::default(),
+        )];
+        let pending = PendingBlock {
+            block_num: 1,
+            txns: txns.clone(),
+            forced: vec![(3, 100)],
+        };
+        block_db.save_pending_block(&pending).unwrap();
+
+        // A crash before the block is saved leaves the transactions in the log
+        let mut reopened = BlockDB::with_store(block_db.db.clone());
+        assert_eq!(reopened.pending_block().unwrap(), Some(pending));
+
+        // A log of the block number and transactions only has no forced ones
+        reopened
+            .db
+            .insert("pending_block", storage::encode(&(1, &txns)).unwrap())
+            .unwrap();
+        let legacy = reopened.pending_block().unwrap().unwrap();
+        assert_eq!((legacy.block_num, &legacy.txns), (1, &txns));
+        assert!(legacy.forced.is_empty());
+
+        // Saving the block clears it in the same batch
+        let mut block = Block::new(txns);
+        block.block_num = 1;
+        reopened.save_block(&block).unwrap();
+        assert!(reopened.pending_block().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_block_cache_size() {
+        let mut block_db = temporary_block_db().with_cache_size(4);
+        for block_num in 1..=10 {
+            let mut block = Block::new(vec![]);
+            block.block_num = block_num;
+            block_db.save_block(&block).unwrap();
+            assert!(block_db.cache.len() <= 4);
+        }
+        // The latest blocks are kept
+        let cached: Vec<u64> = block_db.cache.iter().map(|block| block.block_num).collect();
+        assert_eq!(cached, vec![7, 8, 9, 10]);
+    }
+
+    #[test]
+    fn test_migrate_v0_blocks() {
+        let block_db = temporary_block_db();
+        let mut block = Block::new(vec![]);
+        block.block_num = 1;
+        block.post_state_root = Some([1u8; 32]);
+        // An unversioned block, plain JSON
+        let v0 = serde_json::to_vec(&block).unwrap();
+        block_db.db.insert("block_1", v0).unwrap();
+
+        let loaded = block_db.get_block(1).unwrap();
+        assert_eq!(loaded.post_state_root, block.post_state_root);
+
+        assert_eq!(block_db.migrate().unwrap(), 1);
+        let (migrated, version) =
+            storage::decode::<Block>(&block_db.db.get("block_1").unwrap().unwrap(), |_| None)
+                .unwrap();
+        assert_eq!(version, storage::STORAGE_VERSION);
+        assert_eq!(migrated.post_state_root, block.post_state_root);
+
+        // Nothing left to upgrade
+        assert_eq!(block_db.migrate().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_allocate_unsupported() {
+        let payer = Keypair::new();
+        let instruction = system_instruction::allocate(&payer.pubkey(), 64);
+        let txn = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&payer.pubkey()),
+            &[&payer],
+            Hash::default(),
+        );
+
+        let op = parsing_instruction(&txn.message.instructions[0], &txn).unwrap();
+        assert_eq!(
+            op,
+            Some(SysOp::Unsupported {
+                kind: "Allocate".to_string()
+            })
+        );
+        // Nothing to execute
+        assert!(pre_process(&txn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_transfer_with_memo() {
+        let payer = Keypair::new();
+        let to = Pubkey::new_unique();
+        let transfer = system_instruction::transfer(&payer.pubkey(), &to, 300);
+        let memo = Instruction::new_with_bytes(MEMO_PROGRAM_KEYS[0], b"deposit-ref-42", vec![]);
+        let txn = Transaction::new_signed_with_payer(
+            &[transfer.clone(), memo],
+            Some(&payer.pubkey()),
+            &[&payer],
+            Hash::default(),
+        );
+        assert_eq!(transaction_memo(&txn), Some("deposit-ref-42".to_string()));
+        assert!(matches!(
+            pre_process(&txn).unwrap().as_slice(),
+            [SysOp::Transfer(_), SysOp::Memo { .. }]
+        ));
+
+        // Same balances and state root as the transfer alone
+        let plain = Transaction::new_signed_with_payer(
+            &[transfer],
+            Some(&payer.pubkey()),
+            &[&payer],
+            Hash::default(),
+        );
+        assert_eq!(transaction_memo(&plain), None);
+        let mut with_memo = State::new();
+        with_memo.set_balance(payer.pubkey().to_string(), Lamports(1_000));
+        let mut without_memo = with_memo.clone();
+        with_memo.apply_txns(&[txn], 1);
+        without_memo.apply_txns(&[plain], 1);
+        assert_eq!(with_memo.get_balance(&to.to_string()), Lamports(300));
+        assert_eq!(
+            with_memo.calculate_state_root(),
+            without_memo.calculate_state_root()
+        );
+
+        // A memo that isn't UTF-8 is rejected like on Solana
+        let invalid = Instruction::new_with_bytes(MEMO_PROGRAM_KEYS[1], &[0xff, 0xfe], vec![]);
+        let txn = Transaction::new_signed_with_payer(
+            &[invalid],
+            Some(&payer.pubkey()),
+            &[&payer],
+            Hash::default(),
+        );
+        assert!(parsing_instruction(&txn.message.instructions[0], &txn).is_err());
+    }
+
+    #[test]
+    fn test_forced_transaction() {
+        let from = Keypair::new();
+        let instruction = system_instruction::transfer(&from.pubkey(), &Pubkey::new_unique(), 1);
+        let signed = Transaction::new_signed_with_payer(
+            std::slice::from_ref(&instruction),
+            Some(&from.pubkey()),
+            &[&from],
+            Hash::default(),
+        );
+        let bytes = bincode::serialize(&signed).unwrap();
+        assert_eq!(forced_transaction(&bytes), Some(signed));
+
+        // Skipped: undecodable, trailing bytes, or not signed by the sender
+        assert_eq!(forced_transaction(&bytes[1..]), None);
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(forced_transaction(&trailing), None);
+        let mut unsigned = Transaction::new_with_payer(&[instruction], Some(&from.pubkey()));
+        unsigned.signatures = vec![Signature::from([1; 64])];
+        assert_eq!(
+            forced_transaction(&bincode::serialize(&unsigned).unwrap()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_withdrawal_addresses_hash() {
+        let default = withdrawal_addresses_hash(&[WITHDRAWAL_ADDRESS.to_string()]);
+        // The sha256 of the pubkey, pinned by the l2-state program
+        assert_eq!(
+            default,
+            [
+                95, 180, 50, 4, 88, 215, 74, 32, 225, 15, 112, 55, 209, 251, 76, 225, 65, 0, 92,
+                240, 52, 17, 96, 122, 9, 145, 104, 92, 61, 202, 189, 200
+            ]
+        );
+
+        // A set, whatever the order or the duplicates
+        let other = Pubkey::new_unique().to_string();
+        let set = withdrawal_addresses_hash(&[WITHDRAWAL_ADDRESS.to_string(), other.clone()]);
+        assert_ne!(set, default);
+        assert_eq!(
+            withdrawal_addresses_hash(&[other.clone(), WITHDRAWAL_ADDRESS.to_string(), other]),
+            set
+        );
+    }
+
+    #[test]
+    fn test_deposit_instruction_round_trip() {
+        let sender = Pubkey::new_unique();
+        let l1_signature = Signature::from([3u8; 64]);
+        let data = deposit_instruction_data(&sender, 1_500, &l1_signature);
+        assert_eq!(data.len(), 104);
+        assert_eq!(
+            parsing_deposit(&data).unwrap(),
+            SysOp::Deposit {
+                to: sender.to_string(),
+                amount: Lamports(1_500),
+                l1_signature: Some(l1_signature.to_string()),
+            }
+        );
+
+        // The 40 byte sender and amount of older oracles
+        assert_eq!(
+            parsing_deposit(&data[..40]).unwrap(),
+            SysOp::Deposit {
+                to: sender.to_string(),
+                amount: Lamports(1_500),
+                l1_signature: None,
+            }
+        );
+
+        // Relayed to the L2 system program, it's the deposit op of the transaction
+        let oracle = Keypair::new();
+        let instruction = Instruction {
+            program_id: *L2_SYS_PROGRAM_KEY,
+            accounts: vec![],
+            data: data.clone(),
+        };
+        let txn = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&oracle.pubkey()),
+            &[&oracle],
+            Hash::default(),
+        );
+        assert_eq!(
+            pre_process(&txn).unwrap(),
+            vec![parsing_deposit(&data).unwrap()]
+        );
+
+        for length in [0, 39, 41, 103, 105] {
+            assert!(parsing_deposit(&vec![0; length]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_compute_units() {
+        let payer = Keypair::new();
+        let to = Pubkey::new_unique();
+        let txn = Transaction::new_signed_with_payer(
+            &[
+                ComputeBudgetInstruction::set_compute_unit_price(5_000),
+                system_instruction::transfer(&payer.pubkey(), &to, 300),
+                Instruction::new_with_bytes(MEMO_PROGRAM_KEYS[0], b"order 42", vec![]),
+                Instruction::new_with_bytes(Pubkey::new_unique(), &[1, 2, 3], vec![]),
+            ],
+            Some(&payer.pubkey()),
+            &[&payer],
+            Hash::default(),
+        );
+        // A transfer costs the 150 units the rpc always reported
+        assert_eq!(SYSTEM_INSTRUCTION_COMPUTE_UNITS, 150);
+        assert_eq!(
+            compute_units(&txn),
+            COMPUTE_BUDGET_COMPUTE_UNITS
+                + SYSTEM_INSTRUCTION_COMPUTE_UNITS
+                + MEMO_COMPUTE_UNITS
+                + OTHER_INSTRUCTION_COMPUTE_UNITS
+        );
+    }
+
+    #[test]
+    fn test_priority_order() {
+        let with_price = |price: u64| {
+            let payer = Keypair::new();
+            Transaction::new_signed_with_payer(
+                &[
+                    ComputeBudgetInstruction::set_compute_unit_price(price),
+                    system_instruction::transfer(&payer.pubkey(), &Pubkey::new_unique(), 1),
+                ],
+                Some(&payer.pubkey()),
+                &[&payer],
+                Hash::default(),
+            )
+        };
+        let mut txns: Vec<_> = [5, 0, 5, 5, 9, 0, 5, 5]
+            .into_iter()
+            .map(with_price)
+            .collect();
+
+        // The same order whatever the arrival order
+        let mut sorted = txns.clone();
+        sorted.sort_by(priority_order);
+        for _ in 0..txns.len() {
+            txns.rotate_left(1);
+            txns.reverse();
+            let mut resorted = txns.clone();
+            resorted.sort_by(priority_order);
+            assert_eq!(resorted, sorted);
+        }
+
+        let prices: Vec<_> = sorted.iter().map(compute_unit_price).collect();
+        assert_eq!(prices, vec![9, 5, 5, 5, 5, 5, 0, 0]);
+        // Equal priorities by ascending signature
+        assert!(sorted[1..6]
+            .windows(2)
+            .all(|pair| pair[0].signatures[0] < pair[1].signatures[0]));
+    }
+
+    #[test]
+    fn test_compute_unit_price() {
+        let payer = Keypair::new();
+        let to = Pubkey::new_unique();
+        let transfer = system_instruction::transfer(&payer.pubkey(), &to, 300);
+        let txn = Transaction::new_signed_with_payer(
+            &[
+                ComputeBudgetInstruction::set_compute_unit_limit(200_000),
+                ComputeBudgetInstruction::set_compute_unit_price(5_000),
+                transfer.clone(),
+            ],
+            Some(&payer.pubkey()),
+            &[&payer],
+            Hash::default(),
+        );
+        assert_eq!(compute_unit_price(&txn), 5_000);
+        assert!(matches!(
+            pre_process(&txn).unwrap().as_slice(),
+            [
+                SysOp::ComputeBudget {
+                    compute_unit_price: None
+                },
+                SysOp::ComputeBudget {
+                    compute_unit_price: Some(5_000)
+                },
+                SysOp::Transfer(_)
+            ]
+        ));
+
+        // The price changes no balance
+        let mut state = State::new();
+        state.set_balance(payer.pubkey().to_string(), Lamports(1_000));
+        state.apply_txns(&[txn], 1);
+        assert_eq!(
+            state.get_balance(&payer.pubkey().to_string()),
+            Lamports(700)
+        );
+
+        let plain = Transaction::new_signed_with_payer(
+            &[transfer],
+            Some(&payer.pubkey()),
+            &[&payer],
+            Hash::default(),
+        );
+        assert_eq!(compute_unit_price(&plain), 0);
+    }
+
+    #[test]
+    fn test_create_account() {
+        let payer = Keypair::new();
+        let new_account = Keypair::new();
+        let owner = Pubkey::new_unique();
+        let instruction = system_instruction::create_account(
+            &payer.pubkey(),
+            &new_account.pubkey(),
+            500,
+            16,
+            &owner,
+        );
+        let txn = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&payer.pubkey()),
+            &[&payer, &new_account],
+            Hash::default(),
+        );
+        assert!(matches!(
+            pre_process(&txn).unwrap().as_slice(),
+            [SysOp::CreateAccount { space: 16, .. }]
+        ));
+
+        let mut state = State::new();
+        state.set_balance(payer.pubkey().to_string(), Lamports(1_000));
+        state.apply_txns(std::slice::from_ref(&txn), 1);
+        assert_eq!(
+            state.get_balance(&payer.pubkey().to_string()),
+            Lamports(500)
+        );
+        let account = state
+            .get_account(&new_account.pubkey().to_string())
+            .unwrap();
+        assert_eq!(account.lamports, Lamports(500));
+        assert_eq!(account.owner, owner.to_string());
+        assert_eq!(account.data, vec![0; 16]);
+
+        // The account is in use now, creating it again fails
+        state.apply_txns(&[txn], 2);
+        assert_eq!(
+            state.get_balance(&payer.pubkey().to_string()),
+            Lamports(500)
+        );
+    }
+
+    #[test]
+    fn test_canonical_hashes() {
+        let from = Pubkey::new_from_array([1u8; 32]);
+        let to = Pubkey::new_from_array([2u8; 32]);
+        let txn = Transaction::new_with_payer(
+            &[system_instruction::transfer(&from, &to, 100)],
+            Some(&from),
+        );
+        let mut block = Block::new(vec![txn]);
+        block.block_num = 7;
+        block.txns_root = Some(calculate_txns_root(&block.txns));
+        block.post_state_root = Some([3u8; 32]);
+
+        // Pinned, any change of the encoding breaks proving of committed batches
+        let txns_root = block.txns_root.unwrap();
+        let block_hash = solana_sdk::hash::hash(&block.canonical_bytes()).to_bytes();
+        assert_eq!(
+            bs58::encode(txns_root).into_string(),
+            "AP6GB49yXC1Sn3BsELEjpoUUKkp84ZNo8iZ2WmFRXSQr"
+        );
+        assert_eq!(
+            bs58::encode(block_hash).into_string(),
+            "D8nHtw7abvaPw7iqMkXpS94zyYEvs8PgpKce18MXCKKd"
+        );
+
+        let decoded = Block::from_canonical_bytes(&block.canonical_bytes()).unwrap();
+        assert_eq!(decoded.canonical_bytes(), block.canonical_bytes());
+    }
+}
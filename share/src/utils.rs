@@ -1,7 +1,19 @@
 use std::str::FromStr;
 
+use crate::{DEFAULT_BLOCK_DB_PATH, DEFAULT_STATE_DB_PATH};
+
 pub fn read_env_var<T: Clone + FromStr>(var_name: &'static str, default: T) -> T {
     std::env::var(var_name)
         .map(|s| s.parse::<T>().unwrap_or_else(|_| default.clone()))
         .unwrap_or(default)
 }
+
+/// Path of the state db, overridden by `STATE_DB_PATH`.
+pub fn state_db_path() -> String {
+    read_env_var("STATE_DB_PATH", DEFAULT_STATE_DB_PATH.to_owned())
+}
+
+/// Path of the block db, overridden by `BLOCK_DB_PATH`.
+pub fn block_db_path() -> String {
+    read_env_var("BLOCK_DB_PATH", DEFAULT_BLOCK_DB_PATH.to_owned())
+}
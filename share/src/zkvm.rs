@@ -1,9 +1,677 @@
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use thiserror::Error;
 
-use crate::{state::State, transaction::Block};
+use crate::{
+    state::State,
+    transaction::{
+        calculate_txns_root, forced_transaction, l1_oracle, parsing_instruction, pre_process,
+        transaction_hash, withdrawal_addresses, withdrawal_addresses_hash, Block, SysOp,
+    },
+};
+use solana_sdk::pubkey::Pubkey;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ZkVMInput {
     pub blocks: Vec<Block>,
     pub state: State,
+    /// The withdrawal addresses of the host, installed by the zkVM program so it
+    /// detects withdrawals like the sequencer, see `set_withdrawal_addresses`
+    pub withdrawal_addresses: Vec<String>,
+    /// The L1 oracle of the host, installed by the zkVM program so it credits the same
+    /// deposits as the sequencer, see `set_l1_oracle`
+    pub l1_oracle: Pubkey,
+    /// The bytes of the forced transactions the batch executes, in queue order: the
+    /// ones from the previous batch's `next_forced_index` to the batch's. The l2-state
+    /// program checks their hash, see `forced_transactions_hash`.
+    pub forced_txns: Vec<Vec<u8>>,
+}
+
+impl ZkVMInput {
+    /// Input carrying only the accounts touched by `blocks`, see `State::partial`, and
+    /// the records of the deposits they credit again, for the replays to fail like they
+    /// did on the sequencer. It executes no forced transactions, set `forced_txns` for a
+    /// batch that does.
+    pub fn with_partial_state(blocks: Vec<Block>, state: &State) -> Self {
+        let mut partial = state.partial(&touched_accounts(&blocks));
+        partial.deposits = deposit_signatures(&blocks)
+            .filter_map(|l1_signature| {
+                let record = state.deposits.get(&l1_signature)?;
+                Some((l1_signature, record.clone()))
+            })
+            .collect();
+        Self {
+            blocks,
+            state: partial,
+            withdrawal_addresses: withdrawal_addresses(),
+            l1_oracle: l1_oracle(),
+            forced_txns: vec![],
+        }
+    }
+
+    /// Compact bincode encoding, written to the zkVM with `write_vec`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).unwrap_or_default()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        bincode::deserialize(bytes).ok()
+    }
+}
+
+/// Public values committed by the zkVM program for a valid batch.
+///
+/// `da_hash` is the sha256 of the blocks' canonical bytes, which is the `batch_hash`
+/// the l2-state program stores for a batch committed through calldata. The verifier
+/// checks the proof against the committed `batch_hash`, so proving other blocks fails.
+/// `pi_hash` binds the roots the batch moves between, see `calculate_pi_hash`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PublicValues {
+    pub pi_hash: [u8; 32],
+    pub da_hash: [u8; 32],
+}
+
+impl PublicValues {
+    /// Bytes of the values as `sp1_zkvm::io::commit` writes them, `pi_hash || da_hash`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).unwrap_or_default()
+    }
+}
+
+/// Addresses read or written by the system ops of `blocks`.
+pub fn touched_accounts(blocks: &[Block]) -> HashSet<String> {
+    let mut addresses = HashSet::new();
+    for txn in blocks.iter().flat_map(|block| block.txns.iter()) {
+        for instruction in txn.message.instructions.iter() {
+            if let Ok(Some(op)) = parsing_instruction(instruction, txn) {
+                addresses.extend(op.accounts().into_iter().cloned());
+            }
+        }
+    }
+    addresses
+}
+
+// L1 signatures of the deposits of `blocks`
+fn deposit_signatures(blocks: &[Block]) -> impl Iterator<Item = String> + '_ {
+    blocks
+        .iter()
+        .flat_map(|block| block.txns.iter())
+        .flat_map(|txn| {
+            txn.message.instructions.iter().filter_map(
+                move |instruction| match parsing_instruction(instruction, txn) {
+                    Ok(Some(SysOp::Deposit { l1_signature, .. })) => l1_signature,
+                    _ => None,
+                },
+            )
+        })
+}
+
+/// Why a batch can't be proven, committed by the zkVM program instead of panicking.
+#[derive(Clone, Debug, Error, Serialize, Deserialize, PartialEq, Eq)]
+pub enum InvalidBatch {
+    #[error("batch has no blocks")]
+    EmptyBatch,
+    #[error("input state root doesn't match the batch prev_state_root")]
+    PrevStateRootMismatch,
+    #[error("block {block_num}: prev_state_root doesn't match the previous post_state_root")]
+    StateRootChainBroken { block_num: u64 },
+    #[error("block {block_num}: txns_root mismatch")]
+    TxnsRootMismatch { block_num: u64 },
+    #[error("block {block_num} txn {txn_index}: account {address} missing from the input state")]
+    MissingAccount {
+        block_num: u64,
+        txn_index: usize,
+        address: String,
+    },
+    #[error("block {block_num}: post_state_root mismatch")]
+    PostStateRootMismatch { block_num: u64 },
+    #[error("block {block_num}: withdrawal_root mismatch")]
+    WithdrawalRootMismatch { block_num: u64 },
+    #[error("forced transaction {index} of the batch isn't executed by its blocks")]
+    ForcedTransactionNotExecuted { index: usize },
+}
+
+/// Re-execute the blocks of a batch against the input state and return the public values.
+pub fn execute_batch(input: ZkVMInput) -> Result<PublicValues, InvalidBatch> {
+    let mut state = input.state;
+    let blocks = input.blocks;
+    let (Some(first_block), Some(last_block)) = (blocks.first(), blocks.last()) else {
+        return Err(InvalidBatch::EmptyBatch);
+    };
+    let prev_state_root = first_block.prev_state_root.unwrap_or_default();
+    let post_state_root = last_block.post_state_root.unwrap_or_default();
+    let (last_block_num, last_withdrawal_root) = (
+        last_block.block_num,
+        last_block.withdrawal_root.unwrap_or_default(),
+    );
+
+    // Check the input state, or the witness of a partial state, against the batch
+    if state.calculate_state_root().unwrap_or_default() != prev_state_root {
+        return Err(InvalidBatch::PrevStateRootMismatch);
+    }
+    // The withdrawals queued before the batch, the verifier checks their root is the
+    // previous batch's
+    let prev_withdrawal_root = state.calculate_withdrawal_root().unwrap_or_default();
+
+    let mut blocks_bytes: Vec<u8> = vec![];
+    let mut executed = HashSet::new();
+    let mut current_state_root = prev_state_root;
+    for block in blocks {
+        let block_num = block.block_num;
+        // blocks[n-1].post_state_root == blocks[n].prev_state_root
+        if current_state_root != block.prev_state_root.unwrap_or_default() {
+            return Err(InvalidBatch::StateRootChainBroken { block_num });
+        }
+        current_state_root = block.post_state_root.unwrap_or_default();
+        blocks_bytes.extend_from_slice(&block.canonical_bytes());
+
+        // Calculate txns root
+        let txns_root = calculate_txns_root(&block.txns);
+        if txns_root != block.txns_root.unwrap_or_default() {
+            return Err(InvalidBatch::TxnsRootMismatch { block_num });
+        }
+
+        // Accounts of a partial state must be present in full
+        for (txn_index, txn) in block.txns.iter().enumerate() {
+            let Ok(ops) = pre_process(txn) else {
+                continue;
+            };
+            if let Some(address) = ops
+                .iter()
+                .flat_map(|op| op.accounts())
+                .find(|address| state.is_pruned(address))
+            {
+                return Err(InvalidBatch::MissingAccount {
+                    block_num,
+                    txn_index,
+                    address: address.clone(),
+                });
+            }
+        }
+        // Executed like the sequencer did, by the same code: a transaction that fails
+        // stops the block and the post root tells whether it did so in the same place.
+        // The txns are replayed in the block's order, the txns root commits to it: the
+        // forced ones first, then the mempool's by (priority desc, signature asc), see
+        // `priority_order`. They aren't re-sorted here.
+        let applied = state.apply_txns(&block.txns, block_num);
+        // The transactions gone through, the one that failed was executed as well
+        executed.extend(
+            block
+                .txns
+                .iter()
+                .take(applied.count + 1)
+                .map(transaction_hash),
+        );
+
+        // Calculate current block state root
+        let state_root = state.calculate_state_root().unwrap_or_default();
+        if state_root != block.post_state_root.unwrap_or_default() {
+            return Err(InvalidBatch::PostStateRootMismatch { block_num });
+        }
+    }
+
+    // The withdrawals claimable on L1 once the batch is finalized
+    let withdrawal_root = state.calculate_withdrawal_root().unwrap_or_default();
+    if withdrawal_root != last_withdrawal_root {
+        return Err(InvalidBatch::WithdrawalRootMismatch {
+            block_num: last_block_num,
+        });
+    }
+
+    // The forced transactions the batch claims, the l2-state program checks they're the
+    // ones queued on L1
+    if let Some(index) = input
+        .forced_txns
+        .iter()
+        .position(|forced| !forced_transaction_executed(forced, &executed))
+    {
+        return Err(InvalidBatch::ForcedTransactionNotExecuted { index });
+    }
+
+    // Replace versioned_hash with all txn hashes
+    let da_hash = calculate_da_hash(&blocks_bytes);
+
+    // calculate pi hash
+    let pi_hash = calculate_pi_hash(
+        &prev_state_root,
+        &post_state_root,
+        &prev_withdrawal_root,
+        &withdrawal_root,
+        // The addresses withdrawals were detected on, the verifier pins them
+        &withdrawal_addresses_hash(&withdrawal_addresses()),
+        // and the oracle deposits were credited from, pinned as well
+        &l1_oracle().to_bytes(),
+        // and the forced transactions executed, dequeued on L1 once the batch is final
+        &forced_transactions_hash(&input.forced_txns),
+        &da_hash,
+    );
+    Ok(PublicValues { pi_hash, da_hash })
+}
+
+/// Whether the forced transaction with bytes `forced` is done by a batch whose executed
+/// transactions hash to `executed`, see `transaction_hash`. The bytes the sequencer
+/// skips, see `forced_transaction`, are done without being executed.
+pub fn forced_transaction_executed(forced: &[u8], executed: &HashSet<[u8; 32]>) -> bool {
+    forced_transaction(forced).is_none_or(|txn| executed.contains(&transaction_hash(&txn)))
+}
+
+/// Number of the leading `forced_txns` done by a batch of the sealed `blocks`, whose
+/// transactions all went through, the forced transactions the batch executes.
+pub fn executed_forced_count(forced_txns: &[Vec<u8>], blocks: &[Block]) -> usize {
+    let executed = blocks
+        .iter()
+        .flat_map(|block| block.txns.iter())
+        .map(transaction_hash)
+        .collect();
+    forced_txns
+        .iter()
+        .take_while(|forced| forced_transaction_executed(forced, &executed))
+        .count()
+}
+
+/// Hash of the forced transactions a batch executes, `hash_nested_vector` of the
+/// l2-state program over the sha256 of their bytes, as it computes it from its queue.
+pub fn forced_transactions_hash(forced_txns: &[Vec<u8>]) -> [u8; 32] {
+    if forced_txns.is_empty() {
+        return [0; 32];
+    }
+    let mut hasher = Sha256::new();
+    for forced in forced_txns {
+        hasher.update(Sha256::digest(forced));
+    }
+    hasher.finalize().into()
+}
+
+// Helper function to calculate hash with all blocks' txns for DA.
+fn calculate_da_hash(blocks_bytes: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(blocks_bytes);
+    hasher.finalize().into()
+}
+
+/// Public input hash of a batch, the sha256 of `prev_state_root || post_state_root ||
+/// prev_withdrawal_root || withdrawal_root || withdrawal_addresses_hash || l1_oracle ||
+/// forced_transactions_hash || da_hash`.
+/// `batch_public_inputs` of the l2-state program hashes the same values of the
+/// committed batch in the same order.
+#[allow(clippy::too_many_arguments)]
+pub fn calculate_pi_hash(
+    prev_state_root: &[u8; 32],
+    post_state_root: &[u8; 32],
+    prev_withdrawal_root: &[u8; 32],
+    withdrawal_root: &[u8; 32],
+    withdrawal_addresses_hash: &[u8; 32],
+    l1_oracle: &[u8; 32],
+    forced_transactions_hash: &[u8; 32],
+    da_hash: &[u8; 32],
+) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+
+    hasher.update(prev_state_root);
+    hasher.update(post_state_root);
+    hasher.update(prev_withdrawal_root);
+    hasher.update(withdrawal_root);
+    hasher.update(withdrawal_addresses_hash);
+    hasher.update(l1_oracle);
+    hasher.update(forced_transactions_hash);
+    hasher.update(da_hash);
+
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        calculate_pi_hash, execute_batch, executed_forced_count, forced_transactions_hash,
+        touched_accounts, InvalidBatch, ZkVMInput,
+    };
+    use crate::{
+        lamports::Lamports,
+        state::State,
+        transaction::{
+            calculate_txns_root, deposit_instruction_data, l1_oracle, withdrawal_addresses,
+            withdrawal_addresses_hash, Block, L2_SYS_PROGRAM_KEY,
+        },
+        UNSAFE_PRIVATE_KEY, WITHDRAWAL_ADDRESS,
+    };
+    use sha2::{Digest, Sha256};
+    use solana_sdk::{
+        hash::Hash,
+        instruction::Instruction,
+        pubkey::Pubkey,
+        signature::{Keypair, Signature, Signer},
+        system_transaction,
+        transaction::Transaction,
+    };
+    use std::str::FromStr;
+
+    // A one block batch of `alice` paying `bob`, on top of `accounts` other accounts,
+    // returns alice's address
+    fn transfer_batch(accounts: usize) -> (State, Vec<Block>, String) {
+        let alice = Keypair::new();
+        let bob = Pubkey::new_unique();
+        let mut state = State::new();
+        state.set_balance(alice.pubkey().to_string(), Lamports(1_000));
+        for _ in 0..accounts {
+            state.set_balance(Pubkey::new_unique().to_string(), Lamports(1));
+        }
+
+        let txn = system_transaction::transfer(&alice, &bob, 100, Hash::default());
+        let mut block = Block::new(vec![txn]);
+        block.block_num = 1;
+        block.txns_root = Some(calculate_txns_root(&block.txns));
+        block.prev_state_root = state.calculate_state_root();
+        let mut post_state = state.clone();
+        post_state.sub_balance(alice.pubkey().to_string(), Lamports(100));
+        post_state
+            .add_balance(bob.to_string(), Lamports(100))
+            .unwrap();
+        block.post_state_root = post_state.calculate_state_root();
+
+        (state, vec![block], alice.pubkey().to_string())
+    }
+
+    #[test]
+    fn test_partial_state_input() {
+        let (state, blocks, _) = transfer_batch(1000);
+        let full = ZkVMInput {
+            blocks: blocks.clone(),
+            state: state.clone(),
+            withdrawal_addresses: withdrawal_addresses(),
+            l1_oracle: l1_oracle(),
+            forced_txns: vec![],
+        };
+        let partial = ZkVMInput::with_partial_state(blocks, &state);
+        assert_eq!(partial.state.accounts.len(), 1);
+
+        let full_bytes = full.to_bytes();
+        let partial_bytes = partial.to_bytes();
+        assert!(partial_bytes.len() < full_bytes.len());
+
+        // Both reach the block's post root and commit the same pi hash
+        let partial = ZkVMInput::from_bytes(&partial_bytes).unwrap();
+        let pi_hash = execute_batch(full);
+        assert!(pi_hash.is_ok());
+        assert_eq!(pi_hash, execute_batch(partial));
+    }
+
+    #[test]
+    fn test_public_values_bind_blocks() {
+        let (state, blocks, _) = transfer_batch(1);
+        // The batch_hash committed on L1 for these blocks through calldata
+        let calldata: Vec<Vec<u8>> = blocks.iter().map(Block::canonical_bytes).collect();
+        let batch_hash: [u8; 32] = Sha256::digest(calldata.concat()).into();
+
+        let public_values = execute_batch(ZkVMInput {
+            blocks,
+            state: state.clone(),
+            withdrawal_addresses: withdrawal_addresses(),
+            l1_oracle: l1_oracle(),
+            forced_txns: vec![],
+        })
+        .unwrap();
+        assert_eq!(public_values.da_hash, batch_hash);
+        let bytes = public_values.to_bytes();
+        assert_eq!(bytes[..32], public_values.pi_hash);
+        assert_eq!(bytes[32..], batch_hash);
+
+        // Other valid blocks prove another da_hash, not the committed batch
+        let (other_state, other_blocks, _) = transfer_batch(1);
+        let other = execute_batch(ZkVMInput {
+            blocks: other_blocks,
+            state: other_state,
+            withdrawal_addresses: withdrawal_addresses(),
+            l1_oracle: l1_oracle(),
+            forced_txns: vec![],
+        })
+        .unwrap();
+        assert_ne!(other.da_hash, batch_hash);
+    }
+
+    #[test]
+    fn test_partial_state_sweep() {
+        let alice = Keypair::new();
+        let mut state = State::new();
+        state.set_balance(alice.pubkey().to_string(), Lamports(100));
+        for _ in 0..10 {
+            state.set_balance(Pubkey::new_unique().to_string(), Lamports(1));
+        }
+
+        // Alice empties her account, the sequencer sweeps it
+        let txn = system_transaction::transfer(&alice, &Pubkey::new_unique(), 100, Hash::default());
+        let mut block = Block::new(vec![txn]);
+        block.block_num = 1;
+        block.txns_root = Some(calculate_txns_root(&block.txns));
+        block.prev_state_root = state.calculate_state_root();
+        let mut post_state = state.clone();
+        post_state.apply_txns(&block.txns, 1);
+        assert!(post_state
+            .get_account(&alice.pubkey().to_string())
+            .is_none());
+        block.post_state_root = post_state.calculate_state_root();
+
+        // The prover sweeps it from its witness too and reaches the same root
+        let input = ZkVMInput::with_partial_state(vec![block], &state);
+        assert!(execute_batch(input).is_ok());
+    }
+
+    #[test]
+    fn test_self_transfer_proven() {
+        let alice = Keypair::new();
+        let mut state = State::new();
+        state.set_balance(alice.pubkey().to_string(), Lamports(100));
+
+        // The sequencer leaves the state as it was, the prover reaches the same root
+        let txn = system_transaction::transfer(&alice, &alice.pubkey(), 60, Hash::default());
+        let mut block = Block::new(vec![txn]);
+        block.block_num = 1;
+        block.txns_root = Some(calculate_txns_root(&block.txns));
+        block.prev_state_root = state.calculate_state_root();
+        let mut post_state = state.clone();
+        post_state.apply_txns(&block.txns, 1);
+        block.post_state_root = post_state.calculate_state_root();
+        assert_eq!(block.post_state_root, block.prev_state_root);
+
+        let input = ZkVMInput::with_partial_state(vec![block], &state);
+        assert!(execute_batch(input).is_ok());
+    }
+
+    #[test]
+    fn test_withdrawal_root_committed() {
+        let alice = Keypair::new();
+        let mut state = State::new();
+        state.set_balance(alice.pubkey().to_string(), Lamports(1_000));
+        let withdrawal_address = Pubkey::from_str(WITHDRAWAL_ADDRESS).unwrap();
+        let withdraw = |lamports: u64| {
+            system_transaction::transfer(&alice, &withdrawal_address, lamports, Hash::default())
+        };
+        // A withdrawal queued by an earlier batch
+        state.apply_txns(&[withdraw(100)], 1);
+        let prev_withdrawal_root = state.calculate_withdrawal_root().unwrap();
+        let oracle = Keypair::from_base58_string(UNSAFE_PRIVATE_KEY);
+
+        let mut block = Block::new(vec![withdraw(200)]);
+        block.block_num = 2;
+        block.txns_root = Some(calculate_txns_root(&block.txns));
+        block.prev_state_root = state.calculate_state_root();
+        let mut post_state = state.clone();
+        post_state.apply_txns(&block.txns, 2);
+        block.post_state_root = post_state.calculate_state_root();
+        block.withdrawal_root = post_state.calculate_withdrawal_root();
+
+        let input = ZkVMInput::with_partial_state(vec![block.clone()], &state);
+        let public_values = execute_batch(input.clone()).unwrap();
+        assert_eq!(
+            public_values.pi_hash,
+            calculate_pi_hash(
+                &block.prev_state_root.unwrap(),
+                &block.post_state_root.unwrap(),
+                &prev_withdrawal_root,
+                &block.withdrawal_root.unwrap(),
+                &withdrawal_addresses_hash(&[WITHDRAWAL_ADDRESS.to_string()]),
+                &oracle.pubkey().to_bytes(),
+                &[0; 32],
+                &public_values.da_hash,
+            )
+        );
+
+        // A block claiming another withdrawal root isn't proven
+        block.withdrawal_root = Some([7; 32]);
+        assert_eq!(
+            execute_batch(ZkVMInput::with_partial_state(vec![block], &state)),
+            Err(InvalidBatch::WithdrawalRootMismatch { block_num: 2 })
+        );
+    }
+
+    #[test]
+    fn test_replayed_deposit_proven() {
+        let oracle = Keypair::from_base58_string(UNSAFE_PRIVATE_KEY);
+        let deposit = Transaction::new_signed_with_payer(
+            &[Instruction {
+                program_id: *L2_SYS_PROGRAM_KEY,
+                accounts: vec![],
+                data: deposit_instruction_data(
+                    &Pubkey::new_unique(),
+                    500,
+                    &Signature::from([9u8; 64]),
+                ),
+            }],
+            Some(&oracle.pubkey()),
+            &[&oracle],
+            Hash::default(),
+        );
+        let mut state = State::new();
+        state.apply_txns(std::slice::from_ref(&deposit), 1);
+
+        // Relayed again, the sequencer stops the block at it and credits nothing
+        let mut block = Block::new(vec![deposit]);
+        block.block_num = 2;
+        block.txns_root = Some(calculate_txns_root(&block.txns));
+        block.prev_state_root = state.calculate_state_root();
+        block.post_state_root = block.prev_state_root;
+
+        let input = ZkVMInput::with_partial_state(vec![block], &state);
+        assert_eq!(input.state.deposits.len(), 1);
+        assert!(execute_batch(input.clone()).is_ok());
+
+        // Without the deposit record the prover credits it a second time
+        let mut input = input;
+        input.state.deposits.clear();
+        assert_eq!(
+            execute_batch(input),
+            Err(InvalidBatch::PostStateRootMismatch { block_num: 2 })
+        );
+    }
+
+    #[test]
+    fn test_unsigned_deposit_not_proven() {
+        let oracle = Keypair::from_base58_string(UNSAFE_PRIVATE_KEY);
+        let depositor = Pubkey::new_unique();
+        let mut deposit = Transaction::new_with_payer(
+            &[Instruction {
+                program_id: *L2_SYS_PROGRAM_KEY,
+                accounts: vec![],
+                data: deposit_instruction_data(&depositor, 500, &Signature::from([9u8; 64])),
+            }],
+            Some(&oracle.pubkey()),
+        );
+        deposit.signatures = vec![Signature::default()];
+        let state = State::new();
+
+        // A block crediting a deposit that names the oracle without its signature
+        let mut block = Block::new(vec![deposit]);
+        block.block_num = 1;
+        block.txns_root = Some(calculate_txns_root(&block.txns));
+        block.prev_state_root = state.calculate_state_root();
+        let mut minted = state.clone();
+        minted.set_balance(depositor.to_string(), Lamports(500));
+        block.post_state_root = minted.calculate_state_root();
+
+        assert_eq!(
+            execute_batch(ZkVMInput::with_partial_state(vec![block], &state)),
+            Err(InvalidBatch::PostStateRootMismatch { block_num: 1 })
+        );
+    }
+
+    #[test]
+    fn test_partial_state_missing_account() {
+        let (state, blocks, alice) = transfer_batch(10);
+        // Witness leaving the sender out
+        let mut addresses = touched_accounts(&blocks);
+        addresses.remove(&alice);
+        let input = ZkVMInput {
+            state: state.partial(&addresses),
+            blocks,
+            withdrawal_addresses: withdrawal_addresses(),
+            l1_oracle: l1_oracle(),
+            forced_txns: vec![],
+        };
+
+        assert_eq!(
+            execute_batch(input),
+            Err(InvalidBatch::MissingAccount {
+                block_num: 1,
+                txn_index: 0,
+                address: alice,
+            })
+        );
+    }
+
+    #[test]
+    fn test_forced_transactions_committed() {
+        let (state, blocks, _) = transfer_batch(1);
+        let forced = bincode::serialize(&blocks[0].txns[0]).unwrap();
+        let unexecuted = bincode::serialize(&system_transaction::transfer(
+            &Keypair::new(),
+            &Pubkey::new_unique(),
+            1,
+            Hash::default(),
+        ))
+        .unwrap();
+        let input = |forced_txns: Vec<Vec<u8>>| ZkVMInput {
+            forced_txns,
+            ..ZkVMInput::with_partial_state(blocks.clone(), &state)
+        };
+
+        let withdrawal_root = state.calculate_withdrawal_root().unwrap_or_default();
+
+        // The pi hash commits the forced transactions the blocks executed, bytes the
+        // sequencer skips are done without being executed
+        let none = execute_batch(input(vec![])).unwrap();
+        let public_values = execute_batch(input(vec![forced.clone(), vec![1, 2, 3]])).unwrap();
+        assert_ne!(public_values.pi_hash, none.pi_hash);
+        assert_eq!(public_values.da_hash, none.da_hash);
+        assert_eq!(
+            public_values.pi_hash,
+            calculate_pi_hash(
+                &blocks[0].prev_state_root.unwrap(),
+                &blocks[0].post_state_root.unwrap(),
+                &withdrawal_root,
+                &withdrawal_root,
+                &withdrawal_addresses_hash(&withdrawal_addresses()),
+                &l1_oracle().to_bytes(),
+                &forced_transactions_hash(&[forced.clone(), vec![1, 2, 3]]),
+                &public_values.da_hash,
+            )
+        );
+
+        // A batch can't claim a forced transaction its blocks didn't execute
+        let queued = vec![forced, vec![1, 2, 3], unexecuted];
+        assert_eq!(
+            execute_batch(input(queued.clone())),
+            Err(InvalidBatch::ForcedTransactionNotExecuted { index: 2 })
+        );
+        // the sequencer claims the ones before it
+        assert_eq!(executed_forced_count(&queued, &blocks), 2);
+    }
+
+    #[test]
+    fn test_forced_transactions_hash() {
+        // `hash_nested_vector` of the l2-state program over the transaction hashes
+        assert_eq!(forced_transactions_hash(&[]), [0; 32]);
+        let hashes = [Sha256::digest([1u8]), Sha256::digest([2u8])].concat();
+        let expected: [u8; 32] = Sha256::digest(hashes).into();
+        assert_eq!(forced_transactions_hash(&[vec![1], vec![2]]), expected);
+    }
 }
@@ -1,7 +1,7 @@
-use anchor_client::{Client, ClientError, Cluster};
+use anchor_client::{Client, ClientError, Cluster, EventContext};
 use anchor_lang::prelude::*;
 use anyhow::Result;
-use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::{commitment_config::CommitmentConfig, signature::Signature};
 use std::sync::Arc;
 use tokio::sync::mpsc;
 
@@ -20,14 +20,104 @@ pub struct DepositEvent {
 pub struct EventData {
     pub event: DepositEvent,
     pub slot: u64,
+    // L1 transaction emitting the event
+    pub signature: Signature,
+}
+
+#[derive(Debug, Clone)]
+#[event]
+pub struct ForcedTransactionEvent {
+    pub index: u64,
+    pub sender: Pubkey,
+    pub transaction: Vec<u8>,
+    pub deadline: i64,
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct ForcedTransactionData {
+    pub event: ForcedTransactionEvent,
+    pub slot: u64,
+    pub signature: Signature,
+}
+
+#[derive(Debug, Clone)]
+#[event]
+pub struct BatchCommittedEvent {
+    pub batch_index: u64,
+    pub start_block_num: u64,
+    pub end_block_num: u64,
+    pub batch_hash: [u8; 32],
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct BatchCommittedData {
+    pub event: BatchCommittedEvent,
+    pub slot: u64,
+    pub signature: Signature,
 }
 
 /// Create an event listener and return the receiver
 pub async fn create_listener(
     rpc_url: String,
     ws_url: String,
+    commitment: CommitmentConfig,
     tx: mpsc::UnboundedSender<EventData>,
 ) -> Result<(), ClientError> {
+    listen(rpc_url, ws_url, commitment, tx, |event, ctx| EventData {
+        event,
+        slot: ctx.slot,
+        signature: ctx.signature,
+    })
+    .await
+}
+
+/// Create a listener of the transactions forced through L1
+pub async fn create_forced_transaction_listener(
+    rpc_url: String,
+    ws_url: String,
+    commitment: CommitmentConfig,
+    tx: mpsc::UnboundedSender<ForcedTransactionData>,
+) -> Result<(), ClientError> {
+    listen(rpc_url, ws_url, commitment, tx, |event, ctx| {
+        ForcedTransactionData {
+            event,
+            slot: ctx.slot,
+            signature: ctx.signature,
+        }
+    })
+    .await
+}
+
+/// Create a listener of the batches committed on L1, full or hash only
+pub async fn create_batch_committed_listener(
+    rpc_url: String,
+    ws_url: String,
+    commitment: CommitmentConfig,
+    tx: mpsc::UnboundedSender<BatchCommittedData>,
+) -> Result<(), ClientError> {
+    listen(rpc_url, ws_url, commitment, tx, |event, ctx| {
+        BatchCommittedData {
+            event,
+            slot: ctx.slot,
+            signature: ctx.signature,
+        }
+    })
+    .await
+}
+
+async fn listen<E, T>(
+    rpc_url: String,
+    ws_url: String,
+    commitment: CommitmentConfig,
+    tx: mpsc::UnboundedSender<T>,
+    to_data: fn(E, &EventContext) -> T,
+) -> Result<(), ClientError>
+where
+    E: anchor_lang::Event + Clone + Send + 'static,
+    T: std::fmt::Debug + Send + 'static,
+{
     let program_id: Pubkey = PROGRAM_ID
         .parse()
         .map_err(|e| ClientError::LogParseError(format!("Invalid program ID: {}", e)))?;
@@ -36,7 +126,7 @@ pub async fn create_listener(
     let client = Client::new_with_options(
         Cluster::Custom(rpc_url, ws_url),
         Arc::new(payer),
-        CommitmentConfig::processed(),
+        commitment,
     );
     let program = client.program(program_id).unwrap();
 
@@ -44,11 +134,8 @@ pub async fn create_listener(
     let txa = tx.clone();
 
     let _unsubscriber: anchor_client::EventUnsubscriber<'_> = program
-        .on::<DepositEvent>(move |ctx, event| {
-            let event_data = EventData {
-                event: event.clone(),
-                slot: ctx.slot,
-            };
+        .on::<E>(move |ctx, event| {
+            let event_data = to_data(event.clone(), ctx);
             log::info!("event_data: {:?}", event_data);
 
             if txa.send(event_data).is_err() {
@@ -72,6 +159,7 @@ async fn test_create_listener() -> Result<()> {
         let _ = create_listener(
             "http://127.0.0.1:8899".to_string(),
             "ws://127.0.0.1:8900".to_string(),
+            CommitmentConfig::processed(),
             tx,
         )
         .await;
@@ -1,17 +1,53 @@
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program::hash::hash;
+#[cfg(not(feature = "keccak"))]
+use anchor_lang::solana_program::hash::hashv;
+#[cfg(feature = "keccak")]
+use anchor_lang::solana_program::keccak::hashv;
 use anyhow::Result;
 use base64::{self, engine::general_purpose, Engine};
-use solana_client::rpc_client::RpcClient;
+use serde_json::{json, Value};
+use share::hash::HASH_ALGO;
+use share::zkvm::forced_transactions_hash;
+use solana_client::{
+    rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient},
+    rpc_config::RpcTransactionConfig,
+    rpc_request::RpcRequest,
+    rpc_response::RpcConfirmedTransactionStatusWithSignature,
+};
 use solana_sdk::{
-    commitment_config::CommitmentConfig, instruction::Instruction, pubkey::Pubkey,
-    signature::Keypair, signer::Signer, system_program, transaction::Transaction,
+    commitment_config::CommitmentConfig,
+    instruction::Instruction,
+    message::Message,
+    packet::PACKET_DATA_SIZE,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::Signer,
+    system_program,
+    transaction::Transaction,
 };
+use solana_transaction_status::UiTransactionEncoding;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
+use crate::event_listen::ForcedTransactionEvent;
 use crate::PROGRAM_ID;
 
+/// Bytes of proof per `append_proof_chunk` transaction. The signature, the three
+/// accounts and the instruction header take ~250 bytes of the 1232 byte
+/// `PACKET_DATA_SIZE`, 900 leaves a safe margin.
+pub const PROOF_CHUNK_SIZE: usize = 900;
+
+/// Wait for an airdrop to confirm before giving up, a rate-limited faucet may
+/// never confirm it.
+pub const AIRDROP_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Lamports airdropped to an unfunded fee payer with `auto_airdrop`
+const AUTO_AIRDROP_LAMPORTS: u64 = 1_000_000_000;
+const AIRDROP_POLL_INTERVAL: Duration = Duration::from_millis(500);
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+#[cfg_attr(feature = "dev-tools", derive(serde::Serialize, serde::Deserialize))]
 pub struct BatchInfo {
     pub batch_index: u64,
     pub blocks: Vec<Vec<u8>>,
@@ -20,6 +56,38 @@ pub struct BatchInfo {
     pub prev_state_root: [u8; 32],
     pub post_state_root: [u8; 32],
     pub withdrawal_root: [u8; 32],
+    pub withdrawal_count: u64,
+    /// Index of the first forced transaction not executed by the batch and the ones
+    /// before it
+    pub next_forced_index: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct BatchHashInfo {
+    pub batch_index: u64,
+    pub batch_hash: [u8; 32],
+    pub start_block_num: u64,
+    pub end_block_num: u64,
+    pub prev_state_root: [u8; 32],
+    pub post_state_root: [u8; 32],
+    pub withdrawal_root: [u8; 32],
+    pub withdrawal_count: u64,
+    pub next_forced_index: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct WithdrawalData {
+    pub amount: u64,
+    pub index: u64,
+    pub withdraw_root: [u8; 32],
+    pub withdrawal_proof: Vec<[u8; 32]>,
+}
+
+// Args of `reveal_batch`
+#[derive(AnchorSerialize, AnchorDeserialize)]
+struct RevealBatchArgs {
+    batch_index: u64,
+    blocks: Vec<Vec<u8>>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
@@ -37,18 +105,149 @@ pub struct BatchData {
     pub prev_state_root: [u8; 32],
     pub post_state_root: [u8; 32],
     pub withdrawal_root: [u8; 32],
+    pub withdrawal_count: u64,
+    pub next_forced_index: u64,
+    /// Hash of the forced transactions the batch executes, see
+    /// `share::zkvm::forced_transactions_hash`
+    pub forced_transactions_hash: [u8; 32],
+}
+
+/// The l2-state `ForcedTransactions` queue: the hashes of the transactions forced
+/// through L1 that no finalized batch executed yet.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ForcedTransactionQueue {
+    pub next_index: u64,
+    pub pending: Vec<ForcedTransactionEntry>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ForcedTransactionEntry {
+    pub index: u64,
+    /// The sha256 of the bincode serialized L2 transaction
+    pub transaction_hash: [u8; 32],
+    pub deadline: i64,
+}
+
+/// The l2-state `Config` account: its roles, the L1 oracle proofs must credit the
+/// deposits of and the hash of the withdrawal addresses they must withdraw on.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ProgramConfig {
+    pub authority: Pubkey,
+    pub sequencer: Pubkey,
+    pub l1_oracle: Pubkey,
+    pub withdrawal_addresses_hash: [u8; 32],
+}
+
+/// Errors of `L2StateClient` callers may want to handle, returned inside `anyhow::Error`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ClientError {
+    /// The fee payer can't cover the fee of a transaction.
+    InsufficientFeePayerBalance {
+        payer: Pubkey,
+        balance: u64,
+        fee: u64,
+    },
+    /// The withdrawal isn't in the withdrawals of the last finalized batch yet.
+    WithdrawalNotFinalized { index: u64, finalized_count: u64 },
+    /// The withdrawal was queued by another L2 sender than the fee payer.
+    WithdrawalSenderMismatch {
+        index: u64,
+        from: Pubkey,
+        payer: Pubkey,
+    },
+    /// The proof from the sequencer doesn't lead to its root.
+    InvalidWithdrawalProof { index: u64, reason: String },
+    /// The root the withdrawal is proven against isn't finalized on L1.
+    WithdrawalRootNotFinalized { root: [u8; 32] },
+    /// The sequencer returned the proof of another withdrawal than the one claimed.
+    WithdrawalIndexMismatch { index: u64, proven_index: u64 },
+    /// The sender's balance in the bridge vault is less than the withdrawal.
+    InsufficientBridgeBalance {
+        from: Pubkey,
+        amount: u64,
+        balance: u64,
+    },
+}
+
+impl std::fmt::Display for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientError::InsufficientFeePayerBalance {
+                payer,
+                balance,
+                fee,
+            } => write!(
+                f,
+                "Fee payer {} has {} lamports, not enough for the {} lamports fee",
+                payer, balance, fee
+            ),
+            ClientError::WithdrawalNotFinalized {
+                index,
+                finalized_count,
+            } => write!(
+                f,
+                "Withdrawal {} not finalized yet, the last finalized batch has {} withdrawals",
+                index, finalized_count
+            ),
+            ClientError::WithdrawalSenderMismatch { index, from, payer } => write!(
+                f,
+                "Withdrawal {} was sent by {}, it can't be claimed by fee payer {}",
+                index, from, payer
+            ),
+            ClientError::InvalidWithdrawalProof { index, reason } => {
+                write!(f, "Invalid proof of withdrawal {}: {}", index, reason)
+            }
+            ClientError::WithdrawalRootNotFinalized { root } => {
+                write!(f, "Withdrawal root {:?} not finalized on L1", root)
+            }
+            ClientError::WithdrawalIndexMismatch {
+                index,
+                proven_index,
+            } => write!(
+                f,
+                "Claiming withdrawal {}, the sequencer proved withdrawal {}",
+                index, proven_index
+            ),
+            ClientError::InsufficientBridgeBalance {
+                from,
+                amount,
+                balance,
+            } => write!(
+                f,
+                "{} has {} lamports in the bridge vault, not enough for the {} lamports withdrawal",
+                from, balance, amount
+            ),
+        }
+    }
 }
 
+impl std::error::Error for ClientError {}
+
 pub struct L2StateClient {
     client: RpcClient,
     fee_payer: Keypair,
     program_id: Pubkey,
+    auto_airdrop: bool,
 }
 
 impl L2StateClient {
-    /// Create a new L2StateClient instance
+    /// Create a new L2StateClient instance, reading at `confirmed` commitment
     pub fn new(rpc_url: String, fee_payer_bytes: &[u8]) -> Result<Self> {
-        let client = RpcClient::new_with_commitment(rpc_url, CommitmentConfig::confirmed());
+        Self::new_with_commitment(rpc_url, fee_payer_bytes, CommitmentConfig::confirmed())
+    }
+
+    /// Create a new L2StateClient instance with the given commitment, used for blockhashes,
+    /// simulated reads, sending transactions and scanning commit calldata.
+    ///
+    /// `finalized` suits the reads a prover acts on, `get_committed_batch`,
+    /// `get_last_finalized_batch_index` and `get_batch_calldata`, so it never proves
+    /// a commit that is rolled back. `processed` is the fastest for UIs.
+    pub fn new_with_commitment(
+        rpc_url: String,
+        fee_payer_bytes: &[u8],
+        commitment: CommitmentConfig,
+    ) -> Result<Self> {
+        let client = RpcClient::new_with_commitment(rpc_url, commitment);
         let fee_payer = Keypair::from_bytes(fee_payer_bytes)?;
         let program_id = PROGRAM_ID.parse::<Pubkey>()?;
 
@@ -56,11 +255,24 @@ impl L2StateClient {
             client,
             fee_payer,
             program_id,
+            auto_airdrop: false,
         })
     }
 
+    /// Airdrop to an unfunded fee payer before sending, only for a localnet or a
+    /// devnet faucet.
+    pub fn with_auto_airdrop(mut self, auto_airdrop: bool) -> Self {
+        self.auto_airdrop = auto_airdrop;
+        self
+    }
+
     /// Create a new L2StateClient with default local validator settings
     pub fn new_local() -> Result<Self> {
+        Self::new_local_with_commitment(CommitmentConfig::confirmed())
+    }
+
+    /// Create a new L2StateClient with default local validator settings and the given commitment
+    pub fn new_local_with_commitment(commitment: CommitmentConfig) -> Result<Self> {
         let default_keypair = [
             174, 47, 154, 16, 202, 193, 206, 113, 199, 190, 53, 133, 169, 175, 31, 56, 222, 53,
             138, 189, 224, 216, 117, 173, 10, 149, 53, 45, 73, 251, 237, 246, 15, 185, 186, 82,
@@ -68,12 +280,37 @@ impl L2StateClient {
             226, 218, 48, 63, 176, 109, 168, 89, 238, 135,
         ];
 
-        Self::new("http://localhost:8899".to_string(), &default_keypair)
+        Self::new_with_commitment(
+            "http://localhost:8899".to_string(),
+            &default_keypair,
+            commitment,
+        )
+    }
+
+    /// The fee payer, the sequencer when committing batches
+    pub fn payer(&self) -> Pubkey {
+        self.fee_payer.pubkey()
+    }
+
+    pub fn commitment(&self) -> CommitmentConfig {
+        self.client.commitment()
     }
 
-    /// Initialize the batch storage (should be called once)
-    pub fn initialize(&self) -> Result<()> {
-        let instruction_data: [u8; 8] = [175, 175, 109, 31, 13, 152, 155, 237];
+    /// Initialize the batch storage (should be called once, see `initialize_if_needed`).
+    /// Proofs credit only the deposits `l1_oracle` signs and withdraw only on the
+    /// addresses hashing to `withdrawal_addresses_hash`
+    /// (`share::transaction::withdrawal_addresses_hash`).
+    pub fn initialize(
+        &self,
+        genesis_state_root: [u8; 32],
+        l1_oracle: Pubkey,
+        withdrawal_addresses_hash: [u8; 32],
+    ) -> Result<()> {
+        let discriminator: [u8; 8] = [175, 175, 109, 31, 13, 152, 155, 237];
+        let mut instruction_data = discriminator.to_vec();
+        instruction_data.extend_from_slice(&genesis_state_root.try_to_vec()?);
+        instruction_data.extend_from_slice(&l1_oracle.try_to_vec()?);
+        instruction_data.extend_from_slice(&withdrawal_addresses_hash.try_to_vec()?);
 
         let instruction = Instruction {
             program_id: self.program_id,
@@ -106,10 +343,22 @@ impl L2StateClient {
                     Pubkey::find_program_address(&[b"finalized_withdrawals"], &self.program_id).0,
                     false,
                 ),
+                AccountMeta::new(
+                    Pubkey::find_program_address(&[b"genesis_state"], &self.program_id).0,
+                    false,
+                ),
+                AccountMeta::new(
+                    Pubkey::find_program_address(&[b"withdrawal_limit"], &self.program_id).0,
+                    false,
+                ),
+                AccountMeta::new(
+                    Pubkey::find_program_address(&[b"config"], &self.program_id).0,
+                    false,
+                ),
                 AccountMeta::new(self.fee_payer.pubkey(), true),
                 AccountMeta::new_readonly(system_program::ID, false),
             ],
-            data: instruction_data.to_vec(),
+            data: instruction_data,
         };
 
         let recent_blockhash = self.client.get_latest_blockhash()?;
@@ -120,82 +369,359 @@ impl L2StateClient {
             recent_blockhash,
         );
 
-        let signature = self.client.send_and_confirm_transaction(&transaction)?;
+        let signature = self.send_transaction(&transaction)?;
         log::info!("Initialize transaction signature: {}", signature);
 
         Ok(())
     }
 
-    /// Commit a batch to the Solana program
-    pub fn commit_batch(&self, batch_info: BatchInfo) -> Result<()> {
-        let discriminator: [u8; 8] = [27, 234, 100, 224, 134, 31, 168, 142];
+    /// Whether the program storage is initialized, probing the batch storage PDA
+    pub fn is_initialized(&self) -> Result<bool> {
+        let batch_storage = Pubkey::find_program_address(&[b"batch_storage"], &self.program_id).0;
+        let account = self
+            .client
+            .get_account_with_commitment(&batch_storage, self.client.commitment())?
+            .value;
+        Ok(account.is_some_and(|account| account.owner == self.program_id))
+    }
+
+    /// Initialize the program storage unless already done, returns whether it initialized.
+    /// Safe to call from deploy scripts that may run more than once.
+    pub fn initialize_if_needed(
+        &self,
+        genesis_state_root: [u8; 32],
+        l1_oracle: Pubkey,
+        withdrawal_addresses_hash: [u8; 32],
+    ) -> Result<bool> {
+        if self.is_initialized()? {
+            log::info!("Program storage already initialized, skipping initialize");
+            return Ok(false);
+        }
+        self.initialize(genesis_state_root, l1_oracle, withdrawal_addresses_hash)?;
+        Ok(true)
+    }
 
+    /// Commit a batch to the Solana program, the fee payer must be the sequencer
+    pub fn commit_batch(&self, batch_info: BatchInfo) -> Result<()> {
         let blocks_hash = hash_nested_vector(&batch_info.blocks);
         log::info!("blocks_hash calculated offchain: {:?}", blocks_hash);
 
-        // Serialize the BatchInfo
-        let mut instruction_data = discriminator.to_vec();
-        let serialized_batch_info = batch_info.try_to_vec()?;
-        instruction_data.extend_from_slice(&serialized_batch_info);
+        let instruction = self.commit_batch_instruction(&batch_info)?;
 
-        // Create the instruction
-        let instruction = Instruction {
+        let recent_blockhash = self.client.get_latest_blockhash()?;
+        let transaction = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&self.fee_payer.pubkey()),
+            &[&self.fee_payer],
+            recent_blockhash,
+        );
+
+        let signature = self.send_transaction(&transaction)?;
+        log::info!("Commit batch transaction signature: {}", signature);
+
+        Ok(())
+    }
+
+    /// Commit a `BatchInfo` loaded from a JSON file, to exercise the program with a
+    /// hand-made batch without running the sequencer.
+    #[cfg(feature = "dev-tools")]
+    pub fn commit_raw_batch(
+        &self,
+        batch_info_json_path: impl AsRef<std::path::Path>,
+    ) -> Result<()> {
+        let batch_info = read_batch_json(batch_info_json_path)?;
+        log::info!("Committing raw batch {}", batch_info.batch_index);
+        self.commit_batch(batch_info)
+    }
+
+    /// Write the calldata of committed batch `batch_index` to a JSON file that
+    /// `commit_raw_batch` accepts. Returns false if no commit of the batch was found.
+    #[cfg(feature = "dev-tools")]
+    pub fn dump_batch_to_json(
+        &self,
+        batch_index: u64,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<bool> {
+        let Some(batch_info) = self.get_batch_calldata(batch_index)? else {
+            return Ok(false);
+        };
+        write_batch_json(&batch_info, path)?;
+        Ok(true)
+    }
+
+    /// Commit only the hash of the batch blocks, which stay off chain until revealed
+    /// with `reveal_batch`. Returns the committed batch hash.
+    pub fn commit_batch_hash_only(&self, batch_info: &BatchInfo) -> Result<[u8; 32]> {
+        // Discriminator of `commit_batch_hash_only`
+        let mut instruction_data = vec![46, 179, 95, 128, 41, 229, 136, 39];
+        let batch_hash = hash_nested_vector(&batch_info.blocks);
+        let hash_info = BatchHashInfo {
+            batch_index: batch_info.batch_index,
+            batch_hash,
+            start_block_num: batch_info.start_block_num,
+            end_block_num: batch_info.end_block_num,
+            prev_state_root: batch_info.prev_state_root,
+            post_state_root: batch_info.post_state_root,
+            withdrawal_root: batch_info.withdrawal_root,
+            withdrawal_count: batch_info.withdrawal_count,
+            next_forced_index: batch_info.next_forced_index,
+        };
+        instruction_data.extend_from_slice(&hash_info.try_to_vec()?);
+
+        let signature = self.send_instruction(Instruction {
             program_id: self.program_id,
             accounts: vec![
                 AccountMeta::new(
                     Pubkey::find_program_address(&[b"batch_storage"], &self.program_id).0,
                     false,
                 ),
+                AccountMeta::new(self.batch_reveal_address(batch_info.batch_index), false),
+                AccountMeta::new_readonly(
+                    Pubkey::find_program_address(&[b"genesis_state"], &self.program_id).0,
+                    false,
+                ),
+                AccountMeta::new_readonly(
+                    Pubkey::find_program_address(&[b"config"], &self.program_id).0,
+                    false,
+                ),
                 AccountMeta::new(self.fee_payer.pubkey(), true),
+                AccountMeta::new_readonly(self.forced_transactions_address(), false),
                 AccountMeta::new_readonly(system_program::ID, false),
             ],
             data: instruction_data,
-        };
+        })?;
+        log::info!(
+            "Commit batch hash only transaction signature: {}",
+            signature
+        );
 
-        let recent_blockhash = self.client.get_latest_blockhash()?;
-        let transaction = Transaction::new_signed_with_payer(
-            &[instruction],
-            Some(&self.fee_payer.pubkey()),
-            &[&self.fee_payer],
-            recent_blockhash,
+        Ok(batch_hash)
+    }
+
+    /// Reveal the blocks of a hash-only batch, within the reveal window of its commit
+    pub fn reveal_batch(&self, batch_index: u64, blocks: Vec<Vec<u8>>) -> Result<()> {
+        // Discriminator of `reveal_batch`
+        let mut instruction_data = vec![42, 176, 196, 168, 123, 47, 195, 121];
+        instruction_data.extend_from_slice(
+            &RevealBatchArgs {
+                batch_index,
+                blocks,
+            }
+            .try_to_vec()?,
         );
 
-        let signature = self.client.send_and_confirm_transaction(&transaction)?;
-        log::info!("Commit batch transaction signature: {}", signature);
+        let signature = self.send_instruction(Instruction {
+            program_id: self.program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(
+                    Pubkey::find_program_address(&[b"batch_storage"], &self.program_id).0,
+                    false,
+                ),
+                AccountMeta::new(self.batch_reveal_address(batch_index), false),
+                AccountMeta::new_readonly(self.fee_payer.pubkey(), true),
+            ],
+            data: instruction_data,
+        })?;
+        log::info!("Reveal batch transaction signature: {}", signature);
 
         Ok(())
     }
 
-    /// Prove batch
-    pub fn prove_batch(&self, batch_proof: BatchProof) -> Result<()> {
+    /// Address of the reveal status of the hash-only batch `batch_index`
+    pub fn batch_reveal_address(&self, batch_index: u64) -> Pubkey {
+        Pubkey::find_program_address(
+            &[b"batch_reveal", &batch_index.to_le_bytes()],
+            &self.program_id,
+        )
+        .0
+    }
+
+    /// Address of the queue of transactions forced through L1
+    pub fn forced_transactions_address(&self) -> Pubkey {
+        Pubkey::find_program_address(&[b"forced_transactions"], &self.program_id).0
+    }
+
+    /// Size in bytes of the signed `commit_batch` transaction of `batch_info`, it must
+    /// fit in `PACKET_DATA_SIZE` to be sent.
+    pub fn commit_batch_transaction_size(&self, batch_info: &BatchInfo) -> Result<usize> {
+        let instruction = self.commit_batch_instruction(batch_info)?;
+        Ok(self.transaction_size(&instruction))
+    }
+
+    fn commit_batch_instruction(&self, batch_info: &BatchInfo) -> Result<Instruction> {
         let discriminator: [u8; 8] = [27, 234, 100, 224, 134, 31, 168, 142];
 
         // Serialize the BatchInfo
         let mut instruction_data = discriminator.to_vec();
-        instruction_data.extend_from_slice(&batch_proof.try_to_vec()?);
+        let serialized_batch_info = batch_info.try_to_vec()?;
+        instruction_data.extend_from_slice(&serialized_batch_info);
 
         // Create the instruction
-        let instruction = Instruction {
+        Ok(Instruction {
             program_id: self.program_id,
             accounts: vec![
                 AccountMeta::new(
                     Pubkey::find_program_address(&[b"batch_storage"], &self.program_id).0,
                     false,
                 ),
-                AccountMeta::new(
-                    Pubkey::find_program_address(
-                        &[b"last_finalized_batch_index"],
-                        &self.program_id,
-                    )
-                    .0,
+                AccountMeta::new_readonly(
+                    Pubkey::find_program_address(&[b"genesis_state"], &self.program_id).0,
+                    false,
+                ),
+                AccountMeta::new_readonly(
+                    Pubkey::find_program_address(&[b"config"], &self.program_id).0,
                     false,
                 ),
                 AccountMeta::new(self.fee_payer.pubkey(), true),
+                AccountMeta::new_readonly(self.forced_transactions_address(), false),
                 AccountMeta::new_readonly(system_program::ID, false),
             ],
             data: instruction_data,
-        };
+        })
+    }
+
+    /// Prove batch. A proof whose `prove_state` transaction exceeds `PACKET_DATA_SIZE`
+    /// is uploaded in `PROOF_CHUNK_SIZE` chunks to a proof buffer PDA first, then
+    /// proven by `prove_state_from_buffer`.
+    ///
+    /// A batch that is already finalized, e.g. on a retry after a timeout of a
+    /// transaction that landed, is reported and not submitted again.
+    pub fn prove_batch(&self, batch_proof: BatchProof) -> Result<()> {
+        let last_finalized_batch_index = self.get_last_finalized_batch_index()?;
+        if batch_proof.batch_index <= last_finalized_batch_index {
+            log::info!(
+                "Batch {} already finalized, last finalized batch is {}",
+                batch_proof.batch_index,
+                last_finalized_batch_index
+            );
+            return Ok(());
+        }
+
+        let instruction = self.prove_state_instruction(&batch_proof)?;
+        if self.transaction_size(&instruction) > PACKET_DATA_SIZE {
+            log::info!(
+                "Proof of batch {} is {} bytes, uploading it in chunks",
+                batch_proof.batch_index,
+                batch_proof.proof.len()
+            );
+            return self.prove_batch_chunked(batch_proof);
+        }
+
+        let signature = self.send_instruction(instruction)?;
+        log::info!("Prove batch transaction signature: {}", signature);
+
+        Ok(())
+    }
+
+    fn prove_batch_chunked(&self, batch_proof: BatchProof) -> Result<()> {
+        let batch_index = batch_proof.batch_index;
+        let proof_buffer = self.proof_buffer_address(batch_index);
+
+        if self.client.get_account(&proof_buffer).is_err() {
+            // Discriminator of `open_proof_buffer`
+            let mut instruction_data = vec![87, 164, 242, 233, 185, 97, 175, 148];
+            instruction_data.extend_from_slice(&batch_index.try_to_vec()?);
+            self.send_instruction(Instruction {
+                program_id: self.program_id,
+                accounts: vec![
+                    AccountMeta::new(self.fee_payer.pubkey(), true),
+                    AccountMeta::new(proof_buffer, false),
+                    AccountMeta::new_readonly(system_program::ID, false),
+                ],
+                data: instruction_data,
+            })?;
+        }
+
+        for (offset, chunk) in proof_chunks(&batch_proof.proof, PROOF_CHUNK_SIZE) {
+            // Discriminator of `append_proof_chunk`
+            let mut instruction_data = vec![101, 111, 213, 49, 138, 8, 68, 138];
+            instruction_data.extend_from_slice(&batch_index.try_to_vec()?);
+            instruction_data.extend_from_slice(&offset.try_to_vec()?);
+            instruction_data.extend_from_slice(&chunk.to_vec().try_to_vec()?);
+            self.send_instruction(Instruction {
+                program_id: self.program_id,
+                accounts: vec![
+                    AccountMeta::new(self.fee_payer.pubkey(), true),
+                    AccountMeta::new(proof_buffer, false),
+                    AccountMeta::new_readonly(system_program::ID, false),
+                ],
+                data: instruction_data,
+            })?;
+        }
+
+        // Discriminator of `prove_state_from_buffer`
+        let mut instruction_data = vec![71, 98, 160, 167, 218, 76, 203, 143];
+        instruction_data.extend_from_slice(&batch_index.try_to_vec()?);
+        let mut accounts = self.prove_state_accounts(batch_index);
+        accounts.insert(6, AccountMeta::new(proof_buffer, false));
+        let signature = self.send_instruction(Instruction {
+            program_id: self.program_id,
+            accounts,
+            data: instruction_data,
+        })?;
+        log::info!(
+            "Prove batch from buffer transaction signature: {}",
+            signature
+        );
+
+        Ok(())
+    }
+
+    fn prove_state_instruction(&self, batch_proof: &BatchProof) -> Result<Instruction> {
+        let discriminator: [u8; 8] = [152, 92, 155, 31, 126, 248, 119, 170];
+
+        // Serialize the BatchProof
+        let mut instruction_data = discriminator.to_vec();
+        instruction_data.extend_from_slice(&batch_proof.try_to_vec()?);
+
+        Ok(Instruction {
+            program_id: self.program_id,
+            accounts: self.prove_state_accounts(batch_proof.batch_index),
+            data: instruction_data,
+        })
+    }
+
+    // Accounts of `prove_state`, `prove_state_from_buffer` adds the proof buffer
+    // before the system program
+    fn prove_state_accounts(&self, batch_index: u64) -> Vec<AccountMeta> {
+        vec![
+            AccountMeta::new(self.fee_payer.pubkey(), true),
+            AccountMeta::new_readonly(
+                Pubkey::find_program_address(&[b"batch_storage"], &self.program_id).0,
+                false,
+            ),
+            AccountMeta::new(
+                Pubkey::find_program_address(&[b"last_finalized_batch_index"], &self.program_id).0,
+                false,
+            ),
+            AccountMeta::new(
+                Pubkey::find_program_address(&[b"finalized_withdrawal_roots"], &self.program_id).0,
+                false,
+            ),
+            AccountMeta::new_readonly(self.batch_reveal_address(batch_index), false),
+            AccountMeta::new(self.forced_transactions_address(), false),
+            AccountMeta::new_readonly(
+                Pubkey::find_program_address(&[b"config"], &self.program_id).0,
+                false,
+            ),
+            AccountMeta::new_readonly(system_program::ID, false),
+        ]
+    }
+
+    /// Address of the proof buffer the fee payer uploads the proof of `batch_index` to
+    pub fn proof_buffer_address(&self, batch_index: u64) -> Pubkey {
+        Pubkey::find_program_address(
+            &[
+                b"proof_buffer",
+                self.fee_payer.pubkey().as_ref(),
+                &batch_index.to_le_bytes(),
+            ],
+            &self.program_id,
+        )
+        .0
+    }
 
+    fn send_instruction(&self, instruction: Instruction) -> Result<Signature> {
         let recent_blockhash = self.client.get_latest_blockhash()?;
         let transaction = Transaction::new_signed_with_payer(
             &[instruction],
@@ -203,30 +729,64 @@ impl L2StateClient {
             &[&self.fee_payer],
             recent_blockhash,
         );
+        self.send_transaction(&transaction)
+    }
 
-        let signature = self.client.send_and_confirm_transaction(&transaction)?;
-        log::info!("Prove batch transaction signature: {}", signature);
+    /// Send and confirm a transaction of the fee payer, checking first that it can pay
+    /// the fee so an unfunded payer fails with `ClientError::InsufficientFeePayerBalance`
+    /// rather than a failed simulation. With `auto_airdrop` the payer is funded instead.
+    fn send_transaction(&self, transaction: &Transaction) -> Result<Signature> {
+        let fee = self.client.get_fee_for_message(transaction.message())?;
+        let balance = self.get_sender_balance()?;
+        if let Err(e) = check_fee_payer_balance(self.fee_payer.pubkey(), balance, fee) {
+            if !self.auto_airdrop {
+                return Err(e.into());
+            }
+            log::info!("{}, requesting an airdrop", e);
+            self.request_airdrop(AUTO_AIRDROP_LAMPORTS)?;
+        }
+        Ok(self.client.send_and_confirm_transaction(transaction)?)
+    }
 
-        Ok(())
+    // Size in bytes of the signed transaction of `instruction` alone
+    fn transaction_size(&self, instruction: &Instruction) -> usize {
+        let message = Message::new(
+            std::slice::from_ref(instruction),
+            Some(&self.fee_payer.pubkey()),
+        );
+        // Compact signature count + signatures + message
+        1 + 64 * message.header.num_required_signatures as usize + message.serialize().len()
     }
 
-    /// deposit to the Solana program
-    pub fn deposit(&self, amount: u64) -> Result<()> {
-        let discriminator: [u8; 8] = [242, 35, 198, 137, 82, 225, 242, 182];
+    /// Transfer the authority of all program PDAs, the fee payer must be the current authority
+    pub fn transfer_authority(&self, new_authority: Pubkey) -> Result<()> {
+        let discriminator: [u8; 8] = [48, 169, 76, 72, 229, 180, 55, 161];
         let mut instruction_data = discriminator.to_vec();
-        instruction_data.extend_from_slice(&amount.try_to_vec()?);
+        instruction_data.extend_from_slice(&new_authority.try_to_vec()?);
+
+        let mut accounts: Vec<AccountMeta> = [
+            &b"batch_storage"[..],
+            b"last_finalized_batch_index",
+            b"bridge_vault",
+            b"finalized_withdrawal_roots",
+            b"finalized_withdrawals",
+            b"genesis_state",
+            b"withdrawal_limit",
+            b"config",
+        ]
+        .iter()
+        .map(|seed| {
+            AccountMeta::new(
+                Pubkey::find_program_address(&[seed], &self.program_id).0,
+                false,
+            )
+        })
+        .collect();
+        accounts.push(AccountMeta::new_readonly(self.fee_payer.pubkey(), true));
 
-        // Create the instruction
         let instruction = Instruction {
             program_id: self.program_id,
-            accounts: vec![
-                AccountMeta::new(self.fee_payer.pubkey(), true),
-                AccountMeta::new(
-                    Pubkey::find_program_address(&[b"bridge_vault"], &self.program_id).0,
-                    false,
-                ),
-                AccountMeta::new_readonly(system_program::ID, false),
-            ],
+            accounts,
             data: instruction_data,
         };
 
@@ -238,23 +798,27 @@ impl L2StateClient {
             recent_blockhash,
         );
 
-        let signature = self.client.send_and_confirm_transaction(&transaction)?;
-        log::info!("Commit batch transaction signature: {}", signature);
+        let signature = self.send_transaction(&transaction)?;
+        log::info!("Transfer authority transaction signature: {}", signature);
 
         Ok(())
     }
 
-    /// Get the last finalized batch index
-    pub fn get_last_finalized_batch_index(&self) -> Result<u64> {
-        let discriminator: [u8; 8] = [224, 183, 118, 226, 186, 198, 245, 187];
-        let instruction_data = discriminator.to_vec();
+    /// Set the sequencer allowed to commit batches, the fee payer must be the authority
+    pub fn set_sequencer(&self, new_sequencer: Pubkey) -> Result<()> {
+        let discriminator: [u8; 8] = [69, 210, 93, 9, 44, 230, 78, 22];
+        let mut instruction_data = discriminator.to_vec();
+        instruction_data.extend_from_slice(&new_sequencer.try_to_vec()?);
 
         let instruction = Instruction {
             program_id: self.program_id,
-            accounts: vec![AccountMeta::new_readonly(
-                Pubkey::find_program_address(&[b"last_finalized_batch_index"], &self.program_id).0,
-                false,
-            )],
+            accounts: vec![
+                AccountMeta::new(
+                    Pubkey::find_program_address(&[b"config"], &self.program_id).0,
+                    false,
+                ),
+                AccountMeta::new_readonly(self.fee_payer.pubkey(), true),
+            ],
             data: instruction_data,
         };
 
@@ -266,50 +830,28 @@ impl L2StateClient {
             recent_blockhash,
         );
 
-        let result = self.client.simulate_transaction(&transaction)?;
-
-        if let Some(err) = result.value.err {
-            return Err(anyhow::anyhow!("Transaction simulation failed: {:?}", err));
-        }
+        let signature = self.send_transaction(&transaction)?;
+        log::info!("Set sequencer transaction signature: {}", signature);
 
-        if let Some(return_data) = &result.value.return_data {
-            let (data, _encoding) = &return_data.data;
-            if let Ok(decoded_data) = general_purpose::STANDARD.decode(data) {
-                match u64::try_from_slice(&decoded_data) {
-                    Ok(batch_index) => {
-                        log::info!(
-                            "Successfully retrieved last finalized batch index: {}",
-                            batch_index
-                        );
-                        return Ok(batch_index);
-                    }
-                    Err(e) => {
-                        return Err(anyhow::anyhow!(
-                            "Failed to deserialize last finalized batch index: {:?}",
-                            e
-                        ));
-                    }
-                }
-            } else {
-                return Err(anyhow::anyhow!("Failed to decode return data from base64"));
-            }
-        } else {
-            return Err(anyhow::anyhow!("No return data in simulation result"));
-        }
+        Ok(())
     }
 
-    /// Get committed batch data by index
-    pub fn get_committed_batch(&self, batch_index: u64) -> Result<Option<BatchData>> {
-        let discriminator: [u8; 8] = [246, 70, 81, 64, 254, 87, 48, 173];
+    /// Set the hash of the withdrawal addresses proofs must withdraw on
+    /// (`share::transaction::withdrawal_addresses_hash`), the fee payer must be the authority
+    pub fn set_withdrawal_addresses_hash(&self, withdrawal_addresses_hash: [u8; 32]) -> Result<()> {
+        let discriminator: [u8; 8] = [69, 252, 10, 143, 253, 197, 66, 56];
         let mut instruction_data = discriminator.to_vec();
-        instruction_data.extend_from_slice(&batch_index.try_to_vec().unwrap());
+        instruction_data.extend_from_slice(&withdrawal_addresses_hash.try_to_vec()?);
 
         let instruction = Instruction {
             program_id: self.program_id,
-            accounts: vec![AccountMeta::new(
-                Pubkey::find_program_address(&[b"batch_storage"], &self.program_id).0,
-                false,
-            )],
+            accounts: vec![
+                AccountMeta::new(
+                    Pubkey::find_program_address(&[b"config"], &self.program_id).0,
+                    false,
+                ),
+                AccountMeta::new_readonly(self.fee_payer.pubkey(), true),
+            ],
             data: instruction_data,
         };
 
@@ -321,7 +863,387 @@ impl L2StateClient {
             recent_blockhash,
         );
 
-        let result = self.client.simulate_transaction(&transaction)?;
+        let signature = self.send_transaction(&transaction)?;
+        log::info!(
+            "Set withdrawal addresses hash transaction signature: {}",
+            signature
+        );
+
+        Ok(())
+    }
+
+    /// Cap the lamports withdrawn from the bridge per `window_secs`, the fee payer must be the authority
+    pub fn set_withdrawal_limit(&self, max_amount: u64, window_secs: i64) -> Result<()> {
+        let discriminator: [u8; 8] = [97, 243, 160, 126, 155, 129, 70, 184];
+        let mut instruction_data = discriminator.to_vec();
+        instruction_data.extend_from_slice(&max_amount.try_to_vec()?);
+        instruction_data.extend_from_slice(&window_secs.try_to_vec()?);
+
+        let instruction = Instruction {
+            program_id: self.program_id,
+            accounts: vec![
+                AccountMeta::new(
+                    Pubkey::find_program_address(&[b"withdrawal_limit"], &self.program_id).0,
+                    false,
+                ),
+                AccountMeta::new_readonly(self.fee_payer.pubkey(), true),
+            ],
+            data: instruction_data,
+        };
+
+        let recent_blockhash = self.client.get_latest_blockhash()?;
+        let transaction = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&self.fee_payer.pubkey()),
+            &[&self.fee_payer],
+            recent_blockhash,
+        );
+
+        let signature = self.send_transaction(&transaction)?;
+        log::info!("Set withdrawal limit transaction signature: {}", signature);
+
+        Ok(())
+    }
+
+    /// Roll the last finalized batch index back to `index`, the fee payer must be the authority
+    pub fn set_finalized_index(&self, index: u64) -> Result<()> {
+        let discriminator: [u8; 8] = [157, 117, 0, 3, 99, 121, 214, 236];
+        let mut instruction_data = discriminator.to_vec();
+        instruction_data.extend_from_slice(&index.try_to_vec()?);
+
+        let instruction = Instruction {
+            program_id: self.program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(
+                    Pubkey::find_program_address(&[b"batch_storage"], &self.program_id).0,
+                    false,
+                ),
+                AccountMeta::new(
+                    Pubkey::find_program_address(
+                        &[b"last_finalized_batch_index"],
+                        &self.program_id,
+                    )
+                    .0,
+                    false,
+                ),
+                AccountMeta::new(
+                    Pubkey::find_program_address(
+                        &[b"finalized_withdrawal_roots"],
+                        &self.program_id,
+                    )
+                    .0,
+                    false,
+                ),
+                AccountMeta::new_readonly(self.fee_payer.pubkey(), true),
+            ],
+            data: instruction_data,
+        };
+
+        let recent_blockhash = self.client.get_latest_blockhash()?;
+        let transaction = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&self.fee_payer.pubkey()),
+            &[&self.fee_payer],
+            recent_blockhash,
+        );
+
+        let signature = self.send_transaction(&transaction)?;
+        log::info!("Set finalized index transaction signature: {}", signature);
+
+        Ok(())
+    }
+
+    /// deposit to the Solana program
+    pub fn deposit(&self, amount: u64) -> Result<()> {
+        let discriminator: [u8; 8] = [242, 35, 198, 137, 82, 225, 242, 182];
+        let mut instruction_data = discriminator.to_vec();
+        instruction_data.extend_from_slice(&amount.try_to_vec()?);
+
+        // Create the instruction
+        let instruction = Instruction {
+            program_id: self.program_id,
+            accounts: vec![
+                AccountMeta::new(self.fee_payer.pubkey(), true),
+                AccountMeta::new(
+                    Pubkey::find_program_address(&[b"bridge_vault"], &self.program_id).0,
+                    false,
+                ),
+                AccountMeta::new_readonly(system_program::ID, false),
+            ],
+            data: instruction_data,
+        };
+
+        let recent_blockhash = self.client.get_latest_blockhash()?;
+        let transaction = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&self.fee_payer.pubkey()),
+            &[&self.fee_payer],
+            recent_blockhash,
+        );
+
+        let signature = self.send_transaction(&transaction)?;
+        log::info!("Commit batch transaction signature: {}", signature);
+
+        Ok(())
+    }
+
+    /// Withdraw `withdrawal.amount` from the bridge to `to`, the fee payer being the L2
+    /// sender. The proof is verified locally first, a bad one would only fail on chain.
+    pub fn withdrawal(&self, withdrawal: WithdrawalData, to: Pubkey) -> Result<()> {
+        Self::verify_withdrawal_locally(&withdrawal, &self.fee_payer.pubkey(), &to, None)?;
+        self.send_withdrawal(&withdrawal, to)?;
+        Ok(())
+    }
+
+    /// Claim withdrawal `index` of the fee payer, its L2 sender, in one call: fetch its
+    /// proof from the sequencer at `l2_rpc` against the last finalized batch, verify it
+    /// locally, check its root is finalized on L1 and the vault can pay it, then send
+    /// the `withdrawal` transaction. A failed check returns its `ClientError`.
+    pub fn claim_withdrawal(&self, l2_rpc: &str, index: u64) -> Result<Signature> {
+        let last_finalized_index = self.get_last_finalized_batch_index()?;
+        let finalized_count = self
+            .get_committed_batch(last_finalized_index)?
+            .map(|batch| batch.withdrawal_count)
+            .unwrap_or_default();
+        if index >= finalized_count {
+            return Err(ClientError::WithdrawalNotFinalized {
+                index,
+                finalized_count,
+            }
+            .into());
+        }
+
+        let proof = RpcClient::new(l2_rpc.to_owned()).send::<Value>(
+            RpcRequest::Custom {
+                method: "getWithdrawalProof",
+            },
+            json!([index, finalized_count]),
+        )?;
+        let claim = parse_withdrawal_claim(&proof)?;
+        if claim.withdrawal.index != index {
+            return Err(ClientError::WithdrawalIndexMismatch {
+                index,
+                proven_index: claim.withdrawal.index,
+            }
+            .into());
+        }
+        let payer = self.fee_payer.pubkey();
+        if claim.from != payer {
+            return Err(ClientError::WithdrawalSenderMismatch {
+                index,
+                from: claim.from,
+                payer,
+            }
+            .into());
+        }
+        Self::verify_withdrawal_locally(
+            &claim.withdrawal,
+            &claim.from,
+            &claim.to,
+            Some(claim.leaf_hash),
+        )
+        .map_err(|e| ClientError::InvalidWithdrawalProof {
+            index,
+            reason: e.to_string(),
+        })?;
+
+        let root = claim.withdrawal.withdraw_root;
+        if !self.is_withdrawal_root_finalized(root)? {
+            return Err(ClientError::WithdrawalRootNotFinalized { root }.into());
+        }
+        // The program takes the withdrawal from the sender's balance in the vault
+        let balance = self.get_bridge_balance(&claim.from)?;
+        check_bridge_balance(&claim.from, claim.withdrawal.amount, balance)?;
+
+        let signature = self.send_withdrawal(&claim.withdrawal, claim.to)?;
+        log::info!(
+            "Claimed withdrawal {} of {} lamports",
+            index,
+            claim.withdrawal.amount
+        );
+        Ok(signature)
+    }
+
+    // Send the `withdrawal` instruction of a verified withdrawal
+    fn send_withdrawal(&self, withdrawal: &WithdrawalData, to: Pubkey) -> Result<Signature> {
+        let discriminator: [u8; 8] = [198, 86, 72, 244, 40, 3, 17, 71];
+        let mut instruction_data = discriminator.to_vec();
+        instruction_data.extend_from_slice(&withdrawal.try_to_vec()?);
+
+        let instruction = Instruction {
+            program_id: self.program_id,
+            accounts: vec![
+                AccountMeta::new(self.fee_payer.pubkey(), true),
+                AccountMeta::new(to, false),
+                AccountMeta::new(
+                    Pubkey::find_program_address(&[b"bridge_vault"], &self.program_id).0,
+                    false,
+                ),
+                AccountMeta::new_readonly(
+                    Pubkey::find_program_address(
+                        &[b"finalized_withdrawal_roots"],
+                        &self.program_id,
+                    )
+                    .0,
+                    false,
+                ),
+                AccountMeta::new_readonly(
+                    Pubkey::find_program_address(&[b"finalized_withdrawals"], &self.program_id).0,
+                    false,
+                ),
+                AccountMeta::new(
+                    Pubkey::find_program_address(
+                        &[b"claimed_withdrawal", &withdrawal.index.to_le_bytes()],
+                        &self.program_id,
+                    )
+                    .0,
+                    false,
+                ),
+                AccountMeta::new(
+                    Pubkey::find_program_address(&[b"withdrawal_limit"], &self.program_id).0,
+                    false,
+                ),
+                AccountMeta::new_readonly(system_program::ID, false),
+            ],
+            data: instruction_data,
+        };
+
+        let signature = self.send_instruction(instruction)?;
+        log::info!("Withdrawal transaction signature: {}", signature);
+
+        Ok(signature)
+    }
+
+    /// Check a withdrawal the way the program does before sending it: rebuild the leaf
+    /// `from || to || amount || index` and fold the proof into `withdraw_root`.
+    ///
+    /// `expected_leaf_hash` is the `leafHash` of the sequencer's `getWithdrawalProof`,
+    /// when given a leaf that differs is reported apart from a proof that doesn't
+    /// lead to the root.
+    pub fn verify_withdrawal_locally(
+        withdrawal: &WithdrawalData,
+        from: &Pubkey,
+        to: &Pubkey,
+        expected_leaf_hash: Option<[u8; 32]>,
+    ) -> Result<()> {
+        let leaf_hash = withdrawal_leaf_hash(from, to, withdrawal.amount, withdrawal.index);
+        if let Some(expected_leaf_hash) = expected_leaf_hash {
+            if leaf_hash != expected_leaf_hash {
+                return Err(anyhow::anyhow!(
+                    "Leaf mismatch: withdrawal {} from {} to {} of {} lamports hashes to {:?}, the tree has {:?}",
+                    withdrawal.index,
+                    from,
+                    to,
+                    withdrawal.amount,
+                    leaf_hash,
+                    expected_leaf_hash
+                ));
+            }
+        }
+
+        let root =
+            merkle_root_from_proof(leaf_hash, &withdrawal.withdrawal_proof, withdrawal.index);
+        if root != withdrawal.withdraw_root {
+            return Err(anyhow::anyhow!(
+                "Proof mismatch: the {} siblings of withdrawal {} lead to root {:?}, expected {:?}",
+                withdrawal.withdrawal_proof.len(),
+                withdrawal.index,
+                root,
+                withdrawal.withdraw_root
+            ));
+        }
+        Ok(())
+    }
+
+    /// Prune the finalized withdrawal roots on L1, anyone can send it and the fee payer
+    /// gets the rent freed.
+    pub fn prune_finalized(&self) -> Result<()> {
+        let discriminator: [u8; 8] = [4, 208, 58, 11, 150, 67, 189, 69];
+
+        let instruction = Instruction {
+            program_id: self.program_id,
+            accounts: vec![
+                AccountMeta::new(self.fee_payer.pubkey(), true),
+                AccountMeta::new(
+                    Pubkey::find_program_address(
+                        &[b"finalized_withdrawal_roots"],
+                        &self.program_id,
+                    )
+                    .0,
+                    false,
+                ),
+            ],
+            data: discriminator.to_vec(),
+        };
+
+        let signature = self.send_instruction(instruction)?;
+        log::info!("Prune finalized transaction signature: {}", signature);
+
+        Ok(())
+    }
+
+    /// Force the inclusion of an L2 transaction the sequencer censors, the fee payer
+    /// submits it on L1, paying the sequencer the forced transaction fee, and the
+    /// sequencer must include it before the deadline.
+    pub fn force_include(&self, l2_transaction: &Transaction) -> Result<()> {
+        let discriminator: [u8; 8] = [200, 83, 54, 91, 126, 185, 124, 11];
+        let mut instruction_data = discriminator.to_vec();
+        let serialized_transaction = bincode::serialize(l2_transaction)?;
+        instruction_data.extend_from_slice(&serialized_transaction.try_to_vec()?);
+
+        // Create the instruction
+        let instruction = Instruction {
+            program_id: self.program_id,
+            accounts: vec![
+                AccountMeta::new(self.fee_payer.pubkey(), true),
+                AccountMeta::new_readonly(
+                    Pubkey::find_program_address(&[b"config"], &self.program_id).0,
+                    false,
+                ),
+                AccountMeta::new(self.get_config()?.sequencer, false),
+                AccountMeta::new(self.forced_transactions_address(), false),
+                AccountMeta::new_readonly(system_program::ID, false),
+            ],
+            data: instruction_data,
+        };
+
+        let recent_blockhash = self.client.get_latest_blockhash()?;
+        let transaction = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&self.fee_payer.pubkey()),
+            &[&self.fee_payer],
+            recent_blockhash,
+        );
+
+        let signature = self.send_transaction(&transaction)?;
+        log::info!("Force include transaction signature: {}", signature);
+
+        Ok(())
+    }
+
+    /// Get the last finalized batch index
+    pub fn get_last_finalized_batch_index(&self) -> Result<u64> {
+        let discriminator: [u8; 8] = [224, 183, 118, 226, 186, 198, 245, 187];
+        let instruction_data = discriminator.to_vec();
+
+        let instruction = Instruction {
+            program_id: self.program_id,
+            accounts: vec![AccountMeta::new_readonly(
+                Pubkey::find_program_address(&[b"last_finalized_batch_index"], &self.program_id).0,
+                false,
+            )],
+            data: instruction_data,
+        };
+
+        let recent_blockhash = self.client.get_latest_blockhash()?;
+        let transaction = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&self.fee_payer.pubkey()),
+            &[&self.fee_payer],
+            recent_blockhash,
+        );
+
+        let result = self.client.simulate_transaction(&transaction)?;
 
         if let Some(err) = result.value.err {
             return Err(anyhow::anyhow!("Transaction simulation failed: {:?}", err));
@@ -330,36 +1252,124 @@ impl L2StateClient {
         if let Some(return_data) = &result.value.return_data {
             let (data, _encoding) = &return_data.data;
             if let Ok(decoded_data) = general_purpose::STANDARD.decode(data) {
-                match Option::<BatchData>::try_from_slice(&decoded_data) {
-                    Ok(batch_data_option) => {
-                        if let Some(batch_data) = &batch_data_option {
-                            log::info!(
-                                "Successfully retrieved batch data for index {}: {:?}",
-                                batch_index,
-                                batch_data
-                            );
-                        } else {
-                            log::info!("No batch found for index {}", batch_index);
-                        }
-                        return Ok(batch_data_option);
+                match u64::try_from_slice(&decoded_data) {
+                    Ok(batch_index) => {
+                        log::info!(
+                            "Successfully retrieved last finalized batch index: {}",
+                            batch_index
+                        );
+                        return Ok(batch_index);
                     }
                     Err(e) => {
-                        return Err(anyhow::anyhow!("Failed to deserialize batch data: {:?}", e));
+                        return Err(anyhow::anyhow!(
+                            "Failed to deserialize last finalized batch index: {:?}",
+                            e
+                        ));
                     }
                 }
             } else {
                 return Err(anyhow::anyhow!("Failed to decode return data from base64"));
             }
-        } else {
-            return Err(anyhow::anyhow!("No return data in simulation result"));
+        } else {
+            return Err(anyhow::anyhow!("No return data in simulation result"));
+        }
+    }
+
+    /// Get committed batch data by index
+    pub fn get_committed_batch(&self, batch_index: u64) -> Result<Option<BatchData>> {
+        let discriminator: [u8; 8] = [246, 70, 81, 64, 254, 87, 48, 173];
+        let mut instruction_data = discriminator.to_vec();
+        instruction_data.extend_from_slice(&batch_index.try_to_vec().unwrap());
+
+        let batch_data_option = self.query_batch(instruction_data)?;
+        if let Some(batch_data) = &batch_data_option {
+            log::info!(
+                "Successfully retrieved batch data for index {}: {:?}",
+                batch_index,
+                batch_data
+            );
+        } else {
+            log::info!("No batch found for index {}", batch_index);
+        }
+        Ok(batch_data_option)
+    }
+
+    /// Get latest batch data
+    /// Public values the proof of the committed `batch` must commit, see
+    /// `batch_public_inputs`. Reads the previous batch for its withdrawal root and the
+    /// program config.
+    pub fn committed_public_inputs(&self, batch: &BatchData) -> Result<Vec<u8>> {
+        let prev_withdrawal_root = match batch.batch_index.checked_sub(1).filter(|i| *i > 0) {
+            Some(prev_index) => {
+                self.get_committed_batch(prev_index)?
+                    .ok_or_else(|| anyhow::anyhow!("Batch {} not found", prev_index))?
+                    .withdrawal_root
+            }
+            None => [0u8; 32],
+        };
+        Ok(batch_public_inputs(
+            batch,
+            prev_withdrawal_root,
+            &self.get_config()?,
+        ))
+    }
+
+    /// Bytes of the forced transactions the committed `batch` executes, its proof must
+    /// commit their hash. Reads the previous batch for the first of them.
+    pub fn get_batch_forced_transactions(&self, batch: &BatchData) -> Result<Vec<Vec<u8>>> {
+        let start = match batch.batch_index.checked_sub(1).filter(|i| *i > 0) {
+            Some(prev_index) => {
+                self.get_committed_batch(prev_index)?
+                    .ok_or_else(|| anyhow::anyhow!("Batch {} not found", prev_index))?
+                    .next_forced_index
+            }
+            None => 0,
+        };
+        let forced_txns = self.get_forced_transactions(start, batch.next_forced_index)?;
+        if forced_transactions_hash(&forced_txns) != batch.forced_transactions_hash {
+            return Err(anyhow::anyhow!(
+                "Forced transactions of batch {} don't match its hash",
+                batch.batch_index
+            ));
         }
+        Ok(forced_txns)
     }
 
-    /// Get latest batch data
     pub fn get_latest_batch(&self) -> Result<Option<BatchData>> {
         let discriminator: [u8; 8] = [161, 68, 127, 180, 29, 0, 183, 142];
         let instruction_data = discriminator.to_vec();
 
+        let batch_data_option = self.query_batch(instruction_data)?;
+        if let Some(batch_data) = &batch_data_option {
+            log::info!("Successfully retrieved latest batch data {:?}", batch_data);
+        } else {
+            log::info!("No latest batch found");
+        }
+        Ok(batch_data_option)
+    }
+
+    /// Get the committed batch whose block range contains `block_num`, `None` while
+    /// the block is not batched yet.
+    pub fn get_batch_for_block(&self, block_num: u64) -> Result<Option<BatchData>> {
+        let discriminator: [u8; 8] = [120, 253, 2, 187, 0, 51, 136, 173];
+        let mut instruction_data = discriminator.to_vec();
+        instruction_data.extend_from_slice(&block_num.try_to_vec().unwrap());
+
+        let batch_data_option = self.query_batch(instruction_data)?;
+        if let Some(batch_data) = &batch_data_option {
+            log::info!(
+                "Successfully retrieved batch data for block {}: {:?}",
+                block_num,
+                batch_data
+            );
+        } else {
+            log::info!("No batch found for block {}", block_num);
+        }
+        Ok(batch_data_option)
+    }
+
+    // Simulate a batch storage query and decode the returned batch
+    fn query_batch(&self, instruction_data: Vec<u8>) -> Result<Option<BatchData>> {
         let instruction = Instruction {
             program_id: self.program_id,
             accounts: vec![AccountMeta::new(
@@ -383,41 +1393,361 @@ impl L2StateClient {
             return Err(anyhow::anyhow!("Transaction simulation failed: {:?}", err));
         }
 
-        if let Some(return_data) = &result.value.return_data {
-            let (data, _encoding) = &return_data.data;
-            if let Ok(decoded_data) = general_purpose::STANDARD.decode(data) {
-                match Option::<BatchData>::try_from_slice(&decoded_data) {
-                    Ok(batch_data_option) => {
-                        if let Some(batch_data) = &batch_data_option {
-                            log::info!("Successfully retrieved latest batch data {:?}", batch_data);
-                        } else {
-                            log::info!("No latest batch found");
+        let Some(return_data) = &result.value.return_data else {
+            return Err(anyhow::anyhow!("No return data in simulation result"));
+        };
+        let (data, _encoding) = &return_data.data;
+        let Ok(decoded_data) = general_purpose::STANDARD.decode(data) else {
+            return Err(anyhow::anyhow!("Failed to decode return data from base64"));
+        };
+        Option::<BatchData>::try_from_slice(&decoded_data)
+            .map_err(|e| anyhow::anyhow!("Failed to deserialize batch data: {:?}", e))
+    }
+
+    /// Get the calldata of a committed batch by decoding its commit transaction,
+    /// so a prover can rebuild the batch blocks from L1 data alone.
+    ///
+    /// Only the most recent 1000 transactions touching batch storage are scanned,
+    /// the newest commit of `batch_index` wins.
+    pub fn get_batch_calldata(&self, batch_index: u64) -> Result<Option<BatchInfo>> {
+        let discriminator: [u8; 8] = [27, 234, 100, 224, 134, 31, 168, 142];
+        let batch_storage = Pubkey::find_program_address(&[b"batch_storage"], &self.program_id).0;
+
+        let batch_info = self.find_instruction_data(
+            &batch_storage,
+            &discriminator,
+            |batch_info: &BatchInfo| batch_info.batch_index == batch_index,
+        )?;
+        if batch_info.is_none() {
+            log::info!("No commit calldata found for batch {}", batch_index);
+        }
+        Ok(batch_info)
+    }
+
+    /// Get the blocks of a hash-only batch from its `reveal_batch` transaction, `None`
+    /// while they weren't revealed.
+    pub fn get_revealed_blocks(&self, batch_index: u64) -> Result<Option<Vec<Vec<u8>>>> {
+        let discriminator: [u8; 8] = [42, 176, 196, 168, 123, 47, 195, 121];
+        let reveal = self.find_instruction_data(
+            &self.batch_reveal_address(batch_index),
+            &discriminator,
+            |reveal: &RevealBatchArgs| reveal.batch_index == batch_index,
+        )?;
+        Ok(reveal.map(|reveal| reveal.blocks))
+    }
+
+    /// The forced transaction queue, empty before the first forced transaction.
+    pub fn get_forced_transaction_queue(&self) -> Result<ForcedTransactionQueue> {
+        let Some(account) = self
+            .client
+            .get_account_with_commitment(
+                &self.forced_transactions_address(),
+                self.client.commitment(),
+            )?
+            .value
+        else {
+            return Ok(ForcedTransactionQueue::default());
+        };
+        // The account is sized for a full queue, the bytes after it are left over
+        let mut data = account
+            .data
+            .get(8..)
+            .ok_or_else(|| anyhow::anyhow!("Forced transaction queue account too short"))?;
+        Ok(ForcedTransactionQueue::deserialize(&mut data)?)
+    }
+
+    /// The bytes of the forced transactions `start..end`, in queue order, read from the
+    /// `ForcedTransactionEvent`s the program logged for them.
+    pub fn get_forced_transactions(&self, start: u64, end: u64) -> Result<Vec<Vec<u8>>> {
+        let mut forced: Vec<Option<Vec<u8>>> = vec![None; end.saturating_sub(start) as usize];
+        if forced.is_empty() {
+            return Ok(vec![]);
+        }
+        // The events are found newest first, the indices only go up
+        self.scan_signatures(
+            &self.forced_transactions_address(),
+            |statuses, commitment| {
+                for status in statuses.iter().filter(|status| status.err.is_none()) {
+                    for event in self.forced_transaction_events(&status.signature, commitment)? {
+                        if event.index < start {
+                            return Ok(true);
+                        }
+                        if let Some(transaction) = forced.get_mut((event.index - start) as usize) {
+                            transaction.get_or_insert(event.transaction);
                         }
-                        return Ok(batch_data_option);
                     }
-                    Err(e) => {
-                        return Err(anyhow::anyhow!("Failed to deserialize batch data: {:?}", e));
+                }
+                Ok(forced.iter().all(Option::is_some))
+            },
+        )?;
+        (start..end)
+            .zip(forced)
+            .map(|(index, transaction)| {
+                transaction
+                    .ok_or_else(|| anyhow::anyhow!("Forced transaction {} not found on L1", index))
+            })
+            .collect()
+    }
+
+    // The `ForcedTransactionEvent`s logged by transaction `signature`
+    fn forced_transaction_events(
+        &self,
+        signature: &str,
+        commitment: CommitmentConfig,
+    ) -> Result<Vec<ForcedTransactionEvent>> {
+        let confirmed = self.client.get_transaction_with_config(
+            &signature.parse::<Signature>()?,
+            RpcTransactionConfig {
+                encoding: Some(UiTransactionEncoding::Base64),
+                commitment: Some(commitment),
+                max_supported_transaction_version: Some(0),
+            },
+        )?;
+        let logs: Option<Vec<String>> = confirmed
+            .transaction
+            .meta
+            .map(|meta| meta.log_messages.into())
+            .unwrap_or_default();
+        Ok(program_events(&logs.unwrap_or_default(), &self.program_id))
+    }
+
+    // Decode the args of the newest successful instruction of this program with
+    // `discriminator` among the transactions touching `address` that `matches`.
+    fn find_instruction_data<T: AnchorDeserialize>(
+        &self,
+        address: &Pubkey,
+        discriminator: &[u8; 8],
+        matches: impl Fn(&T) -> bool,
+    ) -> Result<Option<T>> {
+        let mut found = None;
+        self.scan_signatures(address, |statuses, commitment| {
+            found = self.find_in_statuses(statuses, discriminator, &matches, commitment)?;
+            Ok(found.is_some())
+        })?;
+        Ok(found)
+    }
+
+    // Page through the signatures of the transactions touching `address`, newest first,
+    // until `visit` returns true or there are no more
+    fn scan_signatures(
+        &self,
+        address: &Pubkey,
+        mut visit: impl FnMut(
+            &[RpcConfirmedTransactionStatusWithSignature],
+            CommitmentConfig,
+        ) -> Result<bool>,
+    ) -> Result<()> {
+        // Signature scans don't support `processed`
+        let commitment = if self.client.commitment().is_at_least_confirmed() {
+            self.client.commitment()
+        } else {
+            CommitmentConfig::confirmed()
+        };
+        // Signatures are returned newest first, a page at a time
+        let mut before = None;
+        loop {
+            let statuses = self.client.get_signatures_for_address_with_config(
+                address,
+                GetConfirmedSignaturesForAddress2Config {
+                    before,
+                    commitment: Some(commitment),
+                    ..Default::default()
+                },
+            )?;
+            let Some(last) = statuses.last() else {
+                return Ok(());
+            };
+            before = Some(last.signature.parse::<Signature>()?);
+            if visit(&statuses, commitment)? {
+                return Ok(());
+            }
+        }
+    }
+
+    // The args of the first instruction matching in one page of `statuses`
+    fn find_in_statuses<T: AnchorDeserialize>(
+        &self,
+        statuses: &[RpcConfirmedTransactionStatusWithSignature],
+        discriminator: &[u8; 8],
+        matches: impl Fn(&T) -> bool,
+        commitment: CommitmentConfig,
+    ) -> Result<Option<T>> {
+        for status in statuses {
+            if status.err.is_some() {
+                continue;
+            }
+            let signature = status.signature.parse::<Signature>()?;
+            let confirmed = self.client.get_transaction_with_config(
+                &signature,
+                RpcTransactionConfig {
+                    encoding: Some(UiTransactionEncoding::Base64),
+                    commitment: Some(commitment),
+                    max_supported_transaction_version: Some(0),
+                },
+            )?;
+            let Some(transaction) = confirmed.transaction.transaction.decode() else {
+                continue;
+            };
+
+            let account_keys = transaction.message.static_account_keys();
+            for instruction in transaction.message.instructions() {
+                if account_keys.get(instruction.program_id_index as usize) != Some(&self.program_id)
+                {
+                    continue;
+                }
+                let Some(data) = instruction.data.strip_prefix(&discriminator[..]) else {
+                    continue;
+                };
+                if let Ok(args) = T::try_from_slice(data) {
+                    if matches(&args) {
+                        log::info!("Found instruction data in transaction {}", signature);
+                        return Ok(Some(args));
                     }
                 }
-            } else {
-                return Err(anyhow::anyhow!("Failed to decode return data from base64"));
             }
-        } else {
-            return Err(anyhow::anyhow!("No return data in simulation result"));
+        }
+        Ok(None)
+    }
+
+    /// Whether `withdrawal_root` is finalized on chain, false when it was never committed.
+    /// Withdrawals against it can be claimed once it is.
+    pub fn is_withdrawal_root_finalized(&self, withdrawal_root: [u8; 32]) -> Result<bool> {
+        let discriminator: [u8; 8] = [37, 119, 137, 220, 213, 150, 164, 242];
+        let mut instruction_data = discriminator.to_vec();
+        instruction_data.extend_from_slice(&withdrawal_root.try_to_vec()?);
+
+        let return_data =
+            self.simulate_query(&[b"finalized_withdrawal_roots"], instruction_data)?;
+        // `false` is all zeros, which the rpc trims away
+        Ok(return_data.first() == Some(&1))
+    }
+
+    /// The latest finalized withdrawal roots, at most 31 of them.
+    pub fn list_finalized_roots(&self) -> Result<Vec<[u8; 32]>> {
+        let discriminator: [u8; 8] = [84, 117, 248, 122, 158, 209, 155, 35];
+
+        let mut return_data =
+            self.simulate_query(&[b"finalized_withdrawal_roots"], discriminator.to_vec())?;
+        // Restore the trailing zeros trimmed by the rpc
+        return_data.resize(return_data.len().max(4), 0);
+        let len = u32::try_from_slice(&return_data[..4])? as usize;
+        return_data.resize(4 + len * 32, 0);
+        Ok(Vec::<[u8; 32]>::try_from_slice(&return_data)?)
+    }
+
+    /// The program config, its roles and L1 oracle.
+    pub fn get_config(&self) -> Result<ProgramConfig> {
+        let config = Pubkey::find_program_address(&[b"config"], &self.program_id).0;
+        let account = self
+            .client
+            .get_account_with_commitment(&config, self.client.commitment())?
+            .value
+            .ok_or_else(|| anyhow::anyhow!("Config not initialized"))?;
+        let mut data = account
+            .data
+            .get(8..)
+            .ok_or_else(|| anyhow::anyhow!("Config account too short"))?;
+        Ok(ProgramConfig::deserialize(&mut data)?)
+    }
+
+    /// Balance of `owner` in the bridge vault, what its withdrawals can take.
+    pub fn get_bridge_balance(&self, owner: &Pubkey) -> Result<u64> {
+        let bridge_vault = Pubkey::find_program_address(&[b"bridge_vault"], &self.program_id).0;
+        let account = self
+            .client
+            .get_account_with_commitment(&bridge_vault, self.client.commitment())?
+            .value
+            .ok_or_else(|| anyhow::anyhow!("Bridge vault not initialized"))?;
+        bridge_balance(&account.data, owner)
+    }
+
+    /// The bridge vault's `(tracked_total, actual_lamports)`: the sum of the balances it
+    /// tracks and the lamports it holds above its rent-exempt reserve. The bridge is
+    /// solvent while `actual_lamports >= tracked_total`, a monitor should alert otherwise.
+    pub fn get_vault_solvency(&self) -> Result<(u64, u64)> {
+        let discriminator: [u8; 8] = [54, 16, 21, 153, 20, 132, 69, 148];
+
+        let mut return_data = self.simulate_query(&[b"bridge_vault"], discriminator.to_vec())?;
+        // Restore the trailing zeros trimmed by the rpc
+        return_data.resize(16, 0);
+        Ok(<(u64, u64)>::try_from_slice(&return_data)?)
+    }
+
+    /// Indices of the committed batches after the last finalized one, ascending, at most
+    /// 100 of them. The next to prove is the last finalized + 1, a list starting past it
+    /// has a gap.
+    pub fn get_unproven_batches(&self) -> Result<Vec<u64>> {
+        let discriminator: [u8; 8] = [13, 143, 86, 75, 41, 121, 49, 79];
+
+        let mut return_data = self.simulate_query(
+            &[b"batch_storage", b"last_finalized_batch_index"],
+            discriminator.to_vec(),
+        )?;
+        // Restore the trailing zeros trimmed by the rpc
+        return_data.resize(return_data.len().max(4), 0);
+        let len = u32::try_from_slice(&return_data[..4])? as usize;
+        return_data.resize(4 + len * 8, 0);
+        Ok(Vec::<u64>::try_from_slice(&return_data)?)
+    }
+
+    // Simulate a read of the program PDAs at `seeds` and return its return data
+    fn simulate_query(&self, seeds: &[&[u8]], instruction_data: Vec<u8>) -> Result<Vec<u8>> {
+        let instruction = Instruction {
+            program_id: self.program_id,
+            accounts: seeds
+                .iter()
+                .map(|seed| {
+                    AccountMeta::new_readonly(
+                        Pubkey::find_program_address(&[seed], &self.program_id).0,
+                        false,
+                    )
+                })
+                .collect(),
+            data: instruction_data,
+        };
+
+        let recent_blockhash = self.client.get_latest_blockhash()?;
+        let transaction = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&self.fee_payer.pubkey()),
+            &[&self.fee_payer],
+            recent_blockhash,
+        );
+
+        let result = self.client.simulate_transaction(&transaction)?;
+        if let Some(err) = result.value.err {
+            return Err(anyhow::anyhow!("Transaction simulation failed: {:?}", err));
+        }
+
+        match &result.value.return_data {
+            Some(return_data) => general_purpose::STANDARD
+                .decode(&return_data.data.0)
+                .map_err(|_| anyhow::anyhow!("Failed to decode return data from base64")),
+            None => Ok(vec![]),
         }
     }
 
-    /// Request airdrop for the fee payer (useful for testing)
+    /// Request airdrop for the fee payer (useful for testing), waiting up to
+    /// `AIRDROP_TIMEOUT` for it to confirm
     pub fn request_airdrop(&self, amount: u64) -> Result<()> {
+        self.request_airdrop_with_timeout(amount, AIRDROP_TIMEOUT)
+    }
+
+    /// Request airdrop for the fee payer, erroring if it isn't confirmed within `timeout`
+    pub fn request_airdrop_with_timeout(&self, amount: u64, timeout: Duration) -> Result<()> {
         let airdrop_signature = self
             .client
             .request_airdrop(&self.fee_payer.pubkey(), amount)?;
-        self.client.confirm_transaction(&airdrop_signature)?;
-        loop {
-            let confirmed = self.client.confirm_transaction(&airdrop_signature)?;
-            if confirmed {
-                break;
+        let deadline = Instant::now() + timeout;
+        while !self.client.confirm_transaction(&airdrop_signature)? {
+            if Instant::now() >= deadline {
+                return Err(anyhow::anyhow!(
+                    "Airdrop {} not confirmed within {:?}",
+                    airdrop_signature,
+                    timeout
+                ));
             }
+            std::thread::sleep(AIRDROP_POLL_INTERVAL);
         }
 
         log::info!("Airdrop completed: {} lamports", amount);
@@ -430,6 +1760,164 @@ impl L2StateClient {
     }
 }
 
+/// Load a `BatchInfo` from a JSON file written by `write_batch_json`.
+#[cfg(feature = "dev-tools")]
+pub fn read_batch_json(path: impl AsRef<std::path::Path>) -> Result<BatchInfo> {
+    let path = path.as_ref();
+    let data = std::fs::read(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+    serde_json::from_slice(&data)
+        .map_err(|e| anyhow::anyhow!("Invalid batch json {}: {}", path.display(), e))
+}
+
+/// Write a `BatchInfo` as pretty JSON.
+#[cfg(feature = "dev-tools")]
+pub fn write_batch_json(batch_info: &BatchInfo, path: impl AsRef<std::path::Path>) -> Result<()> {
+    std::fs::write(path, serde_json::to_vec_pretty(batch_info)?)?;
+    Ok(())
+}
+
+// Whether `balance` covers a transaction `fee` of `payer`
+fn check_fee_payer_balance(
+    payer: Pubkey,
+    balance: u64,
+    fee: u64,
+) -> std::result::Result<(), ClientError> {
+    if balance < fee {
+        return Err(ClientError::InsufficientFeePayerBalance {
+            payer,
+            balance,
+            fee,
+        });
+    }
+    Ok(())
+}
+
+// Balance of `owner` in the data of the `BridgeVault` account, `{ authority, balances }`
+// after the account discriminator. Owners without a balance have 0.
+fn bridge_balance(data: &[u8], owner: &Pubkey) -> Result<u64> {
+    let mut data = data
+        .get(8..)
+        .ok_or_else(|| anyhow::anyhow!("Bridge vault account too short"))?;
+    let (_authority, balances) = <(Pubkey, Vec<(Pubkey, u64)>)>::deserialize(&mut data)?;
+    Ok(balances
+        .into_iter()
+        .find(|(key, _)| key == owner)
+        .map(|(_, balance)| balance)
+        .unwrap_or(0))
+}
+
+// Whether `from`'s `balance` in the bridge vault covers a withdrawal of `amount`
+fn check_bridge_balance(
+    from: &Pubkey,
+    amount: u64,
+    balance: u64,
+) -> std::result::Result<(), ClientError> {
+    if balance < amount {
+        return Err(ClientError::InsufficientBridgeBalance {
+            from: *from,
+            amount,
+            balance,
+        });
+    }
+    Ok(())
+}
+
+// A withdrawal as returned by the sequencer's `getWithdrawalProof`
+struct WithdrawalClaim {
+    from: Pubkey,
+    to: Pubkey,
+    leaf_hash: [u8; 32],
+    withdrawal: WithdrawalData,
+}
+
+fn parse_withdrawal_claim(proof: &Value) -> Result<WithdrawalClaim> {
+    let field = |name: &str| {
+        proof
+            .get(name)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("getWithdrawalProof result has no {}", name))
+    };
+    let pubkey = |name: &str| -> Result<Pubkey> {
+        let value: String = serde_json::from_value(field(name)?)?;
+        Pubkey::from_str(&value).map_err(|e| anyhow::anyhow!("Invalid {} {}: {}", name, value, e))
+    };
+    Ok(WithdrawalClaim {
+        from: pubkey("from")?,
+        to: pubkey("to")?,
+        leaf_hash: serde_json::from_value(field("leafHash")?)?,
+        withdrawal: WithdrawalData {
+            amount: serde_json::from_value(field("amount")?)?,
+            index: serde_json::from_value(field("index")?)?,
+            withdraw_root: serde_json::from_value(field("root")?)?,
+            withdrawal_proof: serde_json::from_value(field("proof")?)?,
+        },
+    })
+}
+
+/// Split `proof` into `(offset, chunk)` pairs of at most `chunk_size` bytes, in upload order
+pub fn proof_chunks(proof: &[u8], chunk_size: usize) -> impl Iterator<Item = (u32, &[u8])> {
+    proof
+        .chunks(chunk_size)
+        .enumerate()
+        .map(move |(i, chunk)| ((i * chunk_size) as u32, chunk))
+}
+
+/// Leaf of a withdrawal in the withdrawal tree, as the program rebuilds it. The
+/// encoding is `share::state::withdrawal_leaf_bytes`, the sequencer's tree uses it too.
+pub fn withdrawal_leaf_hash(from: &Pubkey, to: &Pubkey, amount: u64, index: u64) -> [u8; 32] {
+    share::state::withdrawal_leaf_hash(HASH_ALGO, from, to, amount, index)
+}
+
+// Root reached by folding `proof` into `leaf_hash`, the program's `verify_merkle_proof`
+fn merkle_root_from_proof(leaf_hash: [u8; 32], proof: &[[u8; 32]], index: u64) -> [u8; 32] {
+    let mut node = leaf_hash;
+    for (height, sibling) in proof.iter().enumerate() {
+        if (index >> height) & 1 == 1 {
+            node = hashv(&[sibling, &node]).to_bytes();
+        } else {
+            node = hashv(&[&node, sibling]).to_bytes();
+        }
+    }
+    node
+}
+
+/// Events `E` logged by `program_id` itself in `logs`, the log messages of a
+/// transaction. Data logged by another program, e.g. one calling it, is left out, so it
+/// can't pass for an event of the program.
+pub fn program_events<E: anchor_lang::Event>(logs: &[String], program_id: &Pubkey) -> Vec<E> {
+    let program_id = program_id.to_string();
+    // Programs invoked at each depth
+    let mut invoked: Vec<&str> = vec![];
+    let mut events = vec![];
+    for log in logs {
+        let Some(log) = log.strip_prefix("Program ") else {
+            continue;
+        };
+        if let Some(data) = log.strip_prefix("data: ") {
+            if invoked.last() != Some(&program_id.as_str()) {
+                continue;
+            }
+            let Ok(data) = general_purpose::STANDARD.decode(data) else {
+                continue;
+            };
+            if let Some(mut event) = data.strip_prefix(E::DISCRIMINATOR) {
+                events.extend(E::deserialize(&mut event).ok());
+            }
+            continue;
+        }
+        let mut words = log.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some(program), Some("invoke")) => invoked.push(program),
+            (Some(_), Some("success" | "failed:")) => {
+                invoked.pop();
+            }
+            _ => {}
+        }
+    }
+    events
+}
+
 /// Hash a nested vector of bytes
 pub fn hash_nested_vector(data: &Vec<Vec<u8>>) -> [u8; 32] {
     if data.is_empty() {
@@ -439,6 +1927,29 @@ pub fn hash_nested_vector(data: &Vec<Vec<u8>>) -> [u8; 32] {
     hash(&concatenated_data).to_bytes()
 }
 
+/// Public values the l2-state program checks the proof of `batch` against, its
+/// `batch_public_inputs`: `pi_hash || batch_hash`, `pi_hash` hashing the roots, the
+/// withdrawal addresses hash and L1 oracle of its `config` and its forced transactions
+/// hash, in the order of `share::zkvm::calculate_pi_hash`. `prev_withdrawal_root` is
+/// the withdrawal root of the previous batch, `[0u8; 32]` for the first one.
+pub fn batch_public_inputs(
+    batch: &BatchData,
+    prev_withdrawal_root: [u8; 32],
+    config: &ProgramConfig,
+) -> Vec<u8> {
+    let pi_hash = hash_nested_vector(&vec![
+        batch.prev_state_root.to_vec(),
+        batch.post_state_root.to_vec(),
+        prev_withdrawal_root.to_vec(),
+        batch.withdrawal_root.to_vec(),
+        config.withdrawal_addresses_hash.to_vec(),
+        config.l1_oracle.to_bytes().to_vec(),
+        batch.forced_transactions_hash.to_vec(),
+        batch.batch_hash.to_vec(),
+    ]);
+    [pi_hash, batch.batch_hash].concat()
+}
+
 #[cfg(test)]
 mod test {
     use anyhow::Result;
@@ -446,6 +1957,16 @@ mod test {
     use super::hash_nested_vector;
     use super::BatchInfo;
     use super::L2StateClient;
+    use super::{
+        bridge_balance, check_bridge_balance, check_fee_payer_balance, parse_withdrawal_claim,
+        ClientError,
+    };
+    use super::{proof_chunks, BatchProof, PACKET_DATA_SIZE, PROOF_CHUNK_SIZE};
+    use super::{withdrawal_leaf_hash, WithdrawalData};
+    use anchor_lang::prelude::{AccountMeta, AnchorSerialize};
+    use share::transaction::{l1_oracle, withdrawal_addresses, withdrawal_addresses_hash};
+    use solana_sdk::{instruction::Instruction, system_program};
+    use solana_sdk::{signature::Keypair, signer::Signer};
 
     // use example: cargo test test_state -- --nocapture
     #[test]
@@ -466,9 +1987,13 @@ mod test {
         // Airdrop 1 SOL to fee payer
         client.request_airdrop(1_000_000_000)?;
 
-        // Call the initialize_batch_storage function
+        // Call the initialize_batch_storage function, skipped when already initialized
         println!("------------> Start call the initialize function");
-        client.initialize()?;
+        client.initialize_if_needed(
+            [0u8; 32],
+            l1_oracle(),
+            withdrawal_addresses_hash(&withdrawal_addresses()),
+        )?;
 
         // Call the commit_committed_batch function
         println!("------------> Start call the commit_batch function");
@@ -484,6 +2009,8 @@ mod test {
             prev_state_root: [0u8; 32],
             post_state_root: [1u8; 32],
             withdrawal_root: [3u8; 32],
+            withdrawal_count: 0,
+            next_forced_index: 0,
         };
 
         let blocks_hash = hash_nested_vector(&batch_info.blocks);
@@ -500,6 +2027,12 @@ mod test {
         let latest_batch = client.get_latest_batch()?;
         println!("latest_batch: {:?}", latest_batch);
 
+        // Look up the batch of its first block
+        if let Some(latest_batch) = &latest_batch {
+            let batch = client.get_batch_for_block(latest_batch.start_block_num)?;
+            assert_eq!(batch.map(|b| b.batch_index), Some(latest_batch.batch_index));
+        }
+
         // Call the get_latest_batch_index function
         println!("------------> Start call the get_last_finalized_batch_index function");
         let latest_batch_index = client.get_last_finalized_batch_index()?;
@@ -513,4 +2046,534 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_commit_wrong_genesis_root() -> Result<()> {
+        let client = L2StateClient::new_local()?;
+        client.request_airdrop(1_000_000_000)?;
+
+        // Initialize with an empty genesis state, may already be initialized.
+        client.initialize_if_needed(
+            [0u8; 32],
+            l1_oracle(),
+            withdrawal_addresses_hash(&withdrawal_addresses()),
+        )?;
+
+        let batch_info = BatchInfo {
+            batch_index: 1,
+            blocks: vec![vec![1, 2, 3, 4, 5]],
+            start_block_num: 1,
+            end_block_num: 1,
+            prev_state_root: [9u8; 32],
+            post_state_root: [1u8; 32],
+            withdrawal_root: [3u8; 32],
+            withdrawal_count: 0,
+            next_forced_index: 0,
+        };
+        let result = client.commit_batch(batch_info);
+        assert!(
+            result.is_err(),
+            "batch 1 with a wrong genesis root must be rejected"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_initialize_twice() -> Result<()> {
+        let client = L2StateClient::new_local()?;
+        client.request_airdrop(1_000_000_000)?;
+
+        client.initialize_if_needed(
+            [0u8; 32],
+            l1_oracle(),
+            withdrawal_addresses_hash(&withdrawal_addresses()),
+        )?;
+        assert!(client.is_initialized()?);
+
+        // A second run skips initialize instead of failing with "account already in use"
+        assert!(!client.initialize_if_needed(
+            [0u8; 32],
+            l1_oracle(),
+            withdrawal_addresses_hash(&withdrawal_addresses())
+        )?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_transfer_authority() -> Result<()> {
+        let client = L2StateClient::new_local()?;
+        client.request_airdrop(1_000_000_000)?;
+        client.initialize_if_needed(
+            [0u8; 32],
+            l1_oracle(),
+            withdrawal_addresses_hash(&withdrawal_addresses()),
+        )?;
+
+        let new_authority = Keypair::new();
+        let new_client = L2StateClient::new(
+            "http://localhost:8899".to_string(),
+            &new_authority.to_bytes(),
+        )?;
+        new_client.request_airdrop(1_000_000_000)?;
+
+        client.transfer_authority(new_authority.pubkey())?;
+        // Only the new authority can move the sequencer role
+        assert!(client.set_sequencer(client.fee_payer.pubkey()).is_err());
+        new_client.set_sequencer(new_authority.pubkey())?;
+
+        let batch_info = BatchInfo {
+            batch_index: 1,
+            blocks: vec![vec![1, 2, 3, 4, 5]],
+            start_block_num: 1,
+            end_block_num: 1,
+            prev_state_root: [0u8; 32],
+            post_state_root: [1u8; 32],
+            withdrawal_root: [3u8; 32],
+            withdrawal_count: 0,
+            next_forced_index: 0,
+        };
+        // The old authority can no longer commit, nor take the authority back
+        assert!(client.commit_batch(batch_info.clone()).is_err());
+        assert!(client
+            .transfer_authority(client.fee_payer.pubkey())
+            .is_err());
+        new_client.commit_batch(batch_info)?;
+
+        // Hand the roles back to the local key for the other tests
+        new_client.set_sequencer(client.fee_payer.pubkey())?;
+        new_client.transfer_authority(client.fee_payer.pubkey())?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_finalized_index() -> Result<()> {
+        let client = L2StateClient::new_local()?;
+        client.request_airdrop(1_000_000_000)?;
+        client.initialize_if_needed(
+            [0u8; 32],
+            l1_oracle(),
+            withdrawal_addresses_hash(&withdrawal_addresses()),
+        )?;
+        let last_finalized = client.get_last_finalized_batch_index()?;
+
+        // Only the authority can roll the index back
+        let other = L2StateClient::new(
+            "http://localhost:8899".to_string(),
+            &Keypair::new().to_bytes(),
+        )?;
+        other.request_airdrop(1_000_000_000)?;
+        assert!(other
+            .set_finalized_index(last_finalized.saturating_sub(1))
+            .is_err());
+
+        // Nor can the authority move it forward
+        assert!(client.set_finalized_index(last_finalized + 1).is_err());
+        assert_eq!(client.get_last_finalized_batch_index()?, last_finalized);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unauthorized_commit() -> Result<()> {
+        let client = L2StateClient::new_local()?;
+        client.request_airdrop(1_000_000_000)?;
+        client.initialize_if_needed(
+            [0u8; 32],
+            l1_oracle(),
+            withdrawal_addresses_hash(&withdrawal_addresses()),
+        )?;
+
+        // Anyone can prove, but only the sequencer commits
+        let stranger = Keypair::new();
+        let stranger_client =
+            L2StateClient::new("http://localhost:8899".to_string(), &stranger.to_bytes())?;
+        stranger_client.request_airdrop(1_000_000_000)?;
+
+        let batch_info = BatchInfo {
+            batch_index: 1,
+            blocks: vec![vec![1, 2, 3, 4, 5]],
+            start_block_num: 1,
+            end_block_num: 1,
+            prev_state_root: [0u8; 32],
+            post_state_root: [1u8; 32],
+            withdrawal_root: [3u8; 32],
+            withdrawal_count: 0,
+            next_forced_index: 0,
+        };
+        let result = stranger_client.commit_batch(batch_info.clone());
+        assert!(
+            result.is_err(),
+            "commit from a non sequencer must be rejected"
+        );
+        client.commit_batch(batch_info)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_withdrawal_root_queries() -> Result<()> {
+        let client = L2StateClient::new_local()?;
+        client.request_airdrop(1_000_000_000)?;
+        client.initialize_if_needed(
+            [0u8; 32],
+            l1_oracle(),
+            withdrawal_addresses_hash(&withdrawal_addresses()),
+        )?;
+
+        // A root that was never committed isn't finalized
+        assert!(!client.is_withdrawal_root_finalized([9u8; 32])?);
+        let finalized_roots = client.list_finalized_roots()?;
+        assert!(finalized_roots.len() <= 31);
+        assert!(!finalized_roots.contains(&[9u8; 32]));
+        for root in finalized_roots {
+            assert!(client.is_withdrawal_root_finalized(root)?);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_vault_solvency() -> Result<()> {
+        let client = L2StateClient::new_local()?;
+        client.request_airdrop(1_000_000_000)?;
+        client.initialize_if_needed(
+            [0u8; 32],
+            l1_oracle(),
+            withdrawal_addresses_hash(&withdrawal_addresses()),
+        )?;
+
+        let (tracked_total, actual_lamports) = client.get_vault_solvency()?;
+        assert!(actual_lamports >= tracked_total);
+
+        // A deposit moves both by its amount
+        client.deposit(1_000)?;
+        let (new_tracked_total, new_actual_lamports) = client.get_vault_solvency()?;
+        assert_eq!(new_tracked_total, tracked_total + 1_000);
+        assert_eq!(
+            new_actual_lamports - new_tracked_total,
+            actual_lamports - tracked_total
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_proof_chunks() -> Result<()> {
+        let client = L2StateClient::new_local()?;
+        let proof: Vec<u8> = (0..3_000u32).map(|i| (i % 251) as u8).collect();
+
+        // A large proof doesn't fit a single `prove_state` transaction
+        let batch_proof = BatchProof {
+            batch_index: 7,
+            proof: proof.clone(),
+        };
+        let instruction = client.prove_state_instruction(&batch_proof)?;
+        assert!(client.transaction_size(&instruction) > PACKET_DATA_SIZE);
+
+        // The chunks reassemble to the proof, each upload fits a packet
+        let mut assembled = Vec::new();
+        for (offset, chunk) in proof_chunks(&proof, PROOF_CHUNK_SIZE) {
+            assert_eq!(offset as usize, assembled.len());
+            assembled.extend_from_slice(chunk);
+
+            let mut data = vec![0u8; 8 + 8 + 4];
+            data.extend_from_slice(&chunk.to_vec().try_to_vec()?);
+            let instruction = Instruction {
+                program_id: client.program_id,
+                accounts: vec![
+                    AccountMeta::new(client.payer(), true),
+                    AccountMeta::new(client.proof_buffer_address(7), false),
+                    AccountMeta::new_readonly(system_program::ID, false),
+                ],
+                data,
+            };
+            assert!(client.transaction_size(&instruction) <= PACKET_DATA_SIZE);
+        }
+        assert_eq!(assembled, proof);
+        assert_eq!(proof_chunks(&proof, PROOF_CHUNK_SIZE).count(), 4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_fee_payer_balance() {
+        let payer = Keypair::new().pubkey();
+        assert!(check_fee_payer_balance(payer, 5_000, 5_000).is_ok());
+
+        // An unfunded payer gets an actionable error
+        let err = check_fee_payer_balance(payer, 0, 5_000).unwrap_err();
+        assert_eq!(
+            err,
+            ClientError::InsufficientFeePayerBalance {
+                payer,
+                balance: 0,
+                fee: 5_000,
+            }
+        );
+        let err = anyhow::Error::from(err);
+        assert!(err
+            .to_string()
+            .contains("not enough for the 5000 lamports fee"));
+        assert!(err.downcast_ref::<ClientError>().is_some());
+    }
+
+    #[test]
+    fn test_verify_withdrawal_locally() -> Result<()> {
+        use super::hashv;
+        use anchor_lang::prelude::Pubkey;
+
+        let (from, to) = (Pubkey::new_unique(), Pubkey::new_unique());
+        // Tree of 4 withdrawals, ours is index 2
+        let leaves: Vec<[u8; 32]> = (0..4)
+            .map(|index| withdrawal_leaf_hash(&from, &to, 1_000 + index, index))
+            .collect();
+        let left = hashv(&[&leaves[0], &leaves[1]]).to_bytes();
+        let right = hashv(&[&leaves[2], &leaves[3]]).to_bytes();
+        let root = hashv(&[&left, &right]).to_bytes();
+
+        let withdrawal = WithdrawalData {
+            amount: 1_002,
+            index: 2,
+            withdraw_root: root,
+            withdrawal_proof: vec![leaves[3], left],
+        };
+        L2StateClient::verify_withdrawal_locally(&withdrawal, &from, &to, Some(leaves[2]))?;
+        L2StateClient::verify_withdrawal_locally(&withdrawal, &from, &to, None)?;
+
+        // Another recipient changes the leaf
+        let stranger = Pubkey::new_unique();
+        let err = L2StateClient::verify_withdrawal_locally(
+            &withdrawal,
+            &from,
+            &stranger,
+            Some(leaves[2]),
+        )
+        .unwrap_err();
+        assert!(err.to_string().starts_with("Leaf mismatch"));
+
+        // A wrong sibling leads elsewhere
+        let mut bad_proof = withdrawal.clone();
+        bad_proof.withdrawal_proof[1] = right;
+        let err = L2StateClient::verify_withdrawal_locally(&bad_proof, &from, &to, Some(leaves[2]))
+            .unwrap_err();
+        assert!(err.to_string().starts_with("Proof mismatch"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_claim_withdrawal_checks() -> Result<()> {
+        use super::hashv;
+        use anchor_lang::prelude::Pubkey;
+
+        let (from, to) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let leaves = [
+            withdrawal_leaf_hash(&from, &to, 1_000, 0),
+            withdrawal_leaf_hash(&from, &to, 2_000, 1),
+        ];
+        let root = hashv(&[&leaves[0], &leaves[1]]).to_bytes();
+        // As returned by the sequencer's getWithdrawalProof
+        let mut proof = serde_json::json!({
+            "leafHash": leaves[1],
+            "proof": [leaves[0]],
+            "index": 1,
+            "root": root,
+            "from": from.to_string(),
+            "to": to.to_string(),
+            "amount": 2_000
+        });
+        let claim = parse_withdrawal_claim(&proof)?;
+        assert_eq!((claim.from, claim.to), (from, to));
+        assert_eq!(claim.withdrawal.amount, 2_000);
+        L2StateClient::verify_withdrawal_locally(
+            &claim.withdrawal,
+            &claim.from,
+            &claim.to,
+            Some(claim.leaf_hash),
+        )?;
+
+        // A sequencer without the withdrawal's fields
+        proof.as_object_mut().unwrap().remove("to");
+        let err = parse_withdrawal_claim(&proof).err().unwrap();
+        assert!(err.to_string().contains("has no to"));
+
+        // The sender's bridge balance, not the vault's lamports, must cover it
+        let mut vault = vec![0; 8];
+        (Pubkey::default(), vec![(to, 5_000u64), (from, 1_999)]).serialize(&mut vault)?;
+        // Trailing bytes left by a realloc
+        vault.extend_from_slice(&[0; 40]);
+        assert_eq!(bridge_balance(&vault, &from)?, 1_999);
+        assert_eq!(bridge_balance(&vault, &Pubkey::new_unique())?, 0);
+        check_bridge_balance(&from, 1_999, 1_999)?;
+        let err = anyhow::Error::from(check_bridge_balance(&from, 2_000, 1_999).unwrap_err());
+        assert_eq!(
+            err.downcast_ref::<ClientError>(),
+            Some(&ClientError::InsufficientBridgeBalance {
+                from,
+                amount: 2_000,
+                balance: 1_999
+            })
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "dev-tools")]
+    #[test]
+    fn test_batch_json() -> Result<()> {
+        use super::{read_batch_json, write_batch_json};
+
+        let batch_info = BatchInfo {
+            batch_index: 3,
+            blocks: vec![vec![1, 2, 3], vec![]],
+            start_block_num: 11,
+            end_block_num: 12,
+            prev_state_root: [10; 32],
+            post_state_root: [12; 32],
+            withdrawal_root: [7; 32],
+            withdrawal_count: 2,
+            next_forced_index: 3,
+        };
+        let path = std::env::temp_dir().join(format!("batch_{}.json", std::process::id()));
+        write_batch_json(&batch_info, &path)?;
+        let loaded = read_batch_json(&path)?;
+        std::fs::remove_file(&path)?;
+
+        // Same instruction data as the original batch
+        assert_eq!(loaded.try_to_vec()?, batch_info.try_to_vec()?);
+        assert!(read_batch_json(&path).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_program_events() {
+        use super::program_events;
+        use crate::event_listen::ForcedTransactionEvent;
+        use anchor_lang::prelude::Pubkey;
+        use anchor_lang::Event;
+        use base64::{engine::general_purpose, Engine};
+
+        let program_id = Pubkey::new_unique();
+        let caller = Pubkey::new_unique();
+        let event = |index: u64| {
+            let data = ForcedTransactionEvent {
+                index,
+                sender: Pubkey::default(),
+                transaction: vec![index as u8],
+                deadline: 0,
+                timestamp: 0,
+            }
+            .data();
+            format!("Program data: {}", general_purpose::STANDARD.encode(data))
+        };
+        // Another program calls the l2-state program, logging a lookalike of its event
+        // before and after the call
+        let logs: Vec<String> = vec![
+            format!("Program {} invoke [1]", caller),
+            event(7),
+            format!("Program {} invoke [2]", program_id),
+            "Program log: Instruction: ForceInclude".to_string(),
+            event(3),
+            format!(
+                "Program {} consumed 1000 of 200000 compute units",
+                program_id
+            ),
+            format!("Program {} success", program_id),
+            event(8),
+            format!("Program {} success", caller),
+        ];
+
+        let events: Vec<ForcedTransactionEvent> = program_events(&logs, &program_id);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].index, 3);
+        assert_eq!(events[0].transaction, vec![3]);
+    }
+
+    #[test]
+    fn test_batch_public_inputs() {
+        use super::{batch_public_inputs, BatchData, ProgramConfig};
+        use share::state::State;
+        use share::transaction::{calculate_txns_root, Block};
+        use share::zkvm::{execute_batch, ZkVMInput};
+        use share::WITHDRAWAL_ADDRESS;
+        use solana_sdk::{hash::Hash, pubkey::Pubkey, system_transaction};
+        use std::str::FromStr;
+
+        // The roots, oracle and withdrawal address of the program's test, `pi_hash` is
+        // the same sha256
+        let config = ProgramConfig {
+            authority: Pubkey::default(),
+            sequencer: Pubkey::default(),
+            l1_oracle: l1_oracle(),
+            withdrawal_addresses_hash: withdrawal_addresses_hash(&withdrawal_addresses()),
+        };
+        let batch = BatchData {
+            batch_index: 1,
+            start_block_num: 1,
+            end_block_num: 10,
+            batch_hash: [4; 32],
+            prev_state_root: [1; 32],
+            post_state_root: [2; 32],
+            withdrawal_root: [3; 32],
+            withdrawal_count: 0,
+            next_forced_index: 0,
+            forced_transactions_hash: [0; 32],
+        };
+        assert_eq!(
+            batch_public_inputs(&batch, [6; 32], &config)[..32],
+            [
+                119, 196, 129, 105, 91, 57, 105, 171, 189, 120, 10, 210, 203, 161, 210, 216, 97,
+                232, 32, 246, 191, 45, 6, 143, 25, 247, 10, 34, 108, 163, 59, 63
+            ]
+        );
+
+        // A batch withdrawing on top of an earlier withdrawal
+        let alice = Keypair::new();
+        let withdrawal_address = Pubkey::from_str(WITHDRAWAL_ADDRESS).unwrap();
+        let withdraw = |lamports: u64| {
+            system_transaction::transfer(&alice, &withdrawal_address, lamports, Hash::default())
+        };
+        let mut state = State::genesis(Some(alice.pubkey().to_string()));
+        state.apply_txns(&[withdraw(100)], 1);
+        let prev_withdrawal_root = state.calculate_withdrawal_root().unwrap();
+        let mut block = Block::new(vec![withdraw(200)]);
+        block.block_num = 2;
+        block.txns_root = Some(calculate_txns_root(&block.txns));
+        block.prev_state_root = state.calculate_state_root();
+        let mut post_state = state.clone();
+        post_state.apply_txns(&block.txns, 2);
+        block.post_state_root = post_state.calculate_state_root();
+        block.withdrawal_root = post_state.calculate_withdrawal_root();
+
+        // The withdrawal was forced through L1, the program hashes its queued hash
+        let forced = bincode::serialize(&block.txns[0]).unwrap();
+        // Committed through calldata, as the batcher does
+        let batch = BatchData {
+            batch_index: 2,
+            start_block_num: 2,
+            end_block_num: 2,
+            batch_hash: hash_nested_vector(&vec![block.canonical_bytes()]),
+            prev_state_root: block.prev_state_root.unwrap(),
+            post_state_root: block.post_state_root.unwrap(),
+            withdrawal_root: block.withdrawal_root.unwrap(),
+            withdrawal_count: 2,
+            next_forced_index: 1,
+            forced_transactions_hash: hash_nested_vector(&vec![super::hash(&forced)
+                .to_bytes()
+                .to_vec()]),
+        };
+
+        // What the zkVM program commits is what the program verifies the proof against
+        let public_values = execute_batch(ZkVMInput {
+            forced_txns: vec![forced],
+            ..ZkVMInput::with_partial_state(vec![block], &state)
+        })
+        .unwrap();
+        assert_eq!(
+            public_values.to_bytes(),
+            batch_public_inputs(&batch, prev_withdrawal_root, &config)
+        );
+    }
 }
@@ -0,0 +1,431 @@
+#![allow(unexpected_cfgs)]
+
+use anchor_lang::prelude::*;
+
+use crate::{
+    biz_error,
+    bridge::{BridgeVault, FinalizedWithdrawalRoots, FinalizedWithdrawals, WithdrawalLimit},
+    state::{BatchStorage, Config, GenesisState, LastFinalizedBatchIndex},
+};
+
+#[event]
+pub struct FinalizedIndexRolledBackEvent {
+    /// The authority that rolled the index back
+    pub authority: Pubkey,
+    /// The finalized batch index before the rollback
+    pub previous_batch_index: u64,
+    /// The finalized batch index after the rollback
+    pub batch_index: u64,
+    /// Timestamp of the rollback
+    pub timestamp: i64,
+}
+
+/*´:°•.°+.*•´.*:˚.°*.˚•´.°:°•.°•.*•´.*:˚.°*.˚•´.°:°•.°+.*•´.*:*/
+/*                          ADMIN IMPL                        */
+/*.•°:°.´+˚.*°.˚:*.´•*.+°.•°:´*.´•*.•°.•°:°.´:•˚°.*°.˚:*.´+°.•*/
+
+/// Impl of transfer authority.
+///
+/// The authority of every program PDA moves to `new_authority` at once, so they
+/// never disagree. `new_authority` may be a multisig account (e.g. a Squads vault),
+/// which then signs admin instructions through CPI once its threshold is met.
+pub fn transfer_authority(ctx: Context<TransferAuthority>, new_authority: Pubkey) -> Result<()> {
+    if new_authority == Pubkey::default() {
+        return Err(Error::from(biz_error::ErrorCode::InvalidAuthority));
+    }
+
+    let accounts = ctx.accounts;
+    accounts.batch_storage.authority = new_authority;
+    accounts.last_finalized.authority = new_authority;
+    accounts.bridge_vault.authority = new_authority;
+    accounts.withdrawal_roots.authority = new_authority;
+    accounts.withdrawals.authority = new_authority;
+    accounts.genesis_state.authority = new_authority;
+    accounts.withdrawal_limit.authority = new_authority;
+    accounts.config.authority = new_authority;
+
+    msg!(
+        "Authority transferred from {} to {}",
+        accounts.authority.key(),
+        new_authority
+    );
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct TransferAuthority<'info> {
+    #[account(
+        mut,
+        seeds = [b"batch_storage"],
+        bump,
+        has_one = authority,
+    )]
+    pub batch_storage: Account<'info, BatchStorage>,
+    #[account(
+        mut,
+        seeds = [b"last_finalized_batch_index"],
+        bump,
+        has_one = authority,
+    )]
+    pub last_finalized: Account<'info, LastFinalizedBatchIndex>,
+    #[account(
+        mut,
+        seeds = [b"bridge_vault"],
+        bump,
+        has_one = authority,
+    )]
+    pub bridge_vault: Account<'info, BridgeVault>,
+    #[account(
+        mut,
+        seeds = [b"finalized_withdrawal_roots"],
+        bump,
+        has_one = authority,
+    )]
+    pub withdrawal_roots: Account<'info, FinalizedWithdrawalRoots>,
+    #[account(
+        mut,
+        seeds = [b"finalized_withdrawals"],
+        bump,
+        has_one = authority,
+    )]
+    pub withdrawals: Account<'info, FinalizedWithdrawals>,
+    #[account(
+        mut,
+        seeds = [b"genesis_state"],
+        bump,
+        has_one = authority,
+    )]
+    pub genesis_state: Account<'info, GenesisState>,
+    #[account(
+        mut,
+        seeds = [b"withdrawal_limit"],
+        bump,
+        has_one = authority,
+    )]
+    pub withdrawal_limit: Account<'info, WithdrawalLimit>,
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump,
+        has_one = authority,
+    )]
+    pub config: Account<'info, Config>,
+    pub authority: Signer<'info>,
+}
+
+/// Impl of set sequencer, the only account allowed to commit batches.
+pub fn set_sequencer(ctx: Context<SetSequencer>, new_sequencer: Pubkey) -> Result<()> {
+    if new_sequencer == Pubkey::default() {
+        return Err(Error::from(biz_error::ErrorCode::InvalidAuthority));
+    }
+
+    let config = &mut ctx.accounts.config;
+    msg!(
+        "Sequencer changed from {} to {}",
+        config.sequencer,
+        new_sequencer
+    );
+    config.sequencer = new_sequencer;
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct SetSequencer<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump,
+        has_one = authority,
+    )]
+    pub config: Account<'info, Config>,
+    pub authority: Signer<'info>,
+}
+
+/// Impl of set withdrawal addresses hash. The batches committed but not finalized yet
+/// were executed with the previous addresses and no longer prove, change it once they
+/// are finalized and before the sequencer commits a batch with the new addresses.
+pub fn set_withdrawal_addresses_hash(
+    ctx: Context<SetWithdrawalAddressesHash>,
+    withdrawal_addresses_hash: [u8; 32],
+) -> Result<()> {
+    let config = &mut ctx.accounts.config;
+    msg!(
+        "Withdrawal addresses hash changed from {:?} to {:?}",
+        config.withdrawal_addresses_hash,
+        withdrawal_addresses_hash
+    );
+    config.withdrawal_addresses_hash = withdrawal_addresses_hash;
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct SetWithdrawalAddressesHash<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump,
+        has_one = authority,
+    )]
+    pub config: Account<'info, Config>,
+    pub authority: Signer<'info>,
+}
+
+/// Impl of set withdrawal limit, at most `max_amount` lamports leave the bridge vault
+/// per `window_secs`. The current window keeps what it has withdrawn so far.
+pub fn set_withdrawal_limit(
+    ctx: Context<SetWithdrawalLimit>,
+    max_amount: u64,
+    window_secs: i64,
+) -> Result<()> {
+    if window_secs <= 0 {
+        return Err(Error::from(biz_error::ErrorCode::InvalidWithdrawalLimit));
+    }
+
+    let withdrawal_limit = &mut ctx.accounts.withdrawal_limit;
+    withdrawal_limit.max_amount = max_amount;
+    withdrawal_limit.window_secs = window_secs;
+    msg!(
+        "Withdrawal limit set to {} lamports per {} seconds",
+        max_amount,
+        window_secs
+    );
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct SetWithdrawalLimit<'info> {
+    #[account(
+        mut,
+        seeds = [b"withdrawal_limit"],
+        bump,
+        has_one = authority,
+    )]
+    pub withdrawal_limit: Account<'info, WithdrawalLimit>,
+    pub authority: Signer<'info>,
+}
+
+/// Impl of set finalized index, an emergency lever when a batch was finalized that
+/// should not have been, e.g. by a misconfigured vkey.
+///
+/// The index can only move back, the rolled back batches must be proven again.
+pub fn set_finalized_index(ctx: Context<SetFinalizedIndex>, index: u64) -> Result<()> {
+    let accounts = ctx.accounts;
+    let previous_batch_index = roll_back_finalized(
+        &accounts.batch_storage,
+        &mut accounts.last_finalized,
+        &mut accounts.withdrawal_roots,
+        index,
+    )?;
+
+    msg!(
+        "Finalized batch index rolled back from {} to {}",
+        previous_batch_index,
+        index
+    );
+    emit!(FinalizedIndexRolledBackEvent {
+        authority: accounts.authority.key(),
+        previous_batch_index,
+        batch_index: index,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    Ok(())
+}
+
+// Move `last_finalized` back to `index` and unfinalize the withdrawal roots of the
+// rolled back batches, unless a batch still finalized shares the root. A root pruned
+// by `prune_finalized` is already not finalized, the account never grows. Returns the
+// previous index.
+fn roll_back_finalized(
+    batch_storage: &BatchStorage,
+    last_finalized: &mut LastFinalizedBatchIndex,
+    withdrawal_roots: &mut FinalizedWithdrawalRoots,
+    index: u64,
+) -> Result<u64> {
+    let previous_batch_index = last_finalized.batch_index;
+    if index >= previous_batch_index {
+        return Err(Error::from(
+            biz_error::ErrorCode::FinalizedIndexCannotAdvance,
+        ));
+    }
+
+    for batch in batch_storage
+        .batches
+        .iter()
+        .filter(|b| index < b.batch_index && b.batch_index <= previous_batch_index)
+    {
+        let still_finalized = batch_storage
+            .batches
+            .iter()
+            .any(|b| b.batch_index <= index && b.withdrawal_root == batch.withdrawal_root);
+        if !still_finalized && withdrawal_roots.get_finalized(batch.withdrawal_root) {
+            withdrawal_roots.set_finalized(batch.withdrawal_root, false);
+        }
+    }
+
+    last_finalized.batch_index = index;
+    Ok(previous_batch_index)
+}
+
+#[derive(Accounts)]
+pub struct SetFinalizedIndex<'info> {
+    #[account(
+        seeds = [b"batch_storage"],
+        bump,
+    )]
+    pub batch_storage: Account<'info, BatchStorage>,
+    #[account(
+        mut,
+        seeds = [b"last_finalized_batch_index"],
+        bump,
+        has_one = authority,
+    )]
+    pub last_finalized: Account<'info, LastFinalizedBatchIndex>,
+    #[account(
+        mut,
+        seeds = [b"finalized_withdrawal_roots"],
+        bump,
+        has_one = authority,
+    )]
+    pub withdrawal_roots: Account<'info, FinalizedWithdrawalRoots>,
+    pub authority: Signer<'info>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::roll_back_finalized;
+    use crate::biz_error::ErrorCode;
+    use crate::bridge::{FinalizedWithdrawalRoots, KEEP_FINALIZED_ROOTS};
+    use crate::state::{BatchData, BatchStorage, LastFinalizedBatchIndex};
+    use anchor_lang::error::Error;
+    use anchor_lang::prelude::Pubkey;
+
+    // Batches 1..=4 with withdrawal roots 1, 2, 2, 3, batch 3 has no new withdrawals
+    fn finalized_batches() -> (
+        BatchStorage,
+        LastFinalizedBatchIndex,
+        FinalizedWithdrawalRoots,
+    ) {
+        let batches = [(1, 1u8), (2, 2), (3, 2), (4, 3)]
+            .iter()
+            .map(|&(batch_index, root)| BatchData {
+                batch_index,
+                start_block_num: batch_index,
+                end_block_num: batch_index,
+                batch_hash: [0; 32],
+                prev_state_root: [0; 32],
+                post_state_root: [0; 32],
+                withdrawal_root: [root; 32],
+                withdrawal_count: 0,
+                next_forced_index: 0,
+                forced_transactions_hash: [0; 32],
+            })
+            .collect();
+        let mut withdrawal_roots = FinalizedWithdrawalRoots {
+            authority: Pubkey::default(),
+            withdrawal_roots: vec![],
+        };
+        for root in 1..=3u8 {
+            withdrawal_roots.set_finalized([root; 32], true);
+        }
+        (
+            BatchStorage {
+                authority: Pubkey::default(),
+                batches,
+            },
+            LastFinalizedBatchIndex {
+                authority: Pubkey::default(),
+                batch_index: 4,
+            },
+            withdrawal_roots,
+        )
+    }
+
+    #[test]
+    fn test_roll_back_finalized() {
+        let (batch_storage, mut last_finalized, mut withdrawal_roots) = finalized_batches();
+
+        let previous = roll_back_finalized(
+            &batch_storage,
+            &mut last_finalized,
+            &mut withdrawal_roots,
+            2,
+        )
+        .unwrap();
+        assert_eq!(previous, 4);
+        assert_eq!(last_finalized.batch_index, 2);
+        // Batch 4's root is unfinalized, batch 3's root is batch 2's and stays finalized
+        assert!(!withdrawal_roots.get_finalized([3; 32]));
+        assert!(withdrawal_roots.get_finalized([2; 32]));
+        assert!(withdrawal_roots.get_finalized([1; 32]));
+    }
+
+    #[test]
+    fn test_roll_back_past_pruned_roots() {
+        let batches = (1..=40u8)
+            .map(|root| BatchData {
+                batch_index: root as u64,
+                start_block_num: root as u64,
+                end_block_num: root as u64,
+                batch_hash: [0; 32],
+                prev_state_root: [0; 32],
+                post_state_root: [0; 32],
+                withdrawal_root: [root; 32],
+                withdrawal_count: 0,
+                next_forced_index: 0,
+                forced_transactions_hash: [0; 32],
+            })
+            .collect();
+        let batch_storage = BatchStorage {
+            authority: Pubkey::default(),
+            batches,
+        };
+        let mut last_finalized = LastFinalizedBatchIndex {
+            authority: Pubkey::default(),
+            batch_index: 40,
+        };
+        let mut withdrawal_roots = FinalizedWithdrawalRoots {
+            authority: Pubkey::default(),
+            withdrawal_roots: vec![],
+        };
+        for root in 1..=40u8 {
+            withdrawal_roots.set_finalized([root; 32], true);
+        }
+        withdrawal_roots.prune(KEEP_FINALIZED_ROOTS);
+        let len = withdrawal_roots.withdrawal_roots.len();
+
+        roll_back_finalized(
+            &batch_storage,
+            &mut last_finalized,
+            &mut withdrawal_roots,
+            5,
+        )
+        .unwrap();
+        assert_eq!(last_finalized.batch_index, 5);
+        // The kept roots are unfinalized in place, the pruned ones are not added back
+        assert_eq!(withdrawal_roots.withdrawal_roots.len(), len);
+        assert!(withdrawal_roots
+            .withdrawal_roots
+            .iter()
+            .all(|(_, finalized)| !finalized));
+    }
+
+    #[test]
+    fn test_finalized_index_cannot_advance() {
+        let (batch_storage, mut last_finalized, mut withdrawal_roots) = finalized_batches();
+
+        for index in [4, 5] {
+            let result = roll_back_finalized(
+                &batch_storage,
+                &mut last_finalized,
+                &mut withdrawal_roots,
+                index,
+            );
+            assert_eq!(
+                result.unwrap_err(),
+                Error::from(ErrorCode::FinalizedIndexCannotAdvance)
+            );
+        }
+        assert_eq!(last_finalized.batch_index, 4);
+        assert!(withdrawal_roots.get_finalized([3; 32]));
+    }
+}
@@ -17,4 +17,50 @@ pub enum ErrorCode {
     WithdrawalRootNotFinalized,
     #[msg("invalid withdrawal inclusion proof")]
     InvalidWithdrawalInclusionProof,
+    #[msg("Batch prev_state_root does not match the genesis state root")]
+    InvalidGenesisStateRoot,
+    #[msg("Invalid authority")]
+    InvalidAuthority,
+    #[msg("Signer is not the sequencer")]
+    UnauthorizedSequencer,
+    #[msg("Withdrawal limit of the current window exceeded")]
+    WithdrawalLimitExceeded,
+    #[msg("Invalid withdrawal limit")]
+    InvalidWithdrawalLimit,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+    #[msg("Forced transaction is empty")]
+    EmptyForcedTransaction,
+    #[msg("Proof chunk offset does not match the uploaded length")]
+    InvalidProofChunkOffset,
+    #[msg("Proof exceeds the max proof size")]
+    ProofTooLarge,
+    #[msg("Reveal window of the batch is closed")]
+    RevealWindowClosed,
+    #[msg("Revealed blocks do not match the committed batch hash")]
+    BatchHashMismatch,
+    #[msg("Batch start_block_num is after its end_block_num")]
+    InvalidBlockRange,
+    #[msg("Batch block range overlaps the previous batch")]
+    BlockRangeOverlap,
+    #[msg("Batch block range leaves a gap after the previous batch")]
+    BlockRangeGap,
+    #[msg("Finalized batch index can only be rolled back")]
+    FinalizedIndexCannotAdvance,
+    #[msg("Batch prev_state_root does not match the previous batch's post_state_root")]
+    StateRootMismatch,
+    #[msg("Withdrawal already claimed")]
+    WithdrawalAlreadyClaimed,
+    #[msg("Batch post_state_root is the zero placeholder")]
+    ZeroPostStateRoot,
+    #[msg("Blocks of the hash-only batch are not revealed and its reveal window is open")]
+    BatchNotRevealed,
+    #[msg("A forced transaction is past its deadline and not committed")]
+    ForcedTransactionOverdue,
+    #[msg("The same forced transaction is already pending")]
+    ForcedTransactionAlreadyPending,
+    #[msg("The forced transaction queue is full")]
+    ForcedTransactionQueueFull,
+    #[msg("Batch forced transactions are not the queued ones after the previous batch's")]
+    InvalidForcedTransactionRange,
 }
@@ -1,9 +1,12 @@
 #![allow(unexpected_cfgs)]
 
 use crate::biz_error;
-use crate::util::verify_merkle_proof;
+use crate::state::Config;
+use crate::util::{hash_nested_vector, merkle_hash, verify_merkle_proof};
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program::hash::hash;
+// The derives of enums refer to `borsh`, pick anchor's over the crate dependency
+use anchor_lang::prelude::borsh;
 use anchor_lang::system_program;
 
 /*´:°•.°+.*•´.*:˚.°*.˚•´.°:°•.°•.*•´.*:˚.°*.˚•´.°:°•.°+.*•´.*:*/
@@ -22,6 +25,20 @@ pub struct DepositEvent {
     pub timestamp: i64,
 }
 
+#[event]
+pub struct ForcedTransactionEvent {
+    /// Index of the transaction in the forced transaction queue
+    pub index: u64,
+    /// The account that submitted the transaction on L1
+    pub sender: Pubkey,
+    /// The bincode serialized L2 transaction
+    pub transaction: Vec<u8>,
+    /// The sequencer must include the transaction in a block before this timestamp
+    pub deadline: i64,
+    /// Timestamp of the submission
+    pub timestamp: i64,
+}
+
 #[event]
 pub struct WithdrawalEvent {
     /// The account that initiated the withdrawal
@@ -38,6 +55,26 @@ pub struct WithdrawalEvent {
     pub timestamp: i64,
 }
 
+#[event]
+pub struct WithdrawalRejectedEvent {
+    /// The account that initiated the withdrawal
+    pub sender: Pubkey,
+    /// The amount requested in lamports
+    pub amount: u64,
+    /// Why the withdrawal was rejected
+    pub reason: WithdrawalRejectReason,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub enum WithdrawalRejectReason {
+    /// The withdrawal isn't included in the withdrawal root
+    InvalidProof,
+    /// The sender's bridge balance is lower than the amount
+    InsufficientBalance,
+    /// The withdrawal was already claimed
+    AlreadyClaimed,
+}
+
 /*´:°•.°+.*•´.*:˚.°*.˚•´.°:°•.°•.*•´.*:˚.°*.˚•´.°:°•.°+.*•´.*:*/
 /*                         BRIDGE IMPL                        */
 /*.•°:°.´+˚.*°.˚:*.´•*.+°.•°:´*.´•*.•°.•°:°.´:•˚°.*°.˚:*.´+°.•*/
@@ -62,7 +99,9 @@ pub fn deposit(ctx: Context<Deposit>, amount: u64) -> Result<()> {
 
     // Update balance
     let current_balance = bridge_vault.get_balance(from.key);
-    let new_balance = current_balance + amount;
+    let new_balance = current_balance
+        .checked_add(amount)
+        .ok_or(Error::from(biz_error::ErrorCode::ArithmeticOverflow))?;
     bridge_vault.set_balance(*from.key, new_balance);
 
     // Get current timestamp
@@ -83,6 +122,137 @@ pub fn deposit(ctx: Context<Deposit>, amount: u64) -> Result<()> {
     Ok(())
 }
 
+// Window the sequencer has to include a forced transaction, one hour
+pub const FORCE_INCLUSION_WINDOW_SECS: i64 = 60 * 60;
+
+// Most forced transactions pending at once, the queue account has a fixed size
+pub const MAX_PENDING_FORCED_TRANSACTIONS: usize = 64;
+
+// Fee of a forced transaction paid to the sequencer, 0.001 SOL, so filling the queue
+// isn't free
+pub const FORCED_TRANSACTION_FEE: u64 = 1_000_000;
+
+/// Impl of force include, the censorship escape hatch.
+///
+/// A transaction the sequencer refuses over rpc is submitted here instead, the sender
+/// pays `FORCED_TRANSACTION_FEE` to the sequencer. Its hash is queued in
+/// `ForcedTransactions` and the sequencer picks the transaction up from the event like
+/// a deposit. It must commit a batch executing it before the deadline: once it's
+/// overdue, a batch leaving it out can't be committed. The zkVM program commits the
+/// hashes of the forced transactions a batch executed, so the proof of a batch that
+/// claims one it didn't execute doesn't verify.
+pub fn force_include(ctx: Context<ForceInclude>, transaction: Vec<u8>) -> Result<()> {
+    if transaction.is_empty() {
+        return Err(Error::from(biz_error::ErrorCode::EmptyForcedTransaction));
+    }
+
+    let clock = Clock::get()?;
+    let timestamp = clock.unix_timestamp;
+    let deadline = timestamp
+        .checked_add(FORCE_INCLUSION_WINDOW_SECS)
+        .ok_or(Error::from(biz_error::ErrorCode::ArithmeticOverflow))?;
+
+    // The queue is created by the first forced transaction, the sender pays its rent
+    let sender = ctx.accounts.sender.to_account_info();
+    let forced_transactions = &ctx.accounts.forced_transactions;
+    let system_program = ctx.accounts.system_program.to_account_info();
+    if forced_transactions.owner != &crate::ID {
+        create_pda(
+            &sender,
+            forced_transactions,
+            &system_program,
+            &[b"forced_transactions", &[ctx.bumps.forced_transactions]],
+            8 + ForcedTransactions::INIT_SPACE,
+        )?;
+    }
+    let mut queue = load_forced_transactions(forced_transactions)?.unwrap_or_default();
+    let index = queue.push(hash(&transaction).to_bytes(), deadline)?;
+    save_forced_transactions(forced_transactions, &queue)?;
+
+    system_program::transfer(
+        CpiContext::new(
+            system_program,
+            system_program::Transfer {
+                from: sender.clone(),
+                to: ctx.accounts.sequencer.to_account_info(),
+            },
+        ),
+        FORCED_TRANSACTION_FEE,
+    )?;
+
+    msg!(
+        "forced transaction {} from account: {:?}, deadline: {:?}",
+        index,
+        sender.key,
+        deadline
+    );
+    emit!(ForcedTransactionEvent {
+        index,
+        sender: *sender.key,
+        transaction,
+        deadline,
+        timestamp,
+    });
+
+    Ok(())
+}
+
+/// The forced transaction queue, `None` before the first forced transaction created it.
+pub fn load_forced_transactions(account: &AccountInfo) -> Result<Option<ForcedTransactions>> {
+    if account.data_is_empty() {
+        return Ok(None);
+    }
+    if account.owner != &crate::ID {
+        return Err(Error::from(ErrorCode::AccountOwnedByWrongProgram));
+    }
+    let queue = ForcedTransactions::try_deserialize(&mut &account.try_borrow_data()?[..])?;
+    Ok(Some(queue))
+}
+
+// Write `queue` back to its account, sized for `MAX_PENDING_FORCED_TRANSACTIONS` so the
+// bytes after a shorter queue are left over and ignored
+fn save_forced_transactions(account: &AccountInfo, queue: &ForcedTransactions) -> Result<()> {
+    queue.try_serialize(&mut &mut account.try_borrow_mut_data()?[..])
+}
+
+/// Hash of the forced transactions `start..end` a committed batch executes, see
+/// `ForcedTransactions::hash_range`. Fails unless they are all queued, or while one
+/// after them is past its deadline at `now`, so a batch leaving it out can't be
+/// committed.
+pub fn forced_transactions_hash(
+    account: &AccountInfo,
+    start: u64,
+    end: u64,
+    now: i64,
+) -> Result<[u8; 32]> {
+    let queue = load_forced_transactions(account)?.unwrap_or_default();
+    let forced_hash = queue.hash_range(start, end).ok_or(Error::from(
+        biz_error::ErrorCode::InvalidForcedTransactionRange,
+    ))?;
+    if let Some(overdue) = queue.first_overdue(end, now) {
+        msg!(
+            "Forced transaction {} is past its deadline {}",
+            overdue.index,
+            overdue.deadline
+        );
+        return Err(Error::from(biz_error::ErrorCode::ForcedTransactionOverdue));
+    }
+    Ok(forced_hash)
+}
+
+/// Dequeue the forced transactions before index `end`, executed by finalized batches.
+pub fn dequeue_forced_transactions(account: &AccountInfo, end: u64) -> Result<()> {
+    let Some(mut queue) = load_forced_transactions(account)? else {
+        return Ok(());
+    };
+    let dequeued = queue.dequeue(end);
+    if dequeued > 0 {
+        save_forced_transactions(account, &queue)?;
+        msg!("Dequeued {} executed forced transactions", dequeued);
+    }
+    Ok(())
+}
+
 /// Impl of withdrawal for native token (sol).
 pub fn withdrawal(ctx: Context<Withdrawal>, withdrawal: WithdrawalData) -> Result<()> {
     let from = &ctx.accounts.sender;
@@ -102,35 +272,80 @@ pub fn withdrawal(ctx: Context<Withdrawal>, withdrawal: WithdrawalData) -> Resul
     }
 
     // Verify that the hash of this withdrawal was stored in the  withdrawal_root.
+    // The leaf is `share::state::withdrawal_leaf_bytes`, the sequencer's tree uses the
+    // same encoding.
     let mut withdrawal_data = from.key.to_bytes().to_vec();
     withdrawal_data.extend_from_slice(&to.key.to_bytes());
     withdrawal_data.extend_from_slice(&amount.to_be_bytes());
     withdrawal_data.extend_from_slice(&index.to_be_bytes());
-    let withdrawal_data_hash = hash(&withdrawal_data).to_bytes();
+    let withdrawal_data_hash = merkle_hash(&[&withdrawal_data]);
     if !verify_merkle_proof(withdrawal_data_hash, withdrawal_proof, index, withdraw_root) {
+        emit!(WithdrawalRejectedEvent {
+            sender: *from.key,
+            amount,
+            reason: WithdrawalRejectReason::InvalidProof,
+        });
         return Err(Error::from(
             biz_error::ErrorCode::InvalidWithdrawalInclusionProof,
         ));
     }
 
+    // Check that this withdrawal has not already been claimed, by its marker or, when
+    // claimed before the markers, in the finalized withdrawals.
+    let claimed_withdrawal = &ctx.accounts.claimed_withdrawal;
+    if claimed_withdrawal.owner == &crate::ID
+        || ctx.accounts.withdrawals.get_finalized(withdrawal_data_hash)
+    {
+        emit!(WithdrawalRejectedEvent {
+            sender: *from.key,
+            amount,
+            reason: WithdrawalRejectReason::AlreadyClaimed,
+        });
+        return Err(Error::from(biz_error::ErrorCode::WithdrawalAlreadyClaimed));
+    }
+
     // Check balance available.
     let current_amount = bridge_vault.get_balance(from.key);
-    if current_amount < amount {
+    let Some(new_balance) = current_amount.checked_sub(amount) else {
+        emit!(WithdrawalRejectedEvent {
+            sender: *from.key,
+            amount,
+            reason: WithdrawalRejectReason::InsufficientBalance,
+        });
         return Err(Error::from(biz_error::ErrorCode::UserBalanceInsufficent));
-    }
+    };
 
-    // Mark the withdrawal as finalized so it can't be replayed.
-    let withdrawals = &mut ctx.accounts.withdrawals;
-    withdrawals.set_finalized(withdrawal_data_hash, true);
+    // Circuit breaker, cap the amount withdrawn per window.
+    let clock = Clock::get()?;
+    ctx.accounts
+        .withdrawal_limit
+        .consume(amount, clock.unix_timestamp)?;
+
+    // Mark the withdrawal as claimed so it can't be replayed, the sender pays the rent
+    // of its marker.
+    let space = 8 + ClaimedWithdrawal::INIT_SPACE;
+    create_pda(
+        &from.to_account_info(),
+        claimed_withdrawal,
+        &ctx.accounts.system_program.to_account_info(),
+        &[
+            b"claimed_withdrawal",
+            &index.to_le_bytes(),
+            &[ctx.bumps.claimed_withdrawal],
+        ],
+        space,
+    )?;
+    ClaimedWithdrawal {
+        withdrawal_hash: withdrawal_data_hash,
+    }
+    .try_serialize(&mut &mut claimed_withdrawal.try_borrow_mut_data()?[..])?;
 
     // Account balance operations
-    let new_balance = current_amount - amount;
     bridge_vault.set_balance(*from.key, new_balance);
     bridge_vault.sub_lamports(amount)?;
     to.add_lamports(amount)?;
 
     // Get current timestamp
-    let clock = Clock::get()?;
     let timestamp = clock.unix_timestamp;
 
     // Emit withdrawal event
@@ -146,6 +361,155 @@ pub fn withdrawal(ctx: Context<Withdrawal>, withdrawal: WithdrawalData) -> Resul
     Ok(())
 }
 
+// Create the program account of `space` bytes at the PDA `account` of `seeds`, its rent
+// paid by `payer`. Lamports sent to the address beforehand don't block it, as with
+// anchor's `init`.
+fn create_pda<'info>(
+    payer: &AccountInfo<'info>,
+    account: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    seeds: &[&[u8]],
+    space: usize,
+) -> Result<()> {
+    let rent = Rent::get()?.minimum_balance(space);
+    let signer_seeds = &[seeds];
+    if account.lamports() == 0 {
+        return system_program::create_account(
+            CpiContext::new_with_signer(
+                system_program.clone(),
+                system_program::CreateAccount {
+                    from: payer.clone(),
+                    to: account.clone(),
+                },
+                signer_seeds,
+            ),
+            rent,
+            space as u64,
+            &crate::ID,
+        );
+    }
+
+    let shortfall = rent.saturating_sub(account.lamports());
+    if shortfall > 0 {
+        system_program::transfer(
+            CpiContext::new(
+                system_program.clone(),
+                system_program::Transfer {
+                    from: payer.clone(),
+                    to: account.clone(),
+                },
+            ),
+            shortfall,
+        )?;
+    }
+    system_program::allocate(
+        CpiContext::new_with_signer(
+            system_program.clone(),
+            system_program::Allocate {
+                account_to_allocate: account.clone(),
+            },
+            signer_seeds,
+        ),
+        space as u64,
+    )?;
+    system_program::assign(
+        CpiContext::new_with_signer(
+            system_program.clone(),
+            system_program::Assign {
+                account_to_assign: account.clone(),
+            },
+            signer_seeds,
+        ),
+        &crate::ID,
+    )
+}
+
+/// Impl of get withdrawal root finalized, false for a root never finalized.
+pub fn get_withdrawal_root_finalized(
+    ctx: Context<GetWithdrawalRoots>,
+    withdrawal_root: [u8; 32],
+) -> Result<bool> {
+    Ok(ctx.accounts.withdrawal_roots.get_finalized(withdrawal_root))
+}
+
+// Return data is capped at 1024 bytes: 4 bytes of vec length + 31 roots
+pub const MAX_LISTED_ROOTS: usize = 31;
+
+// Finalized withdrawal roots kept by `prune_finalized`. Each root covers every
+// withdrawal before it, so a withdrawal is always claimable against the latest one.
+pub const KEEP_FINALIZED_ROOTS: usize = MAX_LISTED_ROOTS;
+
+/// Impl of prune finalized, permissionless. Drops the finalized withdrawal roots but
+/// the latest `KEEP_FINALIZED_ROOTS`, the account shrinks to its entries and the rent
+/// freed goes to the caller. Claimed withdrawals need no pruning, each has its own
+/// `ClaimedWithdrawal` marker.
+pub fn prune_finalized(ctx: Context<PruneFinalized>) -> Result<()> {
+    let caller = ctx.accounts.caller.to_account_info();
+
+    let withdrawal_roots = &mut ctx.accounts.withdrawal_roots;
+    let pruned_roots = withdrawal_roots.prune(KEEP_FINALIZED_ROOTS);
+    let len = withdrawal_roots.withdrawal_roots.len();
+    shrink_account(
+        &withdrawal_roots.to_account_info(),
+        &caller,
+        8 + 32 + 4 + len.saturating_mul(40),
+    )?;
+
+    msg!("Pruned {} withdrawal roots", pruned_roots);
+    Ok(())
+}
+
+// Shrink a program account to `space` bytes, moving the lamports above its
+// rent-exempt minimum to `receiver`
+fn shrink_account(account: &AccountInfo, receiver: &AccountInfo, space: usize) -> Result<()> {
+    if space >= account.data_len() {
+        return Ok(());
+    }
+    account.realloc(space, false)?;
+    let excess = account
+        .lamports()
+        .saturating_sub(Rent::get()?.minimum_balance(space));
+    account.sub_lamports(excess)?;
+    receiver.add_lamports(excess)?;
+    Ok(())
+}
+
+/// Impl of list finalized roots, the latest `MAX_LISTED_ROOTS` ones so the result
+/// fits in the return data.
+pub fn list_finalized_roots(ctx: Context<GetWithdrawalRoots>) -> Result<Vec<[u8; 32]>> {
+    let finalized: Vec<[u8; 32]> = ctx
+        .accounts
+        .withdrawal_roots
+        .withdrawal_roots
+        .iter()
+        .filter(|(_, finalized)| *finalized)
+        .map(|(root, _)| *root)
+        .collect();
+    let skip = finalized.len().saturating_sub(MAX_LISTED_ROOTS);
+    Ok(finalized[skip..].to_vec())
+}
+
+/// Impl of get vault solvency: the sum of the balances the bridge vault tracks and the
+/// lamports it actually holds above its rent-exempt reserve, they drift apart only
+/// through a bug. The bridge is solvent while `actual_lamports >= tracked_total`.
+pub fn get_vault_solvency(ctx: Context<GetVaultSolvency>) -> Result<VaultSolvency> {
+    let bridge_vault = &ctx.accounts.bridge_vault;
+    let account_info = bridge_vault.to_account_info();
+    let rent_exempt_reserve = Rent::get()?.minimum_balance(account_info.data_len());
+    Ok(VaultSolvency {
+        tracked_total: bridge_vault.tracked_total()?,
+        actual_lamports: account_info.lamports().saturating_sub(rent_exempt_reserve),
+    })
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct VaultSolvency {
+    /// Sum of the balances tracked by the bridge vault
+    pub tracked_total: u64,
+    /// Lamports held by the bridge vault PDA above its rent-exempt reserve
+    pub actual_lamports: u64,
+}
+
 #[account]
 pub struct BridgeVault {
     pub authority: Pubkey,
@@ -168,10 +532,18 @@ impl BridgeVault {
             self.balances.push((pubkey, amount));
         }
     }
+
+    /// Sum of the tracked balances, what the vault owes its depositors.
+    pub fn tracked_total(&self) -> Result<u64> {
+        self.balances
+            .iter()
+            .try_fold(0u64, |total, (_, balance)| total.checked_add(*balance))
+            .ok_or(Error::from(biz_error::ErrorCode::ArithmeticOverflow))
+    }
 }
 
 impl Space for BridgeVault {
-    const INIT_SPACE: usize = 32 + 4 + 0; // authority + vec length + 0 entries
+    const INIT_SPACE: usize = 32 + 4; // authority + vec length (0 entries)
 }
 
 #[derive(Accounts)]
@@ -190,6 +562,31 @@ pub struct Deposit<'info> {
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+pub struct ForceInclude<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump,
+    )]
+    pub config: Account<'info, Config>,
+    /// CHECK: The sequencer of `config`, paid the fee
+    #[account(
+        mut,
+        address = config.sequencer,
+    )]
+    pub sequencer: AccountInfo<'info>,
+    /// CHECK: The `ForcedTransactions` queue, created by the first forced transaction
+    #[account(
+        mut,
+        seeds = [b"forced_transactions"],
+        bump,
+    )]
+    pub forced_transactions: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
+}
+
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
 pub struct WithdrawalData {
     pub amount: u64,
@@ -199,6 +596,7 @@ pub struct WithdrawalData {
 }
 
 #[derive(Accounts)]
+#[instruction(withdrawal: WithdrawalData)]
 pub struct Withdrawal<'info> {
     #[account(mut)]
     pub sender: Signer<'info>,
@@ -219,15 +617,54 @@ pub struct Withdrawal<'info> {
     #[account(
         seeds = [b"finalized_withdrawals"],
         bump,
-        mut,
-        realloc = 8 + 32 + 4 + withdrawals.finalized_withdrawals.len().saturating_add(1).saturating_mul(40),
-        realloc::payer = sender,
-        realloc::zero = false,
     )]
     pub withdrawals: Account<'info, FinalizedWithdrawals>,
+    /// CHECK: The `ClaimedWithdrawal` marker of `withdrawal.index`, created by the claim
+    #[account(
+        mut,
+        seeds = [b"claimed_withdrawal".as_ref(), &withdrawal.index.to_le_bytes()],
+        bump,
+    )]
+    pub claimed_withdrawal: AccountInfo<'info>,
+    #[account(
+        seeds = [b"withdrawal_limit"],
+        bump,
+        mut
+    )]
+    pub withdrawal_limit: Account<'info, WithdrawalLimit>,
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+pub struct GetVaultSolvency<'info> {
+    #[account(
+        seeds = [b"bridge_vault"],
+        bump,
+    )]
+    pub bridge_vault: Account<'info, BridgeVault>,
+}
+
+#[derive(Accounts)]
+pub struct PruneFinalized<'info> {
+    #[account(mut)]
+    pub caller: Signer<'info>,
+    #[account(
+        seeds = [b"finalized_withdrawal_roots"],
+        bump,
+        mut,
+    )]
+    pub withdrawal_roots: Account<'info, FinalizedWithdrawalRoots>,
+}
+
+#[derive(Accounts)]
+pub struct GetWithdrawalRoots<'info> {
+    #[account(
+        seeds = [b"finalized_withdrawal_roots"],
+        bump,
+    )]
+    pub withdrawal_roots: Account<'info, FinalizedWithdrawalRoots>,
+}
+
 #[account]
 pub struct FinalizedWithdrawalRoots {
     pub authority: Pubkey,
@@ -254,12 +691,24 @@ impl FinalizedWithdrawalRoots {
             self.withdrawal_roots.push((withdrawal_root, finalized));
         }
     }
+
+    /// Drop the rolled back roots and the finalized ones but the latest `keep`, in
+    /// finalization order. Returns the number of roots dropped.
+    pub fn prune(&mut self, keep: usize) -> usize {
+        let before = self.withdrawal_roots.len();
+        self.withdrawal_roots.retain(|(_, finalized)| *finalized);
+        let skip = self.withdrawal_roots.len().saturating_sub(keep);
+        self.withdrawal_roots.drain(..skip);
+        before - self.withdrawal_roots.len()
+    }
 }
 
 impl Space for FinalizedWithdrawalRoots {
-    const INIT_SPACE: usize = 32 + 4 + 0; // authority + vec length + 0 entries
+    const INIT_SPACE: usize = 32 + 4; // authority + vec length (0 entries)
 }
 
+/// Withdrawals claimed before the `ClaimedWithdrawal` markers, by withdrawal hash. No
+/// longer written, kept in its original layout so a replay of those is rejected.
 #[account]
 pub struct FinalizedWithdrawals {
     pub authority: Pubkey,
@@ -274,21 +723,274 @@ impl FinalizedWithdrawals {
             .map(|(_, finalized)| *finalized)
             .unwrap_or(false)
     }
+}
 
-    pub fn set_finalized(&mut self, withdrawal_data_hash: [u8; 32], finalized: bool) {
-        if let Some(entry) = self
-            .finalized_withdrawals
-            .iter_mut()
-            .find(|(key, _)| key == &withdrawal_data_hash)
+impl Space for FinalizedWithdrawals {
+    const INIT_SPACE: usize = 32 + 4; // authority + vec length (0 entries)
+}
+
+/// Hashes of the transactions forced through L1 and not executed by a finalized batch
+/// yet, in submission order.
+#[account]
+#[derive(Default)]
+pub struct ForcedTransactions {
+    pub next_index: u64,
+    pub pending: Vec<ForcedTransactionEntry>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ForcedTransactionEntry {
+    pub index: u64,
+    /// The sha256 of the bincode serialized L2 transaction
+    pub transaction_hash: [u8; 32],
+    pub deadline: i64,
+}
+
+impl ForcedTransactions {
+    /// Queue the transaction hashing to `transaction_hash`, returns its index.
+    pub fn push(&mut self, transaction_hash: [u8; 32], deadline: i64) -> Result<u64> {
+        if self.pending.len() >= MAX_PENDING_FORCED_TRANSACTIONS {
+            return Err(Error::from(
+                biz_error::ErrorCode::ForcedTransactionQueueFull,
+            ));
+        }
+        if self
+            .pending
+            .iter()
+            .any(|forced| forced.transaction_hash == transaction_hash)
         {
-            entry.1 = finalized;
-        } else {
-            self.finalized_withdrawals
-                .push((withdrawal_data_hash, finalized));
+            return Err(Error::from(
+                biz_error::ErrorCode::ForcedTransactionAlreadyPending,
+            ));
         }
+        let index = self.next_index;
+        self.next_index += 1;
+        self.pending.push(ForcedTransactionEntry {
+            index,
+            transaction_hash,
+            deadline,
+        });
+        Ok(index)
+    }
+
+    /// `hash_nested_vector` of the hashes of the transactions `start..end`, in queue
+    /// order, as `share::zkvm::forced_transactions_hash` computes it. `None` unless
+    /// they are all pending.
+    pub fn hash_range(&self, start: u64, end: u64) -> Option<[u8; 32]> {
+        let hashes: Vec<Vec<u8>> = self
+            .pending
+            .iter()
+            .filter(|forced| start <= forced.index && forced.index < end)
+            .map(|forced| forced.transaction_hash.to_vec())
+            .collect();
+        (hashes.len() as u64 == end.checked_sub(start)?).then(|| hash_nested_vector(&hashes))
+    }
+
+    /// The first transaction from index `start` on past its deadline at `now`.
+    pub fn first_overdue(&self, start: u64, now: i64) -> Option<&ForcedTransactionEntry> {
+        self.pending
+            .iter()
+            .find(|forced| forced.index >= start && forced.deadline < now)
+    }
+
+    /// Drop the transactions before index `end`, returns the number dropped.
+    pub fn dequeue(&mut self, end: u64) -> usize {
+        let pending = self.pending.len();
+        self.pending.retain(|forced| forced.index >= end);
+        pending - self.pending.len()
     }
 }
 
-impl Space for FinalizedWithdrawals {
-    const INIT_SPACE: usize = 32 + 4 + 0; // authority + vec length + 0 entries
+impl Space for ForcedTransactions {
+    // next index + vec length + the entries (index + transaction hash + deadline)
+    const INIT_SPACE: usize = 8 + 4 + MAX_PENDING_FORCED_TRANSACTIONS * (8 + 32 + 8);
+}
+
+/// Marker of a claimed withdrawal, the PDA of its index created by the claim. It keeps
+/// the bridge accounts bounded however many withdrawals stay unclaimed.
+#[account]
+pub struct ClaimedWithdrawal {
+    pub withdrawal_hash: [u8; 32],
+}
+
+impl Space for ClaimedWithdrawal {
+    const INIT_SPACE: usize = 32; // withdrawal hash
+}
+
+/// Global cap on the amount withdrawn from the bridge vault per window.
+#[account]
+pub struct WithdrawalLimit {
+    pub authority: Pubkey,
+    pub max_amount: u64,
+    pub window_secs: i64,
+    pub window_start: i64,
+    pub withdrawn: u64,
+}
+
+impl WithdrawalLimit {
+    /// Record `amount` withdrawn at `now`, starting a new window once the current one ends.
+    pub fn consume(&mut self, amount: u64, now: i64) -> Result<()> {
+        if now >= self.window_start.saturating_add(self.window_secs) {
+            self.window_start = now;
+            self.withdrawn = 0;
+        }
+
+        let withdrawn = self.withdrawn.saturating_add(amount);
+        if withdrawn > self.max_amount {
+            return Err(Error::from(biz_error::ErrorCode::WithdrawalLimitExceeded));
+        }
+        self.withdrawn = withdrawn;
+        Ok(())
+    }
+}
+
+impl Space for WithdrawalLimit {
+    const INIT_SPACE: usize = 32 + 8 + 8 + 8 + 8; // authority + max amount + window + window start + withdrawn
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        BridgeVault, FinalizedWithdrawalRoots, FinalizedWithdrawals, ForcedTransactions,
+        WithdrawalLimit, MAX_PENDING_FORCED_TRANSACTIONS,
+    };
+    use crate::util::hash_nested_vector;
+    use anchor_lang::prelude::Pubkey;
+    use anchor_lang::{AccountDeserialize, AccountSerialize, Space};
+
+    #[test]
+    fn test_forced_transactions() {
+        let mut queue = ForcedTransactions::default();
+        assert_eq!(queue.push([1; 32], 100).unwrap(), 0);
+        assert_eq!(queue.push([2; 32], 200).unwrap(), 1);
+        assert_eq!(queue.push([3; 32], 300).unwrap(), 2);
+        // The same transaction twice
+        assert!(queue.push([2; 32], 400).is_err());
+
+        // The transactions a batch executes hash in queue order
+        assert_eq!(queue.hash_range(0, 0), Some([0; 32]));
+        assert_eq!(
+            queue.hash_range(1, 3),
+            Some(hash_nested_vector(&vec![vec![2; 32], vec![3; 32]]))
+        );
+        // Not queued yet
+        assert!(queue.hash_range(2, 4).is_none());
+        assert!(queue.hash_range(3, 2).is_none());
+
+        // Only the ones the batch leaves out can be overdue
+        assert!(queue.first_overdue(0, 100).is_none());
+        assert_eq!(
+            queue.first_overdue(0, 250).map(|forced| forced.index),
+            Some(0)
+        );
+        assert_eq!(
+            queue.first_overdue(1, 250).map(|forced| forced.index),
+            Some(1)
+        );
+        assert!(queue.first_overdue(2, 250).is_none());
+
+        // Finalized, a dequeued transaction can't be claimed by a batch anymore
+        assert_eq!(queue.dequeue(2), 2);
+        assert_eq!(queue.dequeue(2), 0);
+        assert!(queue.hash_range(1, 3).is_none());
+        assert_eq!(
+            queue.hash_range(2, 3),
+            Some(hash_nested_vector(&vec![vec![3; 32]]))
+        );
+        // Indices keep counting after dequeues
+        assert_eq!(queue.push([1; 32], 400).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_forced_transaction_queue_full() {
+        let mut queue = ForcedTransactions::default();
+        for index in 0..MAX_PENDING_FORCED_TRANSACTIONS {
+            queue.push([index as u8; 32], 100).unwrap();
+        }
+        assert!(queue.push([255; 32], 100).is_err());
+
+        // The full queue fits its account
+        let mut data = Vec::new();
+        queue.try_serialize(&mut data).unwrap();
+        assert_eq!(data.len(), 8 + ForcedTransactions::INIT_SPACE);
+
+        // Room again once a batch executing them is finalized
+        queue.dequeue(1);
+        assert_eq!(
+            queue.push([255; 32], 100).unwrap(),
+            MAX_PENDING_FORCED_TRANSACTIONS as u64
+        );
+    }
+
+    #[test]
+    fn test_withdrawal_limit() {
+        let mut limit = WithdrawalLimit {
+            authority: Pubkey::default(),
+            max_amount: 1_000,
+            window_secs: 3_600,
+            window_start: 0,
+            withdrawn: 0,
+        };
+
+        assert!(limit.consume(600, 100).is_ok());
+        assert!(limit.consume(400, 200).is_ok());
+        // Over the cap within the window
+        assert!(limit.consume(1, 300).is_err());
+        assert_eq!(limit.withdrawn, 1_000);
+
+        // The cap resets after the window
+        assert!(limit.consume(1_000, 3_700).is_ok());
+        assert_eq!(limit.window_start, 3_700);
+        assert!(limit.consume(1_001, 7_300).is_err());
+    }
+
+    #[test]
+    fn test_prune_finalized() {
+        let mut roots = FinalizedWithdrawalRoots {
+            authority: Pubkey::default(),
+            withdrawal_roots: (1..=5).map(|root| ([root; 32], root != 2)).collect(),
+        };
+        // The rolled back root and the oldest finalized one go
+        assert_eq!(roots.prune(3), 2);
+        assert_eq!(
+            roots.withdrawal_roots,
+            vec![([3; 32], true), ([4; 32], true), ([5; 32], true)]
+        );
+        assert!(roots.get_finalized([5; 32]));
+        assert!(!roots.get_finalized([1; 32]));
+    }
+
+    #[test]
+    fn test_legacy_finalized_withdrawals() {
+        // An account written by the program before the claim markers
+        let legacy = FinalizedWithdrawals {
+            authority: Pubkey::new_unique(),
+            finalized_withdrawals: vec![([1; 32], true), ([2; 32], true)],
+        };
+        let mut data = Vec::new();
+        legacy.try_serialize(&mut data).unwrap();
+
+        let withdrawals = FinalizedWithdrawals::try_deserialize(&mut data.as_slice()).unwrap();
+        assert_eq!(withdrawals.authority, legacy.authority);
+        // Its claims are still rejected
+        assert!(withdrawals.get_finalized([2; 32]));
+        assert!(!withdrawals.get_finalized([3; 32]));
+    }
+
+    #[test]
+    fn test_tracked_total() {
+        let mut vault = BridgeVault {
+            authority: Pubkey::default(),
+            balances: vec![],
+        };
+        assert_eq!(vault.tracked_total().unwrap(), 0);
+
+        vault.set_balance(Pubkey::new_unique(), 1_000);
+        vault.set_balance(Pubkey::new_unique(), 2_000);
+        assert_eq!(vault.tracked_total().unwrap(), 3_000);
+
+        // Overflow is an error, not a wrapped total
+        vault.set_balance(Pubkey::new_unique(), u64::MAX);
+        assert!(vault.tracked_total().is_err());
+    }
 }
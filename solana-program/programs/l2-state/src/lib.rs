@@ -2,12 +2,14 @@
 
 use anchor_lang::prelude::*;
 
+mod admin;
 mod biz_error;
 mod bridge;
 mod state;
 mod util;
 mod verifier;
 
+use crate::admin::*;
 use crate::bridge::*;
 use crate::state::*;
 use crate::verifier::*;
@@ -27,12 +29,26 @@ pub mod l2_state {
     /// # Arguments
     ///
     /// * `ctx` - The context of accounts
+    /// * `genesis_state_root` - The state root of the l2 genesis state
+    /// * `l1_oracle` - The key relaying L1 deposits, proofs credit only its deposits
+    /// * `withdrawal_addresses_hash` - The hash of the addresses a transfer to which
+    ///   withdraws, `share::transaction::withdrawal_addresses_hash`
     ///
-    pub fn initialize(ctx: Context<Initialize>) -> Result<()> {
-        state::initialize(ctx)
+    pub fn initialize(
+        ctx: Context<Initialize>,
+        genesis_state_root: [u8; 32],
+        l1_oracle: Pubkey,
+        withdrawal_addresses_hash: [u8; 32],
+    ) -> Result<()> {
+        state::initialize(
+            ctx,
+            genesis_state_root,
+            l1_oracle,
+            withdrawal_addresses_hash,
+        )
     }
 
-    /// Commit batch, use solana network as DA.
+    /// Commit batch, use solana network as DA. Only the sequencer can commit.
     ///
     /// # Arguments
     ///
@@ -43,6 +59,37 @@ pub mod l2_state {
         state::commit_batch(ctx, batch_info)
     }
 
+    /// Commit only the hash of a batch, the blocks stay off chain until revealed with
+    /// `reveal_batch`. Only the sequencer can commit, once per batch index.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The context of accounts
+    /// * `batch_info` - The batch information and the hash of its blocks
+    ///
+    pub fn commit_batch_hash_only(
+        ctx: Context<CommitBatchHashOnly>,
+        batch_info: BatchHashInfo,
+    ) -> Result<()> {
+        state::commit_batch_hash_only(ctx, batch_info)
+    }
+
+    /// Reveal the blocks of a hash-only batch, within `REVEAL_WINDOW_SECS` of its commit.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The context of accounts
+    /// * `batch_index` - The index of the batch to reveal
+    /// * `blocks` - The blocks, hashing to the committed batch hash
+    ///
+    pub fn reveal_batch(
+        ctx: Context<RevealBatch>,
+        batch_index: u64,
+        blocks: Vec<Vec<u8>>,
+    ) -> Result<()> {
+        state::reveal_batch(ctx, batch_index, blocks)
+    }
+
     /// Prove that the state transition of the specified batch is valid.
     ///
     /// # Arguments
@@ -54,6 +101,49 @@ pub mod l2_state {
         verifier::prove_state(ctx, batch_proof)
     }
 
+    /// Open the buffer a proof too large for one transaction is uploaded to.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The context of accounts
+    /// * `batch_index` - The index of the batch the proof is for
+    ///
+    pub fn open_proof_buffer(ctx: Context<OpenProofBuffer>, batch_index: u64) -> Result<()> {
+        verifier::open_proof_buffer(ctx, batch_index)
+    }
+
+    /// Append a chunk of the proof to the proof buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The context of accounts
+    /// * `batch_index` - The index of the batch the proof is for
+    /// * `offset` - The offset of the chunk in the proof, 0 restarts the upload
+    /// * `chunk` - The bytes of the proof at `offset`
+    ///
+    pub fn append_proof_chunk(
+        ctx: Context<AppendProofChunk>,
+        batch_index: u64,
+        offset: u32,
+        chunk: Vec<u8>,
+    ) -> Result<()> {
+        verifier::append_proof_chunk(ctx, batch_index, offset, chunk)
+    }
+
+    /// Prove a batch with the proof assembled in the proof buffer, then close the buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The context of accounts
+    /// * `batch_index` - The index of the batch to prove
+    ///
+    pub fn prove_state_from_buffer(
+        ctx: Context<ProveStateFromBuffer>,
+        batch_index: u64,
+    ) -> Result<()> {
+        verifier::prove_state_from_buffer(ctx, batch_index)
+    }
+
     /// Get committed batch by index.
     ///
     /// # Arguments
@@ -78,6 +168,20 @@ pub mod l2_state {
         state::get_latest_batch(ctx)
     }
 
+    /// Get the committed batch containing a block, none if it is not batched yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The context of accounts
+    /// * `block_num` - The L2 block number to look up
+    ///
+    pub fn get_batch_for_block(
+        ctx: Context<GetCommittedBatch>,
+        block_num: u64,
+    ) -> Result<Option<BatchData>> {
+        state::get_batch_for_block(ctx, block_num)
+    }
+
     /// Get the index of the last finalized batch.
     ///
     /// # Arguments
@@ -90,6 +194,17 @@ pub mod l2_state {
         state::get_last_finalized_batch_index(ctx)
     }
 
+    /// Get the indices of the committed batches after the last finalized one, sorted
+    /// ascending, at most `MAX_UNPROVEN_BATCHES` of them.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The context of accounts
+    ///
+    pub fn get_unproven_batches(ctx: Context<GetUnprovenBatches>) -> Result<Vec<u64>> {
+        state::get_unproven_batches(ctx)
+    }
+
     /// Deposit native token (sol).
     ///
     /// # Arguments
@@ -101,6 +216,18 @@ pub mod l2_state {
         bridge::deposit(ctx, amount)
     }
 
+    /// Force the inclusion of an L2 transaction the sequencer censors.
+    /// The sender pays the sequencer `bridge::FORCED_TRANSACTION_FEE` for it.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The context of accounts
+    /// * `transaction` - The bincode serialized L2 transaction
+    ///
+    pub fn force_include(ctx: Context<ForceInclude>, transaction: Vec<u8>) -> Result<()> {
+        bridge::force_include(ctx, transaction)
+    }
+
     /// Withdraw native token (sol).
     ///
     /// # Arguments
@@ -111,4 +238,119 @@ pub mod l2_state {
     pub fn withdrawal(ctx: Context<Withdrawal>, withdrawal: WithdrawalData) -> Result<()> {
         bridge::withdrawal(ctx, withdrawal)
     }
+
+    /// Whether a withdrawal root is finalized, withdrawals against it can be claimed.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The context of accounts
+    /// * `withdrawal_root` - The withdrawal root to check
+    ///
+    pub fn get_withdrawal_root_finalized(
+        ctx: Context<GetWithdrawalRoots>,
+        withdrawal_root: [u8; 32],
+    ) -> Result<bool> {
+        bridge::get_withdrawal_root_finalized(ctx, withdrawal_root)
+    }
+
+    /// Prune the finalized withdrawal roots, bounding their account. Permissionless,
+    /// the caller gets the rent freed.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The context of accounts
+    ///
+    pub fn prune_finalized(ctx: Context<PruneFinalized>) -> Result<()> {
+        bridge::prune_finalized(ctx)
+    }
+
+    /// List the latest finalized withdrawal roots.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The context of accounts
+    ///
+    pub fn list_finalized_roots(ctx: Context<GetWithdrawalRoots>) -> Result<Vec<[u8; 32]>> {
+        bridge::list_finalized_roots(ctx)
+    }
+
+    /// Check the bridge is solvent: the sum of the tracked balances and the lamports the
+    /// bridge vault holds above its rent-exempt reserve.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The context of accounts
+    ///
+    pub fn get_vault_solvency(ctx: Context<GetVaultSolvency>) -> Result<VaultSolvency> {
+        bridge::get_vault_solvency(ctx)
+    }
+
+    /// Transfer the authority of all program PDAs, signed by the current authority.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The context of accounts
+    /// * `new_authority` - The new authority, may be a multisig account
+    ///
+    pub fn transfer_authority(
+        ctx: Context<TransferAuthority>,
+        new_authority: Pubkey,
+    ) -> Result<()> {
+        admin::transfer_authority(ctx, new_authority)
+    }
+
+    /// Set the sequencer, the only account allowed to commit batches.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The context of accounts
+    /// * `new_sequencer` - The new sequencer
+    ///
+    pub fn set_sequencer(ctx: Context<SetSequencer>, new_sequencer: Pubkey) -> Result<()> {
+        admin::set_sequencer(ctx, new_sequencer)
+    }
+
+    /// Set the hash of the withdrawal addresses proofs are checked against, when the
+    /// sequencer and the prover change their withdrawal addresses.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The context of accounts
+    /// * `withdrawal_addresses_hash` - `share::transaction::withdrawal_addresses_hash`
+    ///   of the new addresses
+    ///
+    pub fn set_withdrawal_addresses_hash(
+        ctx: Context<SetWithdrawalAddressesHash>,
+        withdrawal_addresses_hash: [u8; 32],
+    ) -> Result<()> {
+        admin::set_withdrawal_addresses_hash(ctx, withdrawal_addresses_hash)
+    }
+
+    /// Roll the last finalized batch index back to `index`, an emergency lever for
+    /// batches that were finalized by mistake. It can never advance the index.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The context of accounts
+    /// * `index` - The new last finalized batch index, below the current one
+    ///
+    pub fn set_finalized_index(ctx: Context<SetFinalizedIndex>, index: u64) -> Result<()> {
+        admin::set_finalized_index(ctx, index)
+    }
+
+    /// Set the withdrawal limit of the bridge vault, a circuit breaker during incidents.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The context of accounts
+    /// * `max_amount` - The max lamports withdrawn per window
+    /// * `window_secs` - The window length in seconds
+    ///
+    pub fn set_withdrawal_limit(
+        ctx: Context<SetWithdrawalLimit>,
+        max_amount: u64,
+        window_secs: i64,
+    ) -> Result<()> {
+        admin::set_withdrawal_limit(ctx, max_amount, window_secs)
+    }
 }
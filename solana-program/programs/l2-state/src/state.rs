@@ -3,16 +3,59 @@
 use anchor_lang::prelude::*;
 
 use crate::{
-    bridge::{BridgeVault, FinalizedWithdrawalRoots, FinalizedWithdrawals},
+    biz_error,
+    bridge::{
+        forced_transactions_hash, BridgeVault, FinalizedWithdrawalRoots, FinalizedWithdrawals,
+        WithdrawalLimit,
+    },
     util::hash_nested_vector,
 };
 
+// Window of the withdrawal limit, one day
+const DEFAULT_WITHDRAWAL_WINDOW_SECS: i64 = 24 * 60 * 60;
+
+// Window to reveal the blocks of a hash-only batch after its commit, seven days
+pub const REVEAL_WINDOW_SECS: i64 = 7 * 24 * 60 * 60;
+
+// Most batch indices returned by `get_unproven_batches`, within the 1024 bytes of
+// return data
+pub const MAX_UNPROVEN_BATCHES: usize = 100;
+
+#[event]
+pub struct BatchCommittedEvent {
+    /// The index of the committed batch
+    pub batch_index: u64,
+    /// The first L2 block of the batch
+    pub start_block_num: u64,
+    /// The last L2 block of the batch
+    pub end_block_num: u64,
+    /// The hash of the batch's blocks
+    pub batch_hash: [u8; 32],
+    /// Timestamp of the commit
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BatchRevealedEvent {
+    /// The index of the revealed batch
+    pub batch_index: u64,
+    /// The account that revealed the blocks
+    pub revealer: Pubkey,
+    /// Timestamp of the reveal
+    pub timestamp: i64,
+}
+
 /*´:°•.°+.*•´.*:˚.°*.˚•´.°:°•.°•.*•´.*:˚.°*.˚•´.°:°•.°+.*•´.*:*/
 /*                          STATE IMPL                        */
 /*.•°:°.´+˚.*°.˚:*.´•*.+°.•°:´*.´•*.•°.•°:°.´:•˚°.*°.˚:*.´+°.•*/
 
 /// Impl of initialize storage PDA.
-pub fn initialize(ctx: Context<Initialize>) -> Result<()> {
+pub fn initialize(
+    ctx: Context<Initialize>,
+    genesis_state_root: [u8; 32],
+    l1_oracle: Pubkey,
+    withdrawal_addresses_hash: [u8; 32],
+) -> Result<()> {
     // Batch (blocks) PDA.
     let batch_storage = &mut ctx.accounts.batch_storage;
     batch_storage.authority = ctx.accounts.authority.key();
@@ -38,7 +81,29 @@ pub fn initialize(ctx: Context<Initialize>) -> Result<()> {
     withdrawals.authority = ctx.accounts.authority.key();
     withdrawals.finalized_withdrawals = Vec::new();
 
+    // Genesis state PDA
+    let genesis_state = &mut ctx.accounts.genesis_state;
+    genesis_state.authority = ctx.accounts.authority.key();
+    genesis_state.state_root = genesis_state_root;
+
+    // Withdrawal limit PDA, no cap until the authority sets one
+    let withdrawal_limit = &mut ctx.accounts.withdrawal_limit;
+    withdrawal_limit.authority = ctx.accounts.authority.key();
+    withdrawal_limit.max_amount = u64::MAX;
+    withdrawal_limit.window_secs = DEFAULT_WITHDRAWAL_WINDOW_SECS;
+    withdrawal_limit.window_start = 0;
+    withdrawal_limit.withdrawn = 0;
+
+    // Config PDA, the authority is the first sequencer
+    let config = &mut ctx.accounts.config;
+    config.authority = ctx.accounts.authority.key();
+    config.sequencer = ctx.accounts.authority.key();
+    config.l1_oracle = l1_oracle;
+    config.withdrawal_addresses_hash = withdrawal_addresses_hash;
+
     msg!("Batch storage and last_finalized batch index initialized");
+    msg!("Genesis state root: {:?}", genesis_state_root);
+    msg!("L1 oracle: {}", l1_oracle);
     Ok(())
 }
 
@@ -47,6 +112,38 @@ pub fn commit_batch(ctx: Context<CommitBatch>, batch_info: BatchInfo) -> Result<
     msg!("Committing batch number: {}", batch_info.batch_index);
     msg!("Number of blocks in batch: {}", batch_info.blocks.len());
 
+    check_genesis(
+        batch_info.batch_index,
+        batch_info.prev_state_root,
+        &ctx.accounts.genesis_state,
+    )?;
+    check_block_range(
+        &ctx.accounts.batch_storage,
+        batch_info.batch_index,
+        batch_info.start_block_num,
+        batch_info.end_block_num,
+    )?;
+    check_state_root_chain(
+        &ctx.accounts.batch_storage,
+        batch_info.batch_index,
+        batch_info.prev_state_root,
+        batch_info.post_state_root,
+    )?;
+
+    // The forced transactions the blocks execute follow the previous batch's, none
+    // may be left overdue
+    let forced_start = check_forced_range(
+        &ctx.accounts.batch_storage,
+        batch_info.batch_index,
+        batch_info.next_forced_index,
+    )?;
+    let forced_transactions_hash = forced_transactions_hash(
+        &ctx.accounts.forced_transactions,
+        forced_start,
+        batch_info.next_forced_index,
+        Clock::get()?.unix_timestamp,
+    )?;
+
     let batch_hash = hash_nested_vector(&batch_info.blocks);
 
     // Create BatchData to store
@@ -58,28 +155,263 @@ pub fn commit_batch(ctx: Context<CommitBatch>, batch_info: BatchInfo) -> Result<
         prev_state_root: batch_info.prev_state_root,
         post_state_root: batch_info.post_state_root,
         withdrawal_root: batch_info.withdrawal_root,
+        withdrawal_count: batch_info.withdrawal_count,
+        next_forced_index: batch_info.next_forced_index,
+        forced_transactions_hash,
     };
 
-    let batch_storage = &mut ctx.accounts.batch_storage;
+    store_batch(&mut ctx.accounts.batch_storage, batch_data);
+
+    emit!(BatchCommittedEvent {
+        batch_index: batch_info.batch_index,
+        start_block_num: batch_info.start_block_num,
+        end_block_num: batch_info.end_block_num,
+        batch_hash,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    msg!(
+        "Batch {} committed with hash: {:?}",
+        batch_info.batch_index,
+        batch_hash
+    );
+
+    Ok(batch_hash)
+}
+
+// Impl of commit batch hash only, the blocks stay off chain until revealed.
+pub fn commit_batch_hash_only(
+    ctx: Context<CommitBatchHashOnly>,
+    batch_info: BatchHashInfo,
+) -> Result<()> {
+    msg!(
+        "Committing hash of batch number: {}",
+        batch_info.batch_index
+    );
+
+    check_genesis(
+        batch_info.batch_index,
+        batch_info.prev_state_root,
+        &ctx.accounts.genesis_state,
+    )?;
+    check_block_range(
+        &ctx.accounts.batch_storage,
+        batch_info.batch_index,
+        batch_info.start_block_num,
+        batch_info.end_block_num,
+    )?;
+    check_state_root_chain(
+        &ctx.accounts.batch_storage,
+        batch_info.batch_index,
+        batch_info.prev_state_root,
+        batch_info.post_state_root,
+    )?;
+
+    // The forced transactions the blocks execute follow the previous batch's, none
+    // may be left overdue
+    let forced_start = check_forced_range(
+        &ctx.accounts.batch_storage,
+        batch_info.batch_index,
+        batch_info.next_forced_index,
+    )?;
+    let forced_transactions_hash = forced_transactions_hash(
+        &ctx.accounts.forced_transactions,
+        forced_start,
+        batch_info.next_forced_index,
+        Clock::get()?.unix_timestamp,
+    )?;
+
+    let batch_data = BatchData {
+        batch_index: batch_info.batch_index,
+        start_block_num: batch_info.start_block_num,
+        end_block_num: batch_info.end_block_num,
+        batch_hash: batch_info.batch_hash,
+        prev_state_root: batch_info.prev_state_root,
+        post_state_root: batch_info.post_state_root,
+        withdrawal_root: batch_info.withdrawal_root,
+        withdrawal_count: batch_info.withdrawal_count,
+        next_forced_index: batch_info.next_forced_index,
+        forced_transactions_hash,
+    };
+    store_batch(&mut ctx.accounts.batch_storage, batch_data);
+
+    let batch_reveal = &mut ctx.accounts.batch_reveal;
+    batch_reveal.batch_index = batch_info.batch_index;
+    batch_reveal.committed_at = Clock::get()?.unix_timestamp;
+    batch_reveal.revealed = false;
+
+    emit!(BatchCommittedEvent {
+        batch_index: batch_info.batch_index,
+        start_block_num: batch_info.start_block_num,
+        end_block_num: batch_info.end_block_num,
+        batch_hash: batch_info.batch_hash,
+        timestamp: batch_reveal.committed_at,
+    });
+    msg!(
+        "Batch {} committed hash only: {:?}",
+        batch_info.batch_index,
+        batch_info.batch_hash
+    );
+    Ok(())
+}
+
+// Impl of reveal batch, anyone holding the blocks can reveal them.
+pub fn reveal_batch(
+    ctx: Context<RevealBatch>,
+    batch_index: u64,
+    blocks: Vec<Vec<u8>>,
+) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let batch_reveal = &mut ctx.accounts.batch_reveal;
+    if now > batch_reveal.committed_at.saturating_add(REVEAL_WINDOW_SECS) {
+        return Err(Error::from(biz_error::ErrorCode::RevealWindowClosed));
+    }
+
+    let batch = ctx
+        .accounts
+        .batch_storage
+        .batches
+        .iter()
+        .find(|b| b.batch_index == batch_index)
+        .ok_or(Error::from(biz_error::ErrorCode::BatchNotExist))?;
+    if hash_nested_vector(&blocks) != batch.batch_hash {
+        return Err(Error::from(biz_error::ErrorCode::BatchHashMismatch));
+    }
+    batch_reveal.revealed = true;
+
+    emit!(BatchRevealedEvent {
+        batch_index,
+        revealer: ctx.accounts.revealer.key(),
+        timestamp: now,
+    });
+    msg!("Batch {} revealed, {} blocks", batch_index, blocks.len());
+    Ok(())
+}
+
+// The first batch must start from the genesis state root.
+fn check_genesis(
+    batch_index: u64,
+    prev_state_root: [u8; 32],
+    genesis_state: &GenesisState,
+) -> Result<()> {
+    if batch_index == 1 && prev_state_root != genesis_state.state_root {
+        return Err(Error::from(biz_error::ErrorCode::InvalidGenesisStateRoot));
+    }
+    Ok(())
+}
+
+// The blocks of consecutive batches must be contiguous, `start_block_num` right
+// after the previous batch's `end_block_num`, and a recommitted batch must still
+// end right before the next one.
+fn check_block_range(
+    batch_storage: &BatchStorage,
+    batch_index: u64,
+    start_block_num: u64,
+    end_block_num: u64,
+) -> Result<()> {
+    if start_block_num > end_block_num {
+        return Err(Error::from(biz_error::ErrorCode::InvalidBlockRange));
+    }
+    let batch = |index: u64| {
+        batch_storage
+            .batches
+            .iter()
+            .find(|b| b.batch_index == index)
+    };
+    let ranges = [
+        batch_index
+            .checked_sub(1)
+            .and_then(batch)
+            .map(|prev| (prev.end_block_num, start_block_num)),
+        batch_index
+            .checked_add(1)
+            .and_then(batch)
+            .map(|next| (end_block_num, next.start_block_num)),
+    ];
+    for (end, next_start) in ranges.into_iter().flatten() {
+        if next_start <= end {
+            return Err(Error::from(biz_error::ErrorCode::BlockRangeOverlap));
+        }
+        if next_start != end + 1 {
+            return Err(Error::from(biz_error::ErrorCode::BlockRangeGap));
+        }
+    }
+    Ok(())
+}
+
+// The state roots of consecutive batches must chain, `prev_state_root` being the
+// previous batch's `post_state_root`, and a recommitted batch must still end on the
+// next one's `prev_state_root`.
+fn check_state_root_chain(
+    batch_storage: &BatchStorage,
+    batch_index: u64,
+    prev_state_root: [u8; 32],
+    post_state_root: [u8; 32],
+) -> Result<()> {
+    let batch = |index: u64| {
+        batch_storage
+            .batches
+            .iter()
+            .find(|b| b.batch_index == index)
+    };
+    let roots = [
+        batch_index
+            .checked_sub(1)
+            .and_then(batch)
+            .map(|prev| (prev.post_state_root, prev_state_root)),
+        batch_index
+            .checked_add(1)
+            .and_then(batch)
+            .map(|next| (post_state_root, next.prev_state_root)),
+    ];
+    if roots.into_iter().flatten().any(|(post, prev)| post != prev) {
+        return Err(Error::from(biz_error::ErrorCode::StateRootMismatch));
+    }
+    Ok(())
+}
+
+// The forced transactions of consecutive batches must follow each other, a batch
+// executing those from the previous batch's `next_forced_index` up to its own, and a
+// recommitted batch must still end where the next one starts. Returns the index of the
+// first forced transaction of the batch.
+fn check_forced_range(
+    batch_storage: &BatchStorage,
+    batch_index: u64,
+    next_forced_index: u64,
+) -> Result<u64> {
+    let batch = |index: u64| {
+        batch_storage
+            .batches
+            .iter()
+            .find(|b| b.batch_index == index)
+    };
+    let start = batch_index
+        .checked_sub(1)
+        .and_then(batch)
+        .map_or(0, |prev| prev.next_forced_index);
+    let end = batch_index
+        .checked_add(1)
+        .and_then(batch)
+        .and(batch(batch_index))
+        .map(|recommitted| recommitted.next_forced_index);
+    if next_forced_index < start || end.is_some_and(|end| end != next_forced_index) {
+        return Err(Error::from(
+            biz_error::ErrorCode::InvalidForcedTransactionRange,
+        ));
+    }
+    Ok(start)
+}
 
-    // Check if batch already exists and update, otherwise append
+// Update the batch if it already exists, otherwise append it
+fn store_batch(batch_storage: &mut BatchStorage, batch_data: BatchData) {
     if let Some(existing_batch) = batch_storage
         .batches
         .iter_mut()
-        .find(|b| b.batch_index == batch_info.batch_index)
+        .find(|b| b.batch_index == batch_data.batch_index)
     {
         *existing_batch = batch_data;
     } else {
         batch_storage.batches.push(batch_data);
     }
-
-    msg!(
-        "Batch {} committed with hash: {:?}",
-        batch_info.batch_index,
-        batch_hash
-    );
-
-    Ok(batch_hash)
 }
 
 /// Impl of get committed batch
@@ -105,6 +437,24 @@ pub fn get_latest_batch(ctx: Context<GetCommittedBatch>) -> Result<Option<BatchD
     }
 }
 
+/// Impl of get batch for block
+pub fn get_batch_for_block(
+    ctx: Context<GetCommittedBatch>,
+    block_num: u64,
+) -> Result<Option<BatchData>> {
+    let batch_storage = &ctx.accounts.batch_storage;
+    Ok(batch_storage.batch_for_block(block_num).cloned())
+}
+
+/// Impl of get unproven batches
+pub fn get_unproven_batches(ctx: Context<GetUnprovenBatches>) -> Result<Vec<u64>> {
+    let last_finalized = ctx.accounts.last_finalized.batch_index;
+    Ok(ctx
+        .accounts
+        .batch_storage
+        .unproven_batches(last_finalized, MAX_UNPROVEN_BATCHES))
+}
+
 /// Impl of get batch index
 pub fn get_last_finalized_batch_index(ctx: Context<GetLatestFinalizedBatchIndex>) -> Result<u64> {
     let last_finalized = &ctx.accounts.last_finalized;
@@ -121,6 +471,23 @@ pub struct BatchInfo {
     pub prev_state_root: [u8; 32],
     pub post_state_root: [u8; 32],
     pub withdrawal_root: [u8; 32],
+    pub withdrawal_count: u64,
+    pub next_forced_index: u64,
+}
+
+/// A batch committed by its hash alone, `batch_hash` is `hash_nested_vector` of the
+/// blocks revealed later.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct BatchHashInfo {
+    pub batch_index: u64,
+    pub batch_hash: [u8; 32],
+    pub start_block_num: u64,
+    pub end_block_num: u64,
+    pub prev_state_root: [u8; 32],
+    pub post_state_root: [u8; 32],
+    pub withdrawal_root: [u8; 32],
+    pub withdrawal_count: u64,
+    pub next_forced_index: u64,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
@@ -132,6 +499,14 @@ pub struct BatchData {
     pub prev_state_root: [u8; 32],
     pub post_state_root: [u8; 32],
     pub withdrawal_root: [u8; 32],
+    // Length of the L2 withdrawal queue at the end of the batch, the range of the
+    // withdrawal tree under `withdrawal_root`
+    pub withdrawal_count: u64,
+    // Index of the first forced transaction the batch and the ones before it didn't
+    // execute, the batch executes those from the previous batch's up to it
+    pub next_forced_index: u64,
+    // Hash of the forced transactions the batch executes, the zkVM program commits it
+    pub forced_transactions_hash: [u8; 32],
 }
 
 #[account]
@@ -144,6 +519,51 @@ impl Space for BatchStorage {
     const INIT_SPACE: usize = 32 + 4 + 0; // authority + vec length + initial empty vec
 }
 
+impl BatchStorage {
+    /// The batch whose `[start_block_num, end_block_num]` range contains `block_num`,
+    /// `None` while the block is not batched yet.
+    pub fn batch_for_block(&self, block_num: u64) -> Option<&BatchData> {
+        self.batches
+            .iter()
+            .rev()
+            .find(|b| b.start_block_num <= block_num && block_num <= b.end_block_num)
+    }
+
+    /// Indices of the batches committed after `last_finalized`, the first `limit` in
+    /// ascending order. Anything but `last_finalized + 1, + 2, ..` has a gap.
+    pub fn unproven_batches(&self, last_finalized: u64, limit: usize) -> Vec<u64> {
+        let mut indices: Vec<u64> = self
+            .batches
+            .iter()
+            .map(|b| b.batch_index)
+            .filter(|batch_index| *batch_index > last_finalized)
+            .collect();
+        indices.sort_unstable();
+        indices.truncate(limit);
+        indices
+    }
+}
+
+/// Reveal status of a hash-only batch, one PDA per batch.
+#[account]
+pub struct BatchReveal {
+    pub batch_index: u64,
+    pub committed_at: i64,
+    pub revealed: bool,
+}
+
+impl Space for BatchReveal {
+    const INIT_SPACE: usize = 8 + 8 + 1; // batch_index + committed_at + revealed
+}
+
+impl BatchReveal {
+    /// Whether the batch can be finalized at `now`: its blocks were revealed, or its
+    /// reveal window closed without them.
+    pub fn is_settled(&self, now: i64) -> bool {
+        self.revealed || now > self.committed_at.saturating_add(REVEAL_WINDOW_SECS)
+    }
+}
+
 #[account]
 pub struct LastFinalizedBatchIndex {
     pub authority: Pubkey,
@@ -154,6 +574,32 @@ impl Space for LastFinalizedBatchIndex {
     const INIT_SPACE: usize = 32 + 8; // authority + u64
 }
 
+#[account]
+pub struct GenesisState {
+    pub authority: Pubkey,
+    pub state_root: [u8; 32],
+}
+
+impl Space for GenesisState {
+    const INIT_SPACE: usize = 32 + 32; // authority + state root
+}
+
+/// Roles of the program: only the sequencer commits batches, anyone can prove them.
+/// Proofs must credit deposits from `l1_oracle` only and withdraw on the addresses
+/// hashing to `withdrawal_addresses_hash`, the sequencer's and the prover's.
+#[account]
+pub struct Config {
+    pub authority: Pubkey,
+    pub sequencer: Pubkey,
+    pub l1_oracle: Pubkey,
+    pub withdrawal_addresses_hash: [u8; 32],
+}
+
+impl Space for Config {
+    // authority + sequencer + l1 oracle + withdrawal addresses hash
+    const INIT_SPACE: usize = 32 + 32 + 32 + 32;
+}
+
 #[derive(Accounts)]
 pub struct Initialize<'info> {
     #[account(
@@ -191,11 +637,35 @@ pub struct Initialize<'info> {
     #[account(
         init,
         payer = authority,
-        space = 8 + BridgeVault::INIT_SPACE,
+        space = 8 + FinalizedWithdrawals::INIT_SPACE,
         seeds = [b"finalized_withdrawals"],
         bump,
     )]
     pub withdrawals: Account<'info, FinalizedWithdrawals>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + GenesisState::INIT_SPACE,
+        seeds = [b"genesis_state"],
+        bump,
+    )]
+    pub genesis_state: Account<'info, GenesisState>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + WithdrawalLimit::INIT_SPACE,
+        seeds = [b"withdrawal_limit"],
+        bump,
+    )]
+    pub withdrawal_limit: Account<'info, WithdrawalLimit>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Config::INIT_SPACE,
+        seeds = [b"config"],
+        bump,
+    )]
+    pub config: Account<'info, Config>,
     #[account(mut)]
     pub authority: Signer<'info>,
     pub system_program: Program<'info, System>,
@@ -208,17 +678,92 @@ pub struct CommitBatch<'info> {
         mut,
         seeds = [b"batch_storage"],
         bump,
-        has_one = authority,
-        realloc = 8 + 32 + 4 + batch_storage.batches.len().saturating_add(1).saturating_mul(152),
-        realloc::payer = authority,
+        realloc = 8 + 32 + 4 + batch_storage.batches.len().saturating_add(1).saturating_mul(200),
+        realloc::payer = sequencer,
         realloc::zero = false,
     )]
     pub batch_storage: Account<'info, BatchStorage>,
+    #[account(
+        seeds = [b"genesis_state"],
+        bump,
+    )]
+    pub genesis_state: Account<'info, GenesisState>,
+    #[account(
+        seeds = [b"config"],
+        bump,
+        has_one = sequencer @ biz_error::ErrorCode::UnauthorizedSequencer,
+    )]
+    pub config: Account<'info, Config>,
     #[account(mut)]
-    pub authority: Signer<'info>,
+    pub sequencer: Signer<'info>,
+    /// CHECK: The `ForcedTransactions` queue, created by the first forced transaction
+    #[account(
+        seeds = [b"forced_transactions"],
+        bump,
+    )]
+    pub forced_transactions: AccountInfo<'info>,
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+#[instruction(batch_info: BatchHashInfo)]
+pub struct CommitBatchHashOnly<'info> {
+    #[account(
+        mut,
+        seeds = [b"batch_storage"],
+        bump,
+        realloc = 8 + 32 + 4 + batch_storage.batches.len().saturating_add(1).saturating_mul(200),
+        realloc::payer = sequencer,
+        realloc::zero = false,
+    )]
+    pub batch_storage: Account<'info, BatchStorage>,
+    #[account(
+        init,
+        payer = sequencer,
+        space = 8 + BatchReveal::INIT_SPACE,
+        seeds = [b"batch_reveal".as_ref(), &batch_info.batch_index.to_le_bytes()],
+        bump,
+    )]
+    pub batch_reveal: Account<'info, BatchReveal>,
+    #[account(
+        seeds = [b"genesis_state"],
+        bump,
+    )]
+    pub genesis_state: Account<'info, GenesisState>,
+    #[account(
+        seeds = [b"config"],
+        bump,
+        has_one = sequencer @ biz_error::ErrorCode::UnauthorizedSequencer,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(mut)]
+    pub sequencer: Signer<'info>,
+    /// CHECK: The `ForcedTransactions` queue, created by the first forced transaction
+    #[account(
+        seeds = [b"forced_transactions"],
+        bump,
+    )]
+    pub forced_transactions: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(batch_index: u64)]
+pub struct RevealBatch<'info> {
+    #[account(
+        seeds = [b"batch_storage"],
+        bump,
+    )]
+    pub batch_storage: Account<'info, BatchStorage>,
+    #[account(
+        mut,
+        seeds = [b"batch_reveal".as_ref(), &batch_index.to_le_bytes()],
+        bump,
+    )]
+    pub batch_reveal: Account<'info, BatchReveal>,
+    pub revealer: Signer<'info>,
+}
+
 #[derive(Accounts)]
 pub struct GetCommittedBatch<'info> {
     #[account(
@@ -228,6 +773,20 @@ pub struct GetCommittedBatch<'info> {
     pub batch_storage: Account<'info, BatchStorage>,
 }
 
+#[derive(Accounts)]
+pub struct GetUnprovenBatches<'info> {
+    #[account(
+        seeds = [b"batch_storage"],
+        bump,
+    )]
+    pub batch_storage: Account<'info, BatchStorage>,
+    #[account(
+        seeds = [b"last_finalized_batch_index"],
+        bump,
+    )]
+    pub last_finalized: Account<'info, LastFinalizedBatchIndex>,
+}
+
 #[derive(Accounts)]
 pub struct GetLatestFinalizedBatchIndex<'info> {
     #[account(
@@ -236,3 +795,175 @@ pub struct GetLatestFinalizedBatchIndex<'info> {
     )]
     pub last_finalized: Account<'info, LastFinalizedBatchIndex>,
 }
+
+#[cfg(test)]
+mod test {
+    use super::{
+        check_block_range, check_forced_range, check_state_root_chain, BatchData, BatchReveal,
+        BatchStorage, REVEAL_WINDOW_SECS,
+    };
+    use crate::biz_error::ErrorCode;
+    use anchor_lang::error::Error;
+    use anchor_lang::prelude::Pubkey;
+
+    // Batch storage of `(batch_index, start_block_num, end_block_num)` batches
+    fn batch_storage(ranges: &[(u64, u64, u64)]) -> BatchStorage {
+        let batches = ranges
+            .iter()
+            .map(|&(batch_index, start_block_num, end_block_num)| BatchData {
+                batch_index,
+                start_block_num,
+                end_block_num,
+                batch_hash: [0; 32],
+                prev_state_root: [0; 32],
+                post_state_root: [0; 32],
+                withdrawal_root: [0; 32],
+                withdrawal_count: 0,
+                next_forced_index: 0,
+                forced_transactions_hash: [0; 32],
+            })
+            .collect();
+        BatchStorage {
+            authority: Pubkey::default(),
+            batches,
+        }
+    }
+
+    #[test]
+    fn test_batch_for_block() {
+        let storage = batch_storage(&[(1, 1, 10), (2, 11, 25), (3, 26, 26)]);
+
+        let batch_index = |block_num| storage.batch_for_block(block_num).map(|b| b.batch_index);
+        assert_eq!(batch_index(1), Some(1));
+        assert_eq!(batch_index(10), Some(1));
+        assert_eq!(batch_index(11), Some(2));
+        assert_eq!(batch_index(26), Some(3));
+        // Genesis and blocks not batched yet
+        assert_eq!(batch_index(0), None);
+        assert_eq!(batch_index(27), None);
+    }
+
+    #[test]
+    fn test_unproven_batches() {
+        // Batch 4 was committed before batch 3, and batch 5 is missing
+        let storage = batch_storage(&[
+            (1, 1, 10),
+            (2, 11, 20),
+            (4, 31, 40),
+            (3, 21, 30),
+            (6, 51, 60),
+        ]);
+
+        assert_eq!(storage.unproven_batches(1, 100), vec![2, 3, 4, 6]);
+        assert_eq!(storage.unproven_batches(1, 2), vec![2, 3]);
+        assert_eq!(storage.unproven_batches(6, 100), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn test_batch_reveal_settled() {
+        let mut reveal = BatchReveal {
+            batch_index: 1,
+            committed_at: 1_000,
+            revealed: false,
+        };
+        assert!(!reveal.is_settled(1_000));
+        assert!(!reveal.is_settled(1_000 + REVEAL_WINDOW_SECS));
+        // The window closed without a reveal
+        assert!(reveal.is_settled(1_000 + REVEAL_WINDOW_SECS + 1));
+
+        reveal.revealed = true;
+        assert!(reveal.is_settled(1_000));
+    }
+
+    #[test]
+    fn test_check_block_range() {
+        let storage = batch_storage(&[(1, 1, 10), (2, 11, 25)]);
+        let check = |batch_index, start, end| check_block_range(&storage, batch_index, start, end);
+
+        check(3, 26, 30).unwrap();
+        // The first batch has no previous range
+        check(1, 1, 10).unwrap();
+        // A recommitted batch keeps its neighbours contiguous
+        check(2, 11, 25).unwrap();
+
+        // Overlapping the previous batch, or the next one on recommit
+        assert_eq!(
+            check(3, 25, 30).unwrap_err(),
+            Error::from(ErrorCode::BlockRangeOverlap)
+        );
+        assert_eq!(
+            check(1, 1, 11).unwrap_err(),
+            Error::from(ErrorCode::BlockRangeOverlap)
+        );
+        // Leaving a gap
+        assert_eq!(
+            check(3, 27, 30).unwrap_err(),
+            Error::from(ErrorCode::BlockRangeGap)
+        );
+        assert_eq!(
+            check(1, 1, 9).unwrap_err(),
+            Error::from(ErrorCode::BlockRangeGap)
+        );
+        // Inverted
+        assert_eq!(
+            check(3, 30, 26).unwrap_err(),
+            Error::from(ErrorCode::InvalidBlockRange)
+        );
+    }
+
+    #[test]
+    fn test_check_state_root_chain() {
+        // Batch n goes from root n - 1 to root n
+        let mut storage = batch_storage(&[(1, 1, 10), (2, 11, 25)]);
+        for batch in storage.batches.iter_mut() {
+            batch.prev_state_root = [batch.batch_index as u8 - 1; 32];
+            batch.post_state_root = [batch.batch_index as u8; 32];
+        }
+        let check = |batch_index, prev: u8, post: u8| {
+            check_state_root_chain(&storage, batch_index, [prev; 32], [post; 32])
+        };
+
+        check(3, 2, 3).unwrap();
+        // A recommitted batch keeps chaining with its neighbours
+        check(2, 1, 2).unwrap();
+
+        // Not starting from the previous batch's post_state_root
+        assert_eq!(
+            check(3, 7, 3).unwrap_err(),
+            Error::from(ErrorCode::StateRootMismatch)
+        );
+        // Recommitted, no longer ending on the next batch's prev_state_root
+        assert_eq!(
+            check(1, 0, 7).unwrap_err(),
+            Error::from(ErrorCode::StateRootMismatch)
+        );
+    }
+
+    #[test]
+    fn test_check_forced_range() {
+        // Batch 1 executes forced transactions 0 and 1, batch 2 none
+        let mut storage = batch_storage(&[(1, 1, 10), (2, 11, 25)]);
+        storage.batches[0].next_forced_index = 2;
+        storage.batches[1].next_forced_index = 2;
+        let check = |batch_index, next_forced_index| {
+            check_forced_range(&storage, batch_index, next_forced_index)
+        };
+
+        // From the previous batch's on
+        assert_eq!(check(3, 2).unwrap(), 2);
+        assert_eq!(check(3, 5).unwrap(), 2);
+        // The first batch from the first forced transaction
+        assert_eq!(check(1, 2).unwrap(), 0);
+
+        // Before the previous batch's
+        assert_eq!(
+            check(3, 1).unwrap_err(),
+            Error::from(ErrorCode::InvalidForcedTransactionRange)
+        );
+        // Recommitted, no longer ending where the next batch starts
+        assert_eq!(
+            check(1, 3).unwrap_err(),
+            Error::from(ErrorCode::InvalidForcedTransactionRange)
+        );
+    }
+}
@@ -1,4 +1,8 @@
 use anchor_lang::solana_program::hash::hash;
+#[cfg(not(feature = "keccak"))]
+use anchor_lang::solana_program::hash::hashv;
+#[cfg(feature = "keccak")]
+use anchor_lang::solana_program::keccak::hashv;
 
 /*´:°•.°+.*•´.*:˚.°*.˚•´.°:°•.°•.*•´.*:˚.°*.˚•´.°:°•.°+.*•´.*:*/
 /*                            UTIL                            */
@@ -12,6 +16,12 @@ pub fn hash_nested_vector(data: &Vec<Vec<u8>>) -> [u8; 32] {
     hash(&concatenated_data).to_bytes()
 }
 
+/// Merkle hash of the concatenated `vals`, sha256 or keccak256 (`keccak` feature)
+/// matching the hash algorithm the l2 state was built with.
+pub fn merkle_hash(vals: &[&[u8]]) -> [u8; 32] {
+    hashv(vals).to_bytes()
+}
+
 pub fn verify_merkle_proof(
     leaf_hash: [u8; 32],
     proof: Vec<[u8; 32]>,
@@ -22,15 +32,9 @@ pub fn verify_merkle_proof(
 
     for (height, sibling) in proof.iter().enumerate() {
         if (index >> height) & 1 == 1 {
-            let mut combined = Vec::with_capacity(64);
-            combined.extend_from_slice(sibling);
-            combined.extend_from_slice(&node);
-            node = hash(&combined).to_bytes();
+            node = merkle_hash(&[sibling, &node]);
         } else {
-            let mut combined = Vec::with_capacity(64);
-            combined.extend_from_slice(&node);
-            combined.extend_from_slice(sibling);
-            node = hash(&combined).to_bytes();
+            node = merkle_hash(&[&node, sibling]);
         }
     }
 
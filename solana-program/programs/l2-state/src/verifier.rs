@@ -6,15 +6,22 @@ use anchor_lang::solana_program::{entrypoint::ProgramResult, msg, program_error:
 use sp1_solana::verify_proof;
 
 use crate::biz_error;
-use crate::bridge::FinalizedWithdrawalRoots;
-use crate::state::{BatchStorage, LastFinalizedBatchIndex};
+use crate::bridge::{dequeue_forced_transactions, FinalizedWithdrawalRoots};
+use crate::state::{BatchData, BatchReveal, BatchStorage, Config, LastFinalizedBatchIndex};
 use crate::util::hash_nested_vector;
 
 /*´:°•.°+.*•´.*:˚.°*.˚•´.°:°•.°•.*•´.*:˚.°*.˚•´.°:°•.°+.*•´.*:*/
 /*                        ZKP VERIFIER IMPL                   */
 /*.•°:°.´+˚.*°.˚:*.´•*.+°.•°:´*.´•*.•°.•°:°.´:•˚°.*°.˚:*.´+°.•*/
 
-// Represents the commitment of the layer2 verification circuit
+// Max size of a proof assembled in a `ProofBuffer`, a Groth16 proof takes a few
+// hundred bytes, far below it
+pub const MAX_PROOF_SIZE: usize = 10 * 1024;
+
+// Represents the commitment of the layer2 verification circuit. It must be regenerated
+// whenever `prover/program` or the `share` code it runs changes: rebuild the ELF with
+// `cargo prove build` in `prover/program`, then run the host once, which logs the new
+// value as "Batch ELF Verification Key" (`vk.bytes32()` of `client.setup`).
 const LAYER2_VKEY_HASH: &str = "0x00bb9e57314d7ee4f65a4b9fb46fbeae0495f2015c5a8a737333680ce6bb424e";
 
 #[derive(BorshDeserialize, BorshSerialize)]
@@ -25,42 +32,191 @@ pub struct Groth16Proof {
 
 // Impl of prove state
 pub fn prove_state(ctx: Context<ProveState>, batch_proof: BatchProof) -> Result<()> {
-    let storage = &ctx.accounts.batch_storage.batches;
-    let batch_index = batch_proof.batch_index;
+    let batch_reveal = load_batch_reveal(&ctx.accounts.batch_reveal)?;
+    finalize_batch(
+        &ctx.accounts.batch_storage,
+        &mut ctx.accounts.last_finalized,
+        &mut ctx.accounts.withdrawal_roots,
+        &ctx.accounts.config,
+        batch_reveal.as_ref(),
+        Clock::get()?.unix_timestamp,
+        batch_proof.batch_index,
+        batch_proof.proof,
+    )?;
+    dequeue_executed_forced_transactions(
+        &ctx.accounts.forced_transactions,
+        &ctx.accounts.batch_storage,
+        &ctx.accounts.last_finalized,
+    )
+}
+
+// Impl of prove state, with the proof assembled by `append_proof_chunk`
+pub fn prove_state_from_buffer(ctx: Context<ProveStateFromBuffer>, batch_index: u64) -> Result<()> {
+    let proof = std::mem::take(&mut ctx.accounts.proof_buffer.proof);
+    let batch_reveal = load_batch_reveal(&ctx.accounts.batch_reveal)?;
+    finalize_batch(
+        &ctx.accounts.batch_storage,
+        &mut ctx.accounts.last_finalized,
+        &mut ctx.accounts.withdrawal_roots,
+        &ctx.accounts.config,
+        batch_reveal.as_ref(),
+        Clock::get()?.unix_timestamp,
+        batch_index,
+        proof,
+    )?;
+    dequeue_executed_forced_transactions(
+        &ctx.accounts.forced_transactions,
+        &ctx.accounts.batch_storage,
+        &ctx.accounts.last_finalized,
+    )
+}
+
+// Impl of open proof buffer
+pub fn open_proof_buffer(ctx: Context<OpenProofBuffer>, batch_index: u64) -> Result<()> {
+    let proof_buffer = &mut ctx.accounts.proof_buffer;
+    proof_buffer.owner = ctx.accounts.sender.key();
+    proof_buffer.batch_index = batch_index;
+    proof_buffer.proof = Vec::new();
+    Ok(())
+}
 
-    let batch = storage
+// Impl of append proof chunk
+pub fn append_proof_chunk(
+    ctx: Context<AppendProofChunk>,
+    _batch_index: u64,
+    offset: u32,
+    chunk: Vec<u8>,
+) -> Result<()> {
+    ctx.accounts.proof_buffer.append(offset, &chunk)
+}
+
+// Verify the proof of a committed batch and finalize it. Resubmitting a finalized
+// batch is a no-op, so a retried `prove_state` neither verifies again nor moves
+// `last_finalized` back. A hash-only batch, the one with a `batch_reveal`, is only
+// finalized once settled at `now`, see `BatchReveal::is_settled`.
+#[allow(clippy::too_many_arguments)]
+fn finalize_batch(
+    batch_storage: &BatchStorage,
+    last_finalized: &mut LastFinalizedBatchIndex,
+    withdrawal_roots: &mut FinalizedWithdrawalRoots,
+    config: &Config,
+    batch_reveal: Option<&BatchReveal>,
+    now: i64,
+    batch_index: u64,
+    proof: Vec<u8>,
+) -> Result<()> {
+    if batch_index <= last_finalized.batch_index {
+        msg!("Batch {} already finalized", batch_index);
+        return Ok(());
+    }
+
+    let batch = batch_storage
+        .batches
         .iter()
         .find(|b| b.batch_index == batch_index)
         .ok_or(Error::from(biz_error::ErrorCode::BatchNotExist))?;
+    // A batch holds at least one block, a zero post_state_root is the batcher's
+    // placeholder left unset, never a state it could finalize
+    if batch.post_state_root == [0u8; 32] {
+        return Err(Error::from(biz_error::ErrorCode::ZeroPostStateRoot));
+    }
+    if batch_reveal.is_some_and(|batch_reveal| !batch_reveal.is_settled(now)) {
+        return Err(Error::from(biz_error::ErrorCode::BatchNotRevealed));
+    }
 
-    // Calculate the commitment of publicInput
-    let pi_hash = hash_nested_vector(&vec![
-        batch.prev_state_root.to_vec(),
-        batch.post_state_root.to_vec(),
-        batch.withdrawal_root.to_vec(),
-        batch.batch_hash.to_vec(),
-    ]);
+    // The withdrawal root the batch starts from, the previous batch's, or the empty
+    // queue's before the first batch
+    let prev_withdrawal_root = match batch_index.checked_sub(1).filter(|index| *index > 0) {
+        Some(prev_index) => {
+            batch_storage
+                .batches
+                .iter()
+                .find(|b| b.batch_index == prev_index)
+                .ok_or(Error::from(biz_error::ErrorCode::BatchNotExist))?
+                .withdrawal_root
+        }
+        None => [0u8; 32],
+    };
 
     let groth16_proof = Groth16Proof {
-        proof: batch_proof.proof,
-        public_inputs: pi_hash.to_vec(),
+        proof,
+        public_inputs: batch_public_inputs(batch, prev_withdrawal_root, config),
     };
 
     prove_batch(groth16_proof)?;
 
     // Update last_finalized_batch_index
-    let last_finalized = &mut ctx.accounts.last_finalized;
     last_finalized.batch_index = batch_index;
 
     // Set withdrawal_root finalized
-    let withdrawal_roots = &mut ctx.accounts.withdrawal_roots;
     withdrawal_roots.set_finalized(batch.withdrawal_root, true);
 
     Ok(())
 }
 
+// Drop the forced transactions the finalized batches executed from the queue, a batch
+// rolled back by `set_finalized_index` keeps their hash to be proven again
+fn dequeue_executed_forced_transactions(
+    forced_transactions: &AccountInfo,
+    batch_storage: &BatchStorage,
+    last_finalized: &LastFinalizedBatchIndex,
+) -> Result<()> {
+    match batch_storage
+        .batches
+        .iter()
+        .find(|b| b.batch_index == last_finalized.batch_index)
+    {
+        Some(batch) => dequeue_forced_transactions(forced_transactions, batch.next_forced_index),
+        None => Ok(()),
+    }
+}
+
+// The `BatchReveal` of a hash-only batch, `None` for a batch committed with its blocks,
+// whose reveal PDA was never created
+fn load_batch_reveal(batch_reveal: &AccountInfo) -> Result<Option<BatchReveal>> {
+    if batch_reveal.data_is_empty() {
+        return Ok(None);
+    }
+    if batch_reveal.owner != &crate::ID {
+        return Err(Error::from(ErrorCode::AccountOwnedByWrongProgram));
+    }
+    let batch_reveal = BatchReveal::try_deserialize(&mut &batch_reveal.try_borrow_data()?[..])?;
+    Ok(Some(batch_reveal))
+}
+
+// Public values the proof of `batch` must commit, `pi_hash || da_hash` as the zkVM
+// program commits them. `pi_hash` is the sha256 of the state roots, the withdrawal
+// roots before and after the batch, the withdrawal addresses hash and the L1 oracle of
+// `config`, the hash of the forced transactions the batch executes and the hash of the
+// proven blocks, in that order, see
+// `share::zkvm::calculate_pi_hash`. The blocks must be the committed `batch_hash`.
+fn batch_public_inputs(
+    batch: &BatchData,
+    prev_withdrawal_root: [u8; 32],
+    config: &Config,
+) -> Vec<u8> {
+    let pi_hash = hash_nested_vector(&vec![
+        batch.prev_state_root.to_vec(),
+        batch.post_state_root.to_vec(),
+        prev_withdrawal_root.to_vec(),
+        batch.withdrawal_root.to_vec(),
+        config.withdrawal_addresses_hash.to_vec(),
+        config.l1_oracle.to_bytes().to_vec(),
+        batch.forced_transactions_hash.to_vec(),
+        batch.batch_hash.to_vec(),
+    ]);
+    [pi_hash, batch.batch_hash].concat()
+}
+
 /// prove state for batch
 fn prove_batch(groth16_proof: Groth16Proof) -> ProgramResult {
+    // Dev builds accept the empty proof of the SP1 mock prover
+    #[cfg(feature = "mock-verifier")]
+    if groth16_proof.proof.is_empty() {
+        msg!("Mock proof accepted, never deploy a `mock-verifier` build");
+        return Ok(());
+    }
+
     let vk = sp1_solana::GROTH16_VK_5_0_0_BYTES;
 
     // Verify the proof.
@@ -89,6 +245,7 @@ pub struct BatchProof {
 }
 
 #[derive(Accounts)]
+#[instruction(batch_proof: BatchProof)]
 pub struct ProveState<'info> {
     #[account(mut)]
     pub sender: Signer<'info>,
@@ -100,6 +257,115 @@ pub struct ProveState<'info> {
     #[account(
         seeds = [b"last_finalized_batch_index"],
         bump,
+        mut,
+    )]
+    pub last_finalized: Account<'info, LastFinalizedBatchIndex>,
+    #[account(
+        seeds = [b"finalized_withdrawal_roots"],
+        bump,
+        mut,
+        realloc = 8 + 32 + 4 + withdrawal_roots.withdrawal_roots.len().saturating_add(1).saturating_mul(40),
+        realloc::payer = sender,
+        realloc::zero = false,
+    )]
+    pub withdrawal_roots: Account<'info, FinalizedWithdrawalRoots>,
+    /// CHECK: The `BatchReveal` of the batch, only created for a hash-only batch
+    #[account(
+        seeds = [b"batch_reveal".as_ref(), &batch_proof.batch_index.to_le_bytes()],
+        bump,
+    )]
+    pub batch_reveal: AccountInfo<'info>,
+    /// CHECK: The `ForcedTransactions` queue, created by the first forced transaction
+    #[account(
+        mut,
+        seeds = [b"forced_transactions"],
+        bump,
+    )]
+    pub forced_transactions: AccountInfo<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump,
+    )]
+    pub config: Account<'info, Config>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Temporary PDA a proof too large for a single transaction is uploaded to in chunks,
+/// closed by `prove_state_from_buffer` and its rent returned to the sender.
+#[account]
+pub struct ProofBuffer {
+    pub owner: Pubkey,
+    pub batch_index: u64,
+    pub proof: Vec<u8>,
+}
+
+impl ProofBuffer {
+    // Discriminator + owner + batch_index + proof length
+    pub const BASE_SPACE: usize = 8 + 32 + 8 + 4;
+
+    /// Append `chunk` at `offset`, the current length of the proof. Offset 0 restarts
+    /// the upload, e.g. after a failed verification.
+    pub fn append(&mut self, offset: u32, chunk: &[u8]) -> Result<()> {
+        if offset == 0 {
+            self.proof.clear();
+        } else if offset as usize != self.proof.len() {
+            return Err(Error::from(biz_error::ErrorCode::InvalidProofChunkOffset));
+        }
+        if self.proof.len() + chunk.len() > MAX_PROOF_SIZE {
+            return Err(Error::from(biz_error::ErrorCode::ProofTooLarge));
+        }
+        self.proof.extend_from_slice(chunk);
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(batch_index: u64)]
+pub struct OpenProofBuffer<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    #[account(
+        init,
+        payer = sender,
+        space = ProofBuffer::BASE_SPACE,
+        seeds = [b"proof_buffer", sender.key().as_ref(), &batch_index.to_le_bytes()],
+        bump,
+    )]
+    pub proof_buffer: Account<'info, ProofBuffer>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(batch_index: u64, offset: u32, chunk: Vec<u8>)]
+pub struct AppendProofChunk<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    #[account(
+        seeds = [b"proof_buffer", sender.key().as_ref(), &batch_index.to_le_bytes()],
+        bump,
+        mut,
+        realloc = ProofBuffer::BASE_SPACE + (offset as usize).min(proof_buffer.proof.len()) + chunk.len(),
+        realloc::payer = sender,
+        realloc::zero = false,
+    )]
+    pub proof_buffer: Account<'info, ProofBuffer>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(batch_index: u64)]
+pub struct ProveStateFromBuffer<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    #[account(
+        seeds = [b"batch_storage"],
+        bump,
+    )]
+    pub batch_storage: Account<'info, BatchStorage>,
+    #[account(
+        seeds = [b"last_finalized_batch_index"],
+        bump,
+        mut,
     )]
     pub last_finalized: Account<'info, LastFinalizedBatchIndex>,
     #[account(
@@ -111,5 +377,351 @@ pub struct ProveState<'info> {
         realloc::zero = false,
     )]
     pub withdrawal_roots: Account<'info, FinalizedWithdrawalRoots>,
+    /// CHECK: The `BatchReveal` of the batch, only created for a hash-only batch
+    #[account(
+        seeds = [b"batch_reveal".as_ref(), &batch_index.to_le_bytes()],
+        bump,
+    )]
+    pub batch_reveal: AccountInfo<'info>,
+    /// CHECK: The `ForcedTransactions` queue, created by the first forced transaction
+    #[account(
+        mut,
+        seeds = [b"forced_transactions"],
+        bump,
+    )]
+    pub forced_transactions: AccountInfo<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        seeds = [b"proof_buffer", sender.key().as_ref(), &batch_index.to_le_bytes()],
+        bump,
+        mut,
+        close = sender,
+    )]
+    pub proof_buffer: Account<'info, ProofBuffer>,
     pub system_program: Program<'info, System>,
 }
+
+#[cfg(test)]
+mod test {
+    use super::{batch_public_inputs, finalize_batch, ProofBuffer, MAX_PROOF_SIZE};
+    use crate::biz_error::ErrorCode;
+    use crate::bridge::FinalizedWithdrawalRoots;
+    use crate::state::{BatchData, BatchReveal, BatchStorage, Config, LastFinalizedBatchIndex};
+    use crate::util::hash_nested_vector;
+    use anchor_lang::prelude::{pubkey, Error, Pubkey};
+
+    // The L1 oracle and withdrawal address of the shared tests, the pubkey of
+    // `UNSAFE_PRIVATE_KEY` and `WITHDRAWAL_ADDRESS`
+    fn config() -> Config {
+        let withdrawal_address = pubkey!("Withdraw11111111111111111111111111111111111");
+        Config {
+            authority: Pubkey::default(),
+            sequencer: Pubkey::default(),
+            l1_oracle: pubkey!("67kQc5qytybDqtfQWiGzEE7g2NAfPMUfmVH2aD7WP7vo"),
+            withdrawal_addresses_hash: hash_nested_vector(&vec![withdrawal_address
+                .to_bytes()
+                .to_vec()]),
+        }
+    }
+
+    #[test]
+    fn test_proof_buffer_chunks() {
+        let proof: Vec<u8> = (0..2_000u32).map(|i| i as u8).collect();
+        let mut buffer = ProofBuffer {
+            owner: Pubkey::default(),
+            batch_index: 1,
+            proof: Vec::new(),
+        };
+
+        let mut offset = 0;
+        for chunk in proof.chunks(900) {
+            buffer.append(offset, chunk).unwrap();
+            offset += chunk.len() as u32;
+        }
+        assert_eq!(buffer.proof, proof);
+
+        // Out of order chunks are rejected, offset 0 restarts
+        assert!(buffer.append(100, &proof[..10]).is_err());
+        buffer.append(0, &proof[..10]).unwrap();
+        assert_eq!(buffer.proof, proof[..10]);
+
+        assert!(buffer.append(10, &vec![0; MAX_PROOF_SIZE]).is_err());
+    }
+
+    #[test]
+    fn test_batch_public_inputs() {
+        let batch = BatchData {
+            batch_index: 1,
+            start_block_num: 1,
+            end_block_num: 10,
+            batch_hash: [4; 32],
+            prev_state_root: [1; 32],
+            post_state_root: [2; 32],
+            withdrawal_root: [3; 32],
+            withdrawal_count: 0,
+            next_forced_index: 0,
+            forced_transactions_hash: [0; 32],
+        };
+        let public_inputs = batch_public_inputs(&batch, [6; 32], &config());
+        assert_eq!(public_inputs.len(), 64);
+        assert_eq!(public_inputs[32..], batch.batch_hash);
+        // sha256 of the roots, the default withdrawal address and oracle and no forced
+        // transactions in the order `share::zkvm::calculate_pi_hash` hashes them, the
+        // client's `batch_public_inputs` is checked against the same bytes
+        assert_eq!(
+            public_inputs[..32],
+            [
+                119, 196, 129, 105, 91, 57, 105, 171, 189, 120, 10, 210, 203, 161, 210, 216, 97,
+                232, 32, 246, 191, 45, 6, 143, 25, 247, 10, 34, 108, 163, 59, 63
+            ]
+        );
+
+        // A proof of other blocks commits another da_hash and doesn't verify
+        let mismatched = BatchData {
+            batch_hash: [5; 32],
+            ..batch.clone()
+        };
+        assert_ne!(
+            batch_public_inputs(&mismatched, [6; 32], &config()),
+            public_inputs
+        );
+        // nor one starting from other withdrawals
+        assert_ne!(
+            batch_public_inputs(&batch, [7; 32], &config()),
+            public_inputs
+        );
+        // nor one withdrawing on other addresses
+        let other_addresses = Config {
+            withdrawal_addresses_hash: [8; 32],
+            ..config()
+        };
+        assert_ne!(
+            batch_public_inputs(&batch, [6; 32], &other_addresses),
+            public_inputs
+        );
+        // nor one executing other forced transactions
+        let other_forced = BatchData {
+            forced_transactions_hash: [9; 32],
+            ..batch.clone()
+        };
+        assert_ne!(
+            batch_public_inputs(&other_forced, [6; 32], &config()),
+            public_inputs
+        );
+        // nor one crediting the deposits of another oracle
+        let other_oracle = Config {
+            l1_oracle: Pubkey::new_unique(),
+            ..config()
+        };
+        assert_ne!(
+            batch_public_inputs(&batch, [6; 32], &other_oracle),
+            public_inputs
+        );
+    }
+
+    fn accounts(
+        batches: Vec<BatchData>,
+    ) -> (
+        BatchStorage,
+        LastFinalizedBatchIndex,
+        FinalizedWithdrawalRoots,
+    ) {
+        (
+            BatchStorage {
+                authority: Pubkey::default(),
+                batches,
+            },
+            LastFinalizedBatchIndex {
+                authority: Pubkey::default(),
+                batch_index: 0,
+            },
+            FinalizedWithdrawalRoots {
+                authority: Pubkey::default(),
+                withdrawal_roots: vec![],
+            },
+        )
+    }
+
+    #[test]
+    fn test_finalize_batch_twice() {
+        let batch = |batch_index: u64| BatchData {
+            batch_index,
+            start_block_num: batch_index,
+            end_block_num: batch_index,
+            batch_hash: [batch_index as u8; 32],
+            prev_state_root: [batch_index as u8 - 1; 32],
+            post_state_root: [batch_index as u8; 32],
+            withdrawal_root: [100 + batch_index as u8; 32],
+            withdrawal_count: 0,
+            next_forced_index: 0,
+            forced_transactions_hash: [0; 32],
+        };
+        let (batch_storage, mut last_finalized, mut withdrawal_roots) =
+            accounts(vec![batch(1), batch(2)]);
+        last_finalized.batch_index = 2;
+        withdrawal_roots.withdrawal_roots = vec![([101; 32], true), ([102; 32], true)];
+
+        // Submitting a finalized batch again succeeds before any proof is verified, without
+        // moving last_finalized back
+        for batch_index in [1, 2] {
+            finalize_batch(
+                &batch_storage,
+                &mut last_finalized,
+                &mut withdrawal_roots,
+                &config(),
+                None,
+                0,
+                batch_index,
+                vec![],
+            )
+            .unwrap();
+        }
+        assert_eq!(last_finalized.batch_index, 2);
+        assert_eq!(
+            withdrawal_roots.withdrawal_roots,
+            vec![([101; 32], true), ([102; 32], true)]
+        );
+    }
+
+    #[test]
+    fn test_zero_post_state_root() {
+        let batch = BatchData {
+            batch_index: 1,
+            start_block_num: 1,
+            end_block_num: 10,
+            batch_hash: [4; 32],
+            prev_state_root: [1; 32],
+            post_state_root: [0; 32],
+            withdrawal_root: [0; 32],
+            withdrawal_count: 0,
+            next_forced_index: 0,
+            forced_transactions_hash: [0; 32],
+        };
+        let (batch_storage, mut last_finalized, mut withdrawal_roots) = accounts(vec![batch]);
+
+        // Rejected before the proof is verified, nothing is finalized
+        let error = finalize_batch(
+            &batch_storage,
+            &mut last_finalized,
+            &mut withdrawal_roots,
+            &config(),
+            None,
+            0,
+            1,
+            vec![],
+        )
+        .unwrap_err();
+        assert_eq!(error, Error::from(ErrorCode::ZeroPostStateRoot));
+        assert_eq!(last_finalized.batch_index, 0);
+        assert!(withdrawal_roots.withdrawal_roots.is_empty());
+    }
+
+    #[cfg(feature = "mock-verifier")]
+    #[test]
+    fn test_zero_withdrawal_root_finalized() {
+        let batch = BatchData {
+            batch_index: 1,
+            start_block_num: 1,
+            end_block_num: 10,
+            batch_hash: [4; 32],
+            prev_state_root: [1; 32],
+            post_state_root: [2; 32],
+            withdrawal_root: [0; 32],
+            withdrawal_count: 0,
+            next_forced_index: 0,
+            forced_transactions_hash: [0; 32],
+        };
+        let (batch_storage, mut last_finalized, mut withdrawal_roots) = accounts(vec![batch]);
+
+        // A zero withdrawal_root is the root of no withdrawals, it's finalized
+        finalize_batch(
+            &batch_storage,
+            &mut last_finalized,
+            &mut withdrawal_roots,
+            &config(),
+            None,
+            0,
+            1,
+            vec![],
+        )
+        .unwrap();
+        assert_eq!(last_finalized.batch_index, 1);
+    }
+
+    #[test]
+    fn test_hash_only_batch_not_revealed() {
+        let batch = BatchData {
+            batch_index: 1,
+            start_block_num: 1,
+            end_block_num: 10,
+            batch_hash: [4; 32],
+            prev_state_root: [1; 32],
+            post_state_root: [2; 32],
+            withdrawal_root: [0; 32],
+            withdrawal_count: 0,
+            next_forced_index: 0,
+            forced_transactions_hash: [0; 32],
+        };
+        let (batch_storage, mut last_finalized, mut withdrawal_roots) = accounts(vec![batch]);
+        let batch_reveal = BatchReveal {
+            batch_index: 1,
+            committed_at: 1_000,
+            revealed: false,
+        };
+
+        // Its blocks may still be revealed, it's rejected before the proof is verified
+        let error = finalize_batch(
+            &batch_storage,
+            &mut last_finalized,
+            &mut withdrawal_roots,
+            &config(),
+            Some(&batch_reveal),
+            1_000,
+            1,
+            vec![],
+        )
+        .unwrap_err();
+        assert_eq!(error, Error::from(ErrorCode::BatchNotRevealed));
+        assert_eq!(last_finalized.batch_index, 0);
+    }
+
+    #[cfg(feature = "mock-verifier")]
+    #[test]
+    fn test_hash_only_batch_revealed() {
+        let batch = BatchData {
+            batch_index: 1,
+            start_block_num: 1,
+            end_block_num: 10,
+            batch_hash: [4; 32],
+            prev_state_root: [1; 32],
+            post_state_root: [2; 32],
+            withdrawal_root: [0; 32],
+            withdrawal_count: 0,
+            next_forced_index: 0,
+            forced_transactions_hash: [0; 32],
+        };
+        let (batch_storage, mut last_finalized, mut withdrawal_roots) = accounts(vec![batch]);
+        let batch_reveal = BatchReveal {
+            batch_index: 1,
+            committed_at: 1_000,
+            revealed: true,
+        };
+
+        finalize_batch(
+            &batch_storage,
+            &mut last_finalized,
+            &mut withdrawal_roots,
+            &config(),
+            Some(&batch_reveal),
+            1_000,
+            1,
+            vec![],
+        )
+        .unwrap();
+        assert_eq!(last_finalized.batch_index, 1);
+    }
+}